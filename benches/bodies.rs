@@ -0,0 +1,38 @@
+mod common;
+
+use async_std::task::block_on;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use surf::Body;
+
+fn stream_bodies(c: &mut Criterion) {
+    let client = common::client();
+    let mut group = c.benchmark_group("bodies");
+    for len in [1024, 64 * 1024, 1024 * 1024] {
+        let data = vec![b'x'; len];
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::new("bytes", len), &data, |b, data| {
+            b.iter(|| {
+                let req = client.post("http://example.org/").body_bytes(data);
+                block_on(req.recv_bytes()).unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("reader", len), &data, |b, data| {
+            b.iter(|| {
+                let body = Body::from_reader(async_std::io::Cursor::new(data.clone()), None);
+                let req = client.post("http://example.org/").body(body);
+                block_on(req.recv_bytes()).unwrap()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("string", len), &data, |b, data| {
+            let text = String::from_utf8(data.clone()).unwrap();
+            b.iter(|| {
+                let req = client.post("http://example.org/").body_string(text.clone());
+                block_on(req.recv_string()).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, stream_bodies);
+criterion_main!(benches);
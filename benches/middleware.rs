@@ -0,0 +1,34 @@
+mod common;
+
+use async_std::task::block_on;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use futures_util::future::BoxFuture;
+use surf::middleware::Next;
+use surf::{Client, Request, Response, Result};
+
+/// A middleware which only passes the request on, to measure the cost of the chain itself.
+fn pass(req: Request, client: Client, next: Next<'_>) -> BoxFuture<'_, Result<Response>> {
+    Box::pin(async move { next.run(req, client).await })
+}
+
+fn middleware_chain(c: &mut Criterion) {
+    let mut group = c.benchmark_group("middleware chain");
+    for len in [0, 1, 4, 16] {
+        let client = (0..len).fold(common::client(), |client, _| client.with(pass));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &client, |b, client| {
+            b.iter(|| block_on(client.get("http://example.org/").send()).unwrap())
+        });
+    }
+
+    let client = common::client();
+    group.bench_function("per-request", |b| {
+        b.iter(|| {
+            let req = client.get("http://example.org/").middleware(pass);
+            block_on(req.send()).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, middleware_chain);
+criterion_main!(benches);
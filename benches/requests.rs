@@ -0,0 +1,110 @@
+mod common;
+
+use async_std::task::block_on;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use serde::Serialize;
+use surf::http::Method;
+use surf::{Request, RequestBuilder, Url};
+
+#[derive(Serialize)]
+struct Query {
+    page: u32,
+    sort: &'static str,
+    filter: &'static str,
+}
+
+fn build_requests(c: &mut Criterion) {
+    let url = Url::parse("http://example.org/items").unwrap();
+    let mut group = c.benchmark_group("build");
+    group.bench_function("GET", |b| {
+        b.iter(|| RequestBuilder::new(Method::Get, url.clone()).build())
+    });
+    group.bench_function("GET with query and headers", |b| {
+        b.iter(|| {
+            RequestBuilder::new(Method::Get, url.clone())
+                .query(&Query {
+                    page: 2,
+                    sort: "name",
+                    filter: "active",
+                })
+                .unwrap()
+                .header("Accept", "application/json")
+                .header("X-Request-Id", "8c0b5f4e")
+                .bearer_auth("token")
+                .build()
+        })
+    });
+    group.bench_function("POST with JSON body", |b| {
+        b.iter(|| {
+            RequestBuilder::new(Method::Post, url.clone())
+                .body_json(&serde_json::json!({ "name": "Chashu", "tags": ["cat", "orange"] }))
+                .unwrap()
+                .build()
+        })
+    });
+    group.finish();
+}
+
+fn bodyless_requests(c: &mut Criterion) {
+    let url = Url::parse("http://example.org/items/1").unwrap();
+    let mut group = c.benchmark_group("bodyless requests");
+    for method in [Method::Get, Method::Head, Method::Delete] {
+        let client = common::client();
+        group.bench_function(method.to_string(), |b| {
+            b.iter(|| block_on(client.send(Request::new(method, url.clone()))).unwrap())
+        });
+    }
+
+    let client = common::client().enable_history(16);
+    group.bench_function("GET with history", |b| {
+        b.iter(|| block_on(client.get(url.as_str()).send()).unwrap())
+    });
+    group.finish();
+}
+
+fn headers(c: &mut Criterion) {
+    let url = Url::parse("http://example.org/").unwrap();
+    let names: Vec<String> = (0..32).map(|i| format!("x-header-{}", i)).collect();
+    let mut group = c.benchmark_group("headers");
+    group.bench_function("insert 32", |b| {
+        b.iter_batched(
+            || Request::new(Method::Get, url.clone()),
+            |mut req| {
+                for name in &names {
+                    req.insert_header(name.as_str(), "value");
+                }
+                req
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("append to one 32 times", |b| {
+        b.iter_batched(
+            || Request::new(Method::Get, url.clone()),
+            |mut req| {
+                for name in &names {
+                    req.append_header("x-list", name.as_str());
+                }
+                req
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    let mut req = Request::new(Method::Get, url.clone());
+    for name in &names {
+        req.insert_header(name.as_str(), "value");
+    }
+    group.bench_function("get of 32", |b| {
+        b.iter(|| {
+            names
+                .iter()
+                .filter(|name| req.header(name.as_str()).is_some())
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, build_requests, bodyless_requests, headers);
+criterion_main!(benches);
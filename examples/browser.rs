@@ -1,4 +1,4 @@
-/// Uncomment to run this example in wasm.
+// Uncomment to run this example in wasm.
 // TODO(yoshuawuyts): properly feature flag this example
 
 // use surf;
@@ -0,0 +1,15 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// The first line of the input is the boundary, and the rest is the body.
+fuzz_target!(|data: &[u8]| {
+    let (boundary, body) = match data.iter().position(|&byte| byte == b'\n') {
+        Some(end) => (String::from_utf8_lossy(&data[..end]), &data[end + 1..]),
+        None => return,
+    };
+    if let Ok(form) = surf::parse::multipart(body, &boundary) {
+        for part in &form {
+            let _ = (part.name(), part.file_name(), part.content_type());
+        }
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// The first line of the input is the charset label, and the rest is the body.
+fuzz_target!(|data: &[u8]| {
+    let (label, body) = match data.iter().position(|&byte| byte == b'\n') {
+        Some(end) => (std::str::from_utf8(&data[..end]).ok(), &data[end + 1..]),
+        None => (None, data),
+    };
+    let _ = surf::parse::text(body, label);
+});
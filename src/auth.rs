@@ -0,0 +1,74 @@
+//! Credentials for the `Authorization` header.
+
+use crate::http::auth::{AuthenticationScheme, Authorization, BasicAuth};
+use crate::http::headers::HeaderValue;
+
+use std::fmt;
+
+/// Credentials sent in the `Authorization` header.
+///
+/// Set them for every request of a client with
+/// [`Config::set_default_auth`](crate::Config::set_default_auth), or for a single request with
+/// [`RequestBuilder::basic_auth`](crate::RequestBuilder::basic_auth) and
+/// [`RequestBuilder::bearer_auth`](crate::RequestBuilder::bearer_auth).
+///
+/// The `Debug` output doesn't include passwords or tokens, so credentials don't end up in logs.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Auth {
+    /// HTTP Basic authentication, with a username and password.
+    Basic {
+        /// The username.
+        username: String,
+        /// The password.
+        password: String,
+    },
+    /// A bearer token, such as an OAuth 2.0 access token.
+    Bearer(String),
+}
+
+impl Auth {
+    /// Create credentials for HTTP Basic authentication.
+    pub fn basic(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Auth::Basic {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Create credentials with a bearer token.
+    pub fn bearer(token: impl Into<String>) -> Self {
+        Auth::Bearer(token.into())
+    }
+
+    /// The value of the `Authorization` header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a bearer token contains characters which aren't allowed in headers.
+    pub(crate) fn header_value(&self) -> HeaderValue {
+        match self {
+            Auth::Basic { username, password } => BasicAuth::new(username, password).value(),
+            Auth::Bearer(token) => {
+                assert!(
+                    token
+                        .bytes()
+                        .all(|byte| byte.is_ascii_graphic() || byte == b' '),
+                    "bearer tokens may only contain visible ASCII characters"
+                );
+                Authorization::new(AuthenticationScheme::Bearer, token.clone()).value()
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Auth::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            Auth::Bearer(_) => f.write_str("Bearer(..)"),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Options of the curl backend which the generic config can't express.
+
+use crate::IpVersion;
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+/// Options specific to the `curl-client` backend, set with
+/// [`Config::set_backend_options`](crate::Config::set_backend_options).
+///
+/// Like the rest of the config, these only apply to clients created from a
+/// [`Config`](crate::Config) without a custom [http client](crate::Config::set_http_client).
+///
+/// The curl backend of this version doesn't offer low-speed limits or TCP fast open. Stalled
+/// transfers can be cut short with [`Config::set_read_timeout`](crate::Config::set_read_timeout)
+/// instead, which works with every backend.
+///
+/// ```
+/// use std::convert::TryInto;
+/// use std::time::Duration;
+/// use surf::{BackendOptions, Client, Config, IpVersion};
+///
+/// # fn main() -> surf::Result<()> {
+/// let options = BackendOptions::new()
+///     .set_ip_version(IpVersion::V4)
+///     .set_tcp_keepalive(Duration::from_secs(30))
+///     .set_max_download_speed(1024 * 1024);
+/// let client: Client = Config::new().set_backend_options(options).try_into()?;
+/// # Ok(())
+/// # }
+/// ```
+#[non_exhaustive]
+#[derive(Debug, Clone, Default)]
+pub struct BackendOptions {
+    /// The network interface or local address connections are made from, if any.
+    pub interface: Option<Interface>,
+    /// The DNS servers to resolve host names with, instead of the system resolver.
+    pub dns_servers: Vec<SocketAddr>,
+    /// Which IP versions to connect with.
+    pub ip_version: IpVersion,
+    /// The interval of TCP keepalive probes, if enabled.
+    pub tcp_keepalive: Option<Duration>,
+    /// The most bytes per second to download, if limited.
+    pub max_download_speed: Option<u64>,
+    /// The most bytes per second to upload, if limited.
+    pub max_upload_speed: Option<u64>,
+}
+
+impl BackendOptions {
+    /// Construct new default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind connections to the network interface named `name`, such as `eth0`.
+    ///
+    /// Note: Binding to an interface by name isn't supported on Windows.
+    ///
+    /// Default: Any interface.
+    pub fn set_interface(mut self, name: impl Into<String>) -> Self {
+        self.interface = Some(Interface::Name(name.into()));
+        self
+    }
+
+    /// Make connections from the local address `addr`.
+    ///
+    /// Default: Any address.
+    pub fn set_local_address(mut self, addr: IpAddr) -> Self {
+        self.interface = Some(Interface::Address(addr));
+        self
+    }
+
+    /// Resolve host names with the DNS servers at `servers`.
+    ///
+    /// Note: This requires a libcurl built with c-ares, and has no effect otherwise.
+    ///
+    /// Default: The system resolver.
+    pub fn set_dns_servers(mut self, servers: impl IntoIterator<Item = SocketAddr>) -> Self {
+        self.dns_servers = servers.into_iter().collect();
+        self
+    }
+
+    /// Only connect with addresses of `version`.
+    ///
+    /// Default: [`IpVersion::Any`].
+    pub fn set_ip_version(mut self, version: IpVersion) -> Self {
+        self.ip_version = version;
+        self
+    }
+
+    /// Send TCP keepalive probes every `interval`.
+    ///
+    /// Default: Disabled.
+    pub fn set_tcp_keepalive(mut self, interval: Duration) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Download at most `bytes_per_second`.
+    ///
+    /// Default: Unlimited.
+    pub fn set_max_download_speed(mut self, bytes_per_second: u64) -> Self {
+        self.max_download_speed = Some(bytes_per_second);
+        self
+    }
+
+    /// Upload at most `bytes_per_second`.
+    ///
+    /// Default: Unlimited.
+    pub fn set_max_upload_speed(mut self, bytes_per_second: u64) -> Self {
+        self.max_upload_speed = Some(bytes_per_second);
+        self
+    }
+
+    /// Apply the options to an isahc client builder.
+    pub(crate) fn apply(&self, mut builder: isahc::HttpClientBuilder) -> isahc::HttpClientBuilder {
+        use isahc::config::{Configurable, NetworkInterface};
+
+        match &self.interface {
+            Some(Interface::Name(name)) => {
+                builder = builder.interface(NetworkInterface::name(name))
+            }
+            Some(Interface::Address(addr)) => builder = builder.interface(*addr),
+            None => {}
+        }
+        if !self.dns_servers.is_empty() {
+            builder = builder.dns_servers(self.dns_servers.iter().copied());
+        }
+        builder = builder.ip_version(self.ip_version.to_isahc());
+        if let Some(interval) = self.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+        if let Some(max) = self.max_download_speed {
+            builder = builder.max_download_speed(max);
+        }
+        if let Some(max) = self.max_upload_speed {
+            builder = builder.max_upload_speed(max);
+        }
+        builder
+    }
+}
+
+/// Where connections are made from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Interface {
+    /// The network interface with this name, such as `eth0`.
+    Name(String),
+    /// This local address.
+    Address(IpAddr),
+}
@@ -0,0 +1,14 @@
+//! How responses were obtained from a cache.
+
+/// How a response was obtained from [`Client::get_cached_json`](crate::Client::get_cached_json)
+/// or the [`Cache`](crate::middleware::Cache) middleware, which stores it in the response
+/// extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// The cached response was still fresh, so no request was sent.
+    Fresh,
+    /// The cached response was stale, and the server confirmed it is still valid.
+    Revalidated,
+    /// There was no usable cached response, so the full response was fetched.
+    Miss,
+}
@@ -0,0 +1,71 @@
+//! Text request bodies in encodings other than utf-8.
+
+use crate::http::{Body, Mime};
+
+use encoding_rs::Encoding;
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+/// An error occurred while encoding a request body from a string.
+///
+/// The error carries the encoding that was requested, and the text which could not be encoded,
+/// either because the encoding is unsupported or because the text contains characters the
+/// encoding can't represent.
+#[derive(Clone)]
+pub struct EncodeError {
+    /// The name of the encoding that was requested.
+    pub encoding: String,
+    /// The text which could not be encoded.
+    pub text: String,
+}
+
+// Like `DecodeError`, don't print bodies which may be many megabytes large.
+impl fmt::Debug for EncodeError {
+    #[allow(missing_doc_code_examples)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncodeError")
+            .field("encoding", &self.encoding)
+            .field("text", &format!("{} bytes", self.text.len()))
+            .finish()
+    }
+}
+
+impl fmt::Display for EncodeError {
+    #[allow(missing_doc_code_examples)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "could not encode body as {}", &self.encoding)
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+/// A `text/plain` body holding `text` encoded as `encoding`, with the encoding as its charset.
+///
+/// # Errors
+///
+/// If the encoding is unsupported, or `text` has characters the encoding can't represent, this
+/// function returns an `std::io::Error` of kind `std::io::ErrorKind::InvalidData`, carrying an
+/// `EncodeError` struct.
+pub(crate) fn encoded_string_body(text: &str, encoding: &str) -> crate::Result<Body> {
+    let fail = || {
+        let err = EncodeError {
+            encoding: encoding.to_string(),
+            text: text.to_string(),
+        };
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    };
+
+    let requested = Encoding::for_label(encoding.as_bytes()).ok_or_else(fail)?;
+    // Encodings which can't be written, such as UTF-16, fall back to utf-8, which the charset
+    // reflects.
+    let (bytes, encoding_used, failed) = requested.encode(text);
+    if failed {
+        return Err(fail().into());
+    }
+    let mut body = Body::from(bytes.into_owned());
+    let mime = format!("text/plain;charset={}", encoding_used.name());
+    body.set_mime(Mime::from_str(&mime)?);
+    Ok(body)
+}
@@ -1,18 +1,66 @@
+use std::any::TypeId;
 use std::convert::TryFrom;
 use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 
-use crate::http::{Method, Url};
-use crate::middleware::{Middleware, Next};
-use crate::{Config, HttpClient, Request, RequestBuilder, Response, Result};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connection::Connections;
+use crate::error::classify_connection_error;
+use crate::events::{ClientEvent, ClientEvents, EventHub, EventId};
+#[cfg(feature = "curl-client")]
+use crate::forward_proxy;
+#[cfg(all(
+    feature = "default-client",
+    not(feature = "curl-client"),
+    not(target_arch = "wasm32")
+))]
+use crate::forward_proxy::ProxyUnsupported;
+use crate::history::History;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::history::{capture_body, Capture};
+use crate::http::headers::{HeaderName, ToHeaderValues, ACCEPT_ENCODING};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::http::headers::{CONTENT_ENCODING, DATE};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::http::other::Date;
+use crate::http::{Method, StatusCode, Url};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::middleware::cache::{Cache, SharedCache};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::middleware::CookieJar;
+use crate::middleware::{Middleware, Next, Position};
+use crate::pagination::CursorExtractor;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::response::ReceivedAt;
+use crate::response::{
+    ErrorBodyLimit, HeaderLimitExceeded, JsonLineLimit, ResponseUrl, SuccessPredicate,
+};
+use crate::shutdown::ShutdownToken;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::timeout::{RequestTimeout, TimeoutReader};
+#[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+use crate::tls::{TlsClients, TlsOverride};
+use crate::utils::{map_body, map_body_reader};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::CacheStatus;
+use crate::{
+    ClientBuilder, ClientState, Config, DirEntry, Error, HistoryEntry, HttpClient, Request,
+    RequestBuilder, Resource, Response, Result, Shutdown,
+};
 
 use cfg_if::cfg_if;
+#[cfg(not(target_arch = "wasm32"))]
+use futures_util::future::{self, Either, FutureExt};
+use futures_util::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
 
 cfg_if! {
     if #[cfg(feature = "curl-client")] {
-        use http_client::isahc::IsahcClient as DefaultClient;
+        use crate::curl::CurlClient as DefaultClient;
     } else if #[cfg(feature = "wasm-client")] {
-        use http_client::wasm::WasmClient as DefaultClient;
+        use crate::wasm::FetchClient as DefaultClient;
     } else if #[cfg(any(feature = "h1-client", feature = "h1-client-rustls", feature = "h1-client-no-tls"))] {
         use http_client::h1::H1Client as DefaultClient;
     } else if #[cfg(feature = "hyper-client")] {
@@ -20,12 +68,18 @@ cfg_if! {
     }
 }
 cfg_if! {
-    if #[cfg(any(feature = "curl-client", feature = "h1-client", feature = "h1-client-rustls", feature = "hyper-client"))] {
+    if #[cfg(any(feature = "curl-client", feature = "hyper-client"))] {
         use once_cell::sync::Lazy;
         static GLOBAL_CLIENT: Lazy<Arc<DefaultClient>> = Lazy::new(|| Arc::new(DefaultClient::new()));
+        #[cfg(not(target_arch = "wasm32"))]
+        static GLOBAL_CONNECTIONS: Lazy<Arc<Connections>> = Lazy::new(Arc::default);
     }
 }
 
+/// The client used by the one-off functions, set by `surf::configure` or on first use.
+#[cfg(feature = "default-client")]
+static SHARED_CLIENT: once_cell::sync::OnceCell<Client> = once_cell::sync::OnceCell::new();
+
 /// An HTTP client, capable of sending `Request`s and running a middleware stack.
 ///
 /// Can be optionally set with a base url.
@@ -50,23 +104,61 @@ pub struct Client {
     /// The outer Arc allows us to clone in .send() without cloning the array.
     /// The Vec allows us to add middleware at runtime.
     /// The inner Arc-s allow us to implement Clone without sharing the vector with the parent.
-    /// We don't use a Mutex around the Vec here because adding a middleware during execution should be an error.
+    /// Adding middleware to a stack that is shared (by a clone, or by an in-flight request)
+    /// copies the Vec first, so it never affects the other users of the stack.
     #[allow(clippy::rc_buffer)]
     middleware: Arc<Vec<Arc<dyn Middleware>>>,
+    /// The types of the middleware in `middleware`, in the same order, for `with_ordered`.
+    #[allow(clippy::rc_buffer)]
+    middleware_types: Arc<Vec<TypeId>>,
+    /// Holds the recently sent requests, if recording is enabled.
+    history: Option<Arc<History>>,
+    /// Holds the responses cached by `get_cached_json`, unless the client has its own `Cache`.
+    #[cfg(not(target_arch = "wasm32"))]
+    cache: Arc<Cache>,
+    /// Shuts down the requests of this client and its clones.
+    shutdown: ShutdownToken,
+    /// The values shared by this client, its clones, and its middleware.
+    state: ClientState,
+    /// The subscribers to the events of this client and its clones.
+    events: Arc<EventHub>,
+    /// Tracks the connections of `http_client`, for `ConnectionInfo`.
+    #[cfg(not(target_arch = "wasm32"))]
+    connections: Arc<Connections>,
+    /// The cookie jar added by `Config::set_cookie_store`, if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    cookie_jar: Option<CookieJar>,
+    /// The clients for requests with their own TLS configuration, if the default backend is used.
+    #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+    tls_clients: Option<Arc<TlsClients>>,
 }
 
 impl Clone for Client {
     /// Clones the Client.
     ///
-    /// This copies the middleware stack from the original, but shares
-    /// the `HttpClient` and http client config of the original.
+    /// The middleware stack is copied from the original when either client
+    /// registers more middleware, and the `HttpClient` and http client config
+    /// of the original are shared.
     /// Note that individual middleware in the middleware stack are
     /// still shared by reference.
     fn clone(&self) -> Self {
         Self {
             config: self.config.clone(),
             http_client: self.http_client.clone(),
-            middleware: Arc::new(self.middleware.iter().cloned().collect()),
+            middleware: self.middleware.clone(),
+            middleware_types: self.middleware_types.clone(),
+            history: self.history.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            cache: self.cache.clone(),
+            shutdown: self.shutdown.clone(),
+            state: self.state.clone(),
+            events: self.events.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            connections: self.connections.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            cookie_jar: self.cookie_jar.clone(),
+            #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+            tls_clients: self.tls_clients.clone(),
         }
     }
 }
@@ -100,7 +192,46 @@ impl Client {
     /// ```
     #[cfg(feature = "default-client")]
     pub fn new() -> Self {
-        Self::with_http_client(DefaultClient::new())
+        #[allow(unused_mut)]
+        let mut client = Self::with_http_client(DefaultClient::new());
+        #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+        {
+            client.tls_clients = Some(Arc::default());
+        }
+        #[cfg(all(
+            any(
+                feature = "h1-client",
+                feature = "h1-client-rustls",
+                feature = "h1-client-no-tls"
+            ),
+            not(feature = "curl-client"),
+            not(target_arch = "wasm32")
+        ))]
+        {
+            client.http_client = Arc::new(crate::h1::Transport::new(
+                client.http_client.clone(),
+                client.config.clone(),
+                #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+                client.tls_clients.clone().unwrap(),
+            ));
+        }
+        client
+    }
+
+    /// Create a [`ClientBuilder`], to configure a `Client` in a single chain.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # fn main() -> surf::Result<()> {
+    /// let client = surf::Client::builder()
+    ///     .base_url(surf::Url::parse("https://httpbin.org/")?)
+    ///     .with(surf::middleware::Redirect::default())
+    ///     .build()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
     }
 
     pub(crate) fn new_shared_or_panic() -> Self {
@@ -133,6 +264,19 @@ impl Client {
             config: Config::default(),
             http_client,
             middleware: Arc::new(vec![]),
+            middleware_types: Arc::new(vec![]),
+            history: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            cache: Arc::default(),
+            shutdown: ShutdownToken::default(),
+            state: ClientState::default(),
+            events: Arc::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            connections: Arc::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            cookie_jar: None,
+            #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+            tls_clients: None,
         };
 
         #[cfg(feature = "middleware-logger")]
@@ -143,9 +287,34 @@ impl Client {
 
     #[cfg(feature = "default-client")]
     pub(crate) fn new_shared() -> Self {
+        SHARED_CLIENT.get_or_init(Self::new_global).clone()
+    }
+
+    /// Configure the client used by the one-off functions, unless it has already been used.
+    #[cfg(feature = "default-client")]
+    pub(crate) fn configure_shared(config: Config) -> Result<()> {
+        let mut client = Some(Client::try_from(config)?);
+        SHARED_CLIENT.get_or_init(|| client.take().unwrap());
+        match client {
+            None => Ok(()),
+            Some(_) => Err(Error::from_str(
+                StatusCode::InternalServerError,
+                "the global client has already been configured or used",
+            )),
+        }
+    }
+
+    #[cfg(feature = "default-client")]
+    fn new_global() -> Self {
         cfg_if! {
-            if #[cfg(any(feature = "curl-client", feature = "h1-client", feature = "h1-client-rustls", feature = "hyper-client"))] {
-                Self::with_http_client_internal(GLOBAL_CLIENT.clone())
+            if #[cfg(any(feature = "curl-client", feature = "hyper-client"))] {
+                let client = Self::with_http_client_internal(GLOBAL_CLIENT.clone());
+                #[cfg(not(target_arch = "wasm32"))]
+                let client = Self {
+                    connections: GLOBAL_CONNECTIONS.clone(),
+                    ..client
+                };
+                client
             } else {
                 Self::new()
             }
@@ -156,6 +325,11 @@ impl Client {
     ///
     /// See the [middleware] submodule for more information on middleware.
     ///
+    /// This can be called at any time, including after the client has been used: if the
+    /// middleware stack is shared with clones of this client or with requests in flight, it is
+    /// copied first, so the new middleware only applies to requests sent through the returned
+    /// client.
+    ///
     /// [middleware]: ../middleware/index.html
     ///
     /// # Examples
@@ -169,17 +343,163 @@ impl Client {
     /// let res = client.send(req).await?;
     /// # Ok(()) }
     /// ```
-    pub fn with(mut self, middleware: impl Middleware) -> Self {
-        let m = Arc::get_mut(&mut self.middleware)
-            .expect("Registering middleware is not possible after the Client has been used");
-        m.push(Arc::new(middleware));
+    pub fn with(self, middleware: impl Middleware) -> Self {
+        self.with_ordered(middleware, Position::last())
+    }
+
+    /// Insert middleware into the middleware stack at `position`.
+    ///
+    /// Unlike [`with`](Client::with), which always adds middleware at the end, this can put
+    /// middleware around others, such as a `Retry` inside of the default `Logger`, so every
+    /// attempt is logged. Like `with`, this never affects clones of the client.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use surf::middleware::{Logger, Position, Redirect, Retry};
+    ///
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let client = surf::client()
+    ///     .with(Redirect::default())
+    ///     .with_ordered(Retry::default(), Position::before::<Redirect>());
+    /// let res = client.get("https://httpbin.org/get").await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn with_ordered<M: Middleware>(mut self, middleware: M, position: Position) -> Self {
+        self.insert_middleware(TypeId::of::<M>(), Arc::new(middleware), position);
         self
     }
 
+    /// Insert `middleware`, whose type is `ty`, into the middleware stack at `position`.
+    pub(crate) fn insert_middleware(
+        &mut self,
+        ty: TypeId,
+        middleware: Arc<dyn Middleware>,
+        position: Position,
+    ) {
+        let index = position.resolve(&self.middleware_types);
+        Arc::make_mut(&mut self.middleware_types).insert(index, ty);
+        Arc::make_mut(&mut self.middleware).insert(index, middleware);
+    }
+
+    /// Keep a summary of the last `capacity` requests sent by this client.
+    ///
+    /// Each entry records the method, URL, status, time until the response head arrived, and
+    /// the first kilobyte of both the request and response bodies. Bodies are captured as they
+    /// are streamed, so the response body preview fills up as the body is read.
+    ///
+    /// Clones of this client share the same history.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let client = surf::client().enable_history(20);
+    /// client.get("https://httpbin.org/get").recv_string().await?;
+    ///
+    /// for entry in client.history() {
+    ///     println!("{} {} -> {:?} in {:?}", entry.method, entry.url, entry.status, entry.elapsed);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn enable_history(mut self, capacity: usize) -> Self {
+        self.history = Some(Arc::new(History::new(capacity)));
+        self
+    }
+
+    /// Get the recorded requests, oldest first.
+    ///
+    /// This is empty unless recording was turned on with [`enable_history`](Client::enable_history).
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.history
+            .as_ref()
+            .map(|history| history.entries())
+            .unwrap_or_default()
+    }
+
+    /// Get a handle to shut down this client and its clones.
+    ///
+    /// See [`ShutdownToken`] for more information.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let client = surf::client();
+    /// let token = client.shutdown_token();
+    /// token.shutdown();
+    /// assert!(client.shutdown_token().is_shutdown());
+    /// ```
+    pub fn shutdown_token(&self) -> ShutdownToken {
+        self.shutdown.clone()
+    }
+
+    /// Get the values shared by this client, its clones, and its middleware.
+    ///
+    /// See [`ClientState`] for more information.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// #[derive(Clone, Debug, PartialEq)]
+    /// struct Tenant(&'static str);
+    ///
+    /// let client = surf::client();
+    /// client.state().insert(Tenant("acme"));
+    /// assert_eq!(client.state().get(), Some(Tenant("acme")));
+    /// ```
+    pub fn state(&self) -> &ClientState {
+        &self.state
+    }
+
+    /// Subscribe to the high-level events of the requests of this client and its clones, such
+    /// as to render network activity in a user interface without writing middleware.
+    ///
+    /// Each call returns a new stream, which receives the events of the requests sent after it.
+    /// Events are dropped for streams which fall more than 1024 events behind, so a stream which
+    /// is never read doesn't hold on to them. Events are only tracked while there are streams.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use futures_util::StreamExt;
+    /// use surf::ClientEvent;
+    ///
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let client = surf::client().with(surf::middleware::Redirect::default());
+    /// let mut events = client.events();
+    /// async_std::task::spawn(async move {
+    ///     while let Some(event) = events.next().await {
+    ///         if let ClientEvent::BytesDownloaded { id, read, total } = event {
+    ///             println!("request {}: {} of {:?} bytes", id, read, total);
+    ///         }
+    ///     }
+    /// });
+    /// client.get("https://httpbin.org/bytes/4096").recv_bytes().await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn events(&self) -> ClientEvents {
+        self.events.subscribe()
+    }
+
+    /// Broadcast `event` to the subscribers of [`Client::events`].
+    pub(crate) fn emit(&self, event: ClientEvent) {
+        self.events.emit(event);
+    }
+
     /// Send a `Request` using this client.
     ///
     /// Client middleware is run before per-request middleware.
     ///
+    /// Requests carrying a `Range` header are sent with `Accept-Encoding: identity` unless an
+    /// encoding was set explicitly, so that partial responses can be resumed reliably.
+    ///
+    /// Once the client has been [shut down](Client::shutdown_token), requests fail with a
+    /// [`Shutdown`] error without being sent.
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -191,7 +511,38 @@ impl Client {
     /// # Ok(()) }
     /// ```
     pub async fn send(&self, req: impl Into<Request>) -> Result<Response> {
+        if self.shutdown.is_shutdown() {
+            return Err(Error::new(StatusCode::ServiceUnavailable, Shutdown));
+        }
         let mut req: Request = req.into();
+        crate::scoped_headers::apply(&mut req);
+        #[cfg(unix)]
+        crate::unix_socket::apply(AsMut::<http_types::Request>::as_mut(&mut req), &self.config)?;
+        for validate in &self.config.validators {
+            validate(&req)?;
+        }
+
+        // Content codings apply to the whole representation, so a compressed response can't be
+        // resumed at a byte offset. Negotiate the identity coding for range requests instead.
+        if req.header("Range").is_some() && req.header(ACCEPT_ENCODING).is_none() {
+            req.insert_header(ACCEPT_ENCODING, "identity");
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.config.send_date && req.header(DATE).is_none() {
+            Date::new(self.config.clock.system_now()).apply(&mut req);
+        }
+
+        let event_id = self.events.start();
+        if let Some(id) = event_id {
+            req.set_ext(id);
+            self.emit(ClientEvent::RequestStarted {
+                id: id.0,
+                method: req.method(),
+                url: req.url().clone(),
+            });
+        }
+
         let http_client = self.http_client.clone();
         let middleware = self.middleware.clone();
 
@@ -207,8 +558,41 @@ impl Client {
 
         let next = Next::new(&mw_stack, &|req, client| {
             Box::pin(async move {
+                if !client.config.allows_host(req.url()) {
+                    let host = req.url().host_str().unwrap_or_default().to_string();
+                    return Err(Error::from_str(
+                        StatusCode::Forbidden,
+                        format!("requests to {} are not allowed by this client", host),
+                    ));
+                }
+                let url = req.url().clone();
+                #[cfg(not(target_arch = "wasm32"))]
+                let (method, checkout) = (
+                    req.method(),
+                    client.connections.checkout(&url, client.config.clock.now()),
+                );
                 let req: http_types::Request = req.into();
-                client.http_client.send(req).await.map(Into::into)
+                let mut res = send_with_timeout(&client, req).await?;
+                HeaderLimitExceeded::check(&res, &client.config)
+                    .map_err(|err| Error::new(StatusCode::BadGateway, err))?;
+                map_body(&mut res, |body| {
+                    let len = body.len();
+                    map_body_reader(body, len, |inner| client.shutdown.reader(inner))
+                });
+                let mut res = Response::from(res);
+                res.insert_ext(ResponseUrl(url));
+                #[cfg(not(target_arch = "wasm32"))]
+                checkout.finish(method, &mut res, client.config.http_config.http_keep_alive);
+                #[cfg(not(target_arch = "wasm32"))]
+                if client.config.detect_clock_skew {
+                    res.insert_ext(ReceivedAt(client.config.clock.system_now()));
+                }
+                if let Some(limit) = client.config.error_body_limit {
+                    res.insert_ext(ErrorBodyLimit(limit));
+                }
+                res.insert_ext(JsonLineLimit(client.config.max_json_line_length));
+                res.insert_ext(SuccessPredicate(client.config.success_predicate));
+                Ok(res)
             })
         });
 
@@ -218,12 +602,85 @@ impl Client {
             // Erase the middleware stack for the Client accessible from within middleware.
             // This avoids gratuitous circular borrow & logic issues.
             middleware: Arc::new(vec![]),
+            middleware_types: Arc::new(vec![]),
+            history: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            cache: self.cache.clone(),
+            shutdown: self.shutdown.clone(),
+            state: self.state.clone(),
+            events: self.events.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            connections: self.connections.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            cookie_jar: self.cookie_jar.clone(),
+            #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+            tls_clients: self.tls_clients.clone(),
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let recording = self.history.as_ref().map(|history| {
+            let entry = history.record(req.method(), req.url().clone());
+            entry.lock().unwrap().request_encoding = req
+                .header(CONTENT_ENCODING)
+                .map(|values| values.last().to_string());
+            map_body(&mut req, |body| {
+                capture_body(body, entry.clone(), Capture::Request)
+            });
+            (entry, self.config.clock.now())
+        });
+
+        let url = req.url().clone();
+        let res = next.run(req, client).await;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let res = match recording {
+            Some((entry, start)) => {
+                record_response(res, entry, self.config.clock.now().duration_since(start))
+            }
+            None => res,
+        };
+
+        let res = match event_id {
+            Some(id) => self.observe(id, res),
+            None => res,
         };
 
-        let res = next.run(req, client).await?;
+        let mut res = res?;
+        // Middleware may answer without sending anything, e.g. from a cache.
+        if res.ext::<ResponseUrl>().is_none() {
+            res.insert_ext(ResponseUrl(url));
+        }
         Ok(Response::new(res.into()))
     }
 
+    /// Broadcast the outcome of the request `id`, and the progress of reading its body.
+    fn observe(&self, EventId(id): EventId, res: Result<Response>) -> Result<Response> {
+        let mut res = match res {
+            Ok(res) => res,
+            Err(err) => {
+                self.emit(ClientEvent::RequestFailed {
+                    id,
+                    message: err.to_string(),
+                });
+                return Err(err);
+            }
+        };
+        self.emit(ClientEvent::ResponseReceived {
+            id,
+            status: res.status(),
+        });
+        let events = self.events.clone();
+        res.map_body(|body| {
+            crate::progress::report_progress(
+                body,
+                Box::new(move |read, total| {
+                    events.emit(ClientEvent::BytesDownloaded { id, read, total })
+                }),
+            )
+        });
+        Ok(res)
+    }
+
     /// Submit a `Request` and get the response body as bytes.
     ///
     /// # Examples
@@ -258,6 +715,45 @@ impl Client {
         res.body_string().await
     }
 
+    /// Download a file to `path`, verifying its checksum.
+    ///
+    /// The body is streamed to a temporary file next to `path` and hashed along the way. If
+    /// the checksum matches, the temporary file is renamed to `path`; otherwise it is removed and
+    /// an error carrying a [`ChecksumMismatch`](crate::ChecksumMismatch) is returned, so `path`
+    /// never holds a partial or corrupted file. Returns the number of bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the response status isn't a success, the file
+    /// can't be written, or the checksum doesn't match.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// use surf::Checksum;
+    ///
+    /// let checksum = Checksum::Sha256(
+    ///     "2f9c2c5d7ba8ba42a38e8bfbd1fa8e4aac2b1f3b0b3a6b3c1d6b7a41a0d0c8e1".to_string(),
+    /// );
+    /// surf::client()
+    ///     .download_verified("https://example.com/tool.tar.gz", "tool.tar.gz", checksum)
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "checksum")))]
+    #[cfg(all(feature = "checksum", not(target_arch = "wasm32")))]
+    pub async fn download_verified(
+        &self,
+        uri: impl AsRef<str>,
+        path: impl AsRef<std::path::Path>,
+        checksum: crate::Checksum,
+    ) -> Result<u64> {
+        let req = self.get(uri).build();
+        crate::download::download_verified(self, req, path.as_ref(), checksum).await
+    }
+
     /// Submit a `Request` and decode the response body from json into a struct.
     ///
     /// # Examples
@@ -284,6 +780,192 @@ impl Client {
         res.body_json::<T>().await
     }
 
+    /// Send many `Request`s, returning a stream of their responses.
+    ///
+    /// Requests are taken from `requests` as the stream is polled, and at most `max_concurrency`
+    /// of them are in flight at a time. The results are yielded in the same order as the
+    /// requests, so a slow request holds back the results after it; use
+    /// [`send_all_unordered`](Client::send_all_unordered) to get results as they arrive. A
+    /// failing request doesn't affect the others.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// use futures_util::StreamExt;
+    ///
+    /// let client = surf::client();
+    /// let requests = (1..=500).map(|id| surf::get(format!("https://example.com/items/{}", id)));
+    /// let mut responses = client.send_all(requests, 16);
+    /// while let Some(res) = responses.next().await {
+    ///     println!("{}", res?.status());
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn send_all<I, R>(
+        &self,
+        requests: I,
+        max_concurrency: usize,
+    ) -> BoxStream<'static, Result<Response>>
+    where
+        I: IntoIterator<Item = R>,
+        I::IntoIter: Send + 'static,
+        R: Into<Request>,
+    {
+        let client = self.clone();
+        stream::iter(requests)
+            .map(move |req| {
+                let client = client.clone();
+                let req = req.into();
+                async move { client.send(req).await }
+            })
+            .buffered(max_concurrency.max(1))
+            .boxed()
+    }
+
+    /// Send many `Request`s, returning a stream of their responses as they arrive, each with
+    /// the index of its request.
+    ///
+    /// This is like [`send_all`](Client::send_all), except that results are yielded as soon as
+    /// they are ready, so one slow request doesn't hold back the others.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// use futures_util::StreamExt;
+    ///
+    /// let client = surf::client();
+    /// let urls = vec!["https://example.com/a", "https://example.com/b"];
+    /// let requests = urls.clone().into_iter().map(surf::get);
+    /// let mut responses = client.send_all_unordered(requests, 8);
+    /// while let Some((index, res)) = responses.next().await {
+    ///     println!("{}: {}", urls[index], res?.status());
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn send_all_unordered<I, R>(
+        &self,
+        requests: I,
+        max_concurrency: usize,
+    ) -> BoxStream<'static, (usize, Result<Response>)>
+    where
+        I: IntoIterator<Item = R>,
+        I::IntoIter: Send + 'static,
+        R: Into<Request>,
+    {
+        let client = self.clone();
+        stream::iter(requests.into_iter().enumerate())
+            .map(move |(index, req)| {
+                let client = client.clone();
+                let req = req.into();
+                async move { (index, client.send(req).await) }
+            })
+            .buffer_unordered(max_concurrency.max(1))
+            .boxed()
+    }
+
+    /// Submit many `Request`s and decode each response body from json into a struct.
+    ///
+    /// At most `concurrency` requests are in flight at a time. The results are returned in the
+    /// same order as the requests, and a failing request doesn't affect the others.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use serde::Deserialize;
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// #[derive(Deserialize)]
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// let client = surf::client();
+    /// let requests = (1..=500).map(|id| surf::get(format!("https://example.com/users/{}", id)));
+    /// let users = client.recv_json_all::<User, _>(requests, 16).await;
+    /// for user in users {
+    ///     match user {
+    ///         Ok(user) => println!("{}", user.name),
+    ///         Err(err) => eprintln!("{}", err),
+    ///     }
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub async fn recv_json_all<T, R>(
+        &self,
+        requests: impl IntoIterator<Item = R>,
+        concurrency: usize,
+    ) -> Vec<Result<T>>
+    where
+        T: DeserializeOwned,
+        R: Into<Request>,
+    {
+        stream::iter(requests)
+            .map(|req| self.recv_json::<T>(req))
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Get a JSON resource, reusing a cached copy when possible.
+    ///
+    /// The request goes through the [`Cache`] middleware of this client if it has one, so the
+    /// two share their entries. Otherwise it goes through a `Cache` with a
+    /// [`MemoryStore`](crate::middleware::cache::MemoryStore) shared by clones of this client.
+    /// A cached response is returned without a request while it is fresh; once stale, it is
+    /// revalidated with `If-None-Match` and `If-Modified-Since`, and reused if the server
+    /// answers `304 Not Modified`. The returned [`CacheStatus`] tells which of these happened.
+    ///
+    /// Responses are stored as the `Cache` middleware stores them, so responses marked
+    /// `no-store`, or without validators or a freshness lifetime, are not cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the response status isn't a success, or the body
+    /// can't be decoded.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// #[derive(Deserialize, Serialize)]
+    /// struct Ip {
+    ///     ip: String
+    /// }
+    ///
+    /// let client = surf::client();
+    /// let (ip, status): (Ip, _) = client.get_cached_json("https://api.ipify.org?format=json").await?;
+    /// println!("{} ({:?})", ip.ip, status);
+    /// # Ok(()) }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn get_cached_json<T: DeserializeOwned>(
+        &self,
+        uri: impl AsRef<str>,
+    ) -> Result<(T, CacheStatus)> {
+        let mut req = self.request(Method::Get, self.url(uri));
+        if !self.middleware_types.contains(&TypeId::of::<Cache>()) {
+            req = req.middleware(SharedCache(self.cache.clone()));
+        }
+        let mut res = self.send(req).await?;
+        if !res.status().is_success() {
+            return Err(Error::from_str(
+                res.status(),
+                format!("request failed with status {}", res.status()),
+            ));
+        }
+        let status = res
+            .ext::<CacheStatus>()
+            .copied()
+            .unwrap_or(CacheStatus::Miss);
+        Ok((res.body_json().await?, status))
+    }
+
     /// Submit a `Request` and decode the response body from form encoding into a struct.
     ///
     /// # Errors
@@ -317,6 +999,145 @@ impl Client {
         res.body_form::<T>().await
     }
 
+    /// Submit a `Request` for the first page of a paginated resource, and stream the items of
+    /// all pages.
+    ///
+    /// Each response is decoded from json into a page of type `P`, whose items are yielded in
+    /// order. The `extractor` then looks at the response and page to build the request for the
+    /// next page, typically from a cursor in the body, until it returns `None`.
+    ///
+    /// See the [pagination] module for more information.
+    ///
+    /// [pagination]: crate::pagination
+    ///
+    /// # Errors
+    ///
+    /// The stream yields an error and ends if a request fails, or if a page cannot be decoded.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use serde::Deserialize;
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// use futures_util::TryStreamExt;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Page {
+    ///     items: Vec<u64>,
+    ///     next: Option<String>,
+    /// }
+    ///
+    /// impl From<Page> for Vec<u64> {
+    ///     fn from(page: Page) -> Self {
+    ///         page.items
+    ///     }
+    /// }
+    ///
+    /// let client = surf::client();
+    /// let mut ids = client.paginate(
+    ///     surf::get("https://example.com/ids"),
+    ///     |_: &surf::Response, page: &Page| Some(surf::get(page.next.as_ref()?).build()),
+    /// );
+    /// while let Some(id) = ids.try_next().await? {
+    ///     println!("{}", id);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn paginate<P, T>(
+        &self,
+        req: impl Into<Request>,
+        extractor: impl CursorExtractor<P>,
+    ) -> BoxStream<'static, Result<T>>
+    where
+        P: DeserializeOwned + Into<Vec<T>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let client = self.clone();
+        let pages = stream::try_unfold(
+            (Some(req.into()), client, extractor),
+            |(req, client, extractor)| async move {
+                let req = match req {
+                    Some(req) => req,
+                    None => return Ok(None),
+                };
+                let mut res = client.send(req).await?;
+                let page: P = res.body_json().await?;
+                let next = extractor.next(&res, &page);
+                Result::Ok(Some((page.into(), (next, client, extractor))))
+            },
+        );
+
+        pages
+            .map_ok(|items: Vec<T>| stream::iter(items.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+
+    /// Create a handle to the JSON resource at `uri`, which is read and updated with
+    /// optimistic concurrency control.
+    ///
+    /// See [`Resource`] for more information. No request is sent until the resource is read.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if a malformed URL is passed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// #[derive(Clone, Deserialize, Serialize)]
+    /// struct Settings {
+    ///     theme: String,
+    /// }
+    ///
+    /// let client = surf::client();
+    /// let mut settings = client.resource::<Settings>("https://api.example.com/settings");
+    /// settings.update(|settings| settings.theme = "dark".into()).await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn resource<T>(&self, uri: impl AsRef<str>) -> Resource<T> {
+        Resource::new(self.clone(), self.url(uri))
+    }
+
+    /// List the entries of the directory at `uri`.
+    ///
+    /// The directory is first requested with a WebDAV `PROPFIND` request. If the server doesn't
+    /// answer with a `207 Multi-Status` listing, the directory is fetched with a `GET` request
+    /// instead, and its index is parsed as JSON in the format of nginx's `autoindex_format json`,
+    /// or else as an HTML page whose links to the directory's children are its entries. Sizes and
+    /// modification times are only known from WebDAV and JSON listings.
+    ///
+    /// A `/` is added to the path of `uri` if it doesn't end with one. Subdirectories aren't
+    /// listed recursively.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if a malformed URL is passed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the requests fail, if the response to the `GET` request isn't a
+    /// success, or if its JSON index is malformed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let entries = surf::client().list("https://mirror.example.com/releases/").await?;
+    /// for entry in entries.iter().filter(|entry| !entry.is_dir) {
+    ///     println!("{} ({:?} bytes)", entry.url, entry.size);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub async fn list(&self, uri: impl AsRef<str>) -> Result<Vec<DirEntry>> {
+        crate::listing::list(self, self.url(uri)).await
+    }
+
     /// Perform an HTTP `GET` request using the `Client` connection.
     ///
     /// # Panics
@@ -560,7 +1381,7 @@ impl Client {
     /// # fn main() -> http_types::Result<()> { async_std::task::block_on(async {
     /// let mut client = surf::client();
     /// client.set_base_url(Url::parse("http://example.com/api/v1/")?);
-    /// client.get("posts.json").recv_json().await?; /// http://example.com/api/v1/posts.json
+    /// client.get("posts.json").recv_string().await?; /// http://example.com/api/v1/posts.json
     /// # Ok(()) }) }
     /// ```
     #[deprecated(since = "6.5.0", note = "Please use `Config` instead")]
@@ -568,13 +1389,91 @@ impl Client {
         self.config.base_url = Some(base);
     }
 
+    /// Create a client for a section of an API, whose requests are joined under `path_prefix`.
+    ///
+    /// The prefix is joined onto the base URL of this client (or parsed as an absolute URL if
+    /// there is none), and always treated as a directory. The scoped client shares the backend,
+    /// middleware, and default headers of this client; use [`with_header`](Client::with_header)
+    /// to add headers which only apply to the scope.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the prefix doesn't form a valid URL.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::convert::TryInto;
+    /// use surf::{Client, Config, Url};
+    ///
+    /// # fn main() -> surf::Result<()> {
+    /// let api: Client = Config::new()
+    ///     .set_base_url(Url::parse("https://example.com/v1/")?)
+    ///     .try_into()?;
+    /// let admin = api.scoped("admin").with_header("x-admin-token", "secret");
+    ///
+    /// let req = admin.get("users").build();
+    /// assert_eq!(req.url().as_str(), "https://example.com/v1/admin/users");
+    /// assert_eq!(req["x-admin-token"], "secret");
+    /// assert!(api.get("users").build().header("x-admin-token").is_none());
+    /// # Ok(()) }
+    /// ```
+    pub fn scoped(&self, path_prefix: impl AsRef<str>) -> Self {
+        let mut prefix = path_prefix.as_ref().to_string();
+        if !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+
+        let mut client = self.clone();
+        client.config.base_url = Some(self.url(prefix));
+        client
+    }
+
+    /// Add a header to be added to every request sent through the returned client.
+    ///
+    /// This replaces any default header of the same name.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the header values are invalid.
+    pub fn with_header(mut self, name: impl Into<HeaderName>, values: impl ToHeaderValues) -> Self {
+        let values = values
+            .to_header_values()
+            .expect("invalid header values")
+            .collect();
+        self.config.headers.insert(name.into(), values);
+        self
+    }
+
+    /// Get the cookie jar of this client, if it was created from a [`Config`] with
+    /// [`set_cookie_store`](Config::set_cookie_store).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// use std::convert::TryInto;
+    /// use surf::{Client, Config};
+    ///
+    /// let client: Client = Config::new().set_cookie_store(true).try_into()?;
+    /// client.post("https://example.com/login").await?;
+    /// let jar = client.cookie_jar().expect("the cookie store is enabled");
+    /// std::fs::write("cookies.json", jar.export_json())?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn cookie_jar(&self) -> Option<&CookieJar> {
+        self.cookie_jar.as_ref()
+    }
+
     /// Get the current configuration.
     pub fn config(&self) -> &Config {
         &self.config
     }
 
     // private function to generate a url based on the base_path
-    fn url(&self, uri: impl AsRef<str>) -> Url {
+    pub(crate) fn url(&self, uri: impl AsRef<str>) -> Url {
         match &self.config.base_url {
             None => uri.as_ref().parse().unwrap(),
             Some(base) => base.join(uri.as_ref()).unwrap(),
@@ -582,26 +1481,296 @@ impl Client {
     }
 }
 
+/// Send `req` with the underlying http client, or with the client for its TLS configuration.
+async fn send_with_timeout(
+    client: &Client,
+    req: http_types::Request,
+) -> Result<http_types::Response> {
+    #[cfg(any(
+        feature = "curl-client",
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ))]
+    let req = resolve_host(client, req).await?;
+    #[cfg(not(target_arch = "wasm32"))]
+    let timeout = match req.ext().get::<RequestTimeout>() {
+        Some(RequestTimeout(timeout)) => Some(*timeout),
+        None => client.config.http_config.timeout,
+    };
+    #[cfg(target_arch = "wasm32")]
+    let timeout = client.config.http_config.timeout;
+    #[cfg(all(
+        unix,
+        any(
+            feature = "h1-client",
+            feature = "h1-client-rustls",
+            feature = "h1-client-no-tls"
+        )
+    ))]
+    if let Some(crate::unix_socket::UnixSocket(socket)) = req.ext().get().cloned() {
+        let send = crate::unix_socket::send_h1(&socket, req, &client.config)
+            .map(|res| res.map_err(classify_connection_error))
+            .boxed();
+        return with_timeout(client, timeout, send).await;
+    }
+    #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+    if req.ext().get::<TlsOverride>().is_some() {
+        if client.tls_clients.is_none() {
+            return Err(Error::from_str(
+                StatusCode::BadRequest,
+                "per-request TLS configs require the default http client",
+            ));
+        }
+        if let Some(proxy) = client.config.proxy_for(req.url()) {
+            return Err(Error::from_str(
+                StatusCode::BadRequest,
+                format!("per-request TLS configs can't be used with proxy {}", proxy),
+            ));
+        }
+        // Without curl, the h1 transport of the default client uses the TLS config itself.
+        #[cfg(feature = "curl-client")]
+        if let (Some(TlsOverride(tls)), Some(tls_clients)) =
+            (req.ext().get::<TlsOverride>(), &client.tls_clients)
+        {
+            let http_client = tls_clients.get(tls, &crate::timeout::backend_config(&client.config));
+            return with_timeout(client, timeout, send_caught(&*http_client, req)).await;
+        }
+    }
+    with_timeout(client, timeout, send_caught(&*client.http_client, req)).await
+}
+
+/// Choose the address to connect to for `req` with the DNS overrides and resolver of `client`.
+#[cfg(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "h1-client-no-tls"
+))]
+async fn resolve_host(
+    client: &Client,
+    mut req: http_types::Request,
+) -> Result<http_types::Request> {
+    use crate::resolver::ResolveTo;
+    use crate::{ConnectionError, ConnectionErrorKind, IpVersion};
+
+    if req.ext().get::<ResolveTo>().is_some() {
+        return Ok(req);
+    }
+    let host = match req.url().domain() {
+        Some(host) => host.to_ascii_lowercase(),
+        None => return Ok(req),
+    };
+    if let Some(addr) = client.config.dns_overrides.get(&host) {
+        req.ext_mut().insert(ResolveTo(*addr));
+        return Ok(req);
+    }
+    let resolver = match &client.config.dns_resolver {
+        Some(resolver) => resolver.clone(),
+        None => return Ok(req),
+    };
+
+    // The requests of the resolver itself are resolved by the backend.
+    let mut inner = client.clone();
+    inner.config.dns_resolver = None;
+    let port = req.url().port_or_known_default().unwrap_or_default();
+    let addrs = resolver
+        .resolve(&host, port, &inner)
+        .await
+        .map_err(|err| ConnectionError::wrap(ConnectionErrorKind::Dns, err))?;
+    let version = req.ext().get::<IpVersion>().copied().unwrap_or_default();
+    match addrs.iter().find(|addr| version.allows(addr)) {
+        Some(addr) => {
+            req.ext_mut().insert(ResolveTo(*addr));
+            Ok(req)
+        }
+        None => Err(Error::new(
+            StatusCode::BadGateway,
+            ConnectionError::new(
+                ConnectionErrorKind::Dns,
+                format!("the resolver found no addresses for {}", host),
+            ),
+        )),
+    }
+}
+
+/// Send `req` with `http_client`, turning a panic of the backend into an error and classifying
+/// its connection errors.
+///
+/// Some backends panic on responses they can't represent, such as ones with a status code
+/// outside of `StatusCode`, which shouldn't take the whole program down.
+fn send_caught<'a, C: HttpClient + ?Sized>(
+    http_client: &'a C,
+    req: http_types::Request,
+) -> impl Future<Output = Result<http_types::Response>> + Unpin + 'a {
+    AssertUnwindSafe(http_client.send(req))
+        .catch_unwind()
+        .map(|res| {
+            res.unwrap_or_else(|panic| {
+                let message = match panic.downcast_ref::<&str>() {
+                    Some(message) => message.to_string(),
+                    None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+                };
+                Err(Error::from_str(
+                    StatusCode::BadGateway,
+                    format!("the http client failed to handle the response: {}", message),
+                ))
+            })
+            .map_err(classify_connection_error)
+        })
+}
+
+/// Wait for `send`, failing once `timeout` has passed, and limit reading the response body to
+/// the rest of `timeout` and the read timeout.
+///
+/// On wasm32, the fetch client enforces the timeout itself.
+#[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+async fn with_timeout(
+    client: &Client,
+    timeout: Option<std::time::Duration>,
+    send: impl Future<Output = Result<http_types::Response>> + Unpin,
+) -> Result<http_types::Response> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let clock = &client.config.clock;
+        let (mut res, deadline) = match timeout {
+            Some(timeout) => match future::select(send, clock.sleep(timeout)).await {
+                Either::Left((res, timer)) => (res?, Some(timer)),
+                Either::Right(_) => {
+                    return Err(Error::new(
+                        StatusCode::RequestTimeout,
+                        std::io::Error::new(std::io::ErrorKind::TimedOut, "request timed out"),
+                    ))
+                }
+            },
+            None => (send.await?, None),
+        };
+        let read_timeout = client.config.read_timeout;
+        if deadline.is_some() || read_timeout.is_some() {
+            map_body(&mut res, |body| {
+                let len = body.len();
+                map_body_reader(body, len, |inner| TimeoutReader {
+                    inner,
+                    clock: clock.clone(),
+                    deadline: deadline.map(std::sync::Mutex::new),
+                    read_timeout,
+                    idle: None,
+                })
+            });
+        }
+        Ok(res)
+    }
+    #[cfg(target_arch = "wasm32")]
+    send.await
+}
+
+/// Fill in the outcome of a recorded request.
+#[cfg(not(target_arch = "wasm32"))]
+fn record_response(
+    res: Result<Response>,
+    entry: Arc<std::sync::Mutex<HistoryEntry>>,
+    elapsed: std::time::Duration,
+) -> Result<Response> {
+    let mut recorded = entry.lock().unwrap();
+    recorded.elapsed = elapsed;
+    match res {
+        Ok(mut res) => {
+            recorded.status = Some(res.status());
+            recorded.response_encoding = res
+                .header(CONTENT_ENCODING)
+                .map(|values| values.last().to_string());
+            drop(recorded);
+
+            res.map_body(|body| capture_body(body, entry, Capture::Response));
+            Ok(res)
+        }
+        Err(err) => {
+            recorded.error = Some(err.to_string());
+            Err(err)
+        }
+    }
+}
+
+// Whether the conversion is fallible depends on the backend.
+#[allow(clippy::infallible_try_from, clippy::unnecessary_fallible_conversions)]
 impl TryFrom<Config> for Client {
-    #[cfg(feature = "default-client")]
+    #[cfg(feature = "curl-client")]
+    type Error = isahc::Error;
+    #[cfg(all(feature = "default-client", not(feature = "curl-client")))]
     type Error = <DefaultClient as TryFrom<http_client::Config>>::Error;
     #[cfg(not(feature = "default-client"))]
     type Error = std::convert::Infallible;
 
     fn try_from(mut config: Config) -> std::result::Result<Self, Self::Error> {
+        #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+        let tls_clients: Option<Arc<TlsClients>> = config.http_client.is_none().then(Arc::default);
         let http_client = match config.http_client.take() {
             Some(client) => client,
-            #[cfg(feature = "default-client")]
+            #[cfg(feature = "curl-client")]
+            None => forward_proxy::curl_client(&mut config)?,
+            #[cfg(all(
+                feature = "default-client",
+                not(feature = "curl-client"),
+                not(target_arch = "wasm32")
+            ))]
+            None => {
+                let mut client: Arc<dyn HttpClient> =
+                    Arc::new(DefaultClient::try_from(crate::timeout::backend_config(&config))?);
+                if config.http_proxy.is_some() || config.https_proxy.is_some() {
+                    client = Arc::new(ProxyUnsupported {
+                        inner: client,
+                        config: config.clone(),
+                    });
+                }
+                #[cfg(any(
+                    feature = "h1-client",
+                    feature = "h1-client-rustls",
+                    feature = "h1-client-no-tls"
+                ))]
+                {
+                    client = Arc::new(crate::h1::Transport::new(
+                        client,
+                        config.clone(),
+                        #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+                        tls_clients.clone().unwrap(),
+                    ));
+                }
+                client
+            }
+            #[cfg(all(feature = "default-client", target_arch = "wasm32"))]
             None => Arc::new(DefaultClient::try_from(config.http_config.clone())?),
             #[cfg(not(feature = "default-client"))]
             None => panic!("Config without an http client provided to Surf configured without a default client.")
         };
 
-        Ok(Client {
+        let mut client = Client {
             config,
             http_client,
             middleware: Arc::new(vec![]),
-        })
+            middleware_types: Arc::new(vec![]),
+            history: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            cache: Arc::default(),
+            shutdown: ShutdownToken::default(),
+            state: ClientState::default(),
+            events: Arc::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            connections: Arc::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            cookie_jar: None,
+            #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+            tls_clients,
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if client.config.cookie_store {
+            let jar = CookieJar::new();
+            let jar_type = TypeId::of::<CookieJar>();
+            client.insert_middleware(jar_type, Arc::new(jar.clone()), Position::last());
+            client.cookie_jar = Some(jar);
+        }
+
+        Ok(client)
     }
 }
 
@@ -0,0 +1,171 @@
+use std::any::TypeId;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::http::headers::{HeaderName, ToHeaderValues};
+use crate::http::{StatusCode, Url};
+use crate::middleware::{Middleware, Position};
+use crate::{Client, Config, Error, HttpClient, Result};
+
+/// Client Builder
+///
+/// Provides an ergonomic way to configure a [`Client`] in a single chain: configuration,
+/// middleware, and the HTTP backend are all set here, and [`build`](ClientBuilder::build) turns
+/// them into a client. This is generally accessed through [`Client::builder`].
+///
+/// Errors (e.g. from invalid header values) are collected along the way and reported by `build`,
+/// rather than interrupting the chain.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use surf::{middleware::Redirect, Client, Url};
+///
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// let client = Client::builder()
+///     .base_url(Url::parse("https://httpbin.org/")?)
+///     .header("x-api-version", "2")
+///     .timeout(Some(Duration::from_secs(5)))
+///     .with(Redirect::default())
+///     .build()?;
+///
+/// let text = client.get("get").recv_string().await?;
+/// # Ok(()) }
+/// ```
+pub struct ClientBuilder {
+    /// Holds the configuration of the client.
+    config: Config,
+    /// Holds the middleware stack, in registration order, with the type and position of each.
+    middleware: Vec<(TypeId, Arc<dyn Middleware>, Position)>,
+    /// Holds the first error encountered while building, reported by `build`.
+    error: Option<Error>,
+}
+
+impl ClientBuilder {
+    /// Create a new instance with the default configuration.
+    pub fn new() -> Self {
+        Self {
+            config: Config::new(),
+            middleware: vec![],
+            error: None,
+        }
+    }
+
+    /// Replace the configuration wholesale.
+    ///
+    /// Settings made on the builder before this call are discarded.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set the base URL, which all request URLs are relative to.
+    ///
+    /// See [`Config::set_base_url`] for details.
+    pub fn base_url(mut self, base: Url) -> Self {
+        self.config = self.config.set_base_url(base);
+        self
+    }
+
+    /// Add a header to be added to every request.
+    ///
+    /// Invalid header values are reported when calling [`build`](ClientBuilder::build).
+    pub fn header(mut self, name: impl Into<HeaderName>, values: impl ToHeaderValues) -> Self {
+        match values.to_header_values() {
+            Ok(values) => {
+                self.config.headers.insert(name.into(), values.collect());
+            }
+            Err(err) => {
+                self.error.get_or_insert(err);
+            }
+        }
+        self
+    }
+
+    /// Set the timeout for requests, see [`Config::set_timeout`].
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config = self.config.set_timeout(timeout);
+        self
+    }
+
+    /// Use `http_client` as the backend instead of the default client.
+    ///
+    /// When using this, the HTTP configuration of the builder (e.g. the timeout) is ignored.
+    pub fn http_client(mut self, http_client: impl HttpClient) -> Self {
+        self.config = self.config.set_http_client(http_client);
+        self
+    }
+
+    /// Push middleware onto the middleware stack.
+    ///
+    /// Middleware runs in the order it was registered, after the default logger (if the
+    /// `middleware-logger` feature is enabled).
+    pub fn with(self, middleware: impl Middleware) -> Self {
+        self.with_ordered(middleware, Position::last())
+    }
+
+    /// Insert middleware into the middleware stack at `position`.
+    ///
+    /// Middleware is inserted in the order it was registered, after the default logger, so
+    /// positions can refer to the logger and to middleware registered earlier. See
+    /// [`Client::with_ordered`].
+    pub fn with_ordered<M: Middleware>(mut self, middleware: M, position: Position) -> Self {
+        self.middleware
+            .push((TypeId::of::<M>(), Arc::new(middleware), position));
+        self
+    }
+
+    /// Build the `Client`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a header value was invalid, or if the backend could not be
+    /// constructed from the configuration.
+    pub fn build(self) -> Result<Client> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        #[cfg(not(feature = "default-client"))]
+        if self.config.http_client.is_none() {
+            return Err(Error::from_str(
+                StatusCode::InternalServerError,
+                "no http client provided to a Surf built without a default client",
+            ));
+        }
+
+        let mut client = Client::try_from(self.config)
+            .map_err(|err| Error::new(StatusCode::InternalServerError, err))?;
+
+        #[cfg(feature = "middleware-logger")]
+        client.insert_middleware(
+            TypeId::of::<crate::middleware::Logger>(),
+            Arc::new(crate::middleware::Logger::new()),
+            Position::last(),
+        );
+
+        for (ty, middleware, position) in self.middleware {
+            client.insert_middleware(ty, middleware, position);
+        }
+        Ok(client)
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientBuilder")
+            .field("config", &self.config)
+            .field("middleware", &self.middleware.len())
+            .finish()
+    }
+}
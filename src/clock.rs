@@ -0,0 +1,164 @@
+//! Sources of time, so that timing-dependent behavior can be tested without waiting.
+
+use futures_util::future::BoxFuture;
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant, SystemTime};
+
+/// A source of time for a [`Client`](crate::Client).
+///
+/// The clock of a client is used to enforce its timeout, to wait between attempts of the
+/// [`Retry`](crate::middleware::Retry) middleware, to pace the
+/// [`RateLimit`](crate::middleware::RateLimit) middleware, to decide whether cached responses
+/// are fresh, and to timestamp requests and responses. It is set with
+/// [`Config::set_clock`](crate::Config::set_clock).
+pub trait Clock: Debug + Send + Sync + 'static {
+    /// The current monotonic time.
+    fn now(&self) -> Instant;
+
+    /// The current wall-clock time.
+    fn system_now(&self) -> SystemTime;
+
+    /// Wait until `duration` has passed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// The clock of the operating system.
+///
+/// On wasm32, sleeping uses the global `setTimeout` with the `wasm-client` feature, and returns
+/// immediately without it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(sleep(duration))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn sleep(duration: Duration) {
+    async_io::Timer::after(duration).await;
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm-client"))]
+async fn sleep(duration: Duration) {
+    crate::wasm::delay(duration).await;
+}
+
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm-client")))]
+async fn sleep(_: Duration) {}
+
+/// A clock which only moves when it is advanced.
+///
+/// Sleeps complete once the clock has been [advanced](ManualClock::advance) past their end, so
+/// tests can step through timeouts, retry delays and cache expiry without waiting. Clones share
+/// the same time.
+///
+/// ```
+/// use std::time::Duration;
+/// use surf::{Clock, ManualClock};
+///
+/// let clock = ManualClock::new();
+/// let start = clock.now();
+/// clock.advance(Duration::from_secs(60));
+/// assert_eq!(clock.now() - start, Duration::from_secs(60));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    system_start: SystemTime,
+    state: Arc<Mutex<ManualState>>,
+}
+
+#[derive(Debug, Default)]
+struct ManualState {
+    elapsed: Duration,
+    sleepers: Vec<(Duration, Waker)>,
+}
+
+impl ManualClock {
+    /// Create a new instance, starting at the current time.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            system_start: SystemTime::now(),
+            state: Arc::new(Mutex::new(ManualState::default())),
+        }
+    }
+
+    /// Move the clock forward by `duration`, completing the sleeps which end by then.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.elapsed += duration;
+        let elapsed = state.elapsed;
+        let (done, pending) = state
+            .sleepers
+            .drain(..)
+            .partition(|(deadline, _)| *deadline <= elapsed);
+        state.sleepers = pending;
+        drop(state);
+        for (_, waker) in done {
+            waker.wake();
+        }
+    }
+
+    /// How far the clock has been advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().elapsed
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.system_start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(ManualSleep {
+            state: self.state.clone(),
+            deadline: self.elapsed() + duration,
+        })
+    }
+}
+
+/// A sleep on a `ManualClock`.
+struct ManualSleep {
+    state: Arc<Mutex<ManualState>>,
+    deadline: Duration,
+}
+
+impl Future for ManualSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.elapsed >= self.deadline {
+            return Poll::Ready(());
+        }
+        state.sleepers.push((self.deadline, cx.waker().clone()));
+        Poll::Pending
+    }
+}
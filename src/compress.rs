@@ -0,0 +1,455 @@
+//! Compression of request bodies, and decompression of the bodies of both directions.
+
+use crate::http::content::Encoding;
+use crate::http::headers::{CONTENT_ENCODING, CONTENT_LENGTH};
+use crate::http::{Error, StatusCode};
+use crate::utils::{map_body, map_body_reader, BodyReader};
+use crate::Request;
+
+use futures_util::io::{AsyncBufRead, AsyncRead};
+use miniz_oxide::deflate::core::{
+    compress, create_comp_flags_from_zip_params, CompressorOxide, TDEFLFlush, TDEFLStatus,
+};
+use miniz_oxide::inflate::stream::{inflate, InflateState};
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// The compression level, balancing speed and size like gzip's default.
+const LEVEL: i32 = 6;
+
+/// The most compressed bytes produced at once.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// The codings which can be compressed and decompressed, in order of preference.
+pub(crate) const ENCODINGS: &[Encoding] = &[Encoding::Gzip, Encoding::Deflate];
+
+/// The gzip header: magic bytes, deflate, no flags or modification time, unknown OS.
+const GZIP_HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+
+/// Compress the body of `req` with `encoding` while it is sent, and set `Content-Encoding`.
+pub(crate) fn compress_body(req: &mut Request, encoding: Encoding) -> crate::Result<()> {
+    let gzip = match encoding {
+        Encoding::Identity => return Ok(()),
+        Encoding::Gzip => true,
+        Encoding::Deflate => false,
+        encoding => {
+            return Err(Error::from_str(
+                StatusCode::BadRequest,
+                format!(
+                    "compressing request bodies with {} is not supported, only with {}",
+                    encoding,
+                    accept_encoding()
+                ),
+            ))
+        }
+    };
+
+    // With the zlib wrapper for `deflate`, or the raw stream wrapped in gzip framing.
+    let window_bits = if gzip { -15 } else { 15 };
+    map_body(req, |body| {
+        map_body_reader(body, None, |inner| CompressReader {
+            inner,
+            compressor: Box::new(CompressorOxide::new(create_comp_flags_from_zip_params(
+                LEVEL,
+                window_bits,
+                0,
+            ))),
+            gzip,
+            crc: !0,
+            size: 0,
+            out: if gzip {
+                GZIP_HEADER.to_vec()
+            } else {
+                Vec::new()
+            },
+            pos: 0,
+            state: State::Body,
+        })
+    });
+    // The length of the compressed body is only known once it has been sent.
+    req.remove_header(CONTENT_LENGTH);
+    req.append_header(CONTENT_ENCODING, encoding.to_string());
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Body,
+    Trailer,
+    Done,
+}
+
+/// A reader which compresses another reader as it is read.
+struct CompressReader {
+    inner: BodyReader,
+    compressor: Box<CompressorOxide>,
+    gzip: bool,
+    crc: u32,
+    size: u32,
+    /// Compressed bytes which haven't been read yet, starting at `pos`.
+    out: Vec<u8>,
+    pos: usize,
+    state: State,
+}
+
+impl AsyncRead for CompressReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        loop {
+            if this.pos < this.out.len() {
+                let read = buf.len().min(this.out.len() - this.pos);
+                buf[..read].copy_from_slice(&this.out[this.pos..this.pos + read]);
+                this.pos += read;
+                return Poll::Ready(Ok(read));
+            }
+            this.out.clear();
+            this.pos = 0;
+
+            match this.state {
+                State::Done => return Poll::Ready(Ok(0)),
+                State::Trailer => {
+                    if this.gzip {
+                        this.out.extend_from_slice(&(!this.crc).to_le_bytes());
+                        this.out.extend_from_slice(&this.size.to_le_bytes());
+                    }
+                    this.state = State::Done;
+                }
+                State::Body => {
+                    let input = futures_util::ready!(Pin::new(&mut this.inner).poll_fill_buf(cx))?;
+                    let flush = match input.is_empty() {
+                        true => TDEFLFlush::Finish,
+                        false => TDEFLFlush::None,
+                    };
+                    this.out.resize(CHUNK_SIZE, 0);
+                    let (status, consumed, produced) =
+                        compress(&mut this.compressor, input, &mut this.out, flush);
+                    this.crc = crc32(this.crc, &input[..consumed]);
+                    this.size = this.size.wrapping_add(consumed as u32);
+                    this.out.truncate(produced);
+                    Pin::new(&mut this.inner).consume(consumed);
+                    match status {
+                        TDEFLStatus::Done => this.state = State::Trailer,
+                        TDEFLStatus::Okay => {}
+                        _ => {
+                            return Poll::Ready(Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                "failed to compress the request body",
+                            )))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A streaming decoder of a body compressed with gzip or deflate, which is fed the compressed
+/// bytes as they arrive.
+///
+/// Bodies which don't start like the coding they are labelled with are passed through unchanged,
+/// as some backends decompress bodies themselves but keep the `Content-Encoding` header.
+pub(crate) struct Decoder {
+    gzip: bool,
+    inflater: Option<Box<InflateState>>,
+    /// Bytes taken from the input while sniffing, or the gzip header or trailer, which haven't
+    /// been processed yet.
+    head: Vec<u8>,
+    crc: u32,
+    size: u32,
+    state: DecodeState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecodeState {
+    /// Reading the first bytes, to tell whether the body is compressed at all.
+    Sniff,
+    /// Skipping the gzip header.
+    Header,
+    Body,
+    /// Reading the gzip trailer.
+    Trailer,
+    /// Passing the body through as it is, after the bytes in `head`.
+    Passthrough,
+    Done,
+}
+
+impl Decoder {
+    /// A decoder for bodies with the `Content-Encoding` `encoding`, if it is a single coding
+    /// which can be decompressed.
+    pub(crate) fn new(encoding: &str) -> Option<Self> {
+        let gzip = match encoding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => true,
+            "deflate" => false,
+            _ => return None,
+        };
+        Some(Self::with_gzip(gzip))
+    }
+
+    /// A decoder for gzip if `gzip` is set, and for deflate otherwise.
+    pub(crate) fn with_gzip(gzip: bool) -> Self {
+        Self {
+            gzip,
+            inflater: None,
+            head: Vec::new(),
+            crc: !0,
+            size: 0,
+            state: DecodeState::Sniff,
+        }
+    }
+
+    /// Decode the compressed bytes `input` into `out`, returning how many bytes of each were
+    /// used.
+    ///
+    /// An empty `input` marks the end of the body: the decoder then writes what it still holds,
+    /// and fails if the compressed stream ended early. Once both counts are zero for an empty
+    /// `input`, the body has been decoded completely.
+    pub(crate) fn decode(&mut self, input: &[u8], out: &mut [u8]) -> io::Result<(usize, usize)> {
+        let ended = input.is_empty();
+        let mut consumed = 0;
+        loop {
+            match self.state {
+                // Anything after the end of the compressed stream is ignored.
+                DecodeState::Done => return Ok((input.len(), 0)),
+                DecodeState::Sniff => {
+                    if self.head.len() < 2 && consumed < input.len() {
+                        self.head.push(input[consumed]);
+                        consumed += 1;
+                        continue;
+                    }
+                    if self.head.len() < 2 && !ended {
+                        return Ok((consumed, 0));
+                    }
+                    self.state = match &self.head[..] {
+                        [0x1f, 0x8b] if self.gzip => DecodeState::Header,
+                        &[cmf, flg] if !self.gzip && is_zlib_header(cmf, flg) => {
+                            self.inflater = Some(InflateState::new_boxed(DataFormat::Zlib));
+                            DecodeState::Body
+                        }
+                        _ => DecodeState::Passthrough,
+                    };
+                }
+                DecodeState::Header => match gzip_header_len(&self.head) {
+                    Some(Ok(_)) => {
+                        self.head.clear();
+                        self.inflater = Some(InflateState::new_boxed(DataFormat::Raw));
+                        self.state = DecodeState::Body;
+                    }
+                    Some(Err(err)) => return Err(err),
+                    None if consumed < input.len() => {
+                        self.head.push(input[consumed]);
+                        consumed += 1;
+                    }
+                    None if ended => return Err(truncated()),
+                    None => return Ok((consumed, 0)),
+                },
+                DecodeState::Body => {
+                    let from_head = !self.head.is_empty();
+                    let source = match from_head {
+                        true => &self.head[..],
+                        false => &input[consumed..],
+                    };
+                    if source.is_empty() && !ended {
+                        return Ok((consumed, 0));
+                    }
+                    let inflater = self.inflater.as_mut().unwrap();
+                    let result = inflate(inflater, source, out, MZFlush::None);
+                    let used = result.bytes_consumed;
+                    let written = result.bytes_written;
+                    if self.gzip {
+                        self.crc = crc32(self.crc, &out[..written]);
+                        self.size = self.size.wrapping_add(written as u32);
+                    }
+                    match from_head {
+                        true => drop(self.head.drain(..used)),
+                        false => consumed += used,
+                    }
+                    match result.status {
+                        Ok(MZStatus::StreamEnd) => {
+                            self.inflater = None;
+                            self.state = match self.gzip {
+                                true => DecodeState::Trailer,
+                                false => DecodeState::Done,
+                            };
+                        }
+                        Ok(_) | Err(MZError::Buf) if used > 0 || written > 0 => {}
+                        _ if ended => return Err(truncated()),
+                        _ => return Err(corrupt()),
+                    }
+                    if written > 0 {
+                        return Ok((consumed, written));
+                    }
+                }
+                DecodeState::Trailer => {
+                    if self.head.len() < 8 {
+                        if consumed < input.len() {
+                            self.head.push(input[consumed]);
+                            consumed += 1;
+                            continue;
+                        }
+                        return match ended {
+                            true => Err(truncated()),
+                            false => Ok((consumed, 0)),
+                        };
+                    }
+                    let crc = u32::from_le_bytes([
+                        self.head[0],
+                        self.head[1],
+                        self.head[2],
+                        self.head[3],
+                    ]);
+                    let size = u32::from_le_bytes([
+                        self.head[4],
+                        self.head[5],
+                        self.head[6],
+                        self.head[7],
+                    ]);
+                    if crc != !self.crc || size != self.size {
+                        return Err(corrupt());
+                    }
+                    self.head.clear();
+                    self.state = DecodeState::Done;
+                }
+                DecodeState::Passthrough => {
+                    if !self.head.is_empty() {
+                        let written = out.len().min(self.head.len());
+                        out[..written].copy_from_slice(&self.head[..written]);
+                        self.head.drain(..written);
+                        return Ok((consumed, written));
+                    }
+                    let written = out.len().min(input.len() - consumed);
+                    out[..written].copy_from_slice(&input[consumed..consumed + written]);
+                    return Ok((consumed + written, written));
+                }
+            }
+        }
+    }
+
+    /// Decode the compressed bytes `input`, appending the result to `out` until it holds `limit`
+    /// bytes. As with [`decode`](Decoder::decode), an empty `input` marks the end of the body.
+    pub(crate) fn decode_into(
+        &mut self,
+        mut input: &[u8],
+        out: &mut Vec<u8>,
+        limit: usize,
+    ) -> io::Result<()> {
+        let ended = input.is_empty();
+        while out.len() < limit && (ended || !input.is_empty()) {
+            let start = out.len();
+            out.resize(limit.min(start + CHUNK_SIZE), 0);
+            let (consumed, written) = match self.decode(input, &mut out[start..]) {
+                Ok(counts) => counts,
+                Err(err) => {
+                    out.truncate(start);
+                    return Err(err);
+                }
+            };
+            out.truncate(start + written);
+            input = &input[consumed..];
+            if consumed == 0 && written == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decode all of `body`, which has the `Content-Encoding` `encoding`.
+///
+/// Returns `None` if the coding can't be decompressed, or the body fails to decode.
+pub(crate) fn decode_bytes(encoding: &str, body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoder = Decoder::new(encoding)?;
+    let mut decoded = Vec::new();
+    decoder.decode_into(body, &mut decoded, usize::MAX).ok()?;
+    decoder.decode_into(&[], &mut decoded, usize::MAX).ok()?;
+    Some(decoded)
+}
+
+/// Whether `cmf` and `flg` start a zlib stream using deflate.
+fn is_zlib_header(cmf: u8, flg: u8) -> bool {
+    cmf & 0x0f == 8 && cmf >> 4 <= 7 && (u16::from(cmf) << 8 | u16::from(flg)) % 31 == 0
+}
+
+/// The length of the gzip header at the start of `head`, or `None` if it is incomplete.
+fn gzip_header_len(head: &[u8]) -> Option<io::Result<usize>> {
+    const FHCRC: u8 = 1 << 1;
+    const FEXTRA: u8 = 1 << 2;
+    const FNAME: u8 = 1 << 3;
+    const FCOMMENT: u8 = 1 << 4;
+
+    if head.len() < 10 {
+        return None;
+    }
+    if head[2] != 8 {
+        return Some(Err(corrupt()));
+    }
+    let flags = head[3];
+    let mut len = 10;
+    if flags & FEXTRA != 0 {
+        let extra = head.get(len..len + 2)?;
+        len += 2 + usize::from(u16::from_le_bytes([extra[0], extra[1]]));
+    }
+    for field in &[FNAME, FCOMMENT] {
+        if flags & field != 0 {
+            len += head.get(len..)?.iter().position(|&byte| byte == 0)? + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        len += 2;
+    }
+    match head.len() >= len {
+        true => Some(Ok(len)),
+        false => None,
+    }
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "the compressed body ended early",
+    )
+}
+
+fn corrupt() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "failed to decompress the body")
+}
+
+/// The value of an `Accept-Encoding` header listing the supported codings.
+pub(crate) fn accept_encoding() -> String {
+    let codings: Vec<_> = ENCODINGS.iter().map(ToString::to_string).collect();
+    codings.join(", ")
+}
+
+/// The lookup table of the CRC-32 used by gzip.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+/// Continue the (inverted) CRC-32 `crc` over `bytes`.
+pub(crate) fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |crc, &byte| {
+        CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
@@ -0,0 +1,227 @@
+//! Tracking of the connections responses arrive over.
+
+use crate::http::headers::CONNECTION;
+use crate::http::{Method, StatusCode, Url, Version};
+use crate::utils::{map_body_reader, BodyReader};
+use crate::Response;
+
+use futures_util::io::AsyncRead;
+
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// The connection a response arrived over, as seen by the `Client` which sent the request.
+///
+/// This is stored in the extensions of every response received through a `Client`, which
+/// helps to tell apart slow requests which had to connect first from ones which reused a
+/// pooled connection.
+///
+/// Backends don't report which connection they used, so the client keeps its own account of
+/// the keep-alive connections to each origin: a connection becomes idle once its response
+/// body has been read to the end, and the next request to the same origin counts as reusing
+/// it. Responses with `Connection: close`, bodies which are dropped unread, and clients with
+/// [keep-alive](crate::Config::set_http_keep_alive) disabled end their connection. A
+/// connection the server closed while idle is still counted as reused.
+///
+/// # Examples
+///
+/// ```no_run
+/// use surf::ConnectionInfo;
+///
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// let client = surf::client();
+/// for _ in 0..2 {
+///     let mut res = client.get("https://httpbin.org/get").await?;
+///     res.body_bytes().await?;
+///     let conn = res.ext::<ConnectionInfo>().unwrap();
+///     println!("reused: {}, generation: {}", conn.reused(), conn.generation());
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    reused: bool,
+    generation: u64,
+    requests: u64,
+    age: Duration,
+}
+
+impl ConnectionInfo {
+    /// Whether the connection carried an earlier request, so no new connection was made.
+    pub fn reused(&self) -> bool {
+        self.reused
+    }
+
+    /// The number of the connection among those opened by the client, starting at 1.
+    ///
+    /// Responses with the same generation arrived over the same connection.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// How many requests the connection has carried, including this one.
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    /// How long ago the connection was opened, when the request was sent.
+    pub fn age(&self) -> Duration {
+        self.age
+    }
+}
+
+/// The connections of a client which are open but not in use, by origin.
+#[derive(Debug, Default)]
+pub(crate) struct Connections {
+    generations: AtomicU64,
+    idle: Mutex<HashMap<String, Vec<Idle>>>,
+}
+
+#[derive(Debug, Clone)]
+struct Idle {
+    generation: u64,
+    requests: u64,
+    opened_at: Instant,
+}
+
+impl Connections {
+    /// Take a connection to the origin of `url`, reusing the most recently idle one if any.
+    pub(crate) fn checkout(self: &Arc<Self>, url: &Url, now: Instant) -> Checkout {
+        let origin = url.origin().ascii_serialization();
+        let idle = self
+            .idle
+            .lock()
+            .unwrap()
+            .get_mut(&origin)
+            .and_then(Vec::pop);
+        let (reused, conn) = match idle {
+            Some(conn) => (true, conn),
+            None => {
+                let conn = Idle {
+                    generation: self.generations.fetch_add(1, Ordering::Relaxed) + 1,
+                    requests: 0,
+                    opened_at: now,
+                };
+                (false, conn)
+            }
+        };
+        let conn = Idle {
+            requests: conn.requests + 1,
+            ..conn
+        };
+        Checkout {
+            info: ConnectionInfo {
+                reused,
+                generation: conn.generation,
+                requests: conn.requests,
+                age: now.saturating_duration_since(conn.opened_at),
+            },
+            origin,
+            conn,
+            pool: Arc::downgrade(self),
+        }
+    }
+
+    fn release(&self, origin: String, conn: Idle) {
+        self.idle
+            .lock()
+            .unwrap()
+            .entry(origin)
+            .or_default()
+            .push(conn);
+    }
+}
+
+/// A connection in use by a request.
+#[derive(Debug)]
+pub(crate) struct Checkout {
+    info: ConnectionInfo,
+    origin: String,
+    conn: Idle,
+    pool: Weak<Connections>,
+}
+
+impl Checkout {
+    /// Record the connection in `res`, and return it to the pool once the body has been read
+    /// if it can be kept alive.
+    pub(crate) fn finish(self, method: Method, res: &mut Response, keep_alive: bool) {
+        res.insert_ext(self.info);
+        let closes = res
+            .header(CONNECTION)
+            .map(|values| {
+                values
+                    .iter()
+                    .any(|v| v.as_str().eq_ignore_ascii_case("close"))
+            })
+            .unwrap_or(false);
+        if !keep_alive || closes {
+            return;
+        }
+
+        // Multiplexed connections are free for other requests as soon as a response arrives,
+        // and so are connections of responses which can't have a body.
+        let bodiless = method == Method::Head
+            || matches!(
+                res.status(),
+                StatusCode::NoContent | StatusCode::NotModified
+            )
+            || res.len() == Some(0);
+        if bodiless
+            || matches!(
+                res.version(),
+                Some(Version::Http2_0) | Some(Version::Http3_0)
+            )
+        {
+            self.release();
+            return;
+        }
+
+        let len = res.len();
+        res.map_body(|body| {
+            map_body_reader(body, len, |inner| ReleaseReader {
+                inner,
+                remaining: len,
+                checkout: Some(self),
+            })
+        });
+    }
+
+    fn release(self) {
+        if let Some(pool) = self.pool.upgrade() {
+            pool.release(self.origin, self.conn);
+        }
+    }
+}
+
+/// A reader which returns a connection to the pool when its body has been read to the end.
+struct ReleaseReader {
+    inner: BodyReader,
+    /// The length of the rest of the body, if known, as readers of sized bodies stop there.
+    remaining: Option<usize>,
+    checkout: Option<Checkout>,
+}
+
+impl AsyncRead for ReleaseReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let read = futures_util::ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(read);
+        }
+        if self.remaining == Some(0) || (read == 0 && !buf.is_empty()) {
+            if let Some(checkout) = self.checkout.take() {
+                checkout.release();
+            }
+        }
+        Poll::Ready(Ok(read))
+    }
+}
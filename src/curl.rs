@@ -0,0 +1,167 @@
+//! The `curl-client` backend.
+
+use crate::http::{self, Body, Error, Request, Response, StatusCode};
+use crate::resolver::ResolveTo;
+use crate::response::{known_status, RawStatus};
+#[cfg(unix)]
+use crate::unix_socket::UnixSocket;
+use crate::{HttpClient, IpVersion};
+
+use async_std::io::BufReader;
+use http_client::Config as HttpConfig;
+use isahc::config::Configurable;
+use isahc::ResponseExt;
+
+use std::convert::TryFrom;
+
+/// An `isahc` client which, unlike the one of `http-client`, accepts any status code.
+#[derive(Debug)]
+pub(crate) struct CurlClient {
+    client: isahc::HttpClient,
+    config: HttpConfig,
+    title_case_headers: bool,
+}
+
+impl CurlClient {
+    pub(crate) fn new() -> Self {
+        Self::from_client(isahc::HttpClient::new().unwrap())
+    }
+
+    pub(crate) fn from_client(client: isahc::HttpClient) -> Self {
+        Self {
+            client,
+            config: HttpConfig::default(),
+            title_case_headers: false,
+        }
+    }
+
+    /// Send header names in title case, with a space after the colon as servers which care
+    /// about the case tend to require.
+    pub(crate) fn title_case_headers(mut self, enable: bool) -> Self {
+        self.title_case_headers = enable;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for CurlClient {
+    async fn send(&self, mut req: Request) -> Result<Response, Error> {
+        let mut builder = isahc::http::Request::builder()
+            .uri(req.url().as_str())
+            .method(req.method().to_string().as_str());
+        if let Some(version) = req.ext().get::<IpVersion>() {
+            builder = builder.ip_version(version.to_isahc());
+        }
+        if let Some(ResolveTo(addr)) = req.ext().get::<ResolveTo>() {
+            builder = builder.dial(isahc::config::Dialer::ip_socket(*addr));
+        }
+        #[cfg(unix)]
+        if let Some(UnixSocket(socket)) = req.ext().get::<UnixSocket>() {
+            builder = builder.dial(isahc::config::Dialer::unix_socket(socket));
+        }
+        if self.title_case_headers {
+            builder = builder.title_case_headers(true);
+        }
+        for (name, values) in req.iter() {
+            for value in values {
+                // Isahc joins names and values with a bare colon.
+                match self.title_case_headers {
+                    true => builder = builder.header(name.as_str(), format!(" {}", value)),
+                    false => builder = builder.header(name.as_str(), value.as_str()),
+                }
+            }
+        }
+        let body = req.take_body();
+        let body = match body.len() {
+            // Curl would upload an empty body, with a `Content-Length: 0` header, which methods
+            // not expecting a body shouldn't send.
+            Some(0) if !expects_body(req.method()) => isahc::Body::empty(),
+            Some(len) => isahc::Body::from_reader_sized(body, len as u64),
+            None => isahc::Body::from_reader(body),
+        };
+        let request = builder.body(body).map_err(|err| {
+            Error::from_str(StatusCode::BadRequest, format!("invalid request: {}", err))
+        })?;
+
+        let res = self.client.send_async(request).await?;
+        let metrics = res.metrics().cloned();
+        let (parts, body) = res.into_parts();
+        let code = parts.status.as_u16();
+        let status = known_status(code).ok_or_else(|| {
+            Error::from_str(
+                StatusCode::BadGateway,
+                format!("the server responded with invalid status code {}", code),
+            )
+        })?;
+
+        let mut res = http::Response::new(status);
+        res.set_version(to_version(parts.version));
+        if u16::from(status) != code {
+            res.ext_mut().insert(RawStatus(code));
+        }
+        for (name, value) in &parts.headers {
+            // Header values which aren't ASCII can't be represented, so they are left out.
+            if let Ok(value) = value.to_str() {
+                res.append_header(name.as_str(), value);
+            }
+        }
+        if let Some(metrics) = metrics {
+            res.ext_mut().insert(metrics);
+        }
+        res.set_body(Body::from_reader(BufReader::new(body), None));
+        Ok(res)
+    }
+
+    fn set_config(&mut self, config: HttpConfig) -> http::Result<()> {
+        *self = Self::try_from(config)?;
+        Ok(())
+    }
+
+    fn config(&self) -> &HttpConfig {
+        &self.config
+    }
+}
+
+/// Whether requests with `method` are expected to have a body, even an empty one.
+fn expects_body(method: http::Method) -> bool {
+    matches!(
+        method,
+        http::Method::Post | http::Method::Put | http::Method::Patch
+    )
+}
+
+/// The `http-types` equivalent of the version of an isahc response, if it has one.
+fn to_version(version: isahc::http::Version) -> Option<http::Version> {
+    use isahc::http::Version;
+
+    match version {
+        Version::HTTP_09 => Some(http::Version::Http0_9),
+        Version::HTTP_10 => Some(http::Version::Http1_0),
+        Version::HTTP_11 => Some(http::Version::Http1_1),
+        Version::HTTP_2 => Some(http::Version::Http2_0),
+        Version::HTTP_3 => Some(http::Version::Http3_0),
+        _ => None,
+    }
+}
+
+impl TryFrom<HttpConfig> for CurlClient {
+    type Error = isahc::Error;
+
+    fn try_from(config: HttpConfig) -> Result<Self, Self::Error> {
+        let mut builder = isahc::HttpClient::builder();
+        if !config.http_keep_alive {
+            builder = builder.connection_cache_size(0);
+        }
+        if config.tcp_no_delay {
+            builder = builder.tcp_nodelay();
+        }
+        if let Some(timeout) = config.timeout {
+            builder = builder.timeout(timeout);
+        }
+        Ok(Self {
+            client: builder.build()?,
+            config,
+            title_case_headers: false,
+        })
+    }
+}
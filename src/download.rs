@@ -0,0 +1,299 @@
+//! Downloads to disk.
+
+use crate::http::StatusCode;
+use crate::progress::Progress;
+#[cfg(feature = "checksum")]
+use crate::{Client, Request};
+use crate::{Error, Response, Result};
+
+use async_std::fs::{self, File};
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+#[cfg(feature = "checksum")]
+use sha2::{Digest, Sha256};
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Options for saving a response body to a file, with
+/// [`Response::save_to_with`](crate::Response::save_to_with) or
+/// [`RequestBuilder::download_to_with`](crate::RequestBuilder::download_to_with).
+///
+/// # Examples
+///
+/// ```no_run
+/// use surf::DownloadOptions;
+///
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// let options = DownloadOptions::new()
+///     .buffer_size(1024 * 1024)
+///     .progress(|downloaded, total| match total {
+///         Some(total) => println!("{}/{} bytes", downloaded, total),
+///         None => println!("{} bytes", downloaded),
+///     });
+/// surf::get("https://example.com/tool.tar.gz")
+///     .download_to_with("tool.tar.gz", options)
+///     .await?;
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct DownloadOptions {
+    buffer_size: usize,
+    verify_length: bool,
+    progress: Option<Arc<Progress>>,
+}
+
+impl DownloadOptions {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self {
+            buffer_size: 64 * 1024,
+            verify_length: true,
+            progress: None,
+        }
+    }
+
+    /// Set how many bytes are read from the body before each write to the file.
+    ///
+    /// Default: 64 KiB.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `size` is zero.
+    pub fn buffer_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "the buffer size must not be zero");
+        self.buffer_size = size;
+        self
+    }
+
+    /// Set whether to fail with a [`LengthMismatch`] if the body is shorter or longer than its
+    /// `Content-Length`.
+    ///
+    /// Default: `true`.
+    pub fn verify_length(mut self, verify: bool) -> Self {
+        self.verify_length = verify;
+        self
+    }
+
+    /// Call `progress` with the number of bytes downloaded so far, and the total from the
+    /// `Content-Length` of the response if there is one, after each write to the file.
+    pub fn progress(mut self, progress: impl Fn(u64, Option<u64>) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for DownloadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadOptions")
+            .field("buffer_size", &self.buffer_size)
+            .field("verify_length", &self.verify_length)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+/// A downloaded body was shorter or longer than its `Content-Length`.
+///
+/// Returned inside a `surf::Error` when saving a response body to a file.
+#[derive(Debug, Clone)]
+pub struct LengthMismatch {
+    /// The length in the `Content-Length` header.
+    pub expected: u64,
+    /// The number of bytes received.
+    pub actual: u64,
+}
+
+impl fmt::Display for LengthMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "length mismatch: expected {} bytes, got {}",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for LengthMismatch {}
+
+/// The expected checksum of a download.
+#[cfg(feature = "checksum")]
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    /// A SHA-256 digest, hex encoded.
+    Sha256(String),
+}
+
+#[cfg(feature = "checksum")]
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Checksum::Sha256(hex) => write!(f, "sha256:{}", hex),
+        }
+    }
+}
+
+/// A downloaded file didn't match its expected checksum.
+///
+/// Returned inside a `surf::Error` from
+/// [`Client::download_verified`](crate::Client::download_verified).
+#[cfg(feature = "checksum")]
+#[derive(Debug, Clone)]
+pub struct ChecksumMismatch {
+    /// The checksum the download was expected to have.
+    pub expected: Checksum,
+    /// The checksum of the downloaded bytes.
+    pub actual: Checksum,
+}
+
+#[cfg(feature = "checksum")]
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "checksum mismatch: expected {}, got {}",
+            self.expected, self.actual
+        )
+    }
+}
+
+#[cfg(feature = "checksum")]
+impl std::error::Error for ChecksumMismatch {}
+
+/// Removes the temporary file unless the download completed.
+struct TempFile {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Fail unless the status of `res` is a success, as a download of an error page is useless.
+pub(crate) fn check_status(res: &Response) -> Result<()> {
+    if !res.status().is_success() {
+        return Err(Error::from_str(
+            res.status(),
+            format!("download failed with status {}", res.status()),
+        ));
+    }
+    Ok(())
+}
+
+/// Stream the body of `res` to `path`. Returns the number of bytes written.
+pub(crate) async fn save(
+    res: &mut Response,
+    path: &Path,
+    options: &DownloadOptions,
+) -> Result<u64> {
+    let (temp, written) = write_temp(res, path, options, |_| ()).await?;
+    persist(temp, path).await?;
+    Ok(written)
+}
+
+/// Stream the body of `res` to a temporary file next to `path`, so `path` never holds a
+/// partial file, calling `inspect` with every chunk. Returns the file and its length.
+async fn write_temp(
+    res: &mut Response,
+    path: &Path,
+    options: &DownloadOptions,
+    mut inspect: impl FnMut(&[u8]),
+) -> Result<(TempFile, u64)> {
+    // Write next to the destination, so the final rename stays on one file system.
+    let file_name = path.file_name().ok_or_else(|| {
+        Error::from_str(
+            StatusCode::InternalServerError,
+            "the download path must name a file",
+        )
+    })?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(".download");
+    let temp = TempFile {
+        path: path.with_file_name(temp_name),
+        keep: false,
+    };
+
+    let total = res.len().map(|len| len as u64);
+    let mut file = File::create(&temp.path).await?;
+    let mut buf = vec![0; options.buffer_size];
+    let mut written = 0;
+    loop {
+        let read = res.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        inspect(&buf[..read]);
+        file.write_all(&buf[..read]).await?;
+        written += read as u64;
+        if let Some(progress) = &options.progress {
+            progress(written, total);
+        }
+    }
+    file.sync_all().await?;
+    drop(file);
+
+    if let (true, Some(expected)) = (options.verify_length, total) {
+        if written != expected {
+            let mismatch = LengthMismatch {
+                expected,
+                actual: written,
+            };
+            return Err(Error::new(StatusCode::UnprocessableEntity, mismatch));
+        }
+    }
+    Ok((temp, written))
+}
+
+/// Move the completed temporary file to `path`.
+async fn persist(mut temp: TempFile, path: &Path) -> Result<()> {
+    fs::rename(&temp.path, path).await?;
+    temp.keep = true;
+    Ok(())
+}
+
+#[cfg(feature = "checksum")]
+pub(crate) async fn download_verified(
+    client: &Client,
+    req: Request,
+    path: &Path,
+    checksum: Checksum,
+) -> Result<u64> {
+    let mut res = client.send(req).await?;
+    check_status(&res)?;
+
+    let mut hasher = Sha256::new();
+    let options = DownloadOptions::new();
+    let (temp, written) =
+        write_temp(&mut res, path, &options, |chunk| hasher.update(chunk)).await?;
+
+    let actual: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let Checksum::Sha256(expected) = &checksum;
+    if !actual.eq_ignore_ascii_case(expected) {
+        let mismatch = ChecksumMismatch {
+            expected: checksum,
+            actual: Checksum::Sha256(actual),
+        };
+        return Err(Error::new(StatusCode::UnprocessableEntity, mismatch));
+    }
+
+    persist(temp, path).await?;
+    Ok(written)
+}
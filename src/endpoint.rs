@@ -0,0 +1,288 @@
+//! Declaring the endpoints of an API as typed structs.
+//!
+//! The [`endpoint!`](crate::endpoint!) macro turns a struct whose fields are marked as path,
+//! query, header or body parameters into a request constructor against a [`Client`], saving
+//! hand-written SDKs from assembling each request themselves.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use serde::Deserialize;
+//! # use std::convert::TryInto;
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! #[derive(Deserialize)]
+//! struct Issue {
+//!     number: u64,
+//! }
+//!
+//! surf::endpoint! {
+//!     /// Open an issue in a repository.
+//!     pub struct CreateIssue: POST "repos/{owner}/{repo}/issues" -> Issue {
+//!         path owner: String,
+//!         path repo: String,
+//!         header x_github_api_version: String,
+//!         body title: String,
+//!         body labels: Vec<String>,
+//!     }
+//! }
+//!
+//! let client: surf::Client = surf::Config::new()
+//!     .set_base_url("https://api.github.com/".parse()?)
+//!     .try_into()?;
+//! let issue = CreateIssue {
+//!     owner: "http-rs".into(),
+//!     repo: "surf".into(),
+//!     x_github_api_version: "2022-11-28".into(),
+//!     title: "Add endpoint macros".into(),
+//!     labels: vec!["enhancement".into()],
+//! }
+//! .send(&client)
+//! .await?;
+//! println!("opened #{}", issue.number);
+//! # Ok(()) }
+//! ```
+
+use crate::http::headers::{HeaderName, HeaderValue};
+use crate::http::{Method, StatusCode};
+use crate::utils::percent_encode;
+use crate::{Client, Error, RequestBuilder, Result};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Declare an API endpoint as a struct, with a constructor for its request.
+///
+/// The struct is declared with the HTTP method and path template of the endpoint, and,
+/// optionally, the type its JSON response body is deserialized to. Each field is marked with
+/// where it goes:
+///
+/// - `path`: substituted for `{name}` in the path template, percent-encoded, with `Display`.
+/// - `query`: appended to the query string. Sequences repeat the parameter.
+/// - `header`: sent as a header, named after the field with dashes for underscores.
+/// - `body`: a member of the JSON object sent as the body, which is only sent if there are body
+///   fields.
+///
+/// Query, header and body fields are serialized with `serde`, and skipped if they serialize to
+/// `null`, as `None` does. Query and header values must be strings, numbers or booleans, or
+/// sequences of them.
+///
+/// The struct gets `request(&self, &Client)`, which builds a [`RequestBuilder`] to be extended
+/// or sent, and `send(&self, &Client)`, which sends it and returns the deserialized body, or the
+/// [`Response`](crate::Response) if no response type is declared. The path template is joined
+/// with the base URL of the client, if it has one.
+///
+/// See the [module documentation](crate::endpoint) for an example.
+///
+/// # Errors
+///
+/// `request` returns a `400 Bad Request` error if the method is unknown, a placeholder of the
+/// path template has no `path` field, or a field can't be serialized.
+#[macro_export]
+macro_rules! endpoint {
+    (
+        @struct
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident : $method:ident $path:literal {
+            $($(#[$field_meta:meta])* $kind:ident $field_vis:vis $field:ident : $ty:ty),*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field: $ty,)*
+        }
+
+        impl $name {
+            /// Build the request for this endpoint, to be sent with `client`.
+            $vis fn request(
+                &self,
+                client: &$crate::Client,
+            ) -> $crate::Result<$crate::RequestBuilder> {
+                let mut parts = $crate::endpoint::Parts::new(stringify!($method), $path);
+                $(parts.$kind(stringify!($field), &self.$field)?;)*
+                parts.build(client)
+            }
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident : $method:ident $path:literal -> $res:ty {
+            $($(#[$field_meta:meta])* $kind:ident $field_vis:vis $field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        $crate::endpoint! {
+            @struct
+            $(#[$meta])*
+            $vis struct $name : $method $path {
+                $($(#[$field_meta])* $kind $field_vis $field : $ty),*
+            }
+        }
+
+        impl $name {
+            /// Send the request for this endpoint with `client`, and deserialize its JSON
+            /// response body.
+            $vis async fn send(&self, client: &$crate::Client) -> $crate::Result<$res> {
+                self.request(client)?.recv_json().await
+            }
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident : $method:ident $path:literal {
+            $($(#[$field_meta:meta])* $kind:ident $field_vis:vis $field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        $crate::endpoint! {
+            @struct
+            $(#[$meta])*
+            $vis struct $name : $method $path {
+                $($(#[$field_meta])* $kind $field_vis $field : $ty),*
+            }
+        }
+
+        impl $name {
+            /// Send the request for this endpoint with `client`.
+            $vis async fn send(&self, client: &$crate::Client) -> $crate::Result<$crate::Response> {
+                self.request(client)?.await
+            }
+        }
+    };
+}
+
+/// The parts of an endpoint request, collected from the fields of an
+/// [`endpoint!`](crate::endpoint!) struct.
+///
+/// This is used by the code the macro generates, which calls the method named after the kind of
+/// each field.
+#[derive(Debug)]
+pub struct Parts {
+    method: &'static str,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: Option<Map<String, Value>>,
+}
+
+impl Parts {
+    /// Start a request with `method` to `template`.
+    pub fn new(method: &'static str, template: &str) -> Self {
+        Self {
+            method,
+            path: template.to_string(),
+            query: Vec::new(),
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    /// Substitute `value` for the `{name}` placeholders of the path template.
+    pub fn path(&mut self, name: &str, value: &impl Display) -> Result<()> {
+        let placeholder = format!("{{{}}}", name);
+        if !self.path.contains(&placeholder) {
+            return Err(Error::from_str(
+                StatusCode::BadRequest,
+                format!("the path template has no {} placeholder", placeholder),
+            ));
+        }
+        let value = percent_encode(value.to_string().as_bytes());
+        self.path = self.path.replace(&placeholder, &value);
+        Ok(())
+    }
+
+    /// Append `value` to the query string as the parameter `name`.
+    pub fn query(&mut self, name: &str, value: &impl Serialize) -> Result<()> {
+        for value in scalars(name, value)? {
+            self.query.push((name.to_string(), value));
+        }
+        Ok(())
+    }
+
+    /// Send `value` as the header named after `name`, with dashes for underscores.
+    pub fn header(&mut self, name: &str, value: &impl Serialize) -> Result<()> {
+        for value in scalars(name, value)? {
+            self.headers.push((name.replace('_', "-"), value));
+        }
+        Ok(())
+    }
+
+    /// Set `value` as the member `name` of the JSON body.
+    pub fn body(&mut self, name: &str, value: &impl Serialize) -> Result<()> {
+        let value = serialize(name, value)?;
+        let body = self.body.get_or_insert_with(Map::new);
+        if !value.is_null() {
+            body.insert(name.to_string(), value);
+        }
+        Ok(())
+    }
+
+    /// Build the request, to be sent with `client`.
+    pub fn build(self, client: &Client) -> Result<RequestBuilder> {
+        let method = Method::from_str(self.method).map_err(|_| {
+            Error::from_str(
+                StatusCode::BadRequest,
+                format!("unknown method {}", self.method),
+            )
+        })?;
+        if let Some(start) = self.path.find('{') {
+            let end = self.path[start..]
+                .find('}')
+                .map_or(self.path.len(), |end| start + end + 1);
+            return Err(Error::from_str(
+                StatusCode::BadRequest,
+                format!("no path field for {}", &self.path[start..end]),
+            ));
+        }
+
+        let mut builder = client.request(method, &self.path).query_pairs(self.query);
+        for (name, value) in self.headers {
+            let invalid = |_| {
+                Error::from_str(
+                    StatusCode::BadRequest,
+                    format!("invalid header {}: {:?}", name, value),
+                )
+            };
+            let header: HeaderName = name.parse().map_err(invalid)?;
+            let value: HeaderValue = value.parse().map_err(invalid)?;
+            builder = builder.header(header, value);
+        }
+        if let Some(body) = self.body {
+            builder = builder.body_json(&body)?;
+        }
+        Ok(builder)
+    }
+}
+
+/// Serialize the field `name`.
+fn serialize(name: &str, value: &impl Serialize) -> Result<Value> {
+    serde_json::to_value(value).map_err(|err| {
+        Error::from_str(
+            StatusCode::BadRequest,
+            format!("couldn't serialize {}: {}", name, err),
+        )
+    })
+}
+
+/// The string forms of the field `name`, which must be a scalar or a sequence of scalars.
+fn scalars(name: &str, value: &impl Serialize) -> Result<Vec<String>> {
+    let scalar = |value: Value| match value {
+        Value::String(value) => Ok(value),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Bool(value) => Ok(value.to_string()),
+        _ => Err(Error::from_str(
+            StatusCode::BadRequest,
+            format!("{} must be a string, number or boolean", name),
+        )),
+    };
+    match serialize(name, value)? {
+        Value::Null => Ok(Vec::new()),
+        Value::Array(values) => values
+            .into_iter()
+            .filter(|value| !value.is_null())
+            .map(scalar)
+            .collect(),
+        value => Ok(vec![scalar(value)?]),
+    }
+}
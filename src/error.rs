@@ -0,0 +1,403 @@
+//! Categories of errors, for handling failures without matching on their messages.
+
+use crate::response::{DecodeError, StatusError};
+use crate::{Error, Shutdown, StatusCode};
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+/// The category of an [`Error`], returned by [`ErrorKind::of`].
+///
+/// Errors keep the error which caused them, such as the error of the backend, so it can still
+/// be inspected with [`Error::downcast_ref`] or by following [`source`](StdError::source)
+/// from `err.as_ref()`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use surf::ErrorKind;
+///
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// match surf::get("https://example.invalid").await {
+///     Ok(res) => println!("{}", res.status()),
+///     Err(err) if ErrorKind::of(&err) == ErrorKind::Connect => println!("offline"),
+///     Err(err) => return Err(err),
+/// }
+/// # Ok(()) }
+/// ```
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Connecting to the server failed, including failures to resolve its name. The
+    /// [`ConnectionError`] of the error, if any, tells how.
+    Connect,
+    /// The request, or reading the response body, took longer than allowed.
+    Timeout,
+    /// The TLS handshake failed, or a certificate was rejected.
+    Tls,
+    /// The response body couldn't be decoded, decompressed, or deserialized.
+    Decode,
+    /// The server responded with an error status, as reported by
+    /// [`Response::error_for_status`](crate::Response::error_for_status).
+    Status,
+    /// A middleware failed the request, with a [`MiddlewareError`].
+    Middleware,
+    /// The client was shut down, with its [`ShutdownToken`](crate::ShutdownToken).
+    Shutdown,
+    /// Any other error.
+    Other,
+}
+
+impl ErrorKind {
+    /// The category of `err`.
+    ///
+    /// The category is found from the error and its sources, so it survives wrapping the
+    /// error in another one, except that errors wrapped in a [`MiddlewareError`] are
+    /// [`ErrorKind::Middleware`].
+    pub fn of(err: &Error) -> Self {
+        chain(err)
+            .find_map(Self::of_std)
+            .unwrap_or(ErrorKind::Other)
+    }
+
+    /// The category of `err` itself, if it is a known error type.
+    fn of_std(err: &(dyn StdError + 'static)) -> Option<Self> {
+        if err.is::<MiddlewareError>() {
+            return Some(ErrorKind::Middleware);
+        }
+        if let Some(err) = err.downcast_ref::<ConnectionError>() {
+            return match err.kind() {
+                ConnectionErrorKind::TlsHandshake => Some(ErrorKind::Tls),
+                _ => Some(ErrorKind::Connect),
+            };
+        }
+        if err.is::<Shutdown>() {
+            return Some(ErrorKind::Shutdown);
+        }
+        if err.is::<StatusError>() {
+            return Some(ErrorKind::Status);
+        }
+        if err.is::<DecodeError>() {
+            return Some(ErrorKind::Decode);
+        }
+        if let Some(err) = err.downcast_ref::<serde_json::Error>() {
+            if !err.is_io() {
+                return Some(ErrorKind::Decode);
+            }
+        }
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            let kind = match err.kind() {
+                io::ErrorKind::TimedOut => Some(ErrorKind::Timeout),
+                io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::AddrNotAvailable => Some(ErrorKind::Connect),
+                _ => None,
+            };
+            if kind.is_some() {
+                return kind;
+            }
+        }
+        #[cfg(feature = "curl-client")]
+        if let Some(err) = err.downcast_ref::<isahc::Error>() {
+            return match err {
+                isahc::Error::ConnectFailed
+                | isahc::Error::CouldntResolveHost
+                | isahc::Error::CouldntResolveProxy
+                | isahc::Error::NoResponse => Some(ErrorKind::Connect),
+                isahc::Error::BadClientCertificate(_)
+                | isahc::Error::BadServerCertificate(_)
+                | isahc::Error::SSLConnectFailed(_)
+                | isahc::Error::SSLEngineError(_) => Some(ErrorKind::Tls),
+                isahc::Error::InvalidContentEncoding(_) => Some(ErrorKind::Decode),
+                isahc::Error::Timeout => Some(ErrorKind::Timeout),
+                _ => None,
+            };
+        }
+        #[cfg(feature = "h1-client")]
+        if err.is::<async_native_tls::Error>() {
+            return Some(ErrorKind::Tls);
+        }
+        #[cfg(feature = "h1-client-rustls")]
+        if err.is::<rustls_crate::TLSError>() {
+            return Some(ErrorKind::Tls);
+        }
+        None
+    }
+}
+
+/// `err` followed by its sources.
+fn chain(err: &Error) -> impl Iterator<Item = &(dyn StdError + 'static)> {
+    std::iter::successors(Some(err.as_ref()), |err| source(*err))
+}
+
+/// The source of `err`.
+///
+/// The source of an `io::Error` is the source of the error it wraps, so that error is returned
+/// instead.
+fn source<'a>(err: &'a (dyn StdError + 'static)) -> Option<&'a (dyn StdError + 'static)> {
+    match err.downcast_ref::<io::Error>() {
+        Some(io) => io.get_ref().map(|inner| inner as &(dyn StdError + 'static)),
+        None => err.source(),
+    }
+}
+
+/// Whether `err` was caused by failing to resolve the name of a host.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn is_dns_failure(err: &Error) -> bool {
+    chain(err).any(|err| {
+        #[cfg(feature = "curl-client")]
+        if let Some(isahc::Error::CouldntResolveHost) = err.downcast_ref::<isahc::Error>() {
+            return true;
+        }
+        if let Some(err) = err.downcast_ref::<ConnectionError>() {
+            return err.kind() == ConnectionErrorKind::Dns;
+        }
+        // The message of `getaddrinfo` failures, as reported by the standard library.
+        err.downcast_ref::<io::Error>()
+            .map(|err| err.to_string().starts_with("failed to lookup address"))
+            .unwrap_or(false)
+    })
+}
+
+/// What went wrong with the connection of a request, as reported by a [`ConnectionError`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionErrorKind {
+    /// The name of the host couldn't be resolved.
+    Dns,
+    /// The server refused the connection.
+    Refused,
+    /// The connection was reset or closed by the server, or aborted.
+    Reset,
+    /// The TLS handshake failed, or a certificate was rejected.
+    TlsHandshake,
+    /// The proxy couldn't be used or connected to.
+    Proxy,
+}
+
+impl ConnectionErrorKind {
+    /// The kind of connection failure which caused `err`, if any.
+    fn of(err: &Error) -> Option<Self> {
+        if let Some(kind) = chain(err).find_map(Self::of_std) {
+            return Some(kind);
+        }
+        // Some backends only keep the message of the error, such as the h1 backend when
+        // connecting to add a connection to its pool fails.
+        let message = err.to_string().to_ascii_lowercase();
+        if message.contains("failed to lookup address") {
+            Some(ConnectionErrorKind::Dns)
+        } else if message.contains("connection refused") {
+            Some(ConnectionErrorKind::Refused)
+        } else if message.contains("connection reset") || message.contains("broken pipe") {
+            Some(ConnectionErrorKind::Reset)
+        } else if message.contains("handshake") || message.contains("certificate") {
+            Some(ConnectionErrorKind::TlsHandshake)
+        } else {
+            None
+        }
+    }
+
+    /// The kind of connection failure `err` itself is, if it is a known error type.
+    fn of_std(err: &(dyn StdError + 'static)) -> Option<Self> {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            let kind = match err.kind() {
+                io::ErrorKind::ConnectionRefused => Some(ConnectionErrorKind::Refused),
+                io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe => Some(ConnectionErrorKind::Reset),
+                _ if err.to_string().starts_with("failed to lookup address") => {
+                    Some(ConnectionErrorKind::Dns)
+                }
+                _ => None,
+            };
+            if kind.is_some() {
+                return kind;
+            }
+        }
+        #[cfg(feature = "curl-client")]
+        if let Some(err) = err.downcast_ref::<isahc::Error>() {
+            return match err {
+                isahc::Error::CouldntResolveHost => Some(ConnectionErrorKind::Dns),
+                isahc::Error::CouldntResolveProxy => Some(ConnectionErrorKind::Proxy),
+                isahc::Error::BadClientCertificate(_)
+                | isahc::Error::BadServerCertificate(_)
+                | isahc::Error::SSLConnectFailed(_) => Some(ConnectionErrorKind::TlsHandshake),
+                _ => None,
+            };
+        }
+        #[cfg(feature = "h1-client")]
+        if err.is::<async_native_tls::Error>() {
+            return Some(ConnectionErrorKind::TlsHandshake);
+        }
+        #[cfg(feature = "h1-client-rustls")]
+        if err.is::<rustls_crate::TLSError>() {
+            return Some(ConnectionErrorKind::TlsHandshake);
+        }
+        None
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            ConnectionErrorKind::Dns => "resolving the host failed",
+            ConnectionErrorKind::Refused => "the connection was refused",
+            ConnectionErrorKind::Reset => "the connection was reset",
+            ConnectionErrorKind::TlsHandshake => "the TLS handshake failed",
+            ConnectionErrorKind::Proxy => "the proxy failed",
+        }
+    }
+}
+
+/// An error with the connection of a request, which the backend failed with.
+///
+/// Backends report connection failures in different ways, often as plain I/O errors or only
+/// as a message, so a `Client` classifies the errors of its backend and wraps those which
+/// are about the connection in this type, with a [`ConnectionErrorKind`]. This lets callers
+/// tell a refused connection from a reset one or a failed TLS handshake, such as to decide
+/// whether to retry. The error of the backend is the [`source`](StdError::source) of this one.
+///
+/// # Examples
+///
+/// ```no_run
+/// use surf::{ConnectionError, ConnectionErrorKind};
+///
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// match surf::get("http://localhost:9").await {
+///     Err(err) => match err.downcast_ref::<ConnectionError>().map(|err| err.kind()) {
+///         Some(ConnectionErrorKind::Refused) => println!("nothing is listening"),
+///         Some(kind) => println!("connection failed: {:?}", kind),
+///         None => return Err(err),
+///     },
+///     Ok(res) => println!("{}", res.status()),
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug)]
+pub struct ConnectionError {
+    kind: ConnectionErrorKind,
+    source: Source,
+}
+
+#[derive(Debug)]
+enum Source {
+    /// The error of the backend, kept whole so its own error is the source.
+    Backend(Error),
+    Other(Box<dyn StdError + Send + Sync + 'static>),
+}
+
+impl ConnectionError {
+    /// Create a new instance, for a connection failure of `kind` caused by `source`.
+    pub fn new(
+        kind: ConnectionErrorKind,
+        source: impl Into<Box<dyn StdError + Send + Sync + 'static>>,
+    ) -> Self {
+        Self {
+            kind,
+            source: Source::Other(source.into()),
+        }
+    }
+
+    /// What went wrong with the connection.
+    pub fn kind(&self) -> ConnectionErrorKind {
+        self.kind
+    }
+
+    /// Wrap `err` in an instance of `kind`, unless it already is a `ConnectionError`.
+    pub(crate) fn wrap(kind: ConnectionErrorKind, err: Error) -> Error {
+        if err.downcast_ref::<ConnectionError>().is_some() {
+            return err;
+        }
+        Error::new(
+            StatusCode::BadGateway,
+            ConnectionError {
+                kind,
+                source: Source::Backend(err),
+            },
+        )
+    }
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Source::Backend(err) => write!(f, "{}: {}", self.kind.description(), err),
+            Source::Other(err) => write!(f, "{}: {}", self.kind.description(), err),
+        }
+    }
+}
+
+impl StdError for ConnectionError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match &self.source {
+            Source::Backend(err) => Some(err.as_ref()),
+            Source::Other(err) => Some(&**err),
+        }
+    }
+}
+
+/// Wrap `err`, an error of a backend, in a [`ConnectionError`] if it is about the connection.
+pub(crate) fn classify_connection_error(err: Error) -> Error {
+    if err.downcast_ref::<ConnectionError>().is_some() {
+        return err;
+    }
+    match ConnectionErrorKind::of(&err) {
+        Some(kind) => ConnectionError::wrap(kind, err),
+        None => err,
+    }
+}
+
+/// An error with which a middleware failed a request, rather than the server or the backend.
+///
+/// Middleware can wrap its errors in this type so callers can tell them apart with
+/// [`ErrorKind::of`]. The wrapped error is the [`source`](StdError::source) of this one.
+///
+/// # Examples
+///
+/// ```
+/// use surf::{Error, ErrorKind, MiddlewareError, StatusCode};
+///
+/// let err = Error::new(
+///     StatusCode::TooManyRequests,
+///     MiddlewareError::new("rate-limit", "too many requests in flight"),
+/// );
+/// assert_eq!(ErrorKind::of(&err), ErrorKind::Middleware);
+/// ```
+#[derive(Debug)]
+pub struct MiddlewareError {
+    middleware: &'static str,
+    source: Box<dyn StdError + Send + Sync + 'static>,
+}
+
+impl MiddlewareError {
+    /// Create a new instance, for an error of the middleware named `middleware`.
+    pub fn new(
+        middleware: &'static str,
+        source: impl Into<Box<dyn StdError + Send + Sync + 'static>>,
+    ) -> Self {
+        Self {
+            middleware,
+            source: source.into(),
+        }
+    }
+
+    /// The name of the middleware which failed the request.
+    pub fn middleware(&self) -> &'static str {
+        self.middleware
+    }
+}
+
+impl fmt::Display for MiddlewareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl StdError for MiddlewareError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&*self.source)
+    }
+}
@@ -0,0 +1,144 @@
+//! Broadcasting the high-level events of the requests of a client.
+
+use crate::http::{Method, StatusCode, Url};
+
+use futures_util::stream::Stream;
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// The number of events a subscriber may fall behind by before later ones are dropped for it.
+const CAPACITY: usize = 1024;
+
+/// A high-level event of a request sent by a client, received from [`Client::events`].
+///
+/// Events of the same request share its [`id`](ClientEvent::id).
+///
+/// [`Client::events`]: crate::Client::events
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// A request was sent to the middleware stack.
+    RequestStarted {
+        /// The id of the request.
+        id: u64,
+        /// The method of the request.
+        method: Method,
+        /// The URL of the request.
+        url: Url,
+    },
+    /// The [`Redirect`](crate::middleware::Redirect) middleware followed a redirect.
+    Redirected {
+        /// The id of the request.
+        id: u64,
+        /// The URL which was redirected.
+        from: Url,
+        /// The URL the request is sent to next.
+        to: Url,
+        /// The status of the redirect.
+        status: StatusCode,
+    },
+    /// The [`Retry`](crate::middleware::Retry) middleware is going to send the request again.
+    RetryScheduled {
+        /// The id of the request.
+        id: u64,
+        /// The number of the retry, counting from 1.
+        retry: usize,
+        /// How long the middleware waits before the retry.
+        delay: Duration,
+    },
+    /// The middleware stack returned a response.
+    ResponseReceived {
+        /// The id of the request.
+        id: u64,
+        /// The status of the response.
+        status: StatusCode,
+    },
+    /// The middleware stack returned an error.
+    RequestFailed {
+        /// The id of the request.
+        id: u64,
+        /// The message of the error.
+        message: String,
+    },
+    /// Some of the response body was read.
+    BytesDownloaded {
+        /// The id of the request.
+        id: u64,
+        /// The number of bytes read so far.
+        read: u64,
+        /// The length of the body, if known.
+        total: Option<u64>,
+    },
+}
+
+impl ClientEvent {
+    /// The id of the request this event is about.
+    pub fn id(&self) -> u64 {
+        match self {
+            ClientEvent::RequestStarted { id, .. }
+            | ClientEvent::Redirected { id, .. }
+            | ClientEvent::RetryScheduled { id, .. }
+            | ClientEvent::ResponseReceived { id, .. }
+            | ClientEvent::RequestFailed { id, .. }
+            | ClientEvent::BytesDownloaded { id, .. } => *id,
+        }
+    }
+}
+
+/// A stream of the [events](ClientEvent) of the requests of a client and its clones, returned
+/// by [`Client::events`](crate::Client::events).
+///
+/// The stream ends once the client and its clones are dropped.
+#[derive(Debug)]
+pub struct ClientEvents {
+    receiver: async_channel::Receiver<ClientEvent>,
+}
+
+impl Stream for ClientEvents {
+    type Item = ClientEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+/// The subscribers to the events of a client and its clones.
+#[derive(Debug, Default)]
+pub(crate) struct EventHub {
+    subscribers: Mutex<Vec<async_channel::Sender<ClientEvent>>>,
+    next_id: AtomicU64,
+}
+
+/// The id of a request whose events are broadcast, added as an extension when it is sent.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct EventId(pub(crate) u64);
+
+impl EventHub {
+    /// Add a subscriber.
+    pub(crate) fn subscribe(&self) -> ClientEvents {
+        let (sender, receiver) = async_channel::bounded(CAPACITY);
+        self.subscribers.lock().unwrap().push(sender);
+        ClientEvents { receiver }
+    }
+
+    /// An id for a new request, if anyone is listening.
+    pub(crate) fn start(&self) -> Option<EventId> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|sender| !sender.is_closed());
+        if subscribers.is_empty() {
+            return None;
+        }
+        Some(EventId(self.next_id.fetch_add(1, Ordering::Relaxed)))
+    }
+
+    /// Send `event` to the subscribers, dropping it for those which have fallen behind.
+    pub(crate) fn emit(&self, event: ClientEvent) {
+        for sender in self.subscribers.lock().unwrap().iter() {
+            let _ = sender.try_send(event.clone());
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Fetching JSON resources with sensible defaults, in one call.
+
+use crate::http::headers::ACCEPT;
+use crate::middleware::cache::{Cache, SharedCache};
+use crate::middleware::Retry;
+use crate::{Client, RequestBuilder, Result};
+
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The cache shared by the calls to [`fetch_json`].
+static CACHE: Lazy<Arc<Cache>> = Lazy::new(|| Arc::new(Cache::new()));
+
+/// Options for [`fetch_json`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use surf::FetchOptions;
+///
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// let options = FetchOptions::new()
+///     .timeout(Some(Duration::from_secs(5)))
+///     .retries(4);
+/// let ip: serde_json::Value = surf::fetch_json("https://httpbin.org/ip", options).await?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FetchOptions {
+    timeout: Option<Duration>,
+    retries: usize,
+    #[cfg_attr(not(feature = "gzip"), allow(dead_code))]
+    gzip: bool,
+    cache: bool,
+    client: Option<Client>,
+}
+
+impl FetchOptions {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(30)),
+            retries: 2,
+            gzip: true,
+            cache: true,
+            client: None,
+        }
+    }
+
+    /// Set the timeout of each attempt, or `None` to use the timeout of the client.
+    ///
+    /// Default: 30 seconds.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how many times a failed request is retried, as described in
+    /// [`Retry`](crate::middleware::Retry).
+    ///
+    /// Default: `2`.
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Set whether compressed responses are asked for and decompressed. This has no effect
+    /// without the `gzip` feature.
+    ///
+    /// Default: `true`.
+    pub fn gzip(mut self, gzip: bool) -> Self {
+        self.gzip = gzip;
+        self
+    }
+
+    /// Set whether responses are cached, and revalidated once stale, as described in
+    /// [`Cache`](crate::middleware::cache::Cache). The cache is kept in memory and shared by
+    /// all calls to [`fetch_json`].
+    ///
+    /// Default: `true`.
+    pub fn cache(mut self, cache: bool) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Send the request with `client`, and its middleware, rather than with the client of the
+    /// one-off functions.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Get the JSON resource at `url` and deserialize it, with a timeout, retries, compression and
+/// caching set up by `options`.
+///
+/// This is for application code which wants these behaviors without composing the middleware
+/// itself. The request is sent with `Accept: application/json`, and the
+/// [`Cache`](crate::middleware::cache::Cache), [`Retry`](crate::middleware::Retry) and
+/// [`Decompress`](crate::middleware::Decompress) middleware are added to it as enabled.
+///
+/// # Panics
+///
+/// This will panic if a malformed URL is passed.
+///
+/// # Errors
+///
+/// Returns errors from the middleware, http backend, and network sockets, an error with the
+/// status of the response if it isn't [successful](crate::Response::is_success), and an error if
+/// the body isn't valid JSON for `T`.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// #[derive(serde::Deserialize)]
+/// struct Ip {
+///     origin: String,
+/// }
+///
+/// let ip: Ip = surf::fetch_json("https://httpbin.org/ip", Default::default()).await?;
+/// println!("{}", ip.origin);
+/// # Ok(()) }
+/// ```
+pub async fn fetch_json<T: DeserializeOwned>(
+    url: impl AsRef<str>,
+    options: FetchOptions,
+) -> Result<T> {
+    let mut builder = match &options.client {
+        Some(client) => client.get(url),
+        None => crate::get(url),
+    };
+    builder = builder.header(ACCEPT, "application/json");
+    if let Some(timeout) = options.timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder = with_middleware(builder, &options);
+
+    let mut res = builder.await?.error_for_status().await?;
+    res.body_json().await
+}
+
+/// Add the middleware enabled by `options` to `builder`, outermost first.
+fn with_middleware(mut builder: RequestBuilder, options: &FetchOptions) -> RequestBuilder {
+    #[cfg(feature = "gzip")]
+    if options.gzip {
+        // Outside of the cache, so compressed responses are cached as they are received.
+        builder = builder.middleware(crate::middleware::Decompress::new());
+    }
+    if options.cache {
+        builder = builder.middleware(SharedCache(CACHE.clone()));
+    }
+    if options.retries > 0 {
+        builder = builder.middleware(Retry::new(options.retries));
+    }
+    builder
+}
@@ -0,0 +1,185 @@
+//! Routing requests of the default backend through forward proxies.
+
+#[cfg(feature = "curl-client")]
+use crate::http::Url;
+#[cfg(all(feature = "default-client", not(target_arch = "wasm32")))]
+use crate::Config;
+
+#[cfg(all(feature = "default-client", not(target_arch = "wasm32")))]
+use http_client::{Error, HttpClient, Request, Response};
+#[cfg(all(feature = "default-client", not(target_arch = "wasm32")))]
+use std::sync::Arc;
+
+/// Whether `host` matches one of the `NO_PROXY`-style `patterns`.
+///
+/// A pattern matches the host itself and its subdomains, with or without a leading dot, and `*`
+/// matches every host.
+pub(crate) fn bypasses_proxy(patterns: &[String], host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.trim_start_matches('.');
+        pattern == "*"
+            || host == pattern
+            || (host.len() > pattern.len()
+                && host.ends_with(pattern)
+                && host.as_bytes()[host.len() - pattern.len() - 1] == b'.')
+    })
+}
+
+/// Read the proxies which aren't configured explicitly from the environment.
+#[cfg(feature = "curl-client")]
+pub(crate) fn apply_env(config: &mut Config) {
+    fn var(name: &str) -> Option<String> {
+        std::env::var(name.to_ascii_uppercase())
+            .or_else(|_| std::env::var(name))
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+    }
+    fn proxy_var(name: &str) -> Option<Url> {
+        let value = var(name)?;
+        let value = value.trim();
+        let parsed = match value.contains("://") {
+            true => Url::parse(value),
+            false => Url::parse(&format!("http://{}", value)),
+        };
+        match parsed {
+            Ok(url) => Some(url),
+            Err(err) => {
+                log::warn!("ignoring invalid proxy in ${}: {}", name, err);
+                None
+            }
+        }
+    }
+
+    if config.http_proxy.is_none() {
+        config.http_proxy = proxy_var("http_proxy");
+    }
+    if config.https_proxy.is_none() {
+        config.https_proxy = proxy_var("https_proxy");
+    }
+    if config.no_proxy.is_empty() {
+        if let Some(no_proxy) = var("no_proxy") {
+            config.no_proxy = no_proxy
+                .split(',')
+                .map(|pattern| pattern.trim().to_ascii_lowercase())
+                .filter(|pattern| !pattern.is_empty())
+                .collect();
+        }
+    }
+}
+
+/// Build the curl client, with one connection pool for each route a request may take.
+#[cfg(feature = "curl-client")]
+pub(crate) fn curl_client(config: &mut Config) -> Result<Arc<dyn HttpClient>, isahc::Error> {
+    use crate::curl::CurlClient;
+    use crate::timeout::backend_config;
+    use isahc::config::Configurable;
+    use std::convert::TryFrom;
+
+    if config.proxy_from_env {
+        apply_env(config);
+    }
+
+    let build = |proxy: Option<&Url>| -> Result<CurlClient, isahc::Error> {
+        let http_config = backend_config(config);
+        let proxy = match proxy {
+            Some(proxy) => {
+                Some(isahc::http::Uri::try_from(proxy.as_str()).map_err(isahc::http::Error::from)?)
+            }
+            None => None,
+        };
+        let mut builder = isahc::HttpClient::builder().proxy(proxy);
+        if !http_config.http_keep_alive {
+            builder = builder.connection_cache_size(0);
+        }
+        if http_config.tcp_no_delay {
+            builder = builder.tcp_nodelay();
+        }
+        if let Some(timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        builder = builder.version_negotiation(config.http_version.to_isahc());
+        let builder = config.backend_options.apply(builder);
+        Ok(CurlClient::from_client(builder.build()?)
+            .title_case_headers(config.header_case != crate::HeaderCase::Lower))
+    };
+
+    let direct = build(None)?;
+    if config.http_proxy.is_none() && config.https_proxy.is_none() {
+        return Ok(Arc::new(direct));
+    }
+    Ok(Arc::new(ProxiedClient {
+        http: config
+            .http_proxy
+            .as_ref()
+            .map(|p| build(Some(p)))
+            .transpose()?,
+        https: config
+            .https_proxy
+            .as_ref()
+            .map(|p| build(Some(p)))
+            .transpose()?,
+        direct,
+        no_proxy: config.no_proxy.clone(),
+    }))
+}
+
+/// Sends each request through the proxy for its scheme, unless its host bypasses the proxies.
+#[cfg(feature = "curl-client")]
+#[derive(Debug)]
+struct ProxiedClient {
+    direct: crate::curl::CurlClient,
+    http: Option<crate::curl::CurlClient>,
+    https: Option<crate::curl::CurlClient>,
+    no_proxy: Vec<String>,
+}
+
+#[cfg(feature = "curl-client")]
+#[async_trait::async_trait]
+impl HttpClient for ProxiedClient {
+    async fn send(&self, req: Request) -> Result<Response, Error> {
+        let host = req.url().host_str().unwrap_or_default();
+        let proxied = match req.url().scheme() {
+            "http" => self.http.as_ref(),
+            "https" => self.https.as_ref(),
+            _ => None,
+        };
+        match proxied {
+            Some(client) if !bypasses_proxy(&self.no_proxy, host) => client.send(req).await,
+            _ => self.direct.send(req).await,
+        }
+    }
+}
+
+/// Fails the requests which should go through a proxy, for backends which can't use one.
+#[cfg(all(
+    feature = "default-client",
+    not(feature = "curl-client"),
+    not(target_arch = "wasm32")
+))]
+#[derive(Debug)]
+pub(crate) struct ProxyUnsupported {
+    pub(crate) inner: Arc<dyn HttpClient>,
+    pub(crate) config: Config,
+}
+
+#[cfg(all(
+    feature = "default-client",
+    not(feature = "curl-client"),
+    not(target_arch = "wasm32")
+))]
+#[async_trait::async_trait]
+impl HttpClient for ProxyUnsupported {
+    async fn send(&self, req: Request) -> Result<Response, Error> {
+        if let Some(proxy) = self.config.proxy_for(req.url()) {
+            return Err(Error::new(
+                crate::StatusCode::BadGateway,
+                crate::ConnectionError::new(
+                    crate::ConnectionErrorKind::Proxy,
+                    format!("proxy {} requires the curl-client backend", proxy),
+                ),
+            ));
+        }
+        self.inner.send(req).await
+    }
+}
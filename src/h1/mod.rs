@@ -0,0 +1,603 @@
+//! The connections surf makes itself for the h1 backends, for requests `http-client` can't send
+//! as asked: with headers spelled and ordered as set with `Config::set_header_case` and
+//! `Config::set_header_order`, to an address chosen with a DNS override, a resolver, or
+//! `RequestBuilder::resolve_to`, within the timeout set with `Config::set_connect_timeout`, or
+//! with the response head limited as set with `Config::set_max_response_headers` and
+//! `Config::set_max_response_header_bytes` or kept as set with
+//! `Config::set_raw_response_headers`, or only to addresses of the IP version set with
+//! `RequestBuilder::ip_family`.
+
+use crate::header_case::Recase;
+use crate::http::headers::{CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING};
+use crate::http::{self, Method, StatusCode};
+use crate::resolver::ResolveTo;
+use crate::response::RawHeaders;
+#[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+use crate::tls::{TlsClients, TlsConfig, TlsOverride};
+use crate::utils::map_body_reader;
+use crate::{Config, ConnectionError, ConnectionErrorKind, Error, HeaderLimitExceeded, IpVersion};
+
+use async_io::Async;
+use futures_util::future::{self, Either};
+use futures_util::io::{AsyncRead, AsyncWrite};
+use http_client::HttpClient;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+mod pool;
+
+use pool::{Checkout, Pool, PoolKey, ReleaseReader, Slot};
+
+/// An http client which sends requests with `inner`, or over its own pooled connections if
+/// `inner` can't send them as asked.
+///
+/// The connections honour the keep-alive, connection limit, `TCP_NODELAY` and TLS settings of
+/// the config, as those of `inner` do. Requests through a proxy are sent with `inner`, which
+/// refuses them.
+pub(crate) struct Transport {
+    inner: Arc<dyn HttpClient>,
+    config: Config,
+    /// The clients for requests with their own TLS config, which `inner` can send.
+    #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+    tls_clients: Arc<TlsClients>,
+    pools: Mutex<HashMap<PoolKey, Arc<Pool>>>,
+}
+
+impl fmt::Debug for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transport")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl Transport {
+    /// Create a new instance, sending the requests it doesn't handle itself with `inner`.
+    pub(crate) fn new(
+        inner: Arc<dyn HttpClient>,
+        config: Config,
+        #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))] tls_clients: Arc<
+            TlsClients,
+        >,
+    ) -> Self {
+        Self {
+            inner,
+            config,
+            #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+            tls_clients,
+            pools: Mutex::default(),
+        }
+    }
+
+    /// Whether `req` has to be sent over the connections of this transport.
+    fn sends_itself(&self, req: &http::Request) -> bool {
+        crate::header_case::rewrites(&self.config)
+            || req.ext().get::<ResolveTo>().is_some()
+            || matches!(
+                req.ext().get::<IpVersion>(),
+                Some(IpVersion::V4 | IpVersion::V6)
+            )
+            || self.config.connect_timeout.is_some()
+            || self.config.max_response_headers.is_some()
+            || self.config.max_response_header_bytes.is_some()
+            || self.config.raw_response_headers
+    }
+
+    /// The pool for connections to `key`.
+    fn pool(&self, key: PoolKey) -> Arc<Pool> {
+        let max = self.config.http_config.max_connections_per_host;
+        self.pools
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Arc::new(Pool::new(max)))
+            .clone()
+    }
+
+    /// Send `req` over a connection of this transport.
+    async fn send_pooled(&self, mut req: http::Request) -> http::Result<http::Response> {
+        let url = req.url().clone();
+        let host = url
+            .host_str()
+            .ok_or_else(|| Error::from_str(StatusCode::BadRequest, "the URL has no host"))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| Error::from_str(StatusCode::BadRequest, "the URL has no known port"))?;
+        let tls = match url.scheme() {
+            "http" => None,
+            "https" => Some(self.tls_config(&req)),
+            scheme => {
+                return Err(Error::from_str(
+                    StatusCode::BadRequest,
+                    format!("unsupported URL scheme {}", scheme),
+                ))
+            }
+        };
+        let addr = req.ext().get::<ResolveTo>().map(|ResolveTo(addr)| *addr);
+        let ip_version = req.ext().get::<IpVersion>().copied().unwrap_or_default();
+        let key = PoolKey {
+            host: host.clone(),
+            port,
+            addr,
+            ip_version,
+            tls: tls.as_ref().map(Tls::id),
+        };
+
+        let keep_alive = self.config.http_config.http_keep_alive;
+        let pool = if keep_alive {
+            Some(self.pool(key))
+        } else {
+            None
+        };
+        let (stream, permit) = match &pool {
+            Some(pool) => match pool.checkout().await? {
+                Checkout::Idle(stream, permit) => (stream, Some(permit)),
+                Checkout::New(permit) => (
+                    self.connect(&host, port, addr, ip_version, tls).await?,
+                    Some(permit),
+                ),
+            },
+            None => (
+                self.connect(&host, port, addr, ip_version, tls).await?,
+                None,
+            ),
+        };
+        if keep_alive {
+            req.insert_header(CONNECTION, "keep-alive");
+        }
+
+        let method = req.method();
+        let slot = Arc::new(Mutex::new(Slot {
+            stream: None,
+            permit,
+            pool,
+            read: false,
+            exceeded: None,
+            head: None,
+        }));
+        let lease = Lease::new(stream, slot.clone(), &self.config);
+        let stream = Recase::new(lease, &self.config, &req);
+        let mut res = match async_h1::connect(stream, req).await {
+            Ok(res) => res,
+            Err(err) => match slot.lock().unwrap().exceeded.take() {
+                Some(exceeded) => return Err(Error::new(StatusCode::BadGateway, exceeded)),
+                None => return Err(err),
+            },
+        };
+        if let Some(head) = slot.lock().unwrap().head.take() {
+            res.ext_mut().insert(RawHeaders::parse(&head));
+        }
+        if !reusable(method, &res) {
+            slot.lock().unwrap().pool = None;
+        }
+        let len = res.len();
+        if len == Some(0) {
+            slot.lock().unwrap().finish();
+        } else {
+            crate::utils::map_body(&mut res, |body| {
+                map_body_reader(body, len, |inner| ReleaseReader {
+                    inner,
+                    remaining: len,
+                    slot: Some(slot),
+                })
+            });
+        }
+        Ok(res)
+    }
+
+    /// The TLS config to use for `req`.
+    #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+    fn tls_config(&self, req: &http::Request) -> Tls {
+        match req.ext().get::<TlsOverride>() {
+            Some(TlsOverride(tls)) => Tls(Some(tls.clone())),
+            None => Tls(self.config.http_config.tls_config.clone()),
+        }
+    }
+
+    /// The TLS config to use for `req`.
+    #[cfg(not(any(feature = "h1-client", feature = "h1-client-rustls")))]
+    fn tls_config(&self, _: &http::Request) -> Tls {
+        Tls
+    }
+
+    /// Open a connection to `host`, or to `addr` if set, with an address of `ip_version` and
+    /// within the connect timeout of the config.
+    async fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        addr: Option<SocketAddr>,
+        ip_version: IpVersion,
+        tls: Option<Tls>,
+    ) -> http::Result<Box<dyn Stream>> {
+        let timeout = match self.config.connect_timeout {
+            Some(timeout) => timeout,
+            None => return self.open(host, port, addr, ip_version, tls).await,
+        };
+        let open = Box::pin(self.open(host, port, addr, ip_version, tls));
+        match future::select(open, self.config.clock.sleep(timeout)).await {
+            Either::Left((stream, _)) => stream,
+            Either::Right(_) => Err(Error::new(
+                StatusCode::RequestTimeout,
+                io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("connecting to {}:{} timed out", host, port),
+                ),
+            )),
+        }
+    }
+
+    /// Open a connection to `host`, or to `addr` if set, with an address of `ip_version` and
+    /// without a timeout.
+    async fn open(
+        &self,
+        host: &str,
+        port: u16,
+        addr: Option<SocketAddr>,
+        ip_version: IpVersion,
+        tls: Option<Tls>,
+    ) -> http::Result<Box<dyn Stream>> {
+        let mut addrs: Vec<SocketAddr> = match addr {
+            Some(addr) => vec![addr],
+            None => {
+                use std::net::ToSocketAddrs;
+
+                let target = (host.to_string(), port);
+                blocking::unblock(move || target.to_socket_addrs())
+                    .await?
+                    .collect()
+            }
+        };
+        addrs.retain(|addr| ip_version.allows(addr));
+        if addrs.is_empty() && ip_version != IpVersion::Any {
+            let family = match ip_version {
+                IpVersion::V6 => "IPv6",
+                _ => "IPv4",
+            };
+            return Err(Error::new(
+                StatusCode::BadGateway,
+                ConnectionError::new(
+                    ConnectionErrorKind::Dns,
+                    format!("{} has no {} addresses", host, family),
+                ),
+            ));
+        }
+        let mut last_err = None;
+        let mut stream = None;
+        for addr in addrs {
+            match Async::<TcpStream>::connect(addr).await {
+                Ok(connected) => {
+                    stream = Some(connected);
+                    break;
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        let stream = match stream {
+            Some(stream) => stream,
+            None => {
+                return Err(last_err
+                    .unwrap_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("{} has no addresses", host),
+                        )
+                    })
+                    .into())
+            }
+        };
+        stream
+            .get_ref()
+            .set_nodelay(self.config.http_config.tcp_no_delay)?;
+        match tls {
+            None => Ok(Box::new(stream)),
+            Some(tls) => tls.connect(host, stream).await,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for Transport {
+    async fn send(&self, req: http::Request) -> http::Result<http::Response> {
+        if self.config.proxy_for(req.url()).is_some() || !self.sends_itself(&req) {
+            #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+            if let Some(TlsOverride(tls)) = req.ext().get::<TlsOverride>() {
+                let http_config = crate::timeout::backend_config(&self.config);
+                return self.tls_clients.get(tls, &http_config).send(req).await;
+            }
+            return self.inner.send(req).await;
+        }
+        self.send_pooled(req).await
+    }
+}
+
+/// Whether the connection of `res` can be reused once its body has been read.
+fn reusable(method: Method, res: &http::Response) -> bool {
+    let closes = res
+        .header(CONNECTION)
+        .map(|values| {
+            values
+                .iter()
+                .any(|v| v.as_str().eq_ignore_ascii_case("close"))
+        })
+        .unwrap_or(false);
+    // Bodies without a length run until the connection is closed, and `async-h1` would read
+    // the body of a response to a HEAD request from the connection.
+    let delimited = res.header(CONTENT_LENGTH).is_some()
+        || res.header(TRANSFER_ENCODING).is_some()
+        || matches!(
+            res.status(),
+            StatusCode::NoContent | StatusCode::NotModified
+        );
+    !closes && delimited && method != Method::Head
+}
+
+/// The TLS config of a connection.
+#[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+struct Tls(Option<Arc<TlsConfig>>);
+
+/// The TLS config of a connection, which this backend can't make.
+#[cfg(not(any(feature = "h1-client", feature = "h1-client-rustls")))]
+struct Tls;
+
+impl Tls {
+    /// Identifies the config, for pooling connections.
+    #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+    fn id(&self) -> usize {
+        self.0
+            .as_ref()
+            .map_or(0, |config| Arc::as_ptr(config) as usize)
+    }
+
+    /// Identifies the config, for pooling connections.
+    #[cfg(not(any(feature = "h1-client", feature = "h1-client-rustls")))]
+    fn id(&self) -> usize {
+        0
+    }
+
+    /// Start a TLS session with `host` over `stream`.
+    #[cfg(feature = "h1-client-rustls")]
+    async fn connect(self, host: &str, stream: Async<TcpStream>) -> http::Result<Box<dyn Stream>> {
+        let connector = match self.0 {
+            Some(config) => async_tls::TlsConnector::from(config),
+            None => async_tls::TlsConnector::default(),
+        };
+        Ok(Box::new(connector.connect(host, stream).await?))
+    }
+
+    /// Start a TLS session with `host` over `stream`.
+    #[cfg(all(feature = "h1-client", not(feature = "h1-client-rustls")))]
+    async fn connect(self, host: &str, stream: Async<TcpStream>) -> http::Result<Box<dyn Stream>> {
+        let connector = self.0.unwrap_or_default();
+        Ok(Box::new(connector.connect(host, stream).await?))
+    }
+
+    /// Start a TLS session with `host` over `stream`.
+    #[cfg(not(any(feature = "h1-client", feature = "h1-client-rustls")))]
+    async fn connect(self, _: &str, _: Async<TcpStream>) -> http::Result<Box<dyn Stream>> {
+        Err(Error::from_str(
+            StatusCode::BadRequest,
+            "https URLs require the h1-client or h1-client-rustls backend",
+        ))
+    }
+}
+
+/// A connection, with or without TLS.
+pub(crate) trait Stream: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static {}
+
+impl<S: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static> Stream for S {}
+
+/// The connection `async-h1` reads and writes.
+///
+/// The response head is read into `head` before `async-h1` sees it, and kept in the slot, so the
+/// bytes of its header values are available as the server sent them. Bytes outside ASCII in
+/// header lines are replaced with `?` for `async-h1`, which panics on them.
+struct Lease {
+    stream: Option<Box<dyn Stream>>,
+    slot: Arc<Mutex<Slot>>,
+    /// Limits the response head, until its end has been read.
+    limit: Option<HeadLimit>,
+    /// The response head read so far, until its end has been read.
+    head: Option<Vec<u8>>,
+    /// The bytes read from `stream` which haven't been passed to `async-h1` yet.
+    pending: Vec<u8>,
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let mut slot = self.slot.lock().unwrap();
+        slot.stream = self.stream.take();
+        slot.release();
+    }
+}
+
+impl Lease {
+    fn new(stream: Box<dyn Stream>, slot: Arc<Mutex<Slot>>, config: &Config) -> Self {
+        Self {
+            stream: Some(stream),
+            slot,
+            limit: HeadLimit::new(config),
+            head: Some(Vec::new()),
+            pending: Vec::new(),
+        }
+    }
+
+    fn stream(&mut self) -> Pin<&mut dyn Stream> {
+        Pin::new(&mut **self.stream.as_mut().unwrap())
+    }
+
+    /// Add `read` to the head, passing the head on once its end has been read, or once it is
+    /// larger than `async-h1` accepts.
+    fn read_head(&mut self, read: &[u8]) {
+        let head = self.head.as_mut().unwrap();
+        // The end of the head may span reads.
+        let from = head.len().saturating_sub(3);
+        head.extend_from_slice(read);
+        let end = (from..head.len())
+            .find(|&at| head[..=at].ends_with(b"\n\n") || head[..=at].ends_with(b"\r\n\r\n"))
+            .map(|at| at + 1);
+        match end {
+            Some(end) => {
+                let mut head = self.head.take().unwrap();
+                self.pending = head.split_off(end);
+                let mut ascii = head.clone();
+                let status_line = ascii.iter().position(|&b| b == b'\n').unwrap_or(0);
+                for byte in &mut ascii[status_line..] {
+                    if !byte.is_ascii() {
+                        *byte = b'?';
+                    }
+                }
+                ascii.append(&mut self.pending);
+                self.pending = ascii;
+                self.slot.lock().unwrap().head = Some(head);
+            }
+            None if head.len() > MAX_HEAD_LENGTH => self.pending = self.head.take().unwrap(),
+            None => {}
+        }
+    }
+}
+
+/// The size of the largest response head `async-h1` accepts.
+const MAX_HEAD_LENGTH: usize = 8 * 1024;
+
+impl AsyncRead for Lease {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        loop {
+            if !self.pending.is_empty() {
+                let len = self.pending.len().min(buf.len());
+                buf[..len].copy_from_slice(&self.pending[..len]);
+                self.pending.drain(..len);
+                return Poll::Ready(Ok(len));
+            }
+            let read = futures_util::ready!(self.stream().poll_read(cx, buf))?;
+            if let Some(limit) = &mut self.limit {
+                match limit.scan(&buf[..read]) {
+                    Ok(false) => {}
+                    Ok(true) => self.limit = None,
+                    Err(exceeded) => {
+                        self.slot.lock().unwrap().exceeded = Some(exceeded);
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            exceeded,
+                        )));
+                    }
+                }
+            }
+            if self.head.is_none() {
+                return Poll::Ready(Ok(read));
+            }
+            if read == 0 {
+                // The connection closed before the end of the head, which `async-h1` reports.
+                self.pending = self.head.take().unwrap();
+                if self.pending.is_empty() {
+                    return Poll::Ready(Ok(0));
+                }
+                continue;
+            }
+            self.read_head(&buf[..read]);
+        }
+    }
+}
+
+/// Counts the headers of a response head as it is read, so a response with too many is refused
+/// before `async-h1` has buffered them.
+struct HeadLimit {
+    max_headers: Option<usize>,
+    max_bytes: Option<usize>,
+    /// Whether the status line has been read.
+    status_read: bool,
+    headers: usize,
+    /// The bytes of the header lines read, including the line breaks.
+    bytes: usize,
+    /// The bytes of the current line read so far.
+    line: usize,
+}
+
+impl HeadLimit {
+    /// The limits of `config`, if it has any.
+    fn new(config: &Config) -> Option<Self> {
+        if config.max_response_headers.is_none() && config.max_response_header_bytes.is_none() {
+            return None;
+        }
+        Some(Self {
+            max_headers: config.max_response_headers,
+            max_bytes: config.max_response_header_bytes,
+            status_read: false,
+            headers: 0,
+            bytes: 0,
+            line: 0,
+        })
+    }
+
+    /// Count the headers in `read`, returning whether the end of the head has been reached.
+    fn scan(&mut self, read: &[u8]) -> Result<bool, HeaderLimitExceeded> {
+        for &byte in read {
+            self.line += 1;
+            if byte == b'\n' {
+                if !self.status_read {
+                    self.status_read = true;
+                } else if self.line <= 2 {
+                    return Ok(true);
+                } else {
+                    self.headers += 1;
+                    self.bytes += self.line;
+                }
+                self.line = 0;
+            }
+            self.check()?;
+        }
+        Ok(false)
+    }
+
+    fn check(&self) -> Result<(), HeaderLimitExceeded> {
+        match (self.max_headers, self.max_bytes) {
+            (Some(limit), _) if self.headers > limit => Err(HeaderLimitExceeded::Count {
+                limit,
+                actual: self.headers,
+            }),
+            // The current line counts, so a single endless line is refused too.
+            (_, Some(limit)) if self.status_read && self.bytes + self.line > limit => {
+                Err(HeaderLimitExceeded::Bytes {
+                    limit,
+                    actual: self.bytes + self.line,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl AsyncWrite for Lease {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.stream().poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream().poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.stream().poll_close(cx)
+    }
+}
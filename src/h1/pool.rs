@@ -0,0 +1,263 @@
+//! The pools of connections the h1 transport keeps alive between requests.
+
+use super::Stream;
+use crate::utils::BodyReader;
+use crate::{HeaderLimitExceeded, IpVersion};
+
+use async_channel::{Receiver, Sender};
+use futures_util::future::{self, Either};
+use futures_util::io::AsyncRead;
+
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// Identifies the connections which requests can share.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct PoolKey {
+    pub(super) host: String,
+    pub(super) port: u16,
+    pub(super) addr: Option<SocketAddr>,
+    pub(super) ip_version: IpVersion,
+    pub(super) tls: Option<usize>,
+}
+
+/// The connections to a host, limited to `max_connections_per_host`.
+pub(super) struct Pool {
+    /// The connections which are free for another request.
+    idle: (Sender<Idle>, Receiver<Idle>),
+    /// Holds a message for each open connection, whether idle or in use.
+    open: (Sender<()>, Receiver<()>),
+}
+
+/// An idle connection of a pool.
+type Idle = (Box<dyn Stream>, Permit);
+
+/// Counts an open connection of a pool until dropped.
+pub(super) struct Permit(Receiver<()>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let _ = self.0.try_recv();
+    }
+}
+
+/// A connection of a pool, or the permit to open a new one.
+pub(super) enum Checkout {
+    Idle(Box<dyn Stream>, Permit),
+    New(Permit),
+}
+
+impl Pool {
+    pub(super) fn new(max: usize) -> Self {
+        Self {
+            idle: async_channel::unbounded(),
+            open: async_channel::bounded(max.max(1)),
+        }
+    }
+
+    /// Take an idle connection, or wait until one is idle or another may be opened.
+    pub(super) async fn checkout(&self) -> io::Result<Checkout> {
+        loop {
+            while let Ok((stream, permit)) = self.idle.1.try_recv() {
+                if let Some(stream) = alive(stream) {
+                    return Ok(Checkout::Idle(stream, permit));
+                }
+            }
+            let idle = self.idle.1.recv();
+            let open = self.open.0.send(());
+            futures_util::pin_mut!(idle, open);
+            match future::select(idle, open).await {
+                Either::Left((Ok((stream, permit)), _)) => {
+                    if let Some(stream) = alive(stream) {
+                        return Ok(Checkout::Idle(stream, permit));
+                    }
+                }
+                Either::Right((Ok(()), _)) => {
+                    return Ok(Checkout::New(Permit(self.open.1.clone())))
+                }
+                Either::Left((Err(_), _)) | Either::Right((Err(_), _)) => {
+                    return Err(io::Error::other("the connection pool was closed"))
+                }
+            }
+        }
+    }
+}
+
+/// `stream`, unless the server has closed it or sent something unasked while it was idle.
+fn alive(mut stream: Box<dyn Stream>) -> Option<Box<dyn Stream>> {
+    let mut cx = Context::from_waker(futures_util::task::noop_waker_ref());
+    match Pin::new(&mut stream).poll_read(&mut cx, &mut [0; 1]) {
+        Poll::Pending => Some(stream),
+        Poll::Ready(_) => None,
+    }
+}
+
+/// A connection in use, which goes back to its pool once `async-h1` has dropped it and the
+/// response body has been read.
+pub(super) struct Slot {
+    pub(super) stream: Option<Box<dyn Stream>>,
+    pub(super) permit: Option<Permit>,
+    /// The pool to return the connection to, unless it can't be reused.
+    pub(super) pool: Option<Arc<Pool>>,
+    pub(super) read: bool,
+    /// The limit the response head exceeded, if it did.
+    pub(super) exceeded: Option<HeaderLimitExceeded>,
+    /// The response head as it was read, once its end has been.
+    pub(super) head: Option<Vec<u8>>,
+}
+
+impl Slot {
+    /// Record that the response body has been read.
+    pub(super) fn finish(&mut self) {
+        self.read = true;
+        self.release();
+    }
+
+    pub(super) fn release(&mut self) {
+        if !self.read || self.stream.is_none() {
+            return;
+        }
+        if let (Some(pool), Some(stream), Some(permit)) =
+            (self.pool.take(), self.stream.take(), self.permit.take())
+        {
+            let _ = pool.idle.0.try_send((stream, permit));
+        }
+    }
+}
+
+/// A reader which marks the connection of a response reusable when its body has been read to
+/// the end.
+pub(super) struct ReleaseReader {
+    pub(super) inner: BodyReader,
+    /// The length of the rest of the body, if known, as readers of sized bodies stop there.
+    pub(super) remaining: Option<usize>,
+    pub(super) slot: Option<Arc<Mutex<Slot>>>,
+}
+
+impl AsyncRead for ReleaseReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let read = futures_util::ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(read);
+        }
+        if self.remaining == Some(0) || (read == 0 && !buf.is_empty()) {
+            if let Some(slot) = self.slot.take() {
+                slot.lock().unwrap().finish();
+            }
+        }
+        Poll::Ready(Ok(read))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use async_io::Async;
+    use futures_util::FutureExt;
+
+    use std::net::{TcpListener, TcpStream};
+
+    /// Both ends of a connection over loopback.
+    fn connection() -> (Box<dyn Stream>, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (Box::new(Async::new(client).unwrap()), server)
+    }
+
+    /// Return `stream` to `pool` as a connection whose response has been read.
+    fn release(pool: &Arc<Pool>, stream: Box<dyn Stream>, permit: Permit) {
+        let mut slot = Slot {
+            stream: Some(stream),
+            permit: Some(permit),
+            pool: Some(pool.clone()),
+            read: false,
+            exceeded: None,
+            head: None,
+        };
+        slot.finish();
+    }
+
+    #[test]
+    fn checkout_is_limited_by_open_connections() {
+        async_std::task::block_on(async {
+            let pool = Arc::new(Pool::new(1));
+            let permit = match pool.checkout().await.unwrap() {
+                Checkout::New(permit) => permit,
+                Checkout::Idle(..) => panic!("the pool has no idle connections"),
+            };
+            assert!(pool.checkout().now_or_never().is_none());
+            drop(permit);
+            assert!(matches!(
+                pool.checkout().now_or_never(),
+                Some(Ok(Checkout::New(_)))
+            ));
+        });
+    }
+
+    #[test]
+    fn checkout_reuses_idle_connections() {
+        async_std::task::block_on(async {
+            let pool = Arc::new(Pool::new(1));
+            let permit = match pool.checkout().await.unwrap() {
+                Checkout::New(permit) => permit,
+                Checkout::Idle(..) => panic!("the pool has no idle connections"),
+            };
+            let (stream, _server) = connection();
+            release(&pool, stream, permit);
+            assert!(matches!(pool.checkout().await.unwrap(), Checkout::Idle(..)));
+        });
+    }
+
+    #[test]
+    fn checkout_skips_closed_connections() {
+        async_std::task::block_on(async {
+            let pool = Arc::new(Pool::new(1));
+            let permit = match pool.checkout().await.unwrap() {
+                Checkout::New(permit) => permit,
+                Checkout::Idle(..) => panic!("the pool has no idle connections"),
+            };
+            let (stream, server) = connection();
+            release(&pool, stream, permit);
+            drop(server);
+            // The server's close takes a moment to arrive over loopback.
+            async_io::Timer::after(std::time::Duration::from_millis(50)).await;
+            assert!(matches!(pool.checkout().await.unwrap(), Checkout::New(_)));
+        });
+    }
+
+    #[test]
+    fn unread_responses_keep_their_connection_out_of_the_pool() {
+        async_std::task::block_on(async {
+            let pool = Arc::new(Pool::new(1));
+            let permit = match pool.checkout().await.unwrap() {
+                Checkout::New(permit) => permit,
+                Checkout::Idle(..) => panic!("the pool has no idle connections"),
+            };
+            let (stream, _server) = connection();
+            let mut slot = Slot {
+                stream: Some(stream),
+                permit: Some(permit),
+                pool: Some(pool.clone()),
+                read: false,
+                exceeded: None,
+                head: None,
+            };
+            slot.release();
+            assert!(slot.stream.is_some());
+            drop(slot);
+            assert!(matches!(
+                pool.checkout().now_or_never(),
+                Some(Ok(Checkout::New(_)))
+            ));
+        });
+    }
+}
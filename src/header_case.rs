@@ -0,0 +1,179 @@
+//! Spelling and ordering the request headers sent by the `h1-client` backends, which always
+//! send names in lowercase and sort them, by rewriting the head of each request as it is
+//! written.
+
+use crate::http::{self, headers::HeaderName};
+use crate::{Config, HeaderCase};
+
+use futures_util::io::{AsyncRead, AsyncWrite};
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// The names of the headers of a request, in the order they were first set.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HeaderOrder(Vec<HeaderName>);
+
+/// Record that `name` was set on `req`, unless it was already.
+pub(crate) fn record_order(req: &mut http::Request, name: &HeaderName) {
+    if req.ext().get::<HeaderOrder>().is_none() {
+        req.ext_mut().insert(HeaderOrder::default());
+    }
+    let HeaderOrder(names) = req.ext_mut().get_mut::<HeaderOrder>().unwrap();
+    if !names.contains(name) {
+        names.push(name.clone());
+    }
+}
+
+/// Whether requests sent with `config` have their head rewritten.
+pub(crate) fn rewrites(config: &Config) -> bool {
+    config.header_case != HeaderCase::Lower || config.header_order.is_some()
+}
+
+/// Spell `name` in `case`, with the registered `spellings`.
+fn spell(case: HeaderCase, name: &str, spellings: &[String]) -> String {
+    match case {
+        HeaderCase::Lower => name.to_ascii_lowercase(),
+        HeaderCase::Title => crate::utils::title_case(name),
+        HeaderCase::Preserve => spellings
+            .iter()
+            .find(|spelling| spelling.eq_ignore_ascii_case(name))
+            .cloned()
+            .unwrap_or_else(|| crate::utils::title_case(name)),
+    }
+}
+
+/// A stream which spells and orders the headers of the request head written to it, and passes
+/// the rest of the writes and all reads through.
+#[derive(Debug)]
+pub(crate) struct Recase<S> {
+    inner: S,
+    case: HeaderCase,
+    spellings: Vec<String>,
+    /// The names of the headers to write first, in order.
+    order: Vec<String>,
+    /// The start of the head, until its end has been written.
+    head: Option<Vec<u8>>,
+    /// The rewritten head and what followed it, which haven't been written to `inner` yet.
+    pending: Vec<u8>,
+}
+
+impl<S> Recase<S> {
+    /// Wrap `stream`, with the header case, spellings and order of `config`, for sending `req`.
+    pub(crate) fn new(stream: S, config: &Config, req: &http::Request) -> Self {
+        let mut order: Vec<String> = config.header_order.iter().flatten().cloned().collect();
+        order.push("host".to_string());
+        if let Some(HeaderOrder(names)) = req.ext().get::<HeaderOrder>() {
+            order.extend(names.iter().map(|name| name.as_str().to_string()));
+        }
+        Self {
+            inner: stream,
+            case: config.header_case,
+            spellings: config.header_spellings.clone(),
+            order,
+            head: Some(Vec::new()),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Where the header `name` goes, with the headers which aren't ordered last.
+    fn rank(&self, name: &str) -> usize {
+        self.order
+            .iter()
+            .position(|ordered| ordered.eq_ignore_ascii_case(name))
+            .unwrap_or(self.order.len())
+    }
+
+    /// Spell and order the headers of `head`, which ends with an empty line.
+    fn rewrite(&self, head: &[u8]) -> Vec<u8> {
+        let mut lines = head.split_inclusive(|&b| b == b'\n');
+        let mut rewritten = Vec::with_capacity(head.len());
+        rewritten.extend_from_slice(lines.next().unwrap_or_default());
+        let mut headers = Vec::new();
+        let mut rest = Vec::new();
+        for line in lines {
+            match line.iter().position(|&b| b == b':') {
+                Some(colon) => {
+                    let name = String::from_utf8_lossy(&line[..colon]).into_owned();
+                    headers.push((self.rank(&name), name, &line[colon..]));
+                }
+                None => rest.push(line),
+            }
+        }
+        // The sort is stable, so the values of a header and the unordered headers stay in the
+        // order they were written.
+        headers.sort_by_key(|(rank, _, _)| *rank);
+        for (_, name, line) in headers {
+            rewritten.extend_from_slice(spell(self.case, &name, &self.spellings).as_bytes());
+            rewritten.extend_from_slice(line);
+        }
+        for line in rest {
+            rewritten.extend_from_slice(line);
+        }
+        rewritten
+    }
+}
+
+impl<S: AsyncWrite + Unpin> Recase<S> {
+    /// Write the pending bytes to `inner`.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            match Pin::new(&mut self.inner).poll_write(cx, &self.pending) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(written)) => {
+                    self.pending.drain(..written);
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Recase<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Recase<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if let Some(head) = &mut this.head {
+            head.extend_from_slice(buf);
+            if let Some(end) = head.windows(4).position(|window| window == b"\r\n\r\n") {
+                let head = this.head.take().unwrap();
+                let (head, rest) = head.split_at(end + 4);
+                this.pending = this.rewrite(head);
+                this.pending.extend_from_slice(rest);
+            }
+            // The bytes are written on the next write or flush if `inner` isn't ready.
+            if let Poll::Ready(Err(err)) = this.poll_pending(cx) {
+                return Poll::Ready(Err(err));
+            }
+            return Poll::Ready(Ok(buf.len()));
+        }
+        futures_util::ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures_util::ready!(self.poll_pending(cx))?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures_util::ready!(self.poll_pending(cx))?;
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
@@ -0,0 +1,193 @@
+//! Recording of recent requests, for debugging.
+
+#[cfg(feature = "gzip")]
+use crate::compress::Decoder;
+use crate::http::{Body, Method, StatusCode, Url};
+use crate::utils::{map_body_reader, BodyReader};
+
+use futures_util::io::AsyncRead;
+
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// The maximum number of body bytes kept per recorded request or response.
+pub(crate) const BODY_PREVIEW_LIMIT: usize = 1024;
+
+/// A summary of a request sent by a `Client`, and of its response.
+///
+/// Returned from [`Client::history`](crate::Client::history).
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    /// The request method.
+    pub method: Method,
+    /// The request URL.
+    pub url: Url,
+    /// The response status, if a response was received.
+    pub status: Option<StatusCode>,
+    /// The error the request failed with, if any.
+    pub error: Option<String>,
+    /// The time it took to receive the response head.
+    pub elapsed: Duration,
+    /// The first bytes of the request body which were sent.
+    pub request_body: Vec<u8>,
+    /// The first bytes of the response body which have been read so far.
+    pub response_body: Vec<u8>,
+    /// The `Content-Encoding` of the request body, if any.
+    pub request_encoding: Option<String>,
+    /// The `Content-Encoding` of the response body, if any.
+    pub response_encoding: Option<String>,
+    /// Whether `request_body` holds the decoded bytes of a body in `request_encoding`.
+    ///
+    /// Bodies compressed with gzip or deflate are decoded with the `gzip` feature, and the
+    /// bytes of other codings are recorded as they were sent.
+    pub request_body_decoded: bool,
+    /// Whether `response_body` holds the decoded bytes of a body in `response_encoding`.
+    pub response_body_decoded: bool,
+}
+
+/// A bounded list of the most recent requests.
+#[derive(Debug)]
+pub(crate) struct History {
+    capacity: usize,
+    entries: Mutex<VecDeque<Arc<Mutex<HistoryEntry>>>>,
+}
+
+impl History {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Start recording a request, evicting the oldest entry if needed.
+    pub(crate) fn record(&self, method: Method, url: Url) -> Arc<Mutex<HistoryEntry>> {
+        let entry = Arc::new(Mutex::new(HistoryEntry {
+            method,
+            url,
+            status: None,
+            error: None,
+            elapsed: Duration::default(),
+            request_body: vec![],
+            response_body: vec![],
+            request_encoding: None,
+            response_encoding: None,
+            request_body_decoded: false,
+            response_body_decoded: false,
+        }));
+
+        let mut entries = self.entries.lock().unwrap();
+        if self.capacity > 0 {
+            if entries.len() == self.capacity {
+                entries.pop_front();
+            }
+            entries.push_back(entry.clone());
+        }
+        entry
+    }
+
+    pub(crate) fn entries(&self) -> Vec<HistoryEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .iter()
+            .map(|entry| entry.lock().unwrap().clone())
+            .collect()
+    }
+}
+
+/// Which body of an entry is being captured.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Capture {
+    Request,
+    Response,
+}
+
+/// Wrap a body so that the first bytes read from it are copied into `entry`, decoded if it
+/// has the content coding recorded in `entry`.
+pub(crate) fn capture_body(body: Body, entry: Arc<Mutex<HistoryEntry>>, capture: Capture) -> Body {
+    let len = body.len();
+    // There is nothing to capture from an empty body, so it isn't wrapped.
+    if len == Some(0) {
+        return body;
+    }
+    #[cfg(feature = "gzip")]
+    let decoder = {
+        let mut recorded = entry.lock().unwrap();
+        let recorded = &mut *recorded;
+        let (encoding, decoded) = match capture {
+            Capture::Request => (
+                &recorded.request_encoding,
+                &mut recorded.request_body_decoded,
+            ),
+            Capture::Response => (
+                &recorded.response_encoding,
+                &mut recorded.response_body_decoded,
+            ),
+        };
+        let decoder = encoding.as_deref().and_then(Decoder::new);
+        *decoded = decoder.is_some();
+        decoder
+    };
+    map_body_reader(body, len, |inner| CapturingReader {
+        inner,
+        entry,
+        capture,
+        #[cfg(feature = "gzip")]
+        decoding: decoder.is_some(),
+        #[cfg(feature = "gzip")]
+        decoder,
+    })
+}
+
+struct CapturingReader {
+    inner: BodyReader,
+    entry: Arc<Mutex<HistoryEntry>>,
+    capture: Capture,
+    /// Whether the captured bytes are decoded.
+    #[cfg(feature = "gzip")]
+    decoding: bool,
+    /// The decoder of the captured bytes, until the preview is full or they fail to decode.
+    #[cfg(feature = "gzip")]
+    decoder: Option<Decoder>,
+}
+
+impl AsyncRead for CapturingReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let read = match Pin::new(&mut self.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(read)) => read,
+            other => return other,
+        };
+
+        let this = &mut *self;
+        let mut entry = this.entry.lock().unwrap();
+        let preview = match this.capture {
+            Capture::Request => &mut entry.request_body,
+            Capture::Response => &mut entry.response_body,
+        };
+        #[cfg(feature = "gzip")]
+        if this.decoding {
+            // Only an empty read into an empty buffer doesn't mark the end of the body.
+            if let Some(decoder) = this.decoder.as_mut().filter(|_| !buf.is_empty()) {
+                // Bodies which fail to decode keep the bytes decoded until then.
+                let decoded = decoder.decode_into(&buf[..read], preview, BODY_PREVIEW_LIMIT);
+                if decoded.is_err() || preview.len() >= BODY_PREVIEW_LIMIT {
+                    this.decoder = None;
+                }
+            }
+            return Poll::Ready(Ok(read));
+        }
+        let remaining = BODY_PREVIEW_LIMIT.saturating_sub(preview.len());
+        preview.extend_from_slice(&buf[..read.min(remaining)]);
+
+        Poll::Ready(Ok(read))
+    }
+}
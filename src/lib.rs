@@ -86,9 +86,28 @@
 //! - __`h1-client`:__ use `async-h1` as the HTTP backend with native TLS for HTTPS.
 //! - __`h1-client-rustls`:__ use `async-h1` as the HTTP backend with `rustls` for HTTPS.
 //! - __`hyper-client`:__ use `hyper` (hyper.rs) as the HTTP backend.
-//! - __`wasm-client`:__ use `window.fetch` as the HTTP backend.
+//! - __`wasm-client`:__ use the global `fetch` function as the HTTP backend. This works in browsers
+//!   and web workers, as well as in Deno, Cloudflare Workers, and Node.
 //! - __`middleware-logger` (default):__ enables logging requests and responses using a middleware.
-//! - __`encoding` (default):__ enables support for body encodings other than utf-8.
+//! - __`middleware-metrics`:__ enables `middleware::Metrics`, to record request metrics through the
+//!   `metrics` crate.
+//! - __`middleware-tracing`:__ enables `middleware::Tracing`, to create a `tracing` span per request
+//!   and propagate traces with the W3C Trace Context headers.
+//! - __`encryption`:__ enables `middleware::Encryption`, to encrypt request and response bodies
+//!   end-to-end with some hosts.
+//! - __`encoding` (default):__ enables support for body encodings other than utf-8, decoded as
+//!   bodies are read.
+//! - __`aws-sign`:__ enables AWS Signature Version 4 support, to sign requests and presign URLs.
+//! - __`download`:__ enables `RequestBuilder::download_to` and `Response::save_to`, to stream
+//!   bodies to files with progress reporting.
+//! - __`checksum`:__ enables `Client::download_verified`, to download files and verify their
+//!   checksum. This implies `download`.
+//! - __`gzip`:__ enables gzip and deflate compression through `RequestBuilder::compress`, and
+//!   decompression through `middleware::Decompress`, which only offers compiled-in codecs to
+//!   servers.
+//! - __`compression`:__ the same as `gzip`. surf has no brotli or zstd codecs, so `br` and
+//!   `zstd` are never offered to servers, and `RequestBuilder::compress` refuses them.
+//! - __`disk-cache`:__ enables `middleware::cache::DiskStore`, to keep cached responses on disk.
 
 #![deny(missing_debug_implementations, nonstandard_style)]
 #![warn(missing_docs, unreachable_pub, rust_2018_idioms)]
@@ -97,29 +116,153 @@
 #![doc(html_favicon_url = "https://yoshuawuyts.com/assets/http-rs/favicon.ico")]
 #![doc(html_logo_url = "https://yoshuawuyts.com/assets/http-rs/logo-rounded.png")]
 
+mod auth;
+#[cfg(feature = "curl-client")]
+mod backend_options;
+mod cache;
+#[cfg(feature = "encoding")]
+mod charset;
 mod client;
+mod client_builder;
+mod clock;
+#[cfg(feature = "gzip")]
+mod compress;
 mod config;
+#[cfg(not(target_arch = "wasm32"))]
+mod connection;
+#[cfg(feature = "curl-client")]
+mod curl;
+#[cfg(all(feature = "download", not(target_arch = "wasm32")))]
+mod download;
+mod error;
+mod events;
+mod forward_proxy;
+#[cfg(all(
+    any(
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ),
+    not(feature = "curl-client"),
+    not(target_arch = "wasm32")
+))]
+mod h1;
+#[cfg(all(
+    any(
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ),
+    not(target_arch = "wasm32")
+))]
+mod header_case;
+mod history;
+mod listing;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+mod multipart;
+#[cfg(not(target_arch = "wasm32"))]
+mod nonce;
+mod patch;
+mod problem;
+mod progress;
 mod request;
 mod request_builder;
+mod resolver;
+mod resource;
 mod response;
+mod rng;
+mod scoped_headers;
+mod shutdown;
+mod state;
+#[cfg(not(target_arch = "wasm32"))]
+mod timeout;
+#[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+mod tls;
+#[cfg(unix)]
+mod unix_socket;
 
+#[cfg_attr(feature = "docs", doc(cfg(feature = "aws-sign")))]
+#[cfg(feature = "aws-sign")]
+pub mod aws;
+pub mod endpoint;
 pub mod middleware;
+pub mod pagination;
+pub mod parse;
+pub mod proxy;
+pub mod testing;
 pub mod utils;
+#[cfg(feature = "wasm-client")]
+pub mod wasm;
 
 pub use http_types::{self as http, Body, Error, Status, StatusCode, Url};
 
 pub use http_client::HttpClient;
 
+pub use auth::Auth;
+#[cfg_attr(feature = "docs", doc(cfg(feature = "curl-client")))]
+#[cfg(feature = "curl-client")]
+pub use backend_options::{BackendOptions, Interface};
+pub use cache::CacheStatus;
+#[cfg_attr(feature = "docs", doc(cfg(feature = "encoding")))]
+#[cfg(feature = "encoding")]
+pub use charset::EncodeError;
 pub use client::Client;
-pub use config::Config;
+pub use client_builder::ClientBuilder;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use config::{Config, HeaderCase, HttpVersionPreference};
+#[cfg(not(target_arch = "wasm32"))]
+pub use connection::ConnectionInfo;
+#[cfg_attr(feature = "docs", doc(cfg(feature = "checksum")))]
+#[cfg(all(feature = "checksum", not(target_arch = "wasm32")))]
+pub use download::{Checksum, ChecksumMismatch};
+#[cfg_attr(feature = "docs", doc(cfg(feature = "download")))]
+#[cfg(all(feature = "download", not(target_arch = "wasm32")))]
+pub use download::{DownloadOptions, LengthMismatch};
+pub use error::{ConnectionError, ConnectionErrorKind, ErrorKind, MiddlewareError};
+pub use events::{ClientEvent, ClientEvents};
+pub use history::HistoryEntry;
+pub use listing::DirEntry;
+pub use multipart::{FormPart, Multipart};
+#[cfg(not(target_arch = "wasm32"))]
+pub use nonce::{Nonce, NoncePolicy, NonceProvider, UuidNonce};
+pub use patch::PatchOp;
+pub use problem::ProblemDetails;
 pub use request::Request;
-pub use request_builder::RequestBuilder;
-pub use response::{DecodeError, Response};
+pub use request_builder::{QueryArrayFormat, RequestBuilder};
+#[cfg_attr(
+    feature = "docs",
+    doc(cfg(any(
+        feature = "curl-client",
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    )))
+)]
+#[cfg(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "h1-client-no-tls"
+))]
+pub use resolver::IpVersion;
+pub use resolver::{DohResolver, Resolver};
+pub use resource::Resource;
+pub use response::{ApiError, ClockSkew, DecodeError, HeaderLimitExceeded, Response, StatusError};
+pub use rng::{OsRng, Rng, SeededRng};
+pub use scoped_headers::{with_headers, WithHeaders};
+pub use shutdown::{Shutdown, ShutdownToken};
+pub use state::ClientState;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "default-client")] {
         mod one_off;
-        pub use one_off::{connect, delete, get, head, options, patch, post, put, trace};
+        pub use one_off::{configure, connect, delete, get, head, options, patch, post, put, trace};
+
+        #[cfg(not(target_arch = "wasm32"))]
+        mod fetch;
+        #[cfg(not(target_arch = "wasm32"))]
+        pub use fetch::{fetch_json, FetchOptions};
 
         /// Construct a new `Client`, capable of sending `Request`s and running a middleware stack.
         ///
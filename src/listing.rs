@@ -0,0 +1,477 @@
+//! Listing the entries of remote directories, for [`Client::list`](crate::Client::list).
+
+use crate::http::conditional::LastModified;
+use crate::http::headers::LAST_MODIFIED;
+use crate::http::{self, Method, StatusCode, Url};
+use crate::utils::{percent_decode, percent_encode};
+use crate::{Client, Error, Result};
+
+use serde::Deserialize;
+
+use std::time::SystemTime;
+
+/// The properties asked for in `PROPFIND` requests.
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:propfind xmlns:D="DAV:">
+  <D:prop>
+    <D:resourcetype/>
+    <D:getcontentlength/>
+    <D:getlastmodified/>
+  </D:prop>
+</D:propfind>"#;
+
+/// An entry of a directory listing, returned from [`Client::list`](crate::Client::list).
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// The name of the entry, decoded, without a trailing `/`.
+    pub name: String,
+    /// The URL of the entry. The URLs of directories end with a `/`.
+    pub url: Url,
+    /// Whether the entry is a directory.
+    pub is_dir: bool,
+    /// The size of the entry in bytes, if the listing says.
+    pub size: Option<u64>,
+    /// When the entry was last modified, if the listing says.
+    pub modified: Option<SystemTime>,
+}
+
+impl DirEntry {
+    fn new(url: Url, is_dir: bool) -> Self {
+        let segment = url
+            .path()
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or_default();
+        let name = String::from_utf8_lossy(&percent_decode(segment)).into_owned();
+        Self {
+            name,
+            url,
+            is_dir,
+            size: None,
+            modified: None,
+        }
+    }
+}
+
+/// List the directory at `url`, trying WebDAV before the index of a plain web server.
+pub(crate) async fn list(client: &Client, mut url: Url) -> Result<Vec<DirEntry>> {
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+
+    let mut res = client
+        .request(Method::PropFind, url.as_str())
+        .header("Depth", "1")
+        .body_string(PROPFIND_BODY.to_string())
+        .content_type("application/xml; charset=utf-8")
+        .await?;
+    if res.status() == StatusCode::MultiStatus {
+        let body = res.body_string().await?;
+        return Ok(parse_multistatus(&url, &body));
+    }
+
+    let mut res = client
+        .get(url.as_str())
+        .header("Accept", "application/json, text/html;q=0.9, */*;q=0.1")
+        .await?
+        .error_for_status()
+        .await?;
+    let is_json = res
+        .content_type()
+        .map(|mime| mime.subtype() == "json" || mime.subtype().ends_with("+json"))
+        .unwrap_or(false);
+    let body = res.body_string().await?;
+    if is_json {
+        parse_autoindex_json(&url, &body)
+    } else {
+        Ok(parse_html_index(&url, &body))
+    }
+}
+
+/// Parse a WebDAV `207 Multi-Status` response to a `PROPFIND` request with depth 1.
+///
+/// Elements are matched by their local name, whatever the prefix of the `DAV:` namespace.
+fn parse_multistatus(dir: &Url, body: &str) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+    let mut current: Option<Response> = None;
+    let mut text = String::new();
+    for token in XmlTokens::new(body) {
+        match token {
+            XmlToken::Open(name) => {
+                text.clear();
+                match name {
+                    "response" => current = Some(Response::default()),
+                    "collection" => {
+                        if let Some(response) = &mut current {
+                            response.is_dir = true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            XmlToken::Text(chunk) => text.push_str(&chunk),
+            XmlToken::Close(name) => {
+                let value = text.trim();
+                if let Some(response) = &mut current {
+                    match name {
+                        "href" if response.href.is_none() => {
+                            response.href = Some(value.to_string())
+                        }
+                        "getcontentlength" => response.size = value.parse().ok(),
+                        "getlastmodified" => response.modified = parse_http_date(value),
+                        _ => {}
+                    }
+                }
+                if name == "response" {
+                    if let Some(entry) = current.take().and_then(|res| res.into_entry(dir)) {
+                        entries.push(entry);
+                    }
+                }
+                text.clear();
+            }
+        }
+    }
+    entries
+}
+
+/// The properties of a `<response>` element of a multistatus body.
+#[derive(Debug, Default)]
+struct Response {
+    href: Option<String>,
+    is_dir: bool,
+    size: Option<u64>,
+    modified: Option<SystemTime>,
+}
+
+impl Response {
+    /// The entry for this response, unless it describes the directory itself.
+    fn into_entry(self, dir: &Url) -> Option<DirEntry> {
+        let mut url = dir.join(self.href.as_deref()?).ok()?;
+        if self.is_dir && !url.path().ends_with('/') {
+            let path = format!("{}/", url.path());
+            url.set_path(&path);
+        }
+        if url.path().trim_end_matches('/') == dir.path().trim_end_matches('/') {
+            return None;
+        }
+        let mut entry = DirEntry::new(url, self.is_dir);
+        entry.size = self.size.filter(|_| !self.is_dir);
+        entry.modified = self.modified;
+        Some(entry)
+    }
+}
+
+/// An entry of the JSON index of nginx's `autoindex_format json`.
+#[derive(Debug, Deserialize)]
+struct AutoindexEntry {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    mtime: Option<String>,
+    size: Option<u64>,
+}
+
+/// Parse a JSON index, an array of entries with a `name` and a `type`.
+fn parse_autoindex_json(dir: &Url, body: &str) -> Result<Vec<DirEntry>> {
+    let index: Vec<AutoindexEntry> = serde_json::from_str(body)
+        .map_err(|err| Error::new(StatusCode::UnprocessableEntity, err))?;
+    let mut entries = Vec::with_capacity(index.len());
+    for item in index {
+        let is_dir = item.kind == "directory";
+        let mut href = percent_encode(item.name.as_bytes());
+        if is_dir {
+            href.push('/');
+        }
+        let url = dir.join(&href)?;
+        let mut entry = DirEntry::new(url, is_dir);
+        entry.name = item.name;
+        entry.size = item.size;
+        entry.modified = item.mtime.as_deref().and_then(parse_http_date);
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Parse an HTML index page, taking the links to direct children of the directory as entries.
+///
+/// This skips the links to parent directories, to other sites, and to the sorting options of
+/// Apache's `mod_autoindex`.
+fn parse_html_index(dir: &Url, body: &str) -> Vec<DirEntry> {
+    let mut entries: Vec<DirEntry> = Vec::new();
+    let lower = body.to_ascii_lowercase();
+    let mut rest = 0;
+    while let Some(start) = lower[rest..].find("<a ") {
+        let tag_start = rest + start;
+        let tag_end = match lower[tag_start..].find('>') {
+            Some(end) => tag_start + end,
+            None => break,
+        };
+        rest = tag_end;
+        let href = match attribute(&body[tag_start..tag_end], "href") {
+            Some(href) => decode_entities(href),
+            None => continue,
+        };
+        if href.starts_with('?') || href.starts_with('#') {
+            continue;
+        }
+        let mut url = match dir.join(&href) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+        url.set_query(None);
+        url.set_fragment(None);
+        if url.origin() != dir.origin() {
+            continue;
+        }
+        let child = match url.path().strip_prefix(dir.path()) {
+            Some(child) => child,
+            None => continue,
+        };
+        let name = child.trim_end_matches('/');
+        if name.is_empty() || name.contains('/') {
+            continue;
+        }
+        let is_dir = child.ends_with('/');
+        if entries.iter().all(|entry| entry.url != url) {
+            entries.push(DirEntry::new(url, is_dir));
+        }
+    }
+    entries
+}
+
+/// Find the value of attribute `name` in the start tag `tag`.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(found) = lower[from..].find(name) {
+        let start = from + found;
+        from = start + name.len();
+        let preceded = lower[..start].ends_with(|c: char| c.is_ascii_whitespace());
+        let after = lower[from..].trim_start();
+        if !preceded || !after.starts_with('=') {
+            continue;
+        }
+        let value = tag[tag.len() - after.len() + 1..].trim_start();
+        return Some(match value.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => {
+                let value = &value[1..];
+                &value[..value.find(quote).unwrap_or(value.len())]
+            }
+            _ => {
+                let end = value
+                    .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                    .unwrap_or(value.len());
+                &value[..end]
+            }
+        });
+    }
+    None
+}
+
+/// Parse an HTTP date, such as `Wed, 21 Oct 2015 07:28:00 GMT`.
+fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let mut headers = http::Response::new(StatusCode::Ok);
+    headers.insert_header(LAST_MODIFIED, date);
+    let modified = LastModified::from_headers(&headers).ok()??;
+    Some(modified.modified())
+}
+
+/// Replace the predefined entities and character references of XML and HTML.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) if end <= 10 => end,
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse()))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// A token of an XML document.
+#[derive(Debug, PartialEq)]
+enum XmlToken<'a> {
+    /// A start tag, or an empty-element tag, which is followed by its end tag. The name is
+    /// without its prefix.
+    Open(&'a str),
+    /// An end tag, with its name without its prefix.
+    Close(&'a str),
+    /// Character data, decoded.
+    Text(String),
+}
+
+/// A minimal XML tokenizer, which is enough for the well-formed documents WebDAV servers send.
+///
+/// Comments, processing instructions and declarations are skipped, and `CDATA` sections are
+/// returned as text.
+struct XmlTokens<'a> {
+    rest: &'a str,
+    pending_close: Option<&'a str>,
+}
+
+impl<'a> XmlTokens<'a> {
+    fn new(document: &'a str) -> Self {
+        Self {
+            rest: document,
+            pending_close: None,
+        }
+    }
+}
+
+impl<'a> Iterator for XmlTokens<'a> {
+    type Item = XmlToken<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(name) = self.pending_close.take() {
+            return Some(XmlToken::Close(name));
+        }
+        loop {
+            if self.rest.is_empty() {
+                return None;
+            }
+            if !self.rest.starts_with('<') {
+                let end = self.rest.find('<').unwrap_or(self.rest.len());
+                let text = decode_entities(&self.rest[..end]);
+                self.rest = &self.rest[end..];
+                return Some(XmlToken::Text(text));
+            }
+            if let Some(rest) = self.rest.strip_prefix("<![CDATA[") {
+                let end = rest.find("]]>").unwrap_or(rest.len());
+                let text = rest[..end].to_string();
+                self.rest = rest.get(end + 3..).unwrap_or_default();
+                return Some(XmlToken::Text(text));
+            }
+            if let Some(rest) = self.rest.strip_prefix("<!--") {
+                let end = rest.find("-->").map(|end| end + 3).unwrap_or(rest.len());
+                self.rest = &rest[end..];
+                continue;
+            }
+
+            let end = self.rest.find('>').unwrap_or(self.rest.len());
+            let tag = &self.rest[1..end];
+            self.rest = self.rest.get(end + 1..).unwrap_or_default();
+            if tag.starts_with('?') || tag.starts_with('!') {
+                continue;
+            }
+            let (closing, tag) = match tag.strip_prefix('/') {
+                Some(tag) => (true, tag),
+                None => (false, tag),
+            };
+            let empty = tag.ends_with('/');
+            let name = tag
+                .trim_end_matches('/')
+                .split(|c: char| c.is_ascii_whitespace())
+                .next()
+                .unwrap_or_default();
+            let name = name.rsplit(':').next().unwrap_or_default();
+            if closing {
+                return Some(XmlToken::Close(name));
+            }
+            if empty {
+                self.pending_close = Some(name);
+            }
+            return Some(XmlToken::Open(name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir() -> Url {
+        Url::parse("https://files.example.org/pub/").unwrap()
+    }
+
+    #[test]
+    fn multistatus_entries() {
+        let body = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response>
+    <d:href>/pub/</d:href>
+    <d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/pub/release%20notes.txt</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype/>
+        <d:getcontentlength>42</d:getcontentlength>
+        <d:getlastmodified>Wed, 21 Oct 2015 07:28:00 GMT</d:getlastmodified>
+      </d:prop>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>https://files.example.org/pub/old</d:href>
+    <d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat>
+  </d:response>
+</d:multistatus>"#;
+        let entries = parse_multistatus(&dir(), body);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "release notes.txt");
+        assert!(!entries[0].is_dir);
+        assert_eq!(entries[0].size, Some(42));
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_445_412_480);
+        assert_eq!(entries[0].modified, Some(modified));
+        assert_eq!(entries[1].name, "old");
+        assert!(entries[1].is_dir);
+        assert_eq!(
+            entries[1].url.as_str(),
+            "https://files.example.org/pub/old/"
+        );
+    }
+
+    #[test]
+    fn html_index_entries() {
+        let body = r#"<html><body><h1>Index of /pub</h1>
+<a href="?C=N;O=D">Name</a>
+<a href="/">Parent Directory</a>
+<a href="../">../</a>
+<a href='docs/'>docs/</a>
+<A HREF="a&amp;b.tar.gz">a&amp;b.tar.gz</A>
+<a href="https://elsewhere.example.org/pub/x">x</a>
+<a href="docs/">docs/</a>
+</body></html>"#;
+        let entries = parse_html_index(&dir(), body);
+        let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["docs", "a&b.tar.gz"]);
+        assert!(entries[0].is_dir);
+        assert!(!entries[1].is_dir);
+    }
+}
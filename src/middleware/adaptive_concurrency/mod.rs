@@ -0,0 +1,277 @@
+//! Adaptive per-host concurrency limiting.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! use surf::middleware::AdaptiveConcurrency;
+//!
+//! let client = surf::client().with(AdaptiveConcurrency::new());
+//! let ids = 0..500;
+//! for id in ids {
+//!     let url = format!("https://httpbin.org/anything/{}", id);
+//!     client.get(url).await?;
+//! }
+//! # Ok(()) }
+//! ```
+
+use crate::http::{StatusCode, Url};
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::{Client, Result};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+/// A middleware which limits the number of in-flight requests per host, adapting the limit to
+/// how the upstream is coping.
+///
+/// The limit is tuned using AIMD (additive increase, multiplicative decrease): every response
+/// that arrives in time raises the limit of its host by roughly one request per round trip, while
+/// a `429 Too Many Requests`, a `503 Service Unavailable`, or a response slower than the
+/// configured latency threshold multiplies it by the backoff factor. Requests over the limit are
+/// queued until a slot frees up.
+///
+/// Cloning an `AdaptiveConcurrency` shares its state, so a clone can be kept around to inspect
+/// the current limits.
+#[derive(Debug, Clone)]
+pub struct AdaptiveConcurrency {
+    initial_limit: usize,
+    min_limit: usize,
+    max_limit: usize,
+    backoff_factor: f64,
+    latency_threshold: Option<Duration>,
+    hosts: Arc<Mutex<HashMap<String, Arc<HostLimiter>>>>,
+}
+
+impl AdaptiveConcurrency {
+    /// Create a new instance.
+    ///
+    /// Each host starts out with a limit of 4 concurrent requests, which is allowed to move
+    /// between 1 and 256. The limit is halved on overload, and latency is not taken into account.
+    pub fn new() -> Self {
+        Self {
+            initial_limit: 4,
+            min_limit: 1,
+            max_limit: 256,
+            backoff_factor: 0.5,
+            latency_threshold: None,
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Set the concurrency limit new hosts start out with.
+    pub fn initial_limit(mut self, limit: usize) -> Self {
+        self.initial_limit = limit.max(1);
+        self
+    }
+
+    /// Set the bounds the concurrency limit is allowed to move between.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `min` is zero or greater than `max`.
+    pub fn limits(mut self, min: usize, max: usize) -> Self {
+        assert!(min > 0, "the minimum concurrency limit must be at least 1");
+        assert!(
+            min <= max,
+            "the minimum concurrency limit must not exceed the maximum"
+        );
+        self.min_limit = min;
+        self.max_limit = max;
+        self
+    }
+
+    /// Set the factor the limit is multiplied by when a host signals overload.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `factor` is not between `0.0` and `1.0` (exclusive).
+    pub fn backoff_factor(mut self, factor: f64) -> Self {
+        assert!(
+            factor > 0.0 && factor < 1.0,
+            "the backoff factor must be between 0.0 and 1.0"
+        );
+        self.backoff_factor = factor;
+        self
+    }
+
+    /// Treat responses slower than `threshold`, as measured by the client's
+    /// [clock](crate::Config::set_clock), as a sign of overload.
+    pub fn latency_threshold(mut self, threshold: Duration) -> Self {
+        self.latency_threshold = Some(threshold);
+        self
+    }
+
+    /// Get the current concurrency limit for the host of `url`, if any request has been made to
+    /// it yet.
+    pub fn current_limit(&self, url: &Url) -> Option<usize> {
+        let hosts = self.hosts.lock().unwrap();
+        hosts
+            .get(&host_key(url))
+            .map(|limiter| limiter.state.lock().unwrap().limit as usize)
+    }
+
+    fn limiter(&self, url: &Url) -> Arc<HostLimiter> {
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts
+            .entry(host_key(url))
+            .or_insert_with(|| {
+                let limit = self.initial_limit.max(self.min_limit).min(self.max_limit);
+                Arc::new(HostLimiter::new(limit as f64))
+            })
+            .clone()
+    }
+
+    fn is_overloaded(&self, status: StatusCode, elapsed: Duration) -> bool {
+        status == StatusCode::TooManyRequests
+            || status == StatusCode::ServiceUnavailable
+            || matches!(self.latency_threshold, Some(threshold) if elapsed > threshold)
+    }
+}
+
+impl Default for AdaptiveConcurrency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for AdaptiveConcurrency {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let limiter = self.limiter(req.url());
+        let _permit = Acquire {
+            limiter: &limiter,
+            waiter: None,
+        }
+        .await;
+
+        let clock = client.config().clock.clone();
+        let start = clock.now();
+        let res = next.run(req, client).await?;
+        let elapsed = clock.now().saturating_duration_since(start);
+
+        let (min, max) = (self.min_limit as f64, self.max_limit as f64);
+        let mut state = limiter.state.lock().unwrap();
+        if self.is_overloaded(res.status(), elapsed) {
+            state.limit = (state.limit * self.backoff_factor).max(min);
+        } else {
+            state.limit = (state.limit + 1.0 / state.limit).min(max);
+            state.wake_all();
+        }
+
+        Ok(res)
+    }
+}
+
+/// Identify a host by its scheme, name and port.
+fn host_key(url: &Url) -> String {
+    format!(
+        "{}://{}:{}",
+        url.scheme(),
+        url.host_str().unwrap_or_default(),
+        url.port_or_known_default().unwrap_or_default()
+    )
+}
+
+#[derive(Debug)]
+struct HostLimiter {
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    limit: f64,
+    in_flight: usize,
+    /// The wakers of the queued requests, by the id of their `Acquire`.
+    waiters: HashMap<u64, Waker>,
+    next_waiter: u64,
+}
+
+impl LimiterState {
+    fn wake_all(&mut self) {
+        for (_, waker) in self.waiters.drain() {
+            waker.wake();
+        }
+    }
+}
+
+impl HostLimiter {
+    fn new(limit: f64) -> Self {
+        Self {
+            state: Mutex::new(LimiterState {
+                limit,
+                in_flight: 0,
+                waiters: HashMap::new(),
+                next_waiter: 0,
+            }),
+        }
+    }
+}
+
+/// Wait for a free slot on a host.
+struct Acquire<'a> {
+    limiter: &'a HostLimiter,
+    /// The id of the waker slot of this request, once it has been queued.
+    waiter: Option<u64>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = Permit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let limiter = self.limiter;
+        let mut state = limiter.state.lock().unwrap();
+        if state.in_flight < state.limit as usize {
+            state.in_flight += 1;
+            if let Some(id) = self.waiter.take() {
+                state.waiters.remove(&id);
+            }
+            return Poll::Ready(Permit { limiter });
+        }
+
+        let id = match self.waiter {
+            Some(id) => id,
+            None => {
+                let id = state.next_waiter;
+                state.next_waiter += 1;
+                self.waiter = Some(id);
+                id
+            }
+        };
+        match state.waiters.get_mut(&id) {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            Some(waker) => *waker = cx.waker().clone(),
+            None => {
+                state.waiters.insert(id, cx.waker().clone());
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.waiter {
+            self.limiter.state.lock().unwrap().waiters.remove(&id);
+        }
+    }
+}
+
+/// An occupied slot, released on drop.
+struct Permit<'a> {
+    limiter: &'a HostLimiter,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        state.in_flight -= 1;
+        state.wake_all();
+    }
+}
@@ -0,0 +1,140 @@
+use super::{CacheStore, CachedResponse};
+use crate::http::StatusCode;
+use crate::Result;
+
+use async_std::fs;
+use serde_json::{json, Value};
+
+use std::convert::TryFrom;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A store keeping each response in a file of a directory, so they outlive the process.
+///
+/// Each file holds a line of JSON with the status, headers and key of the response, followed by
+/// its body. Files are replaced atomically, so concurrent clients can share a directory.
+///
+/// # Examples
+///
+/// ```no_run
+/// use surf::middleware::cache::{Cache, DiskStore};
+///
+/// let client = surf::client().with(Cache::with_store(DiskStore::new("./http-cache")));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DiskStore {
+    dir: PathBuf,
+}
+
+impl DiskStore {
+    /// Create a new instance, keeping responses in `dir`.
+    ///
+    /// The directory is created when the first response is stored.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        // FNV-1a, which unlike the std hasher is stable across Rust versions.
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        self.dir.join(format!("{:016x}", hash))
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheStore for DiskStore {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>> {
+        let data = match fs::read(self.path(key)).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let newline = data.iter().position(|&byte| byte == b'\n');
+        let (meta, body) = data.split_at(newline.ok_or_else(corrupt)?);
+        let meta: Value = serde_json::from_slice(meta)?;
+        // Another key with the same hash replaced the file.
+        if meta["key"] != key {
+            return Ok(None);
+        }
+
+        let status = meta["status"]
+            .as_u64()
+            .and_then(|status| u16::try_from(status).ok())
+            .and_then(|status| StatusCode::try_from(status).ok())
+            .ok_or_else(corrupt)?;
+        let pairs = |field: &str| -> Option<Vec<(String, Option<String>)>> {
+            meta[field]
+                .as_array()?
+                .iter()
+                .map(|pair| {
+                    let name = pair[0].as_str()?.to_string();
+                    Some((name, pair[1].as_str().map(str::to_string)))
+                })
+                .collect()
+        };
+        let headers = pairs("headers")
+            .and_then(|headers| {
+                headers
+                    .into_iter()
+                    .map(|(name, value)| Some((name, value?)))
+                    .collect()
+            })
+            .ok_or_else(corrupt)?;
+        let stored_at = meta["stored_at"].as_u64().ok_or_else(corrupt)?;
+        Ok(Some(CachedResponse {
+            status,
+            headers,
+            body: body[1..].to_vec(),
+            vary: pairs("vary").ok_or_else(corrupt)?,
+            stored_at: UNIX_EPOCH + Duration::from_millis(stored_at),
+        }))
+    }
+
+    async fn put(&self, key: &str, response: CachedResponse) -> Result<()> {
+        let stored_at = response
+            .stored_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let meta = json!({
+            "key": key,
+            "status": u16::from(response.status),
+            "headers": response.headers,
+            "vary": response.vary,
+            "stored_at": stored_at,
+        });
+        let mut data = serde_json::to_vec(&meta)?;
+        data.push(b'\n');
+        data.extend_from_slice(&response.body);
+
+        fs::create_dir_all(&self.dir).await?;
+        let path = self.path(key);
+        let tmp = path.with_extension(format!(
+            "{}.tmp",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos()
+        ));
+        fs::write(&tmp, data).await?;
+        if let Err(err) = fs::rename(&tmp, &path).await {
+            fs::remove_file(&tmp).await.ok();
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn corrupt() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupt cache entry")
+}
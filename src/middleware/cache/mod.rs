@@ -0,0 +1,801 @@
+//! HTTP response caching, as described in [RFC 7234].
+//!
+//! The [`Cache`] middleware acts as a private cache: it stores the responses to `GET` requests
+//! which allow it, serves them while they are fresh, and revalidates them with `If-None-Match`
+//! and `If-Modified-Since` once they are stale. Responses carry a [`CacheStatus`] extension
+//! telling how they were obtained.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use surf::middleware::cache::{Cache, MemoryStore};
+//! use surf::CacheStatus;
+//!
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! let client = surf::client().with(Cache::with_store(MemoryStore::new(1024)));
+//! let res = client.get("https://httpbin.org/cache/60").await?;
+//! assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Miss));
+//! let res = client.get("https://httpbin.org/cache/60").await?;
+//! assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Fresh));
+//! # Ok(()) }
+//! ```
+//!
+//! [RFC 7234]: https://tools.ietf.org/html/rfc7234
+
+use crate::error::is_dns_failure;
+use crate::http::cache::{Age, CacheDirective, Expires};
+use crate::http::conditional::LastModified;
+use crate::http::headers::{
+    HeaderName, Headers, AGE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION, VARY,
+};
+use crate::http::other::Date;
+use crate::http::{self, Method, StatusCode};
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::parse::cache_directives;
+use crate::utils::{map_body, strip_hop_by_hop_headers};
+use crate::{Body, CacheStatus, Client, Error, MiddlewareError, Result};
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::{Debug, Write};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "disk-cache")]
+mod disk;
+
+#[cfg(feature = "disk-cache")]
+pub use disk::DiskStore;
+
+/// The statuses whose responses may be stored.
+const CACHEABLE_STATUSES: &[StatusCode] = &[
+    StatusCode::Ok,
+    StatusCode::NonAuthoritativeInformation,
+    StatusCode::NoContent,
+    StatusCode::MultipleChoice,
+    StatusCode::MovedPermanently,
+    StatusCode::PermanentRedirect,
+    StatusCode::NotFound,
+    StatusCode::Gone,
+];
+
+/// How a request uses the cache, like the [cache modes of `fetch`].
+///
+/// A mode can be set for all requests with [`Cache::mode`], or for a single request with
+/// [`RequestBuilder::cache_mode`](crate::RequestBuilder::cache_mode).
+///
+/// [cache modes of `fetch`]: https://fetch.spec.whatwg.org/#concept-request-cache-mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Serve fresh responses from the cache, revalidate stale ones, and store new ones.
+    Default,
+    /// Bypass the cache entirely, without looking up or storing responses.
+    NoStore,
+    /// Always fetch from the network, but store the response.
+    Reload,
+    /// Serve any cached response, even a stale one, and only fetch if there is none.
+    ForceCache,
+    /// Serve any cached response, even a stale one, and fail with `504 Gateway Timeout` if
+    /// there is none.
+    OnlyIfCached,
+}
+
+/// Computes the key the response to a request is cached under.
+///
+/// Implemented for closures taking the request. The key of a request shouldn't depend on its
+/// method, as responses to unsafe methods invalidate the cached response under their own key.
+pub trait CacheKeyFn: Send + Sync + 'static {
+    /// The key to cache the response to `req` under.
+    fn key(&self, req: &Request) -> String;
+}
+
+impl<F> CacheKeyFn for F
+where
+    F: Fn(&Request) -> String + Send + Sync + 'static,
+{
+    fn key(&self, req: &Request) -> String {
+        (self)(req)
+    }
+}
+
+/// A [`CacheKeyFn`] which keys responses by the request URL and the values of some request
+/// headers, such as the id of an account, so their responses are cached apart.
+///
+/// The header values are hashed, so keys don't hold credentials such as `Authorization`
+/// verbatim. Digests are meant to keep values out of stores, not to resist guessing short ones.
+///
+/// # Examples
+///
+/// ```
+/// use surf::middleware::cache::{Cache, HeaderKey};
+///
+/// let cache = Cache::new().key_fn(HeaderKey::new().header("X-Account-Id"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HeaderKey {
+    headers: Vec<HeaderName>,
+}
+
+impl HeaderKey {
+    /// Create a new instance, which keys responses by the URL alone until headers are added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Include the values of the request header `name` in the key.
+    pub fn header(mut self, name: impl Into<HeaderName>) -> Self {
+        self.headers.push(name.into());
+        self
+    }
+}
+
+impl CacheKeyFn for HeaderKey {
+    fn key(&self, req: &Request) -> String {
+        let mut key = req.url().to_string();
+        for name in &self.headers {
+            match header_digest(req, name.as_str()) {
+                Some(digest) => write!(key, " {}={}", name, digest).unwrap(),
+                None => write!(key, " {}", name).unwrap(),
+            }
+        }
+        key
+    }
+}
+
+/// How the [`Cache`] handles the `Vary` header of responses, which names the request headers
+/// the response depends on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VaryStrategy {
+    /// Store digests of the values of the named headers, and only serve the response to
+    /// requests with the same values.
+    Match,
+    /// Serve responses regardless of the named headers, as when the [key](Cache::key_fn)
+    /// already includes them. Responses with `Vary: *` are still not stored.
+    Ignore,
+    /// Don't store responses with a `Vary` header.
+    NoStore,
+}
+
+/// A response kept by a [`CacheStore`].
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The headers of the response, in order, without hop-by-hop headers.
+    pub headers: Vec<(String, String)>,
+    /// The body of the response.
+    pub body: Vec<u8>,
+    /// The request headers named by the `Vary` header of the response, with digests of their
+    /// values, so stores don't hold credentials such as `Authorization` verbatim.
+    pub vary: Vec<(String, Option<String>)>,
+    /// When the response was generated, going by its `Date` and `Age` headers and the local
+    /// time it arrived.
+    pub stored_at: SystemTime,
+}
+
+impl CachedResponse {
+    fn headers(&self) -> Headers {
+        let mut res = http::Response::new(self.status);
+        for (name, value) in &self.headers {
+            res.append_header(name.as_str(), value.as_str());
+        }
+        res.as_ref().clone()
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .rev()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn age(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.stored_at).unwrap_or_default()
+    }
+
+    /// How long the response is fresh for, after it was generated.
+    fn freshness_lifetime(&self) -> Duration {
+        let headers = self.headers();
+        let mut max_age = None;
+        for directive in cache_directives(&headers) {
+            match directive {
+                CacheDirective::NoCache => return Duration::from_secs(0),
+                CacheDirective::MaxAge(age) => max_age = Some(age),
+                _ => {}
+            }
+        }
+        if let Some(max_age) = max_age {
+            return max_age;
+        }
+
+        let date = match Date::from_headers(&headers) {
+            Ok(Some(date)) => date.into(),
+            _ => self.stored_at,
+        };
+        if headers.get(crate::http::headers::EXPIRES).is_some() {
+            // Invalid dates, such as `0`, mean the response has already expired.
+            return match Expires::from_headers(&headers) {
+                Ok(Some(expires)) => expires
+                    .expiration()
+                    .duration_since(date)
+                    .unwrap_or_default(),
+                _ => Duration::from_secs(0),
+            };
+        }
+
+        // Without explicit freshness, use a tenth of the time since the last modification.
+        match LastModified::from_headers(&headers) {
+            Ok(Some(last_modified)) => {
+                date.duration_since(last_modified.modified())
+                    .unwrap_or_default()
+                    / 10
+            }
+            _ => Duration::from_secs(0),
+        }
+    }
+
+    /// Whether the response says how long it is fresh for, with `Cache-Control` or `Expires`.
+    fn has_explicit_freshness(&self) -> bool {
+        let headers = self.headers();
+        has_directive(&headers, |d| {
+            matches!(d, CacheDirective::NoCache | CacheDirective::MaxAge(_))
+        }) || headers.get(crate::http::headers::EXPIRES).is_some()
+    }
+
+    /// Whether the request headers named by `Vary` are the same as in `req`.
+    fn matches(&self, req: &Request) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| header_digest(req, name) == *value)
+    }
+
+    fn to_response(&self, now: SystemTime, status: CacheStatus) -> Response {
+        let mut res = http::Response::new(self.status);
+        for (name, value) in &self.headers {
+            res.append_header(name.as_str(), value.as_str());
+        }
+        Age::new(self.age(now)).apply(&mut res);
+        map_body(&mut res, |_| Body::from_bytes(self.body.clone()));
+        let mut res = Response::from(res);
+        res.insert_ext(status);
+        res
+    }
+}
+
+/// Where the [`Cache`] middleware keeps responses.
+///
+/// Implement this to keep responses in a shared or persistent store. Errors of the store are
+/// logged, and the request proceeds as if nothing was cached.
+#[async_trait::async_trait]
+pub trait CacheStore: Debug + Send + Sync + 'static {
+    /// The response stored for `key`, if any.
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>>;
+
+    /// Store `response` for `key`, replacing any previous one.
+    async fn put(&self, key: &str, response: CachedResponse) -> Result<()>;
+
+    /// Remove the response stored for `key`, if any.
+    async fn remove(&self, key: &str) -> Result<()>;
+}
+
+/// An in-memory store, which drops the least recently used responses once it is full.
+#[derive(Debug)]
+pub struct MemoryStore {
+    capacity: usize,
+    entries: Mutex<LruEntries>,
+}
+
+#[derive(Debug, Default)]
+struct LruEntries {
+    entries: HashMap<String, (Arc<CachedResponse>, u64)>,
+    tick: u64,
+}
+
+impl MemoryStore {
+    /// Create a new instance, which keeps up to `capacity` responses.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    /// Create a new instance, which keeps up to 256 responses.
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+#[async_trait::async_trait]
+impl CacheStore for MemoryStore {
+    async fn get(&self, key: &str) -> Result<Option<CachedResponse>> {
+        let mut lru = self.entries.lock().unwrap();
+        lru.tick += 1;
+        let tick = lru.tick;
+        Ok(lru.entries.get_mut(key).map(|(response, used)| {
+            *used = tick;
+            CachedResponse::clone(response)
+        }))
+    }
+
+    async fn put(&self, key: &str, response: CachedResponse) -> Result<()> {
+        let mut lru = self.entries.lock().unwrap();
+        lru.tick += 1;
+        let tick = lru.tick;
+        if lru.entries.len() >= self.capacity && !lru.entries.contains_key(key) {
+            let oldest = lru
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                lru.entries.remove(&oldest);
+            }
+        }
+        if self.capacity > 0 {
+            lru.entries
+                .insert(key.to_string(), (Arc::new(response), tick));
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<()> {
+        self.entries.lock().unwrap().entries.remove(key);
+        Ok(())
+    }
+}
+
+/// A middleware which caches responses, as described in [RFC 7234].
+///
+/// Only `GET` requests are answered from the cache. Successful responses to other methods
+/// invalidate the cached response for their URL. Requests with a `Range` header bypass the
+/// cache, unless [partial responses](Cache::partial_responses) are cached. Time is measured with the client's
+/// [clock](crate::Config::set_clock).
+///
+/// Responses are cached under their URL, unless a [key function](Cache::key_fn) includes more
+/// of the request, such as the account it is made for.
+///
+/// # Examples
+///
+/// ```
+/// use surf::middleware::cache::{Cache, CacheMode};
+///
+/// let cache = Cache::new().mode(CacheMode::ForceCache);
+/// let client = surf::client().with(cache);
+/// ```
+///
+/// [RFC 7234]: https://tools.ietf.org/html/rfc7234
+pub struct Cache {
+    store: Arc<dyn CacheStore>,
+    mode: CacheMode,
+    key_fn: Arc<dyn CacheKeyFn>,
+    vary: VaryStrategy,
+    negative_ttl: Option<Duration>,
+    dns_failure_ttl: Option<Duration>,
+    partial_responses: bool,
+    /// When the failures to resolve each host stop being remembered.
+    dns_failures: Mutex<HashMap<String, SystemTime>>,
+}
+
+impl Cache {
+    /// Create a new instance, keeping responses in a [`MemoryStore`] with the default capacity.
+    pub fn new() -> Self {
+        Self::with_store(MemoryStore::default())
+    }
+
+    /// Create a new instance, keeping responses in `store`.
+    pub fn with_store(store: impl CacheStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            mode: CacheMode::Default,
+            key_fn: Arc::new(|req: &Request| req.url().to_string()),
+            vary: VaryStrategy::Match,
+            negative_ttl: None,
+            dns_failure_ttl: None,
+            partial_responses: false,
+            dns_failures: Mutex::default(),
+        }
+    }
+
+    /// Set the mode of requests which don't set their own.
+    ///
+    /// Default: [`CacheMode::Default`].
+    pub fn mode(mut self, mode: CacheMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Compute the keys responses are cached under with `key_fn`, such as a [`HeaderKey`].
+    ///
+    /// Default: the request URL.
+    pub fn key_fn(mut self, key_fn: impl CacheKeyFn) -> Self {
+        self.key_fn = Arc::new(key_fn);
+        self
+    }
+
+    /// Set how the `Vary` header of responses is handled.
+    ///
+    /// Default: [`VaryStrategy::Match`].
+    pub fn vary(mut self, strategy: VaryStrategy) -> Self {
+        self.vary = strategy;
+        self
+    }
+
+    /// Keep `404 Not Found` and `410 Gone` responses which don't say how long they are fresh
+    /// for, and serve them for `ttl`, so resources known to be absent aren't requested over
+    /// and over.
+    ///
+    /// Responses with a `Cache-Control` or `Expires` header are cached as they say.
+    ///
+    /// Default: such responses aren't cached.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use surf::middleware::cache::Cache;
+    ///
+    /// let cache = Cache::new().negative_ttl(Duration::from_secs(30));
+    /// ```
+    pub fn negative_ttl(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = Some(ttl);
+        self
+    }
+
+    /// Remember failures to resolve the name of a host for `ttl`, and fail requests to the
+    /// host during that time without sending them.
+    ///
+    /// This applies to requests of all methods. Remembered failures are kept in memory, and
+    /// not in the store.
+    ///
+    /// Default: failures aren't remembered.
+    pub fn dns_failure_ttl(mut self, ttl: Duration) -> Self {
+        self.dns_failure_ttl = Some(ttl);
+        self
+    }
+
+    /// Cache the responses to requests with a `Range` header, including `206 Partial Content`
+    /// ones, under a key which includes the range, so they are only served to requests for the
+    /// same range.
+    ///
+    /// Default: requests with a `Range` header bypass the cache, so they are never answered
+    /// with a stored response to another range or to the whole resource.
+    pub fn partial_responses(mut self, partial_responses: bool) -> Self {
+        self.partial_responses = partial_responses;
+        self
+    }
+
+    /// How long `entry` is fresh for, after it was generated.
+    fn freshness_lifetime(&self, entry: &CachedResponse) -> Duration {
+        match self.negative_ttl {
+            Some(ttl)
+                if matches!(entry.status, StatusCode::NotFound | StatusCode::Gone)
+                    && !entry.has_explicit_freshness() =>
+            {
+                ttl
+            }
+            _ => entry.freshness_lifetime(),
+        }
+    }
+
+    fn is_fresh(&self, entry: &CachedResponse, now: SystemTime) -> bool {
+        entry.age(now) < self.freshness_lifetime(entry)
+    }
+
+    async fn lookup(&self, key: &str, req: &Request) -> Option<CachedResponse> {
+        match self.store.get(key).await {
+            Ok(entry) => entry.filter(|entry| entry.matches(req)),
+            Err(err) => {
+                log::warn!("failed to read the cached response for {}: {}", key, err);
+                None
+            }
+        }
+    }
+
+    async fn put(&self, key: &str, entry: CachedResponse) {
+        if let Err(err) = self.store.put(key, entry).await {
+            log::warn!("failed to cache the response for {}: {}", key, err);
+        }
+    }
+
+    async fn remove(&self, key: &str) {
+        if let Err(err) = self.store.remove(key).await {
+            log::warn!("failed to remove the cached response for {}: {}", key, err);
+        }
+    }
+
+    /// Store `res` if it allows that, reading its body, and return it. `partial` is whether it
+    /// is a `206 Partial Content` response which may be stored.
+    async fn store(
+        &self,
+        key: &str,
+        req: &Request,
+        mut res: Response,
+        partial: bool,
+        now: SystemTime,
+    ) -> Result<Response> {
+        let cacheable = (partial || CACHEABLE_STATUSES.contains(&res.status()))
+            && !has_directive(req, |d| matches!(d, CacheDirective::NoStore))
+            && !has_directive(&res, |d| matches!(d, CacheDirective::NoStore));
+        let vary = match vary(req, &res, self.vary) {
+            Some(vary) if cacheable => vary,
+            _ => {
+                self.remove(key).await;
+                return Ok(res);
+            }
+        };
+
+        let body = res.body_bytes().await?;
+        let entry = CachedResponse {
+            status: res.status(),
+            headers: stored_headers(&res),
+            body: body.clone(),
+            vary,
+            stored_at: generated_at(&res, now),
+        };
+        let validated =
+            entry.header(ETAG.as_str()).is_some() || entry.header(LAST_MODIFIED.as_str()).is_some();
+        if validated || self.freshness_lifetime(&entry) > Duration::from_secs(0) {
+            self.put(key, entry).await;
+        } else {
+            self.remove(key).await;
+        }
+
+        res.map_body(|old| {
+            let mut body = Body::from_bytes(body);
+            body.set_mime(old.mime().clone());
+            body
+        });
+        Ok(res)
+    }
+}
+
+impl Debug for Cache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cache")
+            .field("store", &self.store)
+            .field("mode", &self.mode)
+            .field("vary", &self.vary)
+            .field("negative_ttl", &self.negative_ttl)
+            .field("dns_failure_ttl", &self.dns_failure_ttl)
+            .field("partial_responses", &self.partial_responses)
+            .finish()
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`Cache`] shared by several clients or requests, as a middleware.
+#[derive(Debug)]
+pub(crate) struct SharedCache(pub(crate) Arc<Cache>);
+
+#[async_trait::async_trait]
+impl Middleware for SharedCache {
+    async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        self.0.handle(req, client, next).await
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Cache {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let ttl = match self.dns_failure_ttl {
+            Some(ttl) => ttl,
+            None => return self.respond(req, client, next).await,
+        };
+        let host = req.url().host_str().unwrap_or_default().to_string();
+        let clock = client.config().clock.clone();
+        let failed_until = self.dns_failures.lock().unwrap().get(&host).copied();
+        match failed_until {
+            Some(until) if clock.system_now() < until => {
+                return Err(Error::new(
+                    StatusCode::BadGateway,
+                    MiddlewareError::new("cache", format!("resolving {} failed recently", host)),
+                ));
+            }
+            Some(_) => drop(self.dns_failures.lock().unwrap().remove(&host)),
+            None => {}
+        }
+
+        let res = self.respond(req, client, next).await;
+        if let Err(err) = &res {
+            if is_dns_failure(err) {
+                let until = clock.system_now() + ttl;
+                self.dns_failures.lock().unwrap().insert(host, until);
+            }
+        }
+        res
+    }
+}
+
+impl Cache {
+    async fn respond(&self, req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let mut key = self.key_fn.key(&req);
+        let clock = client.config().clock.clone();
+        let mut mode = req.ext::<CacheMode>().copied().unwrap_or(self.mode);
+        if has_directive(&req, |d| matches!(d, CacheDirective::OnlyIfCached)) {
+            mode = CacheMode::OnlyIfCached;
+        }
+        let range = req.header("Range").map(|values| values.to_string());
+        if let (Some(range), Method::Get) = (&range, req.method()) {
+            if !self.partial_responses {
+                return next.run(req, client).await;
+            }
+            write!(key, " range={}", range).unwrap();
+        }
+
+        if req.method() != Method::Get || mode == CacheMode::NoStore {
+            let method = req.method();
+            let url = req.url().clone();
+            let headers: Headers = AsRef::<Headers>::as_ref(&req).clone();
+            let res = next.run(req, client).await?;
+            if !method.is_safe() && (res.status().is_success() || res.status().is_redirection()) {
+                self.remove(&key).await;
+                if let Some(location) = res.header(LOCATION) {
+                    match url.join(location.last().as_str()) {
+                        Ok(location) if location.origin() == url.origin() => {
+                            // The key of a request for the location with the same headers.
+                            let mut target = Request::new(Method::Get, location);
+                            for (name, values) in headers.iter() {
+                                target.insert_header(name, values);
+                            }
+                            self.remove(&self.key_fn.key(&target)).await
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            return Ok(res);
+        }
+
+        let entry = match mode {
+            CacheMode::Reload => None,
+            _ => self.lookup(&key, &req).await,
+        };
+        if let Some(entry) = &entry {
+            let now = clock.system_now();
+            let usable = match mode {
+                CacheMode::ForceCache | CacheMode::OnlyIfCached => true,
+                _ => self.is_fresh(entry, now) && allows_reuse(&req, entry, now),
+            };
+            if usable {
+                return Ok(entry.to_response(now, CacheStatus::Fresh));
+            }
+        }
+        if mode == CacheMode::OnlyIfCached {
+            return Err(Error::new(
+                StatusCode::GatewayTimeout,
+                MiddlewareError::new("cache", format!("no cached response for {}", key)),
+            ));
+        }
+
+        let mut conditional = req.clone_with_ext();
+        if let Some(entry) = &entry {
+            if conditional.header(IF_NONE_MATCH).is_none()
+                && conditional.header(IF_MODIFIED_SINCE).is_none()
+            {
+                if let Some(etag) = entry.header(ETAG.as_str()) {
+                    conditional.insert_header(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = entry.header(LAST_MODIFIED.as_str()) {
+                    conditional.insert_header(IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+        }
+        let mut res = next.run(conditional, client).await?;
+        let now = clock.system_now();
+        match entry {
+            Some(mut entry) if res.status() == StatusCode::NotModified => {
+                let updated = stored_headers(&res);
+                entry
+                    .headers
+                    .retain(|(name, _)| !updated.iter().any(|(new, _)| new == name));
+                entry.headers.extend(updated);
+                entry.stored_at = generated_at(&res, now);
+                let revalidated = entry.to_response(now, CacheStatus::Revalidated);
+                if has_directive(&res, |d| matches!(d, CacheDirective::NoStore)) {
+                    self.remove(&key).await;
+                } else {
+                    self.put(&key, entry).await;
+                }
+                Ok(revalidated)
+            }
+            _ => {
+                res.insert_ext(CacheStatus::Miss);
+                let partial = range.is_some() && res.status() == StatusCode::PartialContent;
+                self.store(&key, &req, res, partial, now).await
+            }
+        }
+    }
+}
+
+/// Whether the `Cache-Control` header of a message has a directive matching `f`.
+fn has_directive(headers: impl AsRef<Headers>, f: impl Fn(&CacheDirective) -> bool) -> bool {
+    cache_directives(headers).iter().any(f)
+}
+
+/// Whether the `Cache-Control` header of `req` allows reusing the fresh `entry`.
+fn allows_reuse(req: &Request, entry: &CachedResponse, now: SystemTime) -> bool {
+    cache_directives(req)
+        .iter()
+        .all(|directive| match directive {
+            CacheDirective::NoCache => false,
+            CacheDirective::MaxAge(max_age) => entry.age(now) <= *max_age,
+            _ => true,
+        })
+}
+
+/// The request headers named by the `Vary` header of `res` with digests of their values, or
+/// `None` if the response can't be stored because of it.
+fn vary(
+    req: &Request,
+    res: &Response,
+    strategy: VaryStrategy,
+) -> Option<Vec<(String, Option<String>)>> {
+    let mut vary = Vec::new();
+    for values in res.header(VARY).iter().flat_map(|values| values.iter()) {
+        for name in values.as_str().split(',').map(str::trim) {
+            match name {
+                "" => {}
+                "*" => return None,
+                _ if strategy == VaryStrategy::NoStore => return None,
+                _ if strategy == VaryStrategy::Ignore => {}
+                name => {
+                    let name = name.to_ascii_lowercase();
+                    let digest = header_digest(req, &name);
+                    vary.push((name, digest));
+                }
+            }
+        }
+    }
+    Some(vary)
+}
+
+/// A digest of the values of the header `name` of `req`, if it has that header.
+fn header_digest(req: &Request, name: &str) -> Option<String> {
+    let value = req.header(name)?.to_string();
+    // Two 64-bit hashes, so distinct values practically never share a digest.
+    let half = |seed: u8| {
+        let mut hasher = DefaultHasher::new();
+        (seed, value.as_str()).hash(&mut hasher);
+        hasher.finish()
+    };
+    Some(format!("{:016x}{:016x}", half(0), half(1)))
+}
+
+/// The end-to-end headers of `res`.
+fn stored_headers(res: &Response) -> Vec<(String, String)> {
+    let mut headers: Headers = AsRef::<Headers>::as_ref(res).clone();
+    strip_hop_by_hop_headers(&mut headers);
+    headers.remove(AGE);
+    let mut stored = Vec::new();
+    for (name, values) in headers.iter() {
+        for value in values {
+            stored.push((name.as_str().to_string(), value.as_str().to_string()));
+        }
+    }
+    stored
+}
+
+/// When `res` was generated, corrected for its `Age` and the local time `now` it arrived.
+fn generated_at(res: &Response, now: SystemTime) -> SystemTime {
+    let apparent_age = match Date::from_headers(res) {
+        Ok(Some(date)) => now.duration_since(date.into()).unwrap_or_default(),
+        _ => Duration::from_secs(0),
+    };
+    let age = match Age::from_headers(res) {
+        Ok(Some(age)) => age.duration(),
+        _ => Duration::from_secs(0),
+    };
+    now.checked_sub(apparent_age.max(age)).unwrap_or(now)
+}
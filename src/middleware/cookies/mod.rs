@@ -0,0 +1,473 @@
+//! Cookie storage middleware.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! use surf::middleware::CookieJar;
+//!
+//! let jar = CookieJar::new();
+//! let client = surf::client().with(jar.clone());
+//! client.post("https://example.com/login").await?;
+//!
+//! // The session cookie set by the login is sent along.
+//! client.get("https://example.com/account").await?;
+//! std::fs::write("cookies.json", jar.export_json())?;
+//! # Ok(()) }
+//! ```
+//!
+//! Command line tools can keep their session across invocations with a [`JsonFileStore`]:
+//!
+//! ```no_run
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! use surf::middleware::{CookieJar, JsonFileStore};
+//!
+//! let jar = CookieJar::with_store(JsonFileStore::new("cookies.json"));
+//! jar.load()?;
+//! let client = surf::client().with(jar.clone());
+//! client.get("https://example.com/account").await?;
+//! jar.save()?;
+//! # Ok(()) }
+//! ```
+
+use crate::http::cookies::Cookie;
+use crate::http::headers::{COOKIE, SET_COOKIE};
+use crate::http::{StatusCode, Url};
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::{Client, Error, Result};
+
+use serde_json::{json, Value};
+
+use std::fmt::Debug;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A cookie kept in a [`CookieJar`].
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredCookie {
+    /// The name of the cookie.
+    pub name: String,
+    /// The value of the cookie.
+    pub value: String,
+    /// The domain the cookie is sent to.
+    pub domain: String,
+    /// Whether the cookie is only sent to `domain` itself, and not to its subdomains.
+    pub host_only: bool,
+    /// The path the cookie is sent to, including its subpaths.
+    pub path: String,
+    /// When the cookie expires, or `None` for a session cookie.
+    pub expires: Option<SystemTime>,
+    /// Whether the cookie is only sent over HTTPS.
+    pub secure: bool,
+    /// Whether the cookie is hidden from scripts. This doesn't affect surf.
+    pub http_only: bool,
+}
+
+impl StoredCookie {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        matches!(self.expires, Some(expires) if expires <= now)
+    }
+
+    fn matches(&self, url: &Url, now: SystemTime) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return false,
+        };
+        let domain_matches = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(&host, &self.domain)
+        };
+        domain_matches
+            && path_match(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+            && !self.is_expired(now)
+    }
+
+    fn to_json(&self) -> Value {
+        let expires = self.expires.map(|expires| {
+            expires
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default()
+        });
+        json!({
+            "name": self.name,
+            "value": self.value,
+            "domain": self.domain,
+            "host_only": self.host_only,
+            "path": self.path,
+            "expires": expires,
+            "secure": self.secure,
+            "http_only": self.http_only,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let string = |name: &str| value.get(name)?.as_str().map(str::to_string);
+        let flag = |name: &str| value.get(name).and_then(Value::as_bool).unwrap_or(false);
+        Some(Self {
+            name: string("name")?,
+            value: string("value")?,
+            domain: string("domain")?,
+            host_only: flag("host_only"),
+            path: string("path")?,
+            expires: value
+                .get("expires")
+                .and_then(Value::as_u64)
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            secure: flag("secure"),
+            http_only: flag("http_only"),
+        })
+    }
+}
+
+/// A middleware which stores the cookies set by responses, and sends them with later requests.
+///
+/// Cookies are kept per domain and path, and expire as their `Max-Age` and `Expires`
+/// attributes say. Clones of a jar share the same cookies, so a jar can be inspected, exported,
+/// and imported while a client uses it. A jar is also added to clients created from a
+/// [`Config`](crate::Config) with [`set_cookie_store`](crate::Config::set_cookie_store), and
+/// available from [`Client::cookie_jar`](crate::Client::cookie_jar).
+///
+/// Cookies set for public suffixes, such as `Domain=com`, are not rejected, as the jar doesn't
+/// know the public suffix list.
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    cookies: Arc<Mutex<Vec<StoredCookie>>>,
+    store: Option<Arc<dyn CookieStore>>,
+}
+
+impl CookieJar {
+    /// Create a new, empty instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new, empty instance, which [loads](CookieJar::load) cookies from and
+    /// [saves](CookieJar::save) them to `store`.
+    pub fn with_store(store: impl CookieStore) -> Self {
+        Self {
+            cookies: Arc::default(),
+            store: Some(Arc::new(store)),
+        }
+    }
+
+    /// Add the cookies from the store of the jar, as [`import_json`](CookieJar::import_json)
+    /// does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the jar has no store, or if the store fails to load the cookies.
+    pub fn load(&self) -> Result<()> {
+        let now = SystemTime::now();
+        for cookie in self.cookie_store()?.load()? {
+            if !cookie.is_expired(now) {
+                self.insert(cookie);
+            }
+        }
+        Ok(())
+    }
+
+    /// Save the cookies which haven't expired to the store of the jar, replacing the ones it
+    /// had.
+    ///
+    /// Session cookies are saved too, so a session lasts until the jar is
+    /// [cleared](CookieJar::clear).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the jar has no store, or if the store fails to save the cookies.
+    pub fn save(&self) -> Result<()> {
+        self.cookie_store()?.save(&self.cookies())
+    }
+
+    fn cookie_store(&self) -> Result<&dyn CookieStore> {
+        self.store.as_deref().ok_or_else(|| {
+            Error::from_str(
+                StatusCode::InternalServerError,
+                "the cookie jar has no store",
+            )
+        })
+    }
+
+    /// Store a cookie from a `Set-Cookie` header value, as if it was received from `url`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header can't be parsed, or if it sets a cookie for a domain
+    /// `url` doesn't belong to.
+    pub fn set_cookie(&self, set_cookie: &str, url: &Url) -> Result<()> {
+        let cookie = Cookie::parse(set_cookie.to_string())
+            .map_err(|err| Error::new(StatusCode::BadRequest, err))?;
+        self.store(cookie, url, SystemTime::now())
+    }
+
+    /// The value of the `Cookie` header to send to `url`, if any cookies match it.
+    pub fn cookie_header(&self, url: &Url) -> Option<String> {
+        let now = SystemTime::now();
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|cookie| !cookie.is_expired(now));
+        let mut matching: Vec<&StoredCookie> = cookies
+            .iter()
+            .filter(|cookie| cookie.matches(url, now))
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        // More specific paths go first; the sort is stable, so older cookies stay in front.
+        matching.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        let pairs: Vec<String> = matching
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+        Some(pairs.join("; "))
+    }
+
+    /// The cookies in the jar which haven't expired.
+    pub fn cookies(&self) -> Vec<StoredCookie> {
+        let now = SystemTime::now();
+        let cookies = self.cookies.lock().unwrap();
+        cookies
+            .iter()
+            .filter(|cookie| !cookie.is_expired(now))
+            .cloned()
+            .collect()
+    }
+
+    /// Remove all cookies.
+    pub fn clear(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    /// Export the cookies which haven't expired as JSON, to be loaded again with
+    /// [`import_json`](CookieJar::import_json).
+    pub fn export_json(&self) -> String {
+        cookies_to_json(&self.cookies())
+    }
+
+    /// Add the cookies from JSON created with [`export_json`](CookieJar::export_json).
+    ///
+    /// Imported cookies replace stored cookies with the same name, domain, and path. Cookies
+    /// which expired in the meantime are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `json` isn't a list of cookies.
+    pub fn import_json(&self, json: &str) -> Result<()> {
+        let now = SystemTime::now();
+        for cookie in cookies_from_json(json)? {
+            if !cookie.is_expired(now) {
+                self.insert(cookie);
+            }
+        }
+        Ok(())
+    }
+
+    fn store(&self, cookie: Cookie<'_>, url: &Url, now: SystemTime) -> Result<()> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| Error::from_str(StatusCode::BadRequest, "the URL has no host"))?
+            .to_ascii_lowercase();
+
+        let (domain, host_only) = match cookie.domain() {
+            Some(domain) if !domain.trim_start_matches('.').is_empty() => {
+                let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+                if !domain_match(&host, &domain) {
+                    return Err(Error::from_str(
+                        StatusCode::BadRequest,
+                        format!("{} can't set cookies for {}", host, domain),
+                    ));
+                }
+                (domain, false)
+            }
+            _ => (host, true),
+        };
+
+        let path = match cookie.path() {
+            Some(path) if path.starts_with('/') => path.to_string(),
+            _ => default_path(url.path()),
+        };
+
+        let expires = match (cookie.max_age(), cookie.expires()) {
+            (Some(max_age), _) => {
+                let secs = max_age.whole_seconds();
+                Some(if secs <= 0 {
+                    UNIX_EPOCH
+                } else {
+                    now + Duration::from_secs(secs as u64)
+                })
+            }
+            (None, Some(expires)) => Some(SystemTime::from(expires)),
+            (None, None) => None,
+        };
+
+        let (name, value) = cookie.name_value();
+        self.insert(StoredCookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain,
+            host_only,
+            path,
+            expires,
+            secure: cookie.secure().unwrap_or(false),
+            http_only: cookie.http_only().unwrap_or(false),
+        });
+        Ok(())
+    }
+
+    /// Add a cookie, replacing the one with the same name, domain, and path.
+    ///
+    /// An expired cookie only removes the stored one, which is how servers delete cookies.
+    fn insert(&self, cookie: StoredCookie) {
+        let mut cookies = self.cookies.lock().unwrap();
+        let existing = cookies.iter().position(|stored| {
+            stored.name == cookie.name
+                && stored.domain == cookie.domain
+                && stored.path == cookie.path
+        });
+        let expired = cookie.is_expired(SystemTime::now());
+        match existing {
+            Some(index) if expired => {
+                cookies.remove(index);
+            }
+            Some(index) => cookies[index] = cookie,
+            None if expired => {}
+            None => cookies.push(cookie),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for CookieJar {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let url = req.url().clone();
+        if let Some(cookies) = self.cookie_header(&url) {
+            let header = match req.header(COOKIE) {
+                Some(existing) => format!("{}; {}", existing.last(), cookies),
+                None => cookies,
+            };
+            req.insert_header(COOKIE, header);
+        }
+
+        let res = next.run(req, client).await?;
+        if let Some(values) = res.header(SET_COOKIE) {
+            let now = SystemTime::now();
+            for value in values.iter() {
+                // Invalid cookies are ignored, as browsers do.
+                if let Ok(cookie) = Cookie::parse(value.as_str().to_string()) {
+                    let _ = self.store(cookie, &url, now);
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Where a [`CookieJar`] keeps its cookies between runs of a program.
+///
+/// A jar created with [`CookieJar::with_store`] reads the cookies of its store with
+/// [`CookieJar::load`] and writes them back with [`CookieJar::save`]. Neither happens on its
+/// own, so programs decide when the cost of touching the store is paid.
+pub trait CookieStore: Debug + Send + Sync + 'static {
+    /// Read the stored cookies.
+    fn load(&self) -> Result<Vec<StoredCookie>>;
+
+    /// Replace the stored cookies with `cookies`.
+    fn save(&self, cookies: &[StoredCookie]) -> Result<()>;
+}
+
+/// A [`CookieStore`] which keeps cookies in a JSON file, in the format of
+/// [`CookieJar::export_json`].
+///
+/// A missing file holds no cookies. The file is replaced as a whole on every save, and on Unix
+/// it is only readable by its owner, as cookies often grant access to accounts.
+#[derive(Debug, Clone)]
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+impl JsonFileStore {
+    /// Create a new instance, which keeps the cookies in the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl CookieStore for JsonFileStore {
+    fn load(&self) -> Result<Vec<StoredCookie>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(json) => cookies_from_json(&json),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save(&self, cookies: &[StoredCookie]) -> Result<()> {
+        use std::io::Write;
+
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let written = options
+            .open(&tmp)
+            .and_then(|mut file| file.write_all(cookies_to_json(cookies).as_bytes()))
+            .and_then(|_| std::fs::rename(&tmp, &self.path));
+        if let Err(err) = written {
+            std::fs::remove_file(&tmp).ok();
+            return Err(err.into());
+        }
+        Ok(())
+    }
+}
+
+fn cookies_to_json(cookies: &[StoredCookie]) -> String {
+    let cookies: Vec<Value> = cookies.iter().map(StoredCookie::to_json).collect();
+    Value::Array(cookies).to_string()
+}
+
+fn cookies_from_json(json: &str) -> Result<Vec<StoredCookie>> {
+    let invalid = || Error::from_str(StatusCode::BadRequest, "invalid cookie jar export");
+    let values: Vec<Value> = serde_json::from_str(json).map_err(|_| invalid())?;
+    values
+        .iter()
+        .map(|value| StoredCookie::from_json(value).ok_or_else(invalid))
+        .collect()
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    host.parse::<IpAddr>().is_err()
+        && host.len() > domain.len()
+        && host.ends_with(domain)
+        && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+}
+
+/// Whether `request_path` is `cookie_path` or below it.
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/')
+                || request_path.as_bytes().get(cookie_path.len()) == Some(&b'/')))
+}
+
+/// The path cookies without a `Path` attribute are scoped to: the directory of the request.
+fn default_path(request_path: &str) -> String {
+    match request_path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(index) => request_path[..index].to_string(),
+    }
+}
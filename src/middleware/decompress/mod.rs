@@ -0,0 +1,116 @@
+//! Response body decompression middleware.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! let client = surf::client().with(surf::middleware::Decompress::new());
+//! let text = client.get("https://httpbin.org/gzip").recv_string().await?;
+//! # Ok(()) }
+//! ```
+
+use crate::compress::{accept_encoding, Decoder};
+use crate::http::headers::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH};
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::utils::{map_body_reader, BodyReader};
+use crate::{Client, Result};
+
+use futures_util::io::{AsyncBufRead, AsyncRead};
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A middleware which decompresses gzip and deflate response bodies.
+///
+/// Requests without an `Accept-Encoding` header are sent with one listing the codings compiled
+/// into surf, so servers never pick one which can't be decoded. Responses in those codings are
+/// decoded as they are read, and their `Content-Encoding` and `Content-Length` headers are
+/// removed.
+///
+/// surf has no brotli or zstd codecs, so `br` and `zstd` bodies are passed through unchanged.
+///
+/// Backends such as `curl-client` decompress bodies themselves but keep the
+/// `Content-Encoding` header. Bodies which don't start like the coding they are labelled with
+/// are therefore passed through unchanged.
+#[derive(Debug, Default)]
+pub struct Decompress {
+    _priv: (),
+}
+
+impl Decompress {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Decompress {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        if req.header(ACCEPT_ENCODING).is_none() {
+            req.insert_header(ACCEPT_ENCODING, accept_encoding());
+        }
+        let mut res = next.run(req, client).await?;
+
+        let mut codings = res.content_encoding();
+        let mut stages = Vec::new();
+        while let Some(gzip) = codings.last().and_then(|coding| match coding.as_str() {
+            "gzip" | "x-gzip" => Some(true),
+            "deflate" => Some(false),
+            _ => None,
+        }) {
+            codings.pop();
+            stages.push(gzip);
+        }
+
+        if !stages.is_empty() {
+            res.map_body(|mut body| {
+                for gzip in stages {
+                    body = map_body_reader(body, None, |inner| DecompressReader {
+                        inner,
+                        decoder: Decoder::with_gzip(gzip),
+                    });
+                }
+                body
+            });
+            // The length of the decompressed body is unknown until it has been read.
+            res.remove_header(CONTENT_LENGTH);
+            res.remove_header(CONTENT_ENCODING);
+            if !codings.is_empty() {
+                res.insert_header(CONTENT_ENCODING, codings.join(", "));
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// A reader which decompresses another reader as it is read.
+struct DecompressReader {
+    inner: BodyReader,
+    decoder: Decoder,
+}
+
+impl AsyncRead for DecompressReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        loop {
+            let input = futures_util::ready!(Pin::new(&mut this.inner).poll_fill_buf(cx))?;
+            let ended = input.is_empty();
+            let (consumed, written) = this.decoder.decode(input, buf)?;
+            Pin::new(&mut this.inner).consume(consumed);
+            if written > 0 || ended {
+                return Poll::Ready(Ok(written));
+            }
+        }
+    }
+}
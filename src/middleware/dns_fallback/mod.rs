@@ -0,0 +1,176 @@
+//! Resolving host names through a fallback resolver, such as DNS over HTTPS.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use surf::middleware::DnsFallback;
+//! use surf::{DohResolver, Url};
+//!
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! let resolver = DohResolver::new(Url::parse("https://1.1.1.1/dns-query")?);
+//! let client = surf::client().with(DnsFallback::new(resolver));
+//! client.get("http://intranet.example.com/status").recv_string().await?;
+//! # Ok(()) }
+//! ```
+
+use crate::error::is_dns_failure;
+use crate::http::headers::HOST;
+use crate::http::{self, Mime, StatusCode};
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::utils::map_body;
+use crate::{Body, Client, Error, Resolver, Result};
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The addresses from the fallback resolver of each host, and when they expire.
+type Resolved = HashMap<String, (Vec<SocketAddr>, Instant)>;
+
+/// A middleware which resolves host names through a fallback [`Resolver`] when the primary
+/// resolver of the backend fails for them.
+///
+/// A request which fails because its host name couldn't be resolved is sent again to the
+/// addresses from the fallback resolver, in order, with the `Host` header of the original URL.
+/// The addresses are remembered for the [`ttl`](DnsFallback::ttl), during which requests to the
+/// host go to them directly. The request body is buffered in memory, so that it can be sent
+/// again.
+///
+/// Backends verify the certificates of `https` requests against the address they are sent to,
+/// so these only succeed if the certificate of the server covers its address.
+///
+/// # Errors
+///
+/// If the fallback resolver fails too, or finds no addresses, the request fails with the error
+/// of the primary resolver.
+#[derive(Debug, Clone)]
+pub struct DnsFallback {
+    resolver: Arc<dyn Resolver>,
+    ttl: Duration,
+    resolved: Arc<Mutex<Resolved>>,
+}
+
+impl DnsFallback {
+    /// Create a new instance, falling back to `resolver`.
+    pub fn new(resolver: impl Resolver) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            ttl: Duration::from_secs(60),
+            resolved: Arc::default(),
+        }
+    }
+
+    /// Set how long the addresses from the fallback resolver are used for.
+    ///
+    /// Default: 60 seconds.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Send `req` to each of `addrs` in turn, until one succeeds.
+    async fn send_to(
+        &self,
+        req: &Request,
+        addrs: &[SocketAddr],
+        body: &Buffered,
+        client: &Client,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let url = req.url();
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let mut outcome = Err(Error::from_str(
+            StatusCode::BadGateway,
+            format!("no addresses were found for {}", host),
+        ));
+        for addr in addrs {
+            let mut attempt = req.clone_with_ext();
+            let url = AsMut::<http::Request>::as_mut(&mut attempt).url_mut();
+            if url.set_ip_host(addr.ip()).is_err() || url.set_port(Some(addr.port())).is_err() {
+                break;
+            }
+            attempt.insert_header(HOST, host.as_str());
+            body.restore(&mut attempt);
+            outcome = next.run(attempt, client.clone()).await;
+            if outcome.is_ok() {
+                break;
+            }
+        }
+        outcome
+    }
+}
+
+/// The body of a request, buffered to send it more than once.
+struct Buffered {
+    bytes: Vec<u8>,
+    mime: Mime,
+}
+
+impl Buffered {
+    async fn take(req: &mut Request) -> Result<Self> {
+        let body = req.take_body();
+        let mime = body.mime().clone();
+        let bytes = body.into_bytes().await?;
+        Ok(Self { bytes, mime })
+    }
+
+    fn restore(&self, req: &mut Request) {
+        map_body(req, |_| {
+            let mut body = Body::from_bytes(self.bytes.clone());
+            body.set_mime(self.mime.clone());
+            body
+        });
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for DnsFallback {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let host = match req.url().domain() {
+            Some(host) => host.to_ascii_lowercase(),
+            _ => return next.run(req, client).await,
+        };
+        let clock = client.config().clock.clone();
+        let body = Buffered::take(&mut req).await?;
+
+        let cached = self.resolved.lock().unwrap().get(&host).cloned();
+        if let Some((addrs, until)) = cached {
+            if clock.now() < until {
+                return self.send_to(&req, &addrs, &body, &client, next).await;
+            }
+            self.resolved.lock().unwrap().remove(&host);
+        }
+
+        let mut attempt = req.clone_with_ext();
+        body.restore(&mut attempt);
+        let err = match next.run(attempt, client.clone()).await {
+            Err(err) if is_dns_failure(&err) => err,
+            outcome => return outcome,
+        };
+        let port = req.url().port_or_known_default().unwrap_or_default();
+        let addrs = match self.resolver.resolve(&host, port, &client).await {
+            Ok(addrs) if !addrs.is_empty() => addrs,
+            Ok(_) => return Err(err),
+            Err(fallback_err) => {
+                log::warn!(
+                    "the fallback resolver failed for {}: {}",
+                    host,
+                    fallback_err
+                );
+                return Err(err);
+            }
+        };
+        let until = clock.now() + self.ttl;
+        self.resolved
+            .lock()
+            .unwrap()
+            .insert(host, (addrs.clone(), until));
+        self.send_to(&req, &addrs, &body, &client, next).await
+    }
+}
@@ -0,0 +1,249 @@
+//! End-to-end encryption of request and response bodies.
+//!
+//! The [`Encryption`] middleware seals the bodies of requests to some hosts with a
+//! [`PayloadCipher`], and opens the responses those hosts seal with the same cipher. This keeps
+//! sensitive payloads confidential and tamper-proof through intermediaries which terminate TLS,
+//! such as proxies, load balancers, or logging gateways.
+//!
+//! [`EnvelopeKey`] is a cipher using envelope encryption: each body is encrypted with a fresh
+//! data key, which is sent along, itself encrypted with a key shared with the server.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use surf::middleware::encryption::{Encryption, EnvelopeKey};
+//!
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! let key = EnvelopeKey::new([7; 32]);
+//! let client = surf::client().with(Encryption::new().host("vault.example.com", key));
+//! let secret = client
+//!     .post("https://vault.example.com/secrets")
+//!     .body_string("hunter2".to_string())
+//!     .recv_string()
+//!     .await?;
+//! # Ok(()) }
+//! ```
+
+use crate::http::headers::{HeaderName, CONTENT_TYPE};
+use crate::http::{Body, StatusCode};
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::{Client, Error, MiddlewareError, Result};
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+/// The header carrying the media type of a body before it was sealed.
+const ENVELOPE_CONTENT_TYPE: &str = "envelope-content-type";
+
+/// A cipher which seals and opens whole bodies, for the [`Encryption`] middleware.
+pub trait PayloadCipher: Debug + Send + Sync + 'static {
+    /// The media type of sealed bodies, which tells sealed responses apart from others.
+    fn media_type(&self) -> &str;
+
+    /// Encrypt and authenticate `plaintext`.
+    fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>>;
+
+    /// Authenticate and decrypt `sealed`, which was returned by `seal` on either end.
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// A middleware which seals request bodies and opens response bodies for some hosts.
+///
+/// Request bodies to a host with a cipher are read in full and sealed, and sent with the
+/// cipher's [media type](PayloadCipher::media_type). Their original media type is sent in the
+/// `Envelope-Content-Type` header. Responses with the cipher's media type are opened, and given
+/// the media type of their `Envelope-Content-Type` header. Requests and responses without a body
+/// are left as they are, as are requests to other hosts.
+///
+/// Sealing needs the whole body, so bodies are buffered in memory. Headers, including the URL,
+/// aren't encrypted.
+///
+/// # Errors
+///
+/// Requests fail with a [`MiddlewareError`] if a response of the cipher's media type can't be
+/// opened, e.g. because it was tampered with or sealed with another key.
+#[derive(Debug, Clone, Default)]
+pub struct Encryption {
+    ciphers: HashMap<String, Arc<dyn PayloadCipher>>,
+}
+
+impl Encryption {
+    /// Create a new instance, which doesn't encrypt anything until hosts are added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seal the bodies exchanged with `host` with `cipher`.
+    pub fn host(mut self, host: impl AsRef<str>, cipher: impl PayloadCipher) -> Self {
+        self.ciphers
+            .insert(host.as_ref().to_ascii_lowercase(), Arc::new(cipher));
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Encryption {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let host = req
+            .url()
+            .host_str()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let cipher = match self.ciphers.get(&host) {
+            Some(cipher) => cipher.clone(),
+            None => return next.run(req, client).await,
+        };
+
+        if req.len() != Some(0) {
+            let content_type = req
+                .header(CONTENT_TYPE)
+                .map(|values| values.last().to_string());
+            let plaintext = req.take_body().into_bytes().await?;
+            if !plaintext.is_empty() {
+                let sealed = cipher.seal(&plaintext)?;
+                req.set_body(Body::from_bytes(sealed));
+                req.insert_header(CONTENT_TYPE, cipher.media_type());
+                if let Some(content_type) = content_type {
+                    req.insert_header(ENVELOPE_CONTENT_TYPE, content_type);
+                } else {
+                    req.remove_header(ENVELOPE_CONTENT_TYPE);
+                }
+            }
+        }
+
+        let mut res = next.run(req, client).await?;
+        let sealed = res
+            .header(CONTENT_TYPE)
+            .map(|values| media_type(values.last().as_str()) == cipher.media_type())
+            .unwrap_or(false);
+        if sealed {
+            let body = res.body_bytes().await?;
+            let plaintext = cipher.open(&body).map_err(|err| {
+                Error::new(
+                    StatusCode::BadGateway,
+                    MiddlewareError::new("encryption", format!("opening the response: {}", err)),
+                )
+            })?;
+            let content_type = res.remove_header(HeaderName::from(ENVELOPE_CONTENT_TYPE));
+            res.map_body(|_| Body::from_bytes(plaintext));
+            if let Some(content_type) = content_type {
+                res.insert_header(CONTENT_TYPE, content_type.last().as_str());
+            } else {
+                res.remove_header(CONTENT_TYPE);
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// The media type of a `Content-Type` value, without its parameters.
+fn media_type(content_type: &str) -> &str {
+    content_type.split(';').next().unwrap_or_default().trim()
+}
+
+/// A [`PayloadCipher`] using envelope encryption with ChaCha20-Poly1305.
+///
+/// Every body is encrypted with a random data key, and the data key is encrypted with the
+/// 256-bit key shared with the server, the key encryption key, which only protects data keys.
+/// A sealed body has this layout:
+///
+/// | Bytes | Content                                                     |
+/// |-------|-------------------------------------------------------------|
+/// | 1     | The version of the format, `1`.                             |
+/// | 12    | The nonce the data key was encrypted with.                  |
+/// | 48    | The encrypted data key, and its authentication tag.         |
+/// | 12    | The nonce the body was encrypted with.                      |
+/// | rest  | The encrypted body, and its authentication tag.             |
+///
+/// The body is authenticated along with the first 61 bytes, so the parts can't be mixed across
+/// bodies. Its media type is `application/vnd.surf.envelope`.
+#[derive(Clone)]
+pub struct EnvelopeKey {
+    key: Key,
+}
+
+impl EnvelopeKey {
+    /// Create a new instance, with a key encryption key shared with the server.
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { key: key.into() }
+    }
+}
+
+impl Debug for EnvelopeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvelopeKey").finish_non_exhaustive()
+    }
+}
+
+const VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+const WRAPPED_KEY_LEN: usize = 32 + 16;
+const HEADER_LEN: usize = 1 + NONCE_LEN + WRAPPED_KEY_LEN;
+
+impl PayloadCipher for EnvelopeKey {
+    fn media_type(&self) -> &str {
+        "application/vnd.surf.envelope"
+    }
+
+    fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let data_key: [u8; 32] = random()?;
+        let key_nonce: [u8; NONCE_LEN] = random()?;
+        let body_nonce: [u8; NONCE_LEN] = random()?;
+
+        let wrapped_key = ChaCha20Poly1305::new(&self.key)
+            .encrypt(Nonce::from_slice(&key_nonce), &data_key[..])
+            .map_err(|_| failure("encrypting the data key failed"))?;
+        let mut sealed = Vec::with_capacity(HEADER_LEN + NONCE_LEN + plaintext.len() + 16);
+        sealed.push(VERSION);
+        sealed.extend_from_slice(&key_nonce);
+        sealed.extend_from_slice(&wrapped_key);
+        let payload = Payload {
+            msg: plaintext,
+            aad: &sealed,
+        };
+        let body = ChaCha20Poly1305::new(&data_key.into())
+            .encrypt(Nonce::from_slice(&body_nonce), payload)
+            .map_err(|_| failure("encrypting the body failed"))?;
+        sealed.extend_from_slice(&body_nonce);
+        sealed.extend_from_slice(&body);
+        Ok(sealed)
+    }
+
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < HEADER_LEN + NONCE_LEN || sealed[0] != VERSION {
+            return Err(failure("the sealed body is malformed"));
+        }
+        let (header, rest) = sealed.split_at(HEADER_LEN);
+        let (key_nonce, wrapped_key) = header[1..].split_at(NONCE_LEN);
+        let (body_nonce, body) = rest.split_at(NONCE_LEN);
+
+        let data_key = ChaCha20Poly1305::new(&self.key)
+            .decrypt(Nonce::from_slice(key_nonce), wrapped_key)
+            .map_err(|_| failure("the data key can't be decrypted with this key"))?;
+        let payload = Payload {
+            msg: body,
+            aad: header,
+        };
+        ChaCha20Poly1305::new(Key::from_slice(&data_key))
+            .decrypt(Nonce::from_slice(body_nonce), payload)
+            .map_err(|_| failure("the body failed authentication"))
+    }
+}
+
+/// Random bytes from the operating system, as the client's random source may be seeded.
+fn random<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| failure(&format!("generating a key failed: {}", err)))?;
+    Ok(bytes)
+}
+
+fn failure(message: &str) -> Error {
+    Error::from_str(StatusCode::InternalServerError, message.to_string())
+}
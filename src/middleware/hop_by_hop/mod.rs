@@ -0,0 +1,45 @@
+//! Hop-by-hop header sanitation middleware.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! let client = surf::client().with(surf::middleware::StripHopByHop::new());
+//! let res = client.get("https://httpbin.org/get").await?;
+//! assert!(res.header("connection").is_none());
+//! # Ok(()) }
+//! ```
+
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::utils::strip_hop_by_hop_headers;
+use crate::{Client, Result};
+
+/// A middleware which strips hop-by-hop headers from requests and responses.
+///
+/// This is useful when surf is used inside a proxy, so headers describing one connection are
+/// never forwarded to the next. See [`strip_hop_by_hop_headers`] for the list of headers.
+///
+/// [`strip_hop_by_hop_headers`]: crate::utils::strip_hop_by_hop_headers
+#[derive(Debug, Default)]
+pub struct StripHopByHop {
+    _priv: (),
+}
+
+impl StripHopByHop {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for StripHopByHop {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        strip_hop_by_hop_headers(&mut req);
+        let mut res = next.run(req, client).await?;
+        strip_hop_by_hop_headers(&mut res);
+        Ok(res)
+    }
+}
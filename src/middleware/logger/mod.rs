@@ -24,3 +24,64 @@ cfg_if::cfg_if! {
         pub use native::Logger;
     }
 }
+
+/// Key-value pairs which the logging middleware includes in the log records of a request, and
+/// the `Tracing` middleware records on the span of the request.
+///
+/// This is stored as a request extension, and is usually set through
+/// [`RequestBuilder::log_context`](crate::RequestBuilder::log_context).
+///
+/// # Examples
+///
+/// ```
+/// use surf::middleware::LogContext;
+///
+/// let mut req = surf::get("https://httpbin.org/get").build();
+/// let mut context = LogContext::new();
+/// context.insert("order_id", 42);
+/// req.set_ext(context);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LogContext {
+    pairs: Vec<(String, String)>,
+}
+
+impl LogContext {
+    /// Create a new, empty instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a key-value pair, replacing any previous value for the key.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl ToString) {
+        let key = key.into();
+        let value = value.to_string();
+        match self.pairs.iter_mut().find(|(k, _)| *k == key) {
+            Some(pair) => pair.1 = value,
+            None => self.pairs.push((key, value)),
+        }
+    }
+
+    /// Get the value for a key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// An iterator visiting all key-value pairs in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    fn visit<'kvs>(
+        &'kvs self,
+        visitor: &mut dyn log::kv::Visitor<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        for (key, value) in &self.pairs {
+            visitor.visit_pair(key.as_str().into(), value.as_str().into())?;
+        }
+        Ok(())
+    }
+}
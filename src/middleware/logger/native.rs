@@ -1,3 +1,5 @@
+use super::LogContext;
+use crate::middleware::retry::Attempts;
 use crate::middleware::{Middleware, Next};
 use crate::{Client, Request, Response};
 
@@ -33,6 +35,7 @@ impl Middleware for Logger {
         let uri = format!("{}", req.url());
         let method = format!("{}", req.method());
         let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let context = req.ext::<LogContext>().cloned();
         print(
             log::Level::Info,
             format_args!("sending request"),
@@ -40,6 +43,7 @@ impl Middleware for Logger {
                 id,
                 uri: &uri,
                 method: &method,
+                context: context.as_ref(),
             },
         );
 
@@ -47,7 +51,9 @@ impl Middleware for Logger {
 
         let status = res.status();
         let elapsed = start_time.elapsed();
-        let level = if status.is_server_error() {
+        let level = if res.is_success() {
+            log::Level::Info
+        } else if status.is_server_error() {
             log::Level::Error
         } else if status.is_client_error() {
             log::Level::Warn
@@ -62,6 +68,8 @@ impl Middleware for Logger {
                 id,
                 elapsed: &format!("{:?}", elapsed),
                 status: status.into(),
+                attempts: res.ext::<Attempts>().map(Attempts::count),
+                context: context.as_ref(),
             },
         );
 
@@ -73,6 +81,7 @@ struct RequestPairs<'a> {
     id: usize,
     method: &'a str,
     uri: &'a str,
+    context: Option<&'a LogContext>,
 }
 impl<'a> log::kv::Source for RequestPairs<'a> {
     fn visit<'kvs>(
@@ -82,6 +91,9 @@ impl<'a> log::kv::Source for RequestPairs<'a> {
         visitor.visit_pair("req.id".into(), self.id.into())?;
         visitor.visit_pair("req.method".into(), self.method.into())?;
         visitor.visit_pair("req.uri".into(), self.uri.into())?;
+        if let Some(context) = self.context {
+            context.visit(visitor)?;
+        }
         Ok(())
     }
 }
@@ -89,7 +101,9 @@ impl<'a> log::kv::Source for RequestPairs<'a> {
 struct ResponsePairs<'a> {
     id: usize,
     status: u16,
+    attempts: Option<usize>,
     elapsed: &'a str,
+    context: Option<&'a LogContext>,
 }
 
 impl<'a> log::kv::Source for ResponsePairs<'a> {
@@ -99,7 +113,13 @@ impl<'a> log::kv::Source for ResponsePairs<'a> {
     ) -> Result<(), log::kv::Error> {
         visitor.visit_pair("req.id".into(), self.id.into())?;
         visitor.visit_pair("req.status".into(), self.status.into())?;
+        if let Some(attempts) = self.attempts {
+            visitor.visit_pair("req.attempts".into(), attempts.into())?;
+        }
         visitor.visit_pair("elapsed".into(), self.elapsed.into())?;
+        if let Some(context) = self.context {
+            context.visit(visitor)?;
+        }
         Ok(())
     }
 }
@@ -1,3 +1,5 @@
+use super::LogContext;
+use crate::middleware::retry::Attempts;
 use crate::middleware::{Client, Middleware, Next, Request, Response};
 use std::fmt::Arguments;
 
@@ -25,19 +27,23 @@ impl Middleware for Logger {
     ) -> Result<Response, http_types::Error> {
         let uri = format!("{}", req.url());
         let method = format!("{}", req.method());
+        let context = req.ext::<LogContext>().cloned();
         print(
             log::Level::Info,
             format_args!("sending request"),
             RequestPairs {
                 uri: &uri,
                 method: &method,
+                context: context.as_ref(),
             },
         );
 
         let res = next.run(req, client).await?;
 
         let status = res.status();
-        let level = if status.is_server_error() {
+        let level = if res.is_success() {
+            log::Level::Info
+        } else if status.is_server_error() {
             log::Level::Error
         } else if status.is_client_error() {
             log::Level::Warn
@@ -50,6 +56,8 @@ impl Middleware for Logger {
             format_args!("request completed"),
             ResponsePairs {
                 status: status.into(),
+                attempts: res.ext::<Attempts>().map(Attempts::count),
+                context: context.as_ref(),
             },
         );
         Ok(res)
@@ -59,6 +67,7 @@ impl Middleware for Logger {
 struct RequestPairs<'a> {
     method: &'a str,
     uri: &'a str,
+    context: Option<&'a LogContext>,
 }
 impl<'a> log::kv::Source for RequestPairs<'a> {
     fn visit<'kvs>(
@@ -67,20 +76,31 @@ impl<'a> log::kv::Source for RequestPairs<'a> {
     ) -> Result<(), log::kv::Error> {
         visitor.visit_pair("req.method".into(), self.method.into())?;
         visitor.visit_pair("req.uri".into(), self.uri.into())?;
+        if let Some(context) = self.context {
+            context.visit(visitor)?;
+        }
         Ok(())
     }
 }
 
-struct ResponsePairs {
+struct ResponsePairs<'a> {
     status: u16,
+    attempts: Option<usize>,
+    context: Option<&'a LogContext>,
 }
 
-impl log::kv::Source for ResponsePairs {
+impl<'a> log::kv::Source for ResponsePairs<'a> {
     fn visit<'kvs>(
         &'kvs self,
         visitor: &mut dyn log::kv::Visitor<'kvs>,
     ) -> Result<(), log::kv::Error> {
         visitor.visit_pair("req.status".into(), self.status.into())?;
+        if let Some(attempts) = self.attempts {
+            visitor.visit_pair("req.attempts".into(), attempts.into())?;
+        }
+        if let Some(context) = self.context {
+            context.visit(visitor)?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,166 @@
+//! Metrics middleware, which records requests through the [`metrics`] facade.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! // Install a recorder first, such as the one of `metrics-exporter-prometheus`.
+//! let client = surf::client().with(surf::middleware::Metrics::new());
+//! client.get("https://httpbin.org/get").await?;
+//! # Ok(()) }
+//! ```
+
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::utils::{map_body_reader, BodyReader};
+use crate::{Client, Result};
+
+use futures_util::io::AsyncRead;
+use metrics::{counter, gauge, histogram, Gauge, Label};
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A middleware which records metrics about requests through the [`metrics`] facade, to be
+/// exported by whichever recorder is installed, such as one for Prometheus.
+///
+/// The following metrics are recorded, with the default `surf` prefix:
+///
+/// - `surf_requests_total`: a counter of the requests which got a response or failed.
+/// - `surf_requests_in_flight`: a gauge of the requests waiting for a response.
+/// - `surf_request_duration_seconds`: a histogram of the time until the response arrived, or
+///   the request failed.
+/// - `surf_request_body_bytes`: a histogram of the sizes of request bodies of known length.
+/// - `surf_response_body_bytes`: a histogram of the sizes of response bodies, recorded once a
+///   body has been read to the end.
+///
+/// All metrics are labelled with the `method` and `host` of the request, and all but the
+/// gauge and request body sizes with the `status` of the response, which is `error` for
+/// failed requests. Time is measured with the client's [clock](crate::Config::set_clock).
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    prefix: String,
+}
+
+impl Metrics {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self {
+            prefix: "surf".to_string(),
+        }
+    }
+
+    /// Set the prefix of the names of the metrics.
+    ///
+    /// Default: `surf`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn name(&self, name: &str) -> String {
+        format!("{}_{}", self.prefix, name)
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Metrics {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let mut labels = vec![
+            Label::new("method", req.method().to_string()),
+            Label::new("host", req.url().host_str().unwrap_or_default().to_string()),
+        ];
+        if let Some(len) = req.len() {
+            histogram!(self.name("request_body_bytes"), labels.iter()).record(len as f64);
+        }
+        let clock = client.config().clock.clone();
+        let start = clock.now();
+
+        let in_flight = InFlight::new(gauge!(self.name("requests_in_flight"), labels.iter()));
+        let res = next.run(req, client).await;
+        drop(in_flight);
+
+        let status = match &res {
+            Ok(res) => u16::from(res.status()).to_string(),
+            Err(_) => "error".to_string(),
+        };
+        labels.push(Label::new("status", status));
+        counter!(self.name("requests_total"), labels.iter()).increment(1);
+        let elapsed = clock.now().saturating_duration_since(start);
+        histogram!(self.name("request_duration_seconds"), labels.iter())
+            .record(elapsed.as_secs_f64());
+
+        let mut res = res?;
+        let name = self.name("response_body_bytes");
+        if res.len() == Some(0) {
+            histogram!(name, labels.iter()).record(0.0);
+            return Ok(res);
+        }
+        let len = res.len();
+        res.map_body(|body| {
+            map_body_reader(body, len, |inner| CountingReader {
+                inner,
+                remaining: len,
+                read: 0,
+                record: Some(Box::new(move |read| {
+                    histogram!(name, labels.iter()).record(read as f64);
+                })),
+            })
+        });
+        Ok(res)
+    }
+}
+
+/// A request counted by the in-flight gauge, until it is dropped, even if the request is
+/// cancelled.
+struct InFlight(Gauge);
+
+impl InFlight {
+    fn new(gauge: Gauge) -> Self {
+        gauge.increment(1.0);
+        Self(gauge)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.decrement(1.0);
+    }
+}
+
+/// A reader which counts the bytes of a body, and records the count at its end.
+struct CountingReader {
+    inner: BodyReader,
+    /// The length of the rest of the body, if known, as readers of sized bodies stop there.
+    remaining: Option<usize>,
+    read: usize,
+    record: Option<Box<dyn FnOnce(usize) + Send + Sync>>,
+}
+
+impl AsyncRead for CountingReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let read = futures_util::ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        self.read += read;
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.saturating_sub(read);
+        }
+        if self.remaining == Some(0) || (read == 0 && !buf.is_empty()) {
+            if let Some(record) = self.record.take() {
+                record(self.read);
+            }
+        }
+        Poll::Ready(Ok(read))
+    }
+}
@@ -54,15 +54,69 @@
 //! # }
 //! ```
 
+use std::any::TypeId;
 use std::sync::Arc;
 
 use crate::{Client, Request, Response, Result};
 
+#[cfg(not(target_arch = "wasm32"))]
+mod adaptive_concurrency;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cache;
+#[cfg(not(target_arch = "wasm32"))]
+mod cookies;
+#[cfg(feature = "gzip")]
+mod decompress;
+#[cfg(not(target_arch = "wasm32"))]
+mod dns_fallback;
+#[cfg_attr(feature = "docs", doc(cfg(feature = "encryption")))]
+#[cfg(feature = "encryption")]
+pub mod encryption;
+mod hop_by_hop;
 mod logger;
+#[cfg(feature = "middleware-metrics")]
+mod metrics;
+mod rate_limit;
 mod redirect;
+pub mod retry;
+mod signing;
+#[cfg(feature = "middleware-tracing")]
+mod tracing;
+mod traffic;
+#[cfg(not(target_arch = "wasm32"))]
+mod vcr;
+mod verbose;
 
-pub use logger::Logger;
-pub use redirect::Redirect;
+#[cfg_attr(feature = "docs", doc(cfg(feature = "middleware-metrics")))]
+#[cfg(feature = "middleware-metrics")]
+pub use self::metrics::Metrics;
+#[cfg_attr(feature = "docs", doc(cfg(feature = "middleware-tracing")))]
+#[cfg(feature = "middleware-tracing")]
+pub use self::tracing::{TraceContext, Tracing};
+#[cfg(not(target_arch = "wasm32"))]
+pub use adaptive_concurrency::AdaptiveConcurrency;
+#[cfg(not(target_arch = "wasm32"))]
+pub use cache::Cache;
+#[cfg(not(target_arch = "wasm32"))]
+pub use cookies::{CookieJar, CookieStore, JsonFileStore, StoredCookie};
+#[cfg_attr(feature = "docs", doc(cfg(feature = "gzip")))]
+#[cfg(feature = "gzip")]
+pub use decompress::Decompress;
+#[cfg(not(target_arch = "wasm32"))]
+pub use dns_fallback::DnsFallback;
+#[cfg_attr(feature = "docs", doc(cfg(feature = "encryption")))]
+#[cfg(feature = "encryption")]
+pub use encryption::Encryption;
+pub use hop_by_hop::StripHopByHop;
+pub use logger::{LogContext, Logger};
+pub use rate_limit::{Quota, RateLimit, RateLimited};
+pub use redirect::{PermanentRedirects, Redirect, RedirectChain};
+pub use retry::Retry;
+pub use signing::{Signer, Signing};
+pub use traffic::{Traffic, TrafficAccounting, TrafficCounter};
+#[cfg(not(target_arch = "wasm32"))]
+pub use vcr::{Vcr, VcrMode};
+pub use verbose::Verbose;
 
 use async_trait::async_trait;
 use futures_util::future::BoxFuture;
@@ -100,10 +154,7 @@ pub struct Next<'a> {
 
 impl Clone for Next<'_> {
     fn clone(&self) -> Self {
-        Self {
-            next_middleware: self.next_middleware,
-            endpoint: self.endpoint,
-        }
+        *self
     }
 }
 
@@ -134,3 +185,66 @@ impl<'a> Next<'a> {
         }
     }
 }
+
+/// Where [`Client::with_ordered`](crate::Client::with_ordered) puts middleware in the stack.
+///
+/// Middleware earlier in the stack runs first on requests, and last on responses. For example,
+/// middleware placed before a `Logger` sees every request the `Logger` logs as one.
+///
+/// # Examples
+///
+/// ```
+/// use surf::middleware::{Logger, Position, Retry};
+///
+/// let client = surf::client().with_ordered(Retry::default(), Position::after::<Logger>());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position(Place);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Place {
+    First,
+    Last,
+    Index(usize),
+    Before(TypeId),
+    After(TypeId),
+}
+
+impl Position {
+    /// The start of the stack, so the middleware runs before all others.
+    pub fn first() -> Self {
+        Self(Place::First)
+    }
+
+    /// The end of the stack, as with [`Client::with`](crate::Client::with).
+    pub fn last() -> Self {
+        Self(Place::Last)
+    }
+
+    /// At `index` in the stack, or at the end if the stack is shorter.
+    pub fn index(index: usize) -> Self {
+        Self(Place::Index(index))
+    }
+
+    /// Right before the first middleware of type `M`, or at the end if there is none.
+    pub fn before<M: Middleware>() -> Self {
+        Self(Place::Before(TypeId::of::<M>()))
+    }
+
+    /// Right after the last middleware of type `M`, or at the end if there is none.
+    pub fn after<M: Middleware>() -> Self {
+        Self(Place::After(TypeId::of::<M>()))
+    }
+
+    /// The index this position refers to in a stack of middleware with the types `types`.
+    pub(crate) fn resolve(self, types: &[TypeId]) -> usize {
+        let found = match self.0 {
+            Place::First => Some(0),
+            Place::Last => None,
+            Place::Index(index) => Some(index.min(types.len())),
+            Place::Before(ty) => types.iter().position(|t| *t == ty),
+            Place::After(ty) => types.iter().rposition(|t| *t == ty).map(|i| i + 1),
+        };
+        found.unwrap_or(types.len())
+    }
+}
@@ -0,0 +1,341 @@
+//! Client-side rate limiting with token buckets.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use surf::middleware::{Quota, RateLimit};
+//!
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! let limit = RateLimit::new()
+//!     .per_host(Quota::new(10, Duration::from_secs(1)))
+//!     .host("api.github.com", Quota::new(5_000, Duration::from_secs(3600)))
+//!     .honor_headers(true);
+//! let client = surf::client().with(limit);
+//! client.get("https://api.github.com/zen").recv_string().await?;
+//! # Ok(()) }
+//! ```
+
+use crate::http::other::RetryAfter;
+use crate::http::StatusCode;
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::{Client, Error, Result};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A rate of requests: up to `burst` requests at once, refilled evenly at `requests` per
+/// `period`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    burst: u32,
+    interval: Duration,
+}
+
+impl Quota {
+    /// Create a new instance, allowing `requests` requests per `period`, all of which may be
+    /// sent at once.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `requests` or `period` is zero.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        assert!(requests > 0, "a quota must allow at least one request");
+        assert!(
+            period > Duration::from_secs(0),
+            "a quota's period must not be zero"
+        );
+        Self {
+            burst: requests,
+            interval: period / requests,
+        }
+    }
+
+    /// Set how many requests may be sent at once, after the quota went unused for a while.
+    ///
+    /// Default: the `requests` of [`Quota::new`].
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `burst` is zero.
+    pub fn burst(mut self, burst: u32) -> Self {
+        assert!(
+            burst > 0,
+            "a quota must allow a burst of at least one request"
+        );
+        self.burst = burst;
+        self
+    }
+}
+
+/// A middleware which limits the rate of requests, globally and per host, with token buckets.
+///
+/// Each request takes a token from the [global](RateLimit::global) bucket and from the bucket of
+/// its host, which are refilled at the rate of their [`Quota`]. A request which finds a bucket
+/// empty is queued until a token is available, or fails if it would have to wait longer than
+/// [`max_wait`](RateLimit::max_wait). Tokens are handed out in the order requests arrive, and
+/// waiting uses the client's [clock](crate::Config::set_clock).
+///
+/// With [`honor_headers`](RateLimit::honor_headers), the limits servers announce are respected
+/// too: after a response with `X-RateLimit-Remaining: 0`, requests to its host wait until the
+/// time of its `X-RateLimit-Reset` header, and after a `429 Too Many Requests` or
+/// `503 Service Unavailable` response with a `Retry-After` header, until that time.
+///
+/// Cloning a `RateLimit` shares its buckets, so one limit can be applied across clients.
+///
+/// # Errors
+///
+/// Requests which would wait longer than `max_wait` fail with a `429 Too Many Requests` error
+/// wrapping a [`RateLimited`], without being sent.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    global: Option<Quota>,
+    per_host: Option<Quota>,
+    hosts: HashMap<String, Quota>,
+    max_wait: Option<Duration>,
+    honor_headers: bool,
+    state: Arc<Mutex<State>>,
+}
+
+impl RateLimit {
+    /// Create a new instance, which doesn't limit anything until quotas are set.
+    pub fn new() -> Self {
+        Self {
+            global: None,
+            per_host: None,
+            hosts: HashMap::new(),
+            max_wait: None,
+            honor_headers: false,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// Limit the rate of all requests together.
+    ///
+    /// Default: Unlimited.
+    pub fn global(mut self, quota: Quota) -> Self {
+        self.global = Some(quota);
+        self
+    }
+
+    /// Limit the rate of requests to each host.
+    ///
+    /// Default: Unlimited.
+    pub fn per_host(mut self, quota: Quota) -> Self {
+        self.per_host = Some(quota);
+        self
+    }
+
+    /// Limit the rate of requests to `host`, instead of the [`per_host`](RateLimit::per_host)
+    /// quota.
+    pub fn host(mut self, host: impl AsRef<str>, quota: Quota) -> Self {
+        self.hosts.insert(host.as_ref().to_ascii_lowercase(), quota);
+        self
+    }
+
+    /// Set the longest a request waits for the limits, after which it fails instead. A duration
+    /// of zero fails requests as soon as a limit is reached.
+    ///
+    /// Default: Requests wait as long as needed.
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = Some(max_wait);
+        self
+    }
+
+    /// Respect the `X-RateLimit-Remaining`, `X-RateLimit-Reset` and `Retry-After` headers of
+    /// responses.
+    ///
+    /// `X-RateLimit-Reset` is read as a Unix timestamp if it is that large, and as a number of
+    /// seconds otherwise, as APIs differ.
+    ///
+    /// Default: `false`.
+    pub fn honor_headers(mut self, honor_headers: bool) -> Self {
+        self.honor_headers = honor_headers;
+        self
+    }
+
+    /// Take a token for a request to `host` at `now`, returning how long to wait for it, or the
+    /// error to fail with.
+    fn acquire(&self, host: &str, now: Instant) -> Result<Duration> {
+        let host_quota = self.hosts.get(host).or(self.per_host.as_ref());
+        let mut state = self.state.lock().unwrap();
+        let State { global, hosts } = &mut *state;
+        let host_state = hosts.entry(host.to_string()).or_default();
+
+        let global_wait = match &self.global {
+            Some(quota) => global
+                .get_or_insert_with(|| Bucket::new(quota, now))
+                .wait(quota, now),
+            None => Duration::from_secs(0),
+        };
+        let host_wait = match host_quota {
+            Some(quota) => host_state
+                .bucket
+                .get_or_insert_with(|| Bucket::new(quota, now))
+                .wait(quota, now),
+            None => Duration::from_secs(0),
+        };
+        let blocked = host_state
+            .blocked_until
+            .map(|until| until.saturating_duration_since(now))
+            .unwrap_or_default();
+        let wait = global_wait.max(host_wait).max(blocked);
+        if matches!(self.max_wait, Some(max_wait) if wait > max_wait) {
+            return Err(Error::new(
+                StatusCode::TooManyRequests,
+                RateLimited { wait },
+            ));
+        }
+
+        if let (Some(_), Some(bucket)) = (&self.global, global) {
+            bucket.tokens -= 1.0;
+        }
+        if let (Some(_), Some(bucket)) = (host_quota, &mut host_state.bucket) {
+            bucket.tokens -= 1.0;
+        }
+        Ok(wait)
+    }
+
+    /// Block `host` according to the rate limit headers of `res`.
+    fn observe(&self, host: &str, res: &Response, client: &Client) {
+        let clock = &client.config().clock;
+        let now = clock.system_now();
+        let retry_after = match res.status() {
+            StatusCode::TooManyRequests | StatusCode::ServiceUnavailable => {
+                RetryAfter::from_headers(res).ok().flatten()
+            }
+            _ => None,
+        };
+        let exhausted = res
+            .header("X-RateLimit-Remaining")
+            .and_then(|values| values.last().as_str().trim().parse::<u64>().ok())
+            .map(|remaining| remaining == 0)
+            .unwrap_or(false);
+        let reset = if exhausted {
+            res.header("X-RateLimit-Reset")
+                .and_then(|values| values.last().as_str().trim().parse::<u64>().ok())
+                .map(|reset| parse_reset(reset, now))
+        } else {
+            None
+        };
+
+        let delay = match (retry_after, reset) {
+            (Some(retry_after), _) => retry_after.duration_since(now),
+            (None, Some(reset)) => reset.duration_since(now),
+            (None, None) => return,
+        };
+        let delay = delay.unwrap_or_default();
+        let until = clock.now() + delay;
+        let mut state = self.state.lock().unwrap();
+        let host_state = state.hosts.entry(host.to_string()).or_default();
+        host_state.blocked_until = host_state.blocked_until.max(Some(until));
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RateLimit {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let host = req
+            .url()
+            .host_str()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let wait = self.acquire(&host, client.config().clock.now())?;
+        if wait > Duration::from_secs(0) {
+            client.config().clock.sleep(wait).await;
+        }
+
+        let res = next.run(req, client.clone()).await?;
+        if self.honor_headers {
+            self.observe(&host, &res, &client);
+        }
+        Ok(res)
+    }
+}
+
+/// The time a `X-RateLimit-Reset` value stands for.
+fn parse_reset(reset: u64, now: SystemTime) -> SystemTime {
+    // Larger values are timestamps, as a delay of over 30 years is implausible.
+    if reset >= 1_000_000_000 {
+        UNIX_EPOCH + Duration::from_secs(reset)
+    } else {
+        now + Duration::from_secs(reset)
+    }
+}
+
+/// The error of a request which would have waited longer than [`RateLimit::max_wait`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimited {
+    wait: Duration,
+}
+
+impl RateLimited {
+    /// How long the request would have had to wait.
+    pub fn wait(&self) -> Duration {
+        self.wait
+    }
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rate limit exceeded: the request would wait {:?}",
+            self.wait
+        )
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+#[derive(Debug, Default)]
+struct State {
+    global: Option<Bucket>,
+    hosts: HashMap<String, HostState>,
+}
+
+#[derive(Debug, Default)]
+struct HostState {
+    bucket: Option<Bucket>,
+    blocked_until: Option<Instant>,
+}
+
+/// A token bucket, whose tokens go negative as requests queue up for them.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Create a full bucket.
+    fn new(quota: &Quota, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(quota.burst),
+            updated: now,
+        }
+    }
+
+    /// Refill the bucket up to `now`, and return how long until a token is available.
+    fn wait(&mut self, quota: &Quota, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() / quota.interval.as_secs_f64())
+            .min(f64::from(quota.burst));
+        self.updated = self.updated.max(now);
+        if self.tokens >= 1.0 {
+            Duration::from_secs(0)
+        } else {
+            quota.interval.mul_f64(1.0 - self.tokens)
+        }
+    }
+}
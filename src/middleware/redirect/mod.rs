@@ -12,9 +12,20 @@
 //! # Ok(()) }
 //! ```
 
-use crate::http::{self, headers, StatusCode, Url};
+use crate::events::EventId;
+use crate::http::headers::{
+    AUTHORIZATION, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, LOCATION,
+};
+use crate::http::{self, Method, StatusCode, Url};
 use crate::middleware::{Middleware, Next, Request, Response};
-use crate::{Client, Result};
+use crate::utils::map_body;
+use crate::{Body, Client, ClientEvent, Error, Result};
+
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
 
 // List of acceptible 300-series redirect codes.
 const REDIRECT_CODES: &[StatusCode] = &[
@@ -29,6 +40,8 @@ const REDIRECT_CODES: &[StatusCode] = &[
 #[derive(Debug)]
 pub struct Redirect {
     attempts: u8,
+    post_to_get: bool,
+    permanent: Option<PermanentRedirects>,
 }
 
 impl Redirect {
@@ -45,15 +58,22 @@ impl Redirect {
     /// - 307 Temporary Redirect
     /// - 308 Permanent Redirect
     ///
+    /// A 303 turns every request but `HEAD` into a `GET` without a body. The other codes keep the
+    /// method and body, unless [`post_to_get`](Redirect::post_to_get) is enabled. When a redirect
+    /// leads to another origin, the `Authorization` and `Cookie` headers are removed.
+    ///
+    /// If the redirect limit is reached, the last redirect response is returned. Responses
+    /// reached through redirects carry a [`RedirectChain`] extension.
+    ///
     /// # Errors
     ///
     /// An error will be passed through the middleware stack if the value of the `Location`
-    /// header is not a validly parsing url.
+    /// header is not a valid URL or relative reference. Relative references are resolved against
+    /// the URL of the request, whose fragment is kept unless the `Location` has its own.
     ///
     /// # Caveats
     ///
-    /// This will presently make at least one additional HTTP request before the actual request to
-    /// determine if there is a redirect that should be followed, so as to preserve any request body.
+    /// The request body is buffered in memory, so that it can be sent again.
     ///
     /// # Examples
     ///
@@ -67,7 +87,268 @@ impl Redirect {
     /// # Ok(()) }
     /// ```
     pub fn new(attempts: u8) -> Self {
-        Redirect { attempts }
+        Redirect {
+            attempts,
+            post_to_get: false,
+            permanent: None,
+        }
+    }
+
+    /// Whether to turn `POST` requests into `GET` requests without a body on 301 and 302
+    /// responses, as browsers do.
+    ///
+    /// Default: `false`.
+    pub fn post_to_get(mut self, post_to_get: bool) -> Self {
+        self.post_to_get = post_to_get;
+        self
+    }
+
+    /// Remember the targets of 301 and 308 redirects in `redirects`, and send later requests
+    /// for the same URLs straight to their targets.
+    ///
+    /// Redirects taken from `redirects` count towards the redirect limit, and are part of the
+    /// [`RedirectChain`] like any other. Redirects with `Cache-Control: no-store` aren't
+    /// remembered.
+    ///
+    /// Default: permanent redirects aren't remembered.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use surf::middleware::{PermanentRedirects, Redirect};
+    ///
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let redirects = PermanentRedirects::new(1024);
+    /// let client = surf::client().with(Redirect::default().permanent_redirects(redirects.clone()));
+    /// client.get("http://github.com").await?;
+    ///
+    /// // This request goes to https://github.com/ right away.
+    /// client.get("http://github.com").await?;
+    /// std::fs::write("redirects.json", redirects.export_json())?;
+    /// # Ok(()) }
+    /// ```
+    pub fn permanent_redirects(mut self, redirects: PermanentRedirects) -> Self {
+        self.permanent = Some(redirects);
+        self
+    }
+
+    /// Point `req` at `url`, as `status` redirected it there.
+    fn follow(&self, req: &mut Request, bytes: &mut Option<Vec<u8>>, status: StatusCode, url: Url) {
+        let method = req.method();
+        let to_get = match status {
+            StatusCode::SeeOther => method != Method::Head && method != Method::Get,
+            StatusCode::MovedPermanently | StatusCode::Found => {
+                self.post_to_get && method == Method::Post
+            }
+            _ => false,
+        };
+        if to_get {
+            let http_req: &mut http::Request = req.as_mut();
+            http_req.set_method(Method::Get);
+            for name in &[CONTENT_TYPE, CONTENT_LENGTH, CONTENT_ENCODING] {
+                req.remove_header(name);
+            }
+            *bytes = None;
+        }
+        if url.origin() != req.url().origin() {
+            req.remove_header(AUTHORIZATION);
+            req.remove_header(COOKIE);
+        }
+        // An address the request was pinned to belongs to its host.
+        #[cfg(any(
+            feature = "curl-client",
+            feature = "h1-client",
+            feature = "h1-client-rustls",
+            feature = "h1-client-no-tls"
+        ))]
+        if url.host() != req.url().host()
+            || url.port_or_known_default() != req.url().port_or_known_default()
+        {
+            let http_req: &mut http::Request = req.as_mut();
+            http_req.ext_mut().remove::<crate::resolver::ResolveTo>();
+        }
+
+        let http_req: &mut http::Request = req.as_mut();
+        *http_req.url_mut() = url;
+    }
+}
+
+/// The targets of permanent redirects, shared by clones, which the [`Redirect`] middleware
+/// follows without asking the server again.
+///
+/// Once it is full, the least recently used redirect is forgotten. The redirects can be saved
+/// with [`export_json`](PermanentRedirects::export_json), and loaded again with
+/// [`import_json`](PermanentRedirects::import_json).
+#[derive(Debug, Clone)]
+pub struct PermanentRedirects {
+    capacity: usize,
+    entries: Arc<Mutex<LruRedirects>>,
+}
+
+#[derive(Debug, Default)]
+struct LruRedirects {
+    entries: HashMap<String, (Url, StatusCode, u64)>,
+    tick: u64,
+}
+
+impl PermanentRedirects {
+    /// Create a new, empty instance, which keeps up to `capacity` redirects.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Arc::default(),
+        }
+    }
+
+    /// The URL requests for `url` are redirected to, and the status of the redirect.
+    pub fn get(&self, url: &Url) -> Option<(Url, StatusCode)> {
+        let mut lru = self.entries.lock().unwrap();
+        lru.tick += 1;
+        let tick = lru.tick;
+        lru.entries
+            .get_mut(url.as_str())
+            .map(|(target, status, used)| {
+                *used = tick;
+                (target.clone(), *status)
+            })
+    }
+
+    /// Remember that `url` redirects to `target` with `status`.
+    ///
+    /// Statuses other than 301 and 308 are ignored.
+    pub fn insert(&self, url: &Url, target: Url, status: StatusCode) {
+        if !matches!(
+            status,
+            StatusCode::MovedPermanently | StatusCode::PermanentRedirect
+        ) || self.capacity == 0
+        {
+            return;
+        }
+        let mut lru = self.entries.lock().unwrap();
+        lru.tick += 1;
+        let tick = lru.tick;
+        if lru.entries.len() >= self.capacity && !lru.entries.contains_key(url.as_str()) {
+            let oldest = lru
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, _, used))| *used)
+                .map(|(url, _)| url.clone());
+            if let Some(oldest) = oldest {
+                lru.entries.remove(&oldest);
+            }
+        }
+        lru.entries
+            .insert(url.as_str().to_string(), (target, status, tick));
+    }
+
+    /// The number of redirects which are remembered.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().entries.len()
+    }
+
+    /// Whether no redirects are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget all redirects, such as after a site changed its mind about them.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().entries.clear();
+    }
+
+    /// Export the redirects as JSON, to be loaded again with
+    /// [`import_json`](PermanentRedirects::import_json).
+    pub fn export_json(&self) -> String {
+        let lru = self.entries.lock().unwrap();
+        let mut entries: Vec<_> = lru.entries.iter().collect();
+        entries.sort_by_key(|(_, (_, _, used))| *used);
+        let entries: Vec<Value> = entries
+            .into_iter()
+            .map(|(url, (target, status, _))| {
+                json!({
+                    "url": url,
+                    "target": target.as_str(),
+                    "status": u16::from(*status),
+                })
+            })
+            .collect();
+        Value::Array(entries).to_string()
+    }
+
+    /// Add the redirects from JSON created with
+    /// [`export_json`](PermanentRedirects::export_json).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `json` isn't a list of redirects.
+    pub fn import_json(&self, json: &str) -> Result<()> {
+        let invalid = || Error::from_str(StatusCode::BadRequest, "invalid redirects export");
+        let values: Vec<Value> = serde_json::from_str(json).map_err(|_| invalid())?;
+        for value in &values {
+            let url = |key| {
+                value
+                    .get(key)
+                    .and_then(Value::as_str)
+                    .and_then(|url| Url::parse(url).ok())
+            };
+            let status = value
+                .get("status")
+                .and_then(Value::as_u64)
+                .and_then(|status| u16::try_from(status).ok())
+                .and_then(|status| StatusCode::try_from(status).ok());
+            match (url("url"), url("target"), status) {
+                (Some(url), Some(target), Some(status)) => self.insert(&url, target, status),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for PermanentRedirects {
+    /// Create a new instance, which keeps up to 256 redirects.
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+/// The redirects which were followed to get a response.
+///
+/// This is stored in the extensions of responses reached through the [`Redirect`] middleware.
+///
+/// # Examples
+///
+/// ```no_run
+/// use surf::middleware::{Redirect, RedirectChain};
+///
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// let client = surf::client().with(Redirect::default());
+/// let res = client.get("https://httpbin.org/redirect/2").await?;
+/// if let Some(chain) = res.ext::<RedirectChain>() {
+///     for (url, status) in chain.hops() {
+///         println!("{} redirected with {}", url, status);
+///     }
+///     println!("ended up at {}", chain.url());
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedirectChain {
+    hops: Vec<(Url, StatusCode)>,
+    url: Url,
+}
+
+impl RedirectChain {
+    /// The URLs which responded with a redirect, with the status of each redirect, in order.
+    pub fn hops(&self) -> &[(Url, StatusCode)] {
+        &self.hops
+    }
+
+    /// The URL the response came from.
+    pub fn url(&self) -> &Url {
+        &self.url
     }
 }
 
@@ -75,57 +356,135 @@ impl Redirect {
 impl Middleware for Redirect {
     #[allow(missing_doc_code_examples)]
     async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
-        let mut redirect_count: u8 = 0;
-
-        // Note(Jeremiah): This is not ideal.
-        //
-        // HttpClient is currently too limiting for efficient redirects.
-        // We do not want to make unnecessary full requests, but it is
-        // presently required due to how Body streams work.
-        //
-        // Ideally we'd have methods to send a partial request stream,
-        // without sending the body, that would potnetially be able to
-        // get a server status before we attempt to send the body.
-        //
-        // As a work around we clone the request first (without the body),
-        // and try sending it until we get some status back that is not a
-        // redirect.
-
-        let mut base_url = req.url().clone();
-
-        while redirect_count < self.attempts {
-            redirect_count += 1;
-            let r: Request = req.clone();
-            let res: Response = client.send(r).await?;
-            if REDIRECT_CODES.contains(&res.status()) {
-                if let Some(location) = res.header(headers::LOCATION) {
-                    let http_req: &mut http::Request = req.as_mut();
-                    *http_req.url_mut() = match Url::parse(location.last().as_str()) {
-                        Ok(valid_url) => {
-                            base_url = valid_url;
-                            base_url.clone()
-                        }
-                        Err(e) => match e {
-                            http::url::ParseError::RelativeUrlWithoutBase => {
-                                base_url.join(location.last().as_str())?
-                            }
-                            e => return Err(e.into()),
-                        },
-                    };
+        let body = req.take_body();
+        let mime = body.mime().clone();
+        let mut bytes = Some(body.into_bytes().await?);
+        let mut hops = Vec::new();
+
+        loop {
+            if let Some(permanent) = &self.permanent {
+                if hops.len() < usize::from(self.attempts) {
+                    if let Some((url, status)) = permanent.get(req.url()) {
+                        redirected(&client, &req, &url, status);
+                        hops.push((req.url().clone(), status));
+                        self.follow(&mut req, &mut bytes, status, url);
+                        continue;
+                    }
+                }
+            }
+
+            let mut attempt = req.clone_with_ext();
+            if let Some(bytes) = &bytes {
+                map_body(&mut attempt, |_| {
+                    let mut body = Body::from_bytes(bytes.clone());
+                    body.set_mime(mime.clone());
+                    body
+                });
+            }
+
+            let mut res = next.run(attempt, client.clone()).await?;
+            let status = res.status();
+            let location = match res.header(LOCATION) {
+                Some(location) if REDIRECT_CODES.contains(&status) => location.last().clone(),
+                _ => return Ok(with_chain(res, hops, req.url())),
+            };
+            if hops.len() >= usize::from(self.attempts) {
+                return Ok(with_chain(res, hops, req.url()));
+            }
+            let url = resolve_location(req.url(), location.as_str())?;
+            if let Some(permanent) = &self.permanent {
+                let no_store =
+                    res.header(CACHE_CONTROL)
+                        .map(|values| {
+                            values.iter().any(|value| {
+                                value.as_str().split(',').any(|directive| {
+                                    directive.trim().eq_ignore_ascii_case("no-store")
+                                })
+                            })
+                        })
+                        .unwrap_or(false);
+                if !no_store {
+                    permanent.insert(req.url(), url.clone(), status);
                 }
-            } else {
-                break;
             }
+            // Read the rest of the body, so the connection can be reused.
+            res.take_body().into_bytes().await.ok();
+
+            redirected(&client, &req, &url, status);
+            hops.push((req.url().clone(), status));
+            self.follow(&mut req, &mut bytes, status, url);
         }
+    }
+}
 
-        Ok(next.run(req, client).await?)
+/// Resolve the value of a `Location` header against the URL of the request it redirects.
+///
+/// Relative references, such as path-only and scheme-relative ones, are resolved as described
+/// in RFC 3986, so the query of the request is never kept. A `Location` without a fragment keeps
+/// the fragment of the request, as RFC 7231 requires.
+fn resolve_location(base: &Url, location: &str) -> Result<Url> {
+    let mut url = base.join(location)?;
+    if url.fragment().is_none() {
+        url.set_fragment(base.fragment());
     }
+    Ok(url)
+}
+
+/// Broadcast that `req` is being redirected to `url`, if its events are broadcast.
+fn redirected(client: &Client, req: &Request, url: &Url, status: StatusCode) {
+    if let Some(EventId(id)) = req.ext::<EventId>() {
+        client.emit(ClientEvent::Redirected {
+            id: *id,
+            from: req.url().clone(),
+            to: url.clone(),
+            status,
+        });
+    }
+}
+
+/// Record `hops` in `res`, if any redirects were followed.
+fn with_chain(mut res: Response, hops: Vec<(Url, StatusCode)>, url: &Url) -> Response {
+    if !hops.is_empty() {
+        res.insert_ext(RedirectChain {
+            hops,
+            url: url.clone(),
+        });
+    }
+    res
 }
 
 impl Default for Redirect {
     /// Create a new instance of the Redirect middleware, which attempts to follow up to
     /// 3 redirects (not including the actual request).
     fn default() -> Self {
-        Self { attempts: 3 }
+        Self::new(3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_location_references() {
+        let base = Url::parse("https://example.org/a/b?page=2#intro").unwrap();
+        let resolve = |location| resolve_location(&base, location).unwrap().to_string();
+        assert_eq!(resolve("/c"), "https://example.org/c#intro");
+        assert_eq!(resolve("c?x=1"), "https://example.org/a/c?x=1#intro");
+        assert_eq!(resolve("?x=1"), "https://example.org/a/b?x=1#intro");
+        assert_eq!(
+            resolve("//cdn.example.org/d"),
+            "https://cdn.example.org/d#intro"
+        );
+        assert_eq!(resolve("../d#top"), "https://example.org/d#top");
+        assert_eq!(
+            resolve("http://other.example/e"),
+            "http://other.example/e#intro"
+        );
+
+        let base = Url::parse("https://example.org/a?page=2").unwrap();
+        let url = resolve_location(&base, "/c").unwrap();
+        assert_eq!(url.as_str(), "https://example.org/c");
+        assert!(resolve_location(&base, "http://[::1").is_err());
     }
 }
@@ -0,0 +1,498 @@
+//! Retrying of failed requests.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! use surf::middleware::Retry;
+//!
+//! let client = surf::client().with(Retry::new(3));
+//! let res = client.get("https://httpbin.org/status/503").await?;
+//! # Ok(()) }
+//! ```
+
+use crate::events::EventId;
+use crate::http::other::RetryAfter;
+use crate::http::{Method, StatusCode};
+use crate::middleware::{Middleware, Next, Request, Response};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::nonce::NonceSlot;
+use crate::utils::map_body;
+use crate::{Client, ClientEvent, OsRng, Result, Rng};
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Decides whether a request may be retried after an attempt.
+///
+/// Classifiers can be combined with [`and`](RetryClassifier::and) and
+/// [`or`](RetryClassifier::or), and are implemented for closures taking the request and the
+/// outcome of the attempt.
+pub trait RetryClassifier: Send + Sync + 'static {
+    /// Whether `req` should be sent again after an attempt ended with `outcome`.
+    fn should_retry(&self, req: &Request, outcome: &Result<Response>) -> bool;
+
+    /// Retry only if both `self` and `other` allow it.
+    fn and<C: RetryClassifier>(self, other: C) -> And<Self, C>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+
+    /// Retry if either `self` or `other` allows it.
+    fn or<C: RetryClassifier>(self, other: C) -> Or<Self, C>
+    where
+        Self: Sized,
+    {
+        Or(self, other)
+    }
+}
+
+impl<F> RetryClassifier for F
+where
+    F: Fn(&Request, &Result<Response>) -> bool + Send + Sync + 'static,
+{
+    fn should_retry(&self, req: &Request, outcome: &Result<Response>) -> bool {
+        (self)(req, outcome)
+    }
+}
+
+/// Allows retrying requests which are safe to repeat.
+///
+/// These are requests with an idempotent method (`GET`, `HEAD`, `PUT`, `DELETE`, `OPTIONS` and
+/// `TRACE`), and requests carrying an `Idempotency-Key` header, which tells the server to
+/// deduplicate them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdempotentRequests;
+
+impl RetryClassifier for IdempotentRequests {
+    fn should_retry(&self, req: &Request, _: &Result<Response>) -> bool {
+        matches!(
+            req.method(),
+            Method::Get
+                | Method::Head
+                | Method::Put
+                | Method::Delete
+                | Method::Options
+                | Method::Trace
+        ) || req.header("Idempotency-Key").is_some()
+    }
+}
+
+/// Allows retrying any request, including `POST` and `PATCH` requests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AnyRequest;
+
+impl RetryClassifier for AnyRequest {
+    fn should_retry(&self, _: &Request, _: &Result<Response>) -> bool {
+        true
+    }
+}
+
+/// Allows retrying after failures which are likely to be temporary.
+///
+/// These are errors which prevented a response (such as connection failures), and the
+/// `408 Request Timeout`, `429 Too Many Requests`, `500 Internal Server Error`,
+/// `502 Bad Gateway`, `503 Service Unavailable`, and `504 Gateway Timeout` responses, unless the
+/// client counts them as a [success](crate::Config::set_success_predicate).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransientFailures;
+
+impl RetryClassifier for TransientFailures {
+    fn should_retry(&self, _: &Request, outcome: &Result<Response>) -> bool {
+        match outcome {
+            Ok(res) if res.is_success() => false,
+            Ok(res) => matches!(
+                res.status(),
+                StatusCode::RequestTimeout
+                    | StatusCode::TooManyRequests
+                    | StatusCode::InternalServerError
+                    | StatusCode::BadGateway
+                    | StatusCode::ServiceUnavailable
+                    | StatusCode::GatewayTimeout
+            ),
+            Err(_) => true,
+        }
+    }
+}
+
+/// Allows retrying after errors which prevented a response, and after responses with one of a
+/// given set of statuses.
+///
+/// Unlike [`TransientFailures`], the statuses are not checked against the client's
+/// [success predicate](crate::Config::set_success_predicate).
+///
+/// ```
+/// use surf::http::StatusCode;
+/// use surf::middleware::retry::{FailureStatuses, IdempotentRequests, RetryClassifier};
+/// use surf::middleware::Retry;
+///
+/// let statuses = FailureStatuses::new(vec![StatusCode::TooManyRequests, StatusCode::BadGateway]);
+/// let retry = Retry::new(3).classifier(IdempotentRequests.and(statuses));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FailureStatuses {
+    statuses: Vec<StatusCode>,
+}
+
+impl FailureStatuses {
+    /// Create a new instance, which retries after responses with any of `statuses`.
+    pub fn new(statuses: impl IntoIterator<Item = StatusCode>) -> Self {
+        Self {
+            statuses: statuses.into_iter().collect(),
+        }
+    }
+}
+
+impl RetryClassifier for FailureStatuses {
+    fn should_retry(&self, _: &Request, outcome: &Result<Response>) -> bool {
+        match outcome {
+            Ok(res) => self.statuses.contains(&res.status()),
+            Err(_) => true,
+        }
+    }
+}
+
+/// A classifier which requires both of its classifiers to allow a retry.
+#[derive(Debug, Clone, Copy)]
+pub struct And<A, B>(A, B);
+
+impl<A: RetryClassifier, B: RetryClassifier> RetryClassifier for And<A, B> {
+    fn should_retry(&self, req: &Request, outcome: &Result<Response>) -> bool {
+        self.0.should_retry(req, outcome) && self.1.should_retry(req, outcome)
+    }
+}
+
+/// A classifier which requires either of its classifiers to allow a retry.
+#[derive(Debug, Clone, Copy)]
+pub struct Or<A, B>(A, B);
+
+impl<A: RetryClassifier, B: RetryClassifier> RetryClassifier for Or<A, B> {
+    fn should_retry(&self, req: &Request, outcome: &Result<Response>) -> bool {
+        self.0.should_retry(req, outcome) || self.1.should_retry(req, outcome)
+    }
+}
+
+/// How long to wait before each retry.
+///
+/// The delay starts at an initial value and is multiplied by a factor after every retry, up to a
+/// maximum. With jitter enabled, a random delay between half and all of the computed delay is
+/// used instead, so that many clients failing at once don't retry in lockstep.
+///
+/// ```
+/// use std::time::Duration;
+/// use surf::middleware::retry::Backoff;
+///
+/// let backoff = Backoff::exponential(Duration::from_millis(100))
+///     .max_delay(Duration::from_secs(5))
+///     .jitter(false);
+/// assert_eq!(backoff.delay(3), Duration::from_millis(400));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    initial: Duration,
+    factor: u32,
+    max_delay: Duration,
+    jitter: bool,
+}
+
+impl Backoff {
+    /// Double the delay after every retry, starting at `initial`.
+    ///
+    /// Default: the delay is capped at 30 seconds, and jitter is enabled.
+    pub fn exponential(initial: Duration) -> Self {
+        Self {
+            initial,
+            factor: 2,
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+        }
+    }
+
+    /// Wait `delay` before every retry.
+    pub fn constant(delay: Duration) -> Self {
+        Self {
+            initial: delay,
+            factor: 1,
+            max_delay: delay,
+            jitter: false,
+        }
+    }
+
+    /// Retry immediately.
+    pub fn none() -> Self {
+        Self::constant(Duration::from_secs(0))
+    }
+
+    /// Set the factor the delay is multiplied by after every retry.
+    pub fn factor(mut self, factor: u32) -> Self {
+        self.factor = factor;
+        self
+    }
+
+    /// Set the longest delay between two attempts.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set whether delays are randomized.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// The delay before retry number `retry`, counting from 1.
+    ///
+    /// Jitter is drawn from the random number generator of the operating system.
+    pub fn delay(&self, retry: usize) -> Duration {
+        self.delay_with(retry, &OsRng)
+    }
+
+    /// The delay before retry number `retry`, drawing jitter from `rng`.
+    pub fn delay_with(&self, retry: usize, rng: &dyn Rng) -> Duration {
+        let exponent = retry.saturating_sub(1).min(u32::MAX as usize) as u32;
+        let delay = self
+            .factor
+            .checked_pow(exponent)
+            .and_then(|scale| self.initial.checked_mul(scale))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        if !self.jitter {
+            return delay;
+        }
+        delay / 2 + (delay / 2).mul_f64(rng.next_f64())
+    }
+}
+
+impl Default for Backoff {
+    /// Start at 100 milliseconds, doubling up to 30 seconds, with jitter.
+    fn default() -> Self {
+        Self::exponential(Duration::from_millis(100))
+    }
+}
+
+/// A middleware which retries failed requests.
+///
+/// By default, requests are retried after [transient failures](TransientFailures), but only if
+/// they are [safe to repeat](IdempotentRequests): `POST` and `PATCH` requests are only retried
+/// when they carry an `Idempotency-Key` header. Use [`classifier`](Retry::classifier) to change
+/// this policy, e.g. to opt into retrying all requests:
+///
+/// ```
+/// use surf::middleware::retry::{AnyRequest, RetryClassifier, TransientFailures};
+/// use surf::middleware::Retry;
+///
+/// let retry = Retry::new(3).classifier(AnyRequest.and(TransientFailures));
+/// ```
+///
+/// Between attempts, the middleware waits on the client's [clock](crate::Config::set_clock)
+/// according to its [`Backoff`], drawing jitter from the client's
+/// [random source](crate::Config::set_rng). If a response carries a `Retry-After` header, the delay it asks
+/// for is used instead; a response asking for a longer delay than
+/// [`max_retry_after`](Retry::max_retry_after) is returned without retrying.
+///
+/// The request body is buffered in memory, so that it can be sent again. The response which is
+/// returned carries the [`Attempts`] made for it as an extension.
+pub struct Retry {
+    max_retries: usize,
+    classifier: Arc<dyn RetryClassifier>,
+    backoff: Backoff,
+    max_retry_after: Duration,
+}
+
+impl Retry {
+    /// Create a new instance, which sends a request at most `max_retries` more times after the
+    /// first attempt.
+    pub fn new(max_retries: usize) -> Self {
+        Self {
+            max_retries,
+            classifier: Arc::new(IdempotentRequests.and(TransientFailures)),
+            backoff: Backoff::default(),
+            max_retry_after: Duration::from_secs(60),
+        }
+    }
+
+    /// Set the policy deciding which attempts are retried.
+    pub fn classifier(mut self, classifier: impl RetryClassifier) -> Self {
+        self.classifier = Arc::new(classifier);
+        self
+    }
+
+    /// Set how long to wait before each retry.
+    ///
+    /// Default: [`Backoff::default`].
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Set the longest `Retry-After` delay to wait for.
+    ///
+    /// Default: 60 seconds.
+    pub fn max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = max_retry_after;
+        self
+    }
+}
+
+impl Default for Retry {
+    /// Create a new instance, which retries up to 3 times.
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl fmt::Debug for Retry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Retry")
+            .field("max_retries", &self.max_retries)
+            .field("backoff", &self.backoff)
+            .field("max_retry_after", &self.max_retry_after)
+            .finish()
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Retry {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let body = req.take_body();
+        let mime = body.mime().clone();
+        let bytes = body.into_bytes().await?;
+        let template = req.clone_with_ext();
+        let event_id = req.ext::<EventId>().copied();
+        #[cfg(not(target_arch = "wasm32"))]
+        let nonce = NonceSlot::default();
+
+        let mut attempt = req;
+        let mut attempts = Attempts::default();
+        let mut retries = 0;
+        loop {
+            #[cfg(not(target_arch = "wasm32"))]
+            attempt.set_ext(nonce.clone());
+            map_body(&mut attempt, |_| {
+                let mut body = crate::Body::from_bytes(bytes.clone());
+                body.set_mime(mime.clone());
+                body
+            });
+
+            let start = client.config().clock.now();
+            let outcome = next.run(attempt, client.clone()).await;
+            let elapsed = client.config().clock.now().saturating_duration_since(start);
+            attempts.record(&outcome, elapsed);
+            if retries == self.max_retries || !self.classifier.should_retry(&template, &outcome) {
+                return attempts.attach(outcome);
+            }
+            retries += 1;
+
+            let retry_after = match &outcome {
+                Ok(res) => RetryAfter::from_headers(res).ok().flatten(),
+                Err(_) => None,
+            };
+            let delay = match retry_after {
+                Some(retry_after) => {
+                    let delay = retry_after
+                        .duration_since(client.config().clock.system_now())
+                        .unwrap_or_default();
+                    if delay > self.max_retry_after {
+                        return attempts.attach(outcome);
+                    }
+                    delay
+                }
+                None => self
+                    .backoff
+                    .delay_with(retries, client.config().rng.as_ref()),
+            };
+            if let Some(EventId(id)) = event_id {
+                client.emit(ClientEvent::RetryScheduled {
+                    id,
+                    retry: retries,
+                    delay,
+                });
+            }
+            if delay > Duration::from_secs(0) {
+                client.config().clock.sleep(delay).await;
+            }
+            attempt = template.clone_with_ext();
+        }
+    }
+}
+
+/// The attempts the [`Retry`] middleware made for a request, added as an extension of the
+/// response it returns.
+///
+/// # Examples
+///
+/// ```no_run
+/// use surf::middleware::retry::Attempts;
+/// use surf::middleware::Retry;
+///
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// let client = surf::client().with(Retry::new(3));
+/// let res = client.get("https://httpbin.org/status/200").await?;
+/// let attempts = res.ext::<Attempts>().unwrap();
+/// if attempts.retried() {
+///     for attempt in attempts.iter() {
+///         println!("{:?} after {:?}", attempt.status, attempt.elapsed);
+///     }
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Attempts {
+    attempts: Vec<Attempt>,
+}
+
+impl Attempts {
+    /// The number of attempts, including the last one.
+    pub fn count(&self) -> usize {
+        self.attempts.len()
+    }
+
+    /// Whether the request was sent more than once.
+    pub fn retried(&self) -> bool {
+        self.attempts.len() > 1
+    }
+
+    /// An iterator visiting the attempts in the order they were made.
+    pub fn iter(&self) -> impl Iterator<Item = &Attempt> {
+        self.attempts.iter()
+    }
+
+    fn record(&mut self, outcome: &Result<Response>, elapsed: Duration) {
+        let (status, error) = match outcome {
+            Ok(res) => (Some(res.status()), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        self.attempts.push(Attempt {
+            status,
+            error,
+            elapsed,
+        });
+    }
+
+    /// Add the attempts to the response of the last one.
+    fn attach(self, outcome: Result<Response>) -> Result<Response> {
+        outcome.map(|mut res| {
+            res.insert_ext(self);
+            res
+        })
+    }
+}
+
+/// A single attempt of the [`Retry`] middleware, as recorded in [`Attempts`].
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attempt {
+    /// The status of the response, if one was received.
+    pub status: Option<StatusCode>,
+    /// The error the attempt failed with, if it didn't get a response.
+    pub error: Option<String>,
+    /// The time until the response arrived, or the attempt failed, as measured with the client's
+    /// [clock](crate::Config::set_clock).
+    pub elapsed: Duration,
+}
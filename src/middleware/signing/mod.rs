@@ -0,0 +1,106 @@
+//! Signing of requests, such as for HMAC-authenticated APIs or AWS Signature Version 4.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! use std::time::SystemTime;
+//! use surf::middleware::{Position, Signer, Signing};
+//! use surf::Request;
+//!
+//! #[derive(Debug)]
+//! struct BodyLength;
+//!
+//! impl Signer for BodyLength {
+//!     fn sign(&self, req: &mut Request, body: Option<&[u8]>, _: SystemTime) -> surf::Result<()> {
+//!         let len = body.map(<[u8]>::len).unwrap_or_default();
+//!         req.insert_header("X-Signed-Length", len.to_string());
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let client = surf::client().with_ordered(Signing::new(BodyLength), Position::last());
+//! client.post("https://example.com/orders").body_string("{}".into()).await?;
+//! # Ok(()) }
+//! ```
+
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::utils::map_body;
+use crate::{Body, Client, Result};
+
+use std::fmt::Debug;
+use std::time::SystemTime;
+
+/// Signs requests before they are sent, for the [`Signing`] middleware.
+///
+/// Signers are given the request as it will be sent, with its method, URL and headers, and the
+/// bytes of its body, so they can hash the body and add the signature as headers or query
+/// parameters. The body itself can't be changed.
+///
+/// The `aws-sign` feature provides [`aws::RequestSigner`](crate::aws::RequestSigner), which signs
+/// requests with AWS Signature Version 4.
+pub trait Signer: Debug + Send + Sync + 'static {
+    /// Sign `req`, whose body is `body`, at `time`.
+    ///
+    /// `body` is `None` if [`needs_body`](Signer::needs_body) returns `false`. `time` is the
+    /// current time of the client's [clock](crate::Config::set_clock).
+    fn sign(&self, req: &mut Request, body: Option<&[u8]>, time: SystemTime) -> Result<()>;
+
+    /// Whether [`sign`](Signer::sign) needs the bytes of the body.
+    ///
+    /// The body is read into memory to sign it, so signers which don't cover the body should
+    /// return `false`, which lets it be streamed.
+    ///
+    /// Default: `true`.
+    fn needs_body(&self) -> bool {
+        true
+    }
+}
+
+/// A middleware which signs requests with a [`Signer`].
+///
+/// The signature only covers what the request looks like when this middleware runs, so it
+/// should be added after the middleware which changes requests, e.g. with
+/// [`Position::last`](crate::middleware::Position::last). Per-request middleware runs after all
+/// middleware of the client.
+///
+/// # Errors
+///
+/// Requests fail with the error of the signer, or if their body can't be read.
+#[derive(Debug)]
+pub struct Signing<S> {
+    signer: S,
+}
+
+impl<S: Signer> Signing<S> {
+    /// Create a new instance, signing with `signer`.
+    pub fn new(signer: S) -> Self {
+        Self { signer }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: Signer> Middleware for Signing<S> {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let time = client.config().clock.system_now();
+        if !self.signer.needs_body() {
+            self.signer.sign(&mut req, None, time)?;
+            return next.run(req, client).await;
+        }
+
+        let body = req.take_body();
+        let mime = body.mime().clone();
+        let bytes = body.into_bytes().await?;
+        self.signer.sign(&mut req, Some(&bytes), time)?;
+
+        // Keep the headers exactly as they were signed.
+        map_body(&mut req, |_| {
+            let mut body = Body::from_bytes(bytes);
+            body.set_mime(mime);
+            body
+        });
+        next.run(req, client).await
+    }
+}
@@ -0,0 +1,295 @@
+//! Tracing middleware, which creates a [`tracing`] span per request and propagates the trace
+//! to the server.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! // Install a subscriber first, such as the one of `tracing-subscriber`.
+//! let client = surf::client().with(surf::middleware::Tracing::new());
+//! client.get("https://httpbin.org/get").await?;
+//! # Ok(()) }
+//! ```
+
+use crate::middleware::{LogContext, Middleware, Next, Request, Response};
+use crate::{Client, Result};
+
+use ::tracing::field::Empty;
+use ::tracing::Instrument;
+
+use std::fmt;
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+
+/// A middleware which creates a [`tracing`] span for each request, and propagates the trace to
+/// the server with the W3C Trace Context `traceparent` and `tracestate` headers.
+///
+/// The span is named `HTTP request` and has these fields:
+///
+/// - `method` and `url`: those of the request.
+/// - `trace_id`: the trace the request belongs to, as sent in `traceparent`.
+/// - `status`: the status of the response, if the request got one.
+/// - `duration`: the time until the response arrived, or the request failed, in seconds, as
+///   measured with the client's [clock](crate::Config::set_clock).
+/// - `error`: the error of a failed request.
+/// - `context`: the [`LogContext`] of the request, such as one set with
+///   [`RequestBuilder::log_context`](crate::RequestBuilder::log_context), as space-separated
+///   `key=value` pairs, if it has one.
+///
+/// The trace is continued from a [`TraceContext`] set as an extension of the request, or else
+/// from the `traceparent` and `tracestate` headers of the request, such as those copied from an
+/// incoming request by a server. Otherwise a new trace is started. Either way the request is
+/// sent with a new span id, drawn from the client's [random source](crate::Config::set_rng),
+/// and the context which was sent is added as an extension of the response.
+///
+/// # Examples
+///
+/// ```no_run
+/// use surf::middleware::{TraceContext, Tracing};
+///
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// let client = surf::client().with(Tracing::new());
+/// let parent = TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01", None)
+///     .expect("a valid traceparent");
+/// let mut req = surf::get("https://httpbin.org/get").build();
+/// req.set_ext(parent);
+/// let res = client.send(req).await?;
+/// let sent = res.ext::<TraceContext>().unwrap();
+/// assert_eq!(sent.trace_id(), 0x4bf92f3577b34da6a3ce929d0e0e4736);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Tracing {
+    _priv: (),
+}
+
+impl Tracing {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Tracing {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let parent = match req.ext::<TraceContext>() {
+            Some(parent) => Some(parent.clone()),
+            None => req.header(TRACEPARENT).and_then(|traceparent| {
+                let tracestate = req.header(TRACESTATE).map(|values| values.as_str());
+                TraceContext::parse(traceparent.as_str(), tracestate)
+            }),
+        };
+        let rng = &client.config().rng;
+        let context = match parent {
+            Some(parent) => TraceContext {
+                span_id: random_id(|| rng.next_u64()),
+                ..parent
+            },
+            None => TraceContext::new(
+                random_id(|| (u128::from(rng.next_u64()) << 64) | u128::from(rng.next_u64())),
+                random_id(|| rng.next_u64()),
+                true,
+            ),
+        };
+        req.insert_header(TRACEPARENT, context.traceparent());
+        if let Some(state) = context.state() {
+            req.insert_header(TRACESTATE, state);
+        } else {
+            req.remove_header(TRACESTATE);
+        }
+
+        let span = ::tracing::info_span!(
+            "HTTP request",
+            method = %req.method(),
+            url = %req.url(),
+            trace_id = %format_args!("{:032x}", context.trace_id),
+            status = Empty,
+            duration = Empty,
+            error = Empty,
+            context = Empty,
+        );
+        if let Some(context) = req
+            .ext::<LogContext>()
+            .filter(|context| context.iter().count() > 0)
+        {
+            span.record("context", ::tracing::field::display(Pairs(context)));
+        }
+        let clock = client.config().clock.clone();
+        let start = clock.now();
+        let res = next.run(req, client).instrument(span.clone()).await;
+        let elapsed = clock.now().saturating_duration_since(start);
+        span.record("duration", elapsed.as_secs_f64());
+        match res {
+            Ok(mut res) => {
+                span.record("status", u16::from(res.status()));
+                res.insert_ext(context);
+                Ok(res)
+            }
+            Err(err) => {
+                span.record("error", ::tracing::field::display(&err));
+                Err(err)
+            }
+        }
+    }
+}
+
+/// The pairs of a [`LogContext`], formatted as `key=value`, with values quoted if they contain
+/// spaces, quotes or `=`.
+struct Pairs<'a>(&'a LogContext);
+
+impl fmt::Display for Pairs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            let plain = !value.is_empty()
+                && !value
+                    .chars()
+                    .any(|c| c.is_whitespace() || c == '"' || c == '=');
+            match plain {
+                true => write!(f, "{}={}", key, value)?,
+                false => write!(f, "{}={:?}", key, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Draw a random id with `next`, which must not be zero, as zero ids are invalid.
+fn random_id<T: Default + PartialEq>(mut next: impl FnMut() -> T) -> T {
+    loop {
+        let id = next();
+        if id != T::default() {
+            return id;
+        }
+    }
+}
+
+/// The W3C Trace Context of a request: the trace it belongs to, and its parent span.
+///
+/// Set this as an extension of a request to continue a trace through the [`Tracing`]
+/// middleware, and read it from the extensions of the response to find the context which was
+/// sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: u128,
+    span_id: u64,
+    sampled: bool,
+    state: Option<String>,
+}
+
+impl TraceContext {
+    /// Create a new instance.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `trace_id` or `span_id` is zero, which the standard reserves as
+    /// invalid.
+    pub fn new(trace_id: u128, span_id: u64, sampled: bool) -> Self {
+        assert!(trace_id != 0, "the trace id must not be zero");
+        assert!(span_id != 0, "the span id must not be zero");
+        Self {
+            trace_id,
+            span_id,
+            sampled,
+            state: None,
+        }
+    }
+
+    /// Parse the values of the `traceparent` and `tracestate` headers.
+    ///
+    /// Returns `None` if `traceparent` is invalid. Versions after `00` are parsed as far as
+    /// they are compatible with it, as the standard requires.
+    ///
+    /// ```
+    /// use surf::middleware::TraceContext;
+    ///
+    /// let context = TraceContext::parse(
+    ///     "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+    ///     Some("congo=t61rcWkgMzE"),
+    /// ).unwrap();
+    /// assert_eq!(context.span_id(), 0x00f067aa0ba902b7);
+    /// assert!(context.sampled());
+    /// assert_eq!(context.state(), Some("congo=t61rcWkgMzE"));
+    /// ```
+    pub fn parse(traceparent: &str, tracestate: Option<&str>) -> Option<Self> {
+        let traceparent = traceparent.trim();
+        let mut parts = traceparent.splitn(5, '-');
+        let version = parse_hex(parts.next()?, 2)?;
+        let trace_id = parse_hex(parts.next()?, 32)?;
+        let span_id = parse_hex(parts.next()?, 16)?;
+        let flags = parse_hex(parts.next()?, 2)?;
+        let rest = parts.next();
+        if version == 0xff || (version == 0 && rest.is_some()) {
+            return None;
+        }
+        if trace_id == 0 || span_id == 0 {
+            return None;
+        }
+        let state = tracestate
+            .map(str::trim)
+            .filter(|state| !state.is_empty())
+            .map(String::from);
+        Some(Self {
+            trace_id,
+            span_id: span_id as u64,
+            sampled: flags & 1 == 1,
+            state,
+        })
+    }
+
+    /// Set the vendor-specific state sent in the `tracestate` header.
+    pub fn with_state(mut self, state: impl Into<String>) -> Self {
+        self.state = Some(state.into());
+        self
+    }
+
+    /// The id of the trace.
+    pub fn trace_id(&self) -> u128 {
+        self.trace_id
+    }
+
+    /// The id of the span which is the parent of the server's spans.
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    /// Whether the trace is recorded by the caller, as a hint for the server.
+    pub fn sampled(&self) -> bool {
+        self.sampled
+    }
+
+    /// The vendor-specific state sent in the `tracestate` header, if any.
+    pub fn state(&self) -> Option<&str> {
+        self.state.as_deref()
+    }
+
+    /// The value of the `traceparent` header for this context.
+    pub fn traceparent(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.sampled as u8
+        )
+    }
+}
+
+/// Parse exactly `len` lowercase hex digits.
+fn parse_hex(s: &str, len: usize) -> Option<u128> {
+    if s.len() != len || !s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+    u128::from_str_radix(s, 16).ok()
+}
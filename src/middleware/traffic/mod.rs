@@ -0,0 +1,330 @@
+//! Traffic accounting middleware, which counts the bytes sent to and received from each host.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use surf::middleware::TrafficAccounting;
+//!
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! let traffic = TrafficAccounting::new();
+//! let client = surf::client().with(traffic.clone());
+//! client.get("https://httpbin.org/get").recv_string().await?;
+//! println!("{:?}", traffic.host("httpbin.org"));
+//! # Ok(()) }
+//! ```
+
+use crate::http::headers::{HeaderName, HeaderValues};
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::utils::{map_body, map_body_reader, BodyReader};
+use crate::{Client, Result};
+
+use futures_util::io::AsyncRead;
+
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// A middleware which counts the header and body bytes sent to and received from each host,
+/// such as to account for egress costs.
+///
+/// Totals per host are kept by the middleware, and shared by its clones. The bytes of each
+/// request are also added to its response as a [`TrafficCounter`] extension.
+///
+/// Header sizes are those of the headers as serialized in HTTP/1.1, including the request or
+/// status line, for the headers surf sends and receives. Backends may add a few headers, such as
+/// `Host`, and compress headers in HTTP/2, so these are estimates. Body sizes are exact: they are
+/// counted as bodies are read, so a response body counts once it has been read.
+///
+/// With the `middleware-metrics` feature, the bytes can also be recorded through the `metrics`
+/// facade with [`TrafficAccounting::metrics`].
+#[derive(Debug, Clone, Default)]
+pub struct TrafficAccounting {
+    hosts: Arc<Mutex<HashMap<String, Arc<Counts>>>>,
+    #[cfg(feature = "middleware-metrics")]
+    metrics_prefix: Option<String>,
+}
+
+impl TrafficAccounting {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also record the bytes as the `{prefix}_bytes_sent_total` and
+    /// `{prefix}_bytes_received_total` counters of the `metrics` facade, labelled with the `host`
+    /// and the `part` of the message, which is `headers` or `body`.
+    ///
+    /// Default: No metrics are recorded.
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "middleware-metrics")))]
+    #[cfg(feature = "middleware-metrics")]
+    pub fn metrics(mut self, prefix: impl Into<String>) -> Self {
+        self.metrics_prefix = Some(prefix.into());
+        self
+    }
+
+    /// The bytes exchanged with `host` so far.
+    pub fn host(&self, host: &str) -> Traffic {
+        let hosts = self.hosts.lock().unwrap();
+        hosts
+            .get(&host.to_ascii_lowercase())
+            .map(|counts| counts.get())
+            .unwrap_or_default()
+    }
+
+    /// The bytes exchanged with every host so far, by host.
+    pub fn totals(&self) -> HashMap<String, Traffic> {
+        let hosts = self.hosts.lock().unwrap();
+        hosts
+            .iter()
+            .map(|(host, counts)| (host.clone(), counts.get()))
+            .collect()
+    }
+
+    /// Forget the bytes counted so far.
+    pub fn reset(&self) {
+        self.hosts.lock().unwrap().clear();
+    }
+
+    /// The counters bytes exchanged with `host` are added to.
+    fn sinks(&self, host: &str) -> Vec<Sink> {
+        let host_counts = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_default()
+            .clone();
+        #[allow(unused_mut)]
+        let mut sinks = vec![Sink::Counts(host_counts)];
+        #[cfg(feature = "middleware-metrics")]
+        if let Some(prefix) = &self.metrics_prefix {
+            sinks.push(Sink::Metrics {
+                prefix: prefix.clone(),
+                host: host.to_string(),
+            });
+        }
+        sinks
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for TrafficAccounting {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let host = req
+            .url()
+            .host_str()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let counter = TrafficCounter::default();
+        let mut sinks = self.sinks(&host);
+        sinks.push(Sink::Counts(counter.counts.clone()));
+        let sinks = Arc::new(sinks);
+
+        let target = match req.url().query() {
+            Some(query) => format!("{}?{}", req.url().path(), query),
+            None => req.url().path().to_string(),
+        };
+        let request_line = format!("{} {} HTTP/1.1", req.method(), target);
+        add(
+            &sinks,
+            Part::RequestHeaders,
+            header_bytes(&request_line, req.iter()),
+        );
+
+        // Empty bodies, such as those of most `GET` requests, have no bytes to count.
+        let len = req.len();
+        if len != Some(0) {
+            map_body(&mut req, |body| {
+                map_body_reader(body, len, |inner| CountingReader {
+                    inner,
+                    sinks: sinks.clone(),
+                    part: Part::RequestBody,
+                })
+            });
+        }
+
+        let mut res = next.run(req, client).await?;
+        let status = res.status();
+        let status_line = format!("HTTP/1.1 {} {}", status, status.canonical_reason());
+        add(
+            &sinks,
+            Part::ResponseHeaders,
+            header_bytes(&status_line, res.iter()),
+        );
+
+        let len = res.len();
+        res.map_body(|body| {
+            map_body_reader(body, len, |inner| CountingReader {
+                inner,
+                sinks,
+                part: Part::ResponseBody,
+            })
+        });
+        res.insert_ext(counter);
+        Ok(res)
+    }
+}
+
+/// Numbers of bytes sent and received, as counted by [`TrafficAccounting`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traffic {
+    /// The bytes of the request lines and headers sent.
+    pub request_header_bytes: u64,
+    /// The bytes of request bodies sent.
+    pub request_body_bytes: u64,
+    /// The bytes of the status lines and headers received.
+    pub response_header_bytes: u64,
+    /// The bytes of response bodies received.
+    pub response_body_bytes: u64,
+}
+
+impl Traffic {
+    /// The bytes sent, headers and bodies.
+    pub fn sent(&self) -> u64 {
+        self.request_header_bytes + self.request_body_bytes
+    }
+
+    /// The bytes received, headers and bodies.
+    pub fn received(&self) -> u64 {
+        self.response_header_bytes + self.response_body_bytes
+    }
+}
+
+/// The bytes exchanged for a single request, added to its response by [`TrafficAccounting`].
+///
+/// The count of response body bytes grows as the body is read.
+///
+/// # Examples
+///
+/// ```no_run
+/// use surf::middleware::{TrafficAccounting, TrafficCounter};
+///
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// let client = surf::client().with(TrafficAccounting::new());
+/// let mut res = client.get("https://httpbin.org/get").await?;
+/// res.body_bytes().await?;
+/// let traffic = res.ext::<TrafficCounter>().unwrap().get();
+/// println!("received {} bytes", traffic.received());
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TrafficCounter {
+    counts: Arc<Counts>,
+}
+
+impl TrafficCounter {
+    /// The bytes counted so far.
+    pub fn get(&self) -> Traffic {
+        self.counts.get()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    request_header_bytes: AtomicU64,
+    request_body_bytes: AtomicU64,
+    response_header_bytes: AtomicU64,
+    response_body_bytes: AtomicU64,
+}
+
+impl Counts {
+    fn get(&self) -> Traffic {
+        Traffic {
+            request_header_bytes: self.request_header_bytes.load(Ordering::Relaxed),
+            request_body_bytes: self.request_body_bytes.load(Ordering::Relaxed),
+            response_header_bytes: self.response_header_bytes.load(Ordering::Relaxed),
+            response_body_bytes: self.response_body_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    fn add(&self, part: Part, bytes: u64) {
+        let count = match part {
+            Part::RequestHeaders => &self.request_header_bytes,
+            Part::RequestBody => &self.request_body_bytes,
+            Part::ResponseHeaders => &self.response_header_bytes,
+            Part::ResponseBody => &self.response_body_bytes,
+        };
+        count.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+/// A part of a request or response.
+#[derive(Debug, Clone, Copy)]
+enum Part {
+    RequestHeaders,
+    RequestBody,
+    ResponseHeaders,
+    ResponseBody,
+}
+
+/// Where counted bytes go.
+enum Sink {
+    Counts(Arc<Counts>),
+    #[cfg(feature = "middleware-metrics")]
+    Metrics {
+        prefix: String,
+        host: String,
+    },
+}
+
+fn add(sinks: &[Sink], part: Part, bytes: u64) {
+    if bytes == 0 {
+        return;
+    }
+    for sink in sinks {
+        match sink {
+            Sink::Counts(counts) => counts.add(part, bytes),
+            #[cfg(feature = "middleware-metrics")]
+            Sink::Metrics { prefix, host } => {
+                let (direction, part) = match part {
+                    Part::RequestHeaders => ("sent", "headers"),
+                    Part::RequestBody => ("sent", "body"),
+                    Part::ResponseHeaders => ("received", "headers"),
+                    Part::ResponseBody => ("received", "body"),
+                };
+                let name = format!("{}_bytes_{}_total", prefix, direction);
+                metrics::counter!(name, "host" => host.clone(), "part" => part).increment(bytes);
+            }
+        }
+    }
+}
+
+/// The size of a start line and headers, as serialized in HTTP/1.1.
+fn header_bytes<'a>(
+    start_line: &str,
+    headers: impl Iterator<Item = (&'a HeaderName, &'a HeaderValues)>,
+) -> u64 {
+    let mut bytes = start_line.len() + 2;
+    for (name, values) in headers {
+        for value in values {
+            bytes += name.as_str().len() + 2 + value.as_str().len() + 2;
+        }
+    }
+    // The empty line which ends the headers.
+    (bytes + 2) as u64
+}
+
+/// A reader which counts the bytes of a body as they are read.
+struct CountingReader {
+    inner: BodyReader,
+    sinks: Arc<Vec<Sink>>,
+    part: Part,
+}
+
+impl AsyncRead for CountingReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let read = futures_util::ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        add(&self.sinks, self.part, read as u64);
+        Poll::Ready(Ok(read))
+    }
+}
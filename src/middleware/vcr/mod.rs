@@ -0,0 +1,453 @@
+//! Recording responses to a cassette file, and replaying them in later runs.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use surf::middleware::Vcr;
+//!
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! // The first run records the response, later runs replay it without the network.
+//! let vcr = Vcr::new("tests/cassettes/zen.json").redact_header("X-Api-Key");
+//! let client = surf::client().with(vcr);
+//! let zen = client.get("https://api.github.com/zen").recv_string().await?;
+//! # Ok(()) }
+//! ```
+
+#[cfg(feature = "gzip")]
+use crate::compress::decode_bytes;
+use crate::http::headers::{
+    HeaderName, HeaderValues, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE,
+    COOKIE, SET_COOKIE,
+};
+use crate::http::{self, Method, StatusCode, Url};
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::utils::map_body;
+use crate::{Body, Client, Error, Result};
+
+use serde_json::{json, Value};
+
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// What replaces redacted values in cassettes.
+const REDACTED: &str = "[REDACTED]";
+
+/// Whether a [`Vcr`] records or replays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VcrMode {
+    /// Replay the cassette if its file exists, and record a new one otherwise.
+    Once,
+    /// Send every request, and record a new cassette, replacing the file if it exists.
+    Record,
+    /// Only replay the cassette, failing requests it has no response for.
+    Replay,
+}
+
+/// A middleware which records requests and their responses to a cassette file, and replays the
+/// responses in later runs, for hermetic tests of code built on surf.
+///
+/// Requests are matched to recorded ones by their method, URL and body. Each recorded response
+/// is replayed once, in the order they were recorded, so a sequence of requests to the same URL
+/// gets the responses recorded for it in turn.
+///
+/// The cassette is a JSON file, meant to be checked in along with the tests. The values of
+/// `Authorization`, `Cookie` and `Set-Cookie` headers are redacted from it, along with the
+/// headers, query parameters and secrets added with [`redact_header`](Vcr::redact_header),
+/// [`redact_query_param`](Vcr::redact_query_param) and [`redact_secret`](Vcr::redact_secret).
+/// Requests are redacted the same way before matching them, so they still match.
+///
+/// With the `gzip` feature, bodies compressed with gzip or deflate are stored decoded, and
+/// marked as `decoded` in the cassette. Responses with decoded bodies are replayed without their
+/// `Content-Encoding` and `Content-Length` headers.
+///
+/// # Errors
+///
+/// When replaying, requests no recorded request matches fail with `501 Not Implemented`.
+#[derive(Debug, Clone)]
+pub struct Vcr {
+    path: PathBuf,
+    mode: VcrMode,
+    match_body: bool,
+    headers: Vec<HeaderName>,
+    query_params: Vec<String>,
+    secrets: Vec<String>,
+    cassette: Arc<Mutex<Option<Cassette>>>,
+}
+
+/// The interactions of a cassette, and whether it is being recorded.
+#[derive(Debug)]
+struct Cassette {
+    recording: bool,
+    interactions: Vec<Interaction>,
+    /// Which of `interactions` were replayed already.
+    replayed: Vec<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Interaction {
+    method: Method,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    /// Whether `body` was decoded from its content coding.
+    body_decoded: bool,
+    status: StatusCode,
+    response_headers: Vec<(String, String)>,
+    response_body: Vec<u8>,
+    response_body_decoded: bool,
+}
+
+impl Vcr {
+    /// Create a new instance, keeping the cassette in the file at `path`, in [`VcrMode::Once`].
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            mode: VcrMode::Once,
+            match_body: true,
+            headers: vec![AUTHORIZATION, COOKIE, SET_COOKIE],
+            query_params: Vec::new(),
+            secrets: Vec::new(),
+            cassette: Arc::default(),
+        }
+    }
+
+    /// Set whether to record or replay.
+    ///
+    /// Default: [`VcrMode::Once`].
+    pub fn mode(mut self, mode: VcrMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set whether requests must have the body of a recorded request to match it.
+    ///
+    /// Default: `true`.
+    pub fn match_body(mut self, match_body: bool) -> Self {
+        self.match_body = match_body;
+        self
+    }
+
+    /// Redact the values of the request and response header `name`, such as an API key.
+    pub fn redact_header(mut self, name: impl Into<HeaderName>) -> Self {
+        self.headers.push(name.into());
+        self
+    }
+
+    /// Redact the values of the query parameter `name` of request URLs, such as an access
+    /// token.
+    pub fn redact_query_param(mut self, name: impl Into<String>) -> Self {
+        self.query_params.push(name.into());
+        self
+    }
+
+    /// Redact `secret` wherever it appears in URLs, headers and text bodies.
+    pub fn redact_secret(mut self, secret: impl Into<String>) -> Self {
+        let secret = secret.into();
+        if !secret.is_empty() {
+            self.secrets.push(secret);
+        }
+        self
+    }
+
+    /// Load the cassette the first time it is needed.
+    fn load(&self, cassette: &mut Option<Cassette>) -> Result<()> {
+        if cassette.is_some() {
+            return Ok(());
+        }
+        let recording = match self.mode {
+            VcrMode::Record => true,
+            VcrMode::Replay => false,
+            VcrMode::Once => !self.path.exists(),
+        };
+        let interactions = match recording {
+            true => Vec::new(),
+            false => {
+                let json = std::fs::read_to_string(&self.path)?;
+                interactions_from_json(&json)?
+            }
+        };
+        *cassette = Some(Cassette {
+            recording,
+            replayed: vec![false; interactions.len()],
+            interactions,
+        });
+        Ok(())
+    }
+
+    /// Write the cassette, replacing its file atomically.
+    fn save(&self, interactions: &[Interaction]) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let json = interactions_to_json(interactions);
+        let written = serde_json::to_vec_pretty(&json)
+            .map_err(std::io::Error::from)
+            .and_then(|data| std::fs::write(&tmp, data))
+            .and_then(|_| std::fs::rename(&tmp, &self.path));
+        if let Err(err) = written {
+            std::fs::remove_file(&tmp).ok();
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// Redact the secrets from `text`.
+    fn redact_text(&self, text: &str) -> String {
+        self.secrets.iter().fold(text.to_string(), |text, secret| {
+            text.replace(secret, REDACTED)
+        })
+    }
+
+    fn redact_url(&self, url: &Url) -> String {
+        let mut url = url.clone();
+        if url.query().is_some() && !self.query_params.is_empty() {
+            let pairs: Vec<(String, String)> = url
+                .query_pairs()
+                .map(|(name, value)| {
+                    let value = match self.query_params.iter().any(|param| *param == name) {
+                        true => REDACTED.to_string(),
+                        false => value.into_owned(),
+                    };
+                    (name.into_owned(), value)
+                })
+                .collect();
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+        self.redact_text(url.as_str())
+    }
+
+    fn redact_headers<'a>(
+        &self,
+        headers: impl Iterator<Item = (&'a HeaderName, &'a http::headers::HeaderValues)>,
+    ) -> Vec<(String, String)> {
+        let mut redacted = Vec::new();
+        for (name, values) in headers {
+            for value in values {
+                let value = match self.headers.contains(name) {
+                    true => REDACTED.to_string(),
+                    false => self.redact_text(value.as_str()),
+                };
+                redacted.push((name.as_str().to_string(), value));
+            }
+        }
+        redacted
+    }
+
+    /// The bytes of `body` to store, decoded if it has a content coding which can be
+    /// decompressed, and whether they were decoded.
+    #[cfg_attr(not(feature = "gzip"), allow(unused_variables))]
+    fn stored_body(&self, encoding: Option<&HeaderValues>, body: &[u8]) -> (Vec<u8>, bool) {
+        #[cfg(feature = "gzip")]
+        {
+            let decoded = encoding.and_then(|values| decode_bytes(values.last().as_str(), body));
+            if let Some(decoded) = decoded {
+                return (self.redact_body(&decoded), true);
+            }
+        }
+        (self.redact_body(body), false)
+    }
+
+    fn redact_body(&self, body: &[u8]) -> Vec<u8> {
+        match std::str::from_utf8(body) {
+            Ok(text) if !self.secrets.is_empty() => self.redact_text(text).into_bytes(),
+            _ => body.to_vec(),
+        }
+    }
+
+    /// Find the first recorded interaction matching `request` which wasn't replayed yet.
+    fn replay(&self, cassette: &mut Cassette, request: &Interaction) -> Option<Interaction> {
+        let position = cassette
+            .interactions
+            .iter()
+            .zip(&cassette.replayed)
+            .position(|(recorded, replayed)| {
+                !replayed
+                    && recorded.method == request.method
+                    && recorded.url == request.url
+                    && (!self.match_body || recorded.body == request.body)
+            })?;
+        cassette.replayed[position] = true;
+        Some(cassette.interactions[position].clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Vcr {
+    #[allow(missing_doc_code_examples)]
+    async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        let body = req.take_body();
+        let mime = body.mime().clone();
+        let body = body.into_bytes().await?;
+        let (stored, body_decoded) = self.stored_body(req.header(CONTENT_ENCODING), &body);
+        let recorded_request = Interaction {
+            method: req.method(),
+            url: self.redact_url(req.url()),
+            headers: self.redact_headers(req.iter()),
+            body: stored,
+            body_decoded,
+            status: StatusCode::Ok,
+            response_headers: Vec::new(),
+            response_body: Vec::new(),
+            response_body_decoded: false,
+        };
+
+        {
+            let mut cassette = self.cassette.lock().unwrap();
+            self.load(&mut cassette)?;
+            let cassette = cassette.as_mut().unwrap();
+            if !cassette.recording {
+                return match self.replay(cassette, &recorded_request) {
+                    Some(interaction) => Ok(replayed_response(interaction)),
+                    None => Err(Error::from_str(
+                        StatusCode::NotImplemented,
+                        format!(
+                            "the cassette {} has no response for {} {}",
+                            self.path.display(),
+                            recorded_request.method,
+                            recorded_request.url
+                        ),
+                    )),
+                };
+            }
+        }
+
+        map_body(&mut req, |_| {
+            let mut sent = Body::from_bytes(body);
+            sent.set_mime(mime);
+            sent
+        });
+        let mut res = next.run(req, client).await?;
+
+        let body = res.take_body();
+        let mime = body.mime().clone();
+        let body = body.into_bytes().await?;
+        let (stored, response_body_decoded) = self.stored_body(res.header(CONTENT_ENCODING), &body);
+        let interaction = Interaction {
+            status: res.status(),
+            response_headers: self.redact_headers(res.iter()),
+            response_body: stored,
+            response_body_decoded,
+            ..recorded_request
+        };
+        {
+            let mut cassette = self.cassette.lock().unwrap();
+            let cassette = cassette.as_mut().unwrap();
+            cassette.interactions.push(interaction);
+            cassette.replayed.push(true);
+            self.save(&cassette.interactions)?;
+        }
+
+        res.map_body(|_| {
+            let mut received = Body::from_bytes(body);
+            received.set_mime(mime);
+            received
+        });
+        Ok(res)
+    }
+}
+
+/// The response recorded in `interaction`.
+fn replayed_response(interaction: Interaction) -> Response {
+    let mut res = http::Response::new(interaction.status);
+    res.set_body(Body::from_bytes(interaction.response_body));
+    res.remove_header(CONTENT_TYPE);
+    for (name, value) in &interaction.response_headers {
+        // These describe the body as it was received, not as it was decoded.
+        let encoded = name.eq_ignore_ascii_case(CONTENT_ENCODING.as_str())
+            || name.eq_ignore_ascii_case(CONTENT_LENGTH.as_str());
+        if interaction.response_body_decoded && encoded {
+            continue;
+        }
+        res.append_header(name.as_str(), value.as_str());
+    }
+    Response::from(res)
+}
+
+fn interactions_to_json(interactions: &[Interaction]) -> Value {
+    fn body(bytes: &[u8], decoded: bool) -> Value {
+        let mut body = match std::str::from_utf8(bytes) {
+            Ok(text) => json!({ "text": text }),
+            Err(_) => {
+                json!({ "hex": bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>() })
+            }
+        };
+        if decoded {
+            body["decoded"] = Value::Bool(true);
+        }
+        body
+    }
+    let interactions: Vec<Value> = interactions
+        .iter()
+        .map(|interaction| {
+            json!({
+                "request": {
+                    "method": interaction.method.to_string(),
+                    "url": interaction.url,
+                    "headers": interaction.headers,
+                    "body": body(&interaction.body, interaction.body_decoded),
+                },
+                "response": {
+                    "status": u16::from(interaction.status),
+                    "headers": interaction.response_headers,
+                    "body": body(&interaction.response_body, interaction.response_body_decoded),
+                },
+            })
+        })
+        .collect();
+    json!({ "interactions": interactions })
+}
+
+fn interactions_from_json(json: &str) -> Result<Vec<Interaction>> {
+    fn body(value: &Value) -> Option<Vec<u8>> {
+        if let Some(text) = value["text"].as_str() {
+            return Some(text.as_bytes().to_vec());
+        }
+        let hex = value["hex"].as_str()?;
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+    fn decoded(value: &Value) -> bool {
+        value["decoded"].as_bool().unwrap_or(false)
+    }
+    fn headers(value: &Value) -> Option<Vec<(String, String)>> {
+        value
+            .as_array()?
+            .iter()
+            .map(|pair| Some((pair[0].as_str()?.to_string(), pair[1].as_str()?.to_string())))
+            .collect()
+    }
+    fn interaction(value: &Value) -> Option<Interaction> {
+        let (request, response) = (&value["request"], &value["response"]);
+        let status = response["status"]
+            .as_u64()
+            .and_then(|status| u16::try_from(status).ok())
+            .and_then(|status| StatusCode::try_from(status).ok())?;
+        Some(Interaction {
+            method: request["method"].as_str()?.parse().ok()?,
+            url: request["url"].as_str()?.to_string(),
+            headers: headers(&request["headers"])?,
+            body: body(&request["body"])?,
+            body_decoded: decoded(&request["body"]),
+            status,
+            response_headers: headers(&response["headers"])?,
+            response_body: body(&response["body"])?,
+            response_body_decoded: decoded(&response["body"]),
+        })
+    }
+
+    let invalid = || Error::from_str(StatusCode::UnprocessableEntity, "invalid VCR cassette");
+    let value: Value = serde_json::from_str(json).map_err(|_| invalid())?;
+    value["interactions"]
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|value| interaction(value).ok_or_else(invalid))
+        .collect()
+}
@@ -0,0 +1,297 @@
+//! Dumping requests and responses as they are sent and received, for debugging.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use surf::middleware::Verbose;
+//!
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! let client = surf::client().with(Verbose::new().to_writer(std::io::stderr()));
+//! client.post("https://httpbin.org/post").body_string("hello".into()).await?;
+//! // > POST /post HTTP/1.1
+//! // > Host: httpbin.org
+//! // > Content-Type: text/plain;charset=utf-8
+//! // > [body: 5 bytes]
+//! // > hello
+//! // < HTTP/1.1 200 OK
+//! // < Content-Type: application/json
+//! // ...
+//! # Ok(()) }
+//! ```
+
+#[cfg(feature = "gzip")]
+use crate::compress::Decoder;
+use crate::http::headers::{Headers, CONTENT_ENCODING};
+use crate::middleware::{Middleware, Next, Request, Response};
+use crate::utils::{map_body, map_body_reader, BodyReader};
+use crate::{Client, Result};
+
+use futures_util::io::AsyncRead;
+
+use std::fmt;
+use std::io::{self, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// A middleware which dumps the head and the start of the body of each request and response,
+/// in HTTP/1.1 form, to the `log` facade or a writer.
+///
+/// Request lines are prefixed with `>` and response lines with `<`, as `curl --verbose` does.
+/// Heads are dumped with [`Request::dump_head`] and [`Response::dump_head`]. Bodies are dumped
+/// as they are read, up to the [body limit](Verbose::body_limit), once the limit is exceeded, the
+/// body ends, or it is dropped; binary bodies are only described. With the `gzip` feature, bodies
+/// compressed with gzip or deflate are dumped decoded. Backends may add headers which aren't
+/// dumped, such as `Content-Length`.
+///
+/// Dumps can hold credentials, such as the `Authorization` header, so they shouldn't be kept.
+#[derive(Clone)]
+pub struct Verbose {
+    sink: Sink,
+    body_limit: usize,
+}
+
+#[derive(Clone)]
+enum Sink {
+    Log(log::Level),
+    Writer(Arc<Mutex<dyn Write + Send>>),
+}
+
+impl Verbose {
+    /// Create a new instance, which logs dumps at the debug level with the `surf::verbose`
+    /// target.
+    pub fn new() -> Self {
+        Self {
+            sink: Sink::Log(log::Level::Debug),
+            body_limit: 1024,
+        }
+    }
+
+    /// Log dumps at `level`, rather than to a writer.
+    ///
+    /// Default: `log::Level::Debug`.
+    pub fn log_level(mut self, level: log::Level) -> Self {
+        self.sink = Sink::Log(level);
+        self
+    }
+
+    /// Write dumps to `writer`, such as `std::io::stderr()` or a file, rather than logging them.
+    ///
+    /// Each dump is written whole, followed by a newline, and flushed. Write errors are ignored.
+    pub fn to_writer(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.sink = Sink::Writer(Arc::new(Mutex::new(writer)));
+        self
+    }
+
+    /// Dump at most `limit` bytes of each body.
+    ///
+    /// Default: `1024`.
+    pub fn body_limit(mut self, limit: usize) -> Self {
+        self.body_limit = limit;
+        self
+    }
+
+    /// Wrap `body`, which has the `Content-Encoding` `encoding`, to dump its start as it is read.
+    #[cfg_attr(not(feature = "gzip"), allow(unused_variables))]
+    fn preview(
+        &self,
+        body: crate::http::Body,
+        prefix: char,
+        encoding: Option<String>,
+    ) -> crate::http::Body {
+        let len = body.len();
+        #[cfg(feature = "gzip")]
+        let decoder = encoding
+            .as_deref()
+            .and_then(Decoder::new)
+            .zip(encoding.clone());
+        map_body_reader(body, len, |inner| PreviewReader {
+            inner,
+            preview: Vec::new(),
+            read: 0,
+            len: len.map(|len| len as u64),
+            limit: self.body_limit,
+            prefix,
+            sink: Some(self.sink.clone()),
+            #[cfg(feature = "gzip")]
+            decoder,
+        })
+    }
+}
+
+impl Default for Verbose {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Verbose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sink = match &self.sink {
+            Sink::Log(level) => format!("log at {}", level),
+            Sink::Writer(_) => "writer".to_string(),
+        };
+        f.debug_struct("Verbose")
+            .field("sink", &sink)
+            .field("body_limit", &self.body_limit)
+            .finish()
+    }
+}
+
+impl Sink {
+    /// Dump `text`, prefixing each of its lines with `prefix`.
+    fn emit(&self, prefix: char, text: &str) {
+        let dump: Vec<String> = text
+            .lines()
+            .map(|line| format!("{} {}", prefix, line))
+            .collect();
+        let dump = dump.join("\n");
+        match self {
+            Sink::Log(level) => log::log!(target: "surf::verbose", *level, "{}", dump),
+            Sink::Writer(writer) => {
+                let mut writer = writer.lock().unwrap();
+                let _ = writeln!(writer, "{}", dump).and_then(|_| writer.flush());
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for Verbose {
+    async fn handle(&self, mut req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        self.sink.emit('>', &req.dump_head());
+        if req.len() != Some(0) {
+            let encoding = content_encoding(req.as_ref());
+            map_body(&mut req, |body| self.preview(body, '>', encoding));
+        }
+
+        let mut res = next.run(req, client).await?;
+        self.sink.emit('<', &res.dump_head());
+        if res.len() != Some(0) {
+            let encoding = content_encoding(res.as_ref());
+            res.map_body(|body| self.preview(body, '<', encoding));
+        }
+        Ok(res)
+    }
+}
+
+/// The value of the `Content-Encoding` header.
+fn content_encoding(headers: &Headers) -> Option<String> {
+    headers
+        .get(CONTENT_ENCODING)
+        .map(|values| values.last().to_string())
+}
+
+/// A reader which dumps the first bytes read from a body.
+struct PreviewReader {
+    inner: BodyReader,
+    preview: Vec<u8>,
+    read: u64,
+    /// The length of the body, if known, as readers may stop once they have read it.
+    len: Option<u64>,
+    limit: usize,
+    prefix: char,
+    /// Where to dump the preview, until it has been dumped.
+    sink: Option<Sink>,
+    /// The decoder of a compressed body, and its coding.
+    #[cfg(feature = "gzip")]
+    decoder: Option<(Decoder, String)>,
+}
+
+impl PreviewReader {
+    /// Dump the preview, described by `summary`, unless it has been dumped already.
+    fn dump(&mut self, summary: String) {
+        let sink = match self.sink.take() {
+            Some(sink) => sink,
+            None => return,
+        };
+        let text = match std::str::from_utf8(&self.preview) {
+            Ok(text) => Some(text),
+            // The preview may end in the middle of a character.
+            Err(err) if err.error_len().is_none() => {
+                std::str::from_utf8(&self.preview[..err.valid_up_to()]).ok()
+            }
+            Err(_) => None,
+        }
+        .filter(|text| {
+            !text
+                .chars()
+                .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+        });
+        let dump = match text {
+            Some(text) => format!("[body: {}]\n{}", summary, text),
+            None => format!("[body: {}, binary]", summary),
+        };
+        sink.emit(self.prefix, &dump);
+    }
+}
+
+impl AsyncRead for PreviewReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let read = match Pin::new(&mut self.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(read)) => read,
+            other => return other,
+        };
+        if self.sink.is_none() {
+            return Poll::Ready(Ok(read));
+        }
+
+        let this = &mut *self;
+        this.read += read as u64;
+        let complete = read == 0 || Some(this.read) == this.len;
+        #[cfg(feature = "gzip")]
+        if let Some((decoder, coding)) = &mut this.decoder {
+            // One byte more than the limit tells whether the decoded body exceeds it.
+            let limit = this.limit.saturating_add(1);
+            let mut decoded = decoder.decode_into(&buf[..read], &mut this.preview, limit);
+            if complete && read > 0 && decoded.is_ok() {
+                decoded = decoder.decode_into(&[], &mut this.preview, limit);
+            }
+            let truncated = this.preview.len() > this.limit;
+            this.preview.truncate(this.limit);
+            let summary = match (decoded, truncated) {
+                (Err(_), _) => format!(
+                    "{} bytes decoded from {} before it failed to decode",
+                    this.preview.len(),
+                    coding
+                ),
+                (Ok(()), true) => format!("first {} bytes decoded from {}", this.limit, coding),
+                (Ok(()), false) if complete => format!(
+                    "{} bytes of {}, {} bytes decoded",
+                    this.read,
+                    coding,
+                    this.preview.len()
+                ),
+                (Ok(()), false) => return Poll::Ready(Ok(read)),
+            };
+            this.dump(summary);
+            return Poll::Ready(Ok(read));
+        }
+        let room = this.limit.saturating_sub(this.preview.len()).min(read);
+        this.preview.extend_from_slice(&buf[..room]);
+        let truncated = this.read > this.limit as u64;
+        if complete || truncated {
+            let summary = match (complete, truncated) {
+                (true, true) => format!("first {} of {} bytes", this.limit, this.read),
+                (false, _) => format!("first {} bytes", this.limit),
+                (true, false) => format!("{} bytes", this.read),
+            };
+            this.dump(summary);
+        }
+        Poll::Ready(Ok(read))
+    }
+}
+
+impl Drop for PreviewReader {
+    fn drop(&mut self) {
+        if self.read > 0 {
+            let summary = format!("{} bytes read before the body was dropped", self.read);
+            self.dump(summary);
+        }
+    }
+}
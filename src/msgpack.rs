@@ -0,0 +1,39 @@
+//! MessagePack bodies.
+
+use crate::http::{mime::Mime, Body, StatusCode};
+use crate::Error;
+
+use serde::Serialize;
+
+use std::str::FromStr;
+
+/// The media type of MessagePack bodies.
+const MSGPACK: &str = "application/msgpack";
+
+/// A MessagePack body holding `value`, with its struct fields as map keys.
+pub(crate) fn msgpack_body(value: &impl Serialize) -> crate::Result<Body> {
+    let bytes = rmp_serde::to_vec_named(value)?;
+    let mut body = Body::from_bytes(bytes);
+    body.set_mime(Mime::from_str(MSGPACK).unwrap());
+    Ok(body)
+}
+
+/// Fail with a `415 Unsupported Media Type` error unless `mime` is a MessagePack media type.
+pub(crate) fn expect_msgpack(mime: Option<Mime>) -> crate::Result<()> {
+    match mime {
+        Some(mime) if is_msgpack(&mime) => Ok(()),
+        _ => Err(Error::from_str(
+            StatusCode::UnsupportedMediaType,
+            "the response isn't MessagePack",
+        )),
+    }
+}
+
+/// Whether `mime` is a MessagePack media type, including the unregistered `x-msgpack` and
+/// `vnd.msgpack` spellings and `+msgpack` suffixes.
+fn is_msgpack(mime: &Mime) -> bool {
+    let subtype = mime.subtype().to_ascii_lowercase();
+    mime.basetype().eq_ignore_ascii_case("application")
+        && (matches!(subtype.as_str(), "msgpack" | "x-msgpack" | "vnd.msgpack")
+            || subtype.ends_with("+msgpack"))
+}
@@ -0,0 +1,273 @@
+//! Decoding of `multipart/form-data` bodies, as described in [RFC 7578].
+//!
+//! [RFC 7578]: https://tools.ietf.org/html/rfc7578
+
+use crate::http::{Mime, StatusCode};
+use crate::Error;
+
+use std::str::Utf8Error;
+
+/// The parts of a `multipart/form-data` body, returned from
+/// [`Response::body_multipart`](crate::Response::body_multipart).
+///
+/// Parts are kept in the order they were sent, and can be looked up by the name of their field.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// let mut res = surf::post("https://example.com/upload").await?;
+/// let form = res.body_multipart().await?;
+/// if let Some(part) = form.get("receipt") {
+///     println!("{:?}: {} bytes", part.file_name(), part.bytes().len());
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Multipart {
+    parts: Vec<FormPart>,
+}
+
+impl Multipart {
+    /// Parse a body delimited by `boundary`.
+    pub(crate) fn parse(body: &[u8], boundary: &str) -> crate::Result<Self> {
+        let delimiter = format!("--{}", boundary).into_bytes();
+        let mut rest = match find(body, &delimiter) {
+            Some(0) => &body[delimiter.len()..],
+            // Anything before the first delimiter is a preamble, which is ignored.
+            _ => match find(body, &[b"\n".as_ref(), &delimiter].concat()) {
+                Some(start) => &body[start + 1 + delimiter.len()..],
+                None => return Err(malformed("the body has no boundary delimiter")),
+            },
+        };
+
+        let mut parts = Vec::new();
+        loop {
+            if rest.starts_with(b"--") {
+                return Ok(Self { parts });
+            }
+            rest = skip_line(rest).ok_or_else(|| malformed("the body ends after a delimiter"))?;
+            let end = find(rest, &[b"\n".as_ref(), &delimiter].concat())
+                .ok_or_else(|| malformed("the body has no closing delimiter"))?;
+            let mut content = &rest[..end];
+            if content.ends_with(b"\r") {
+                content = &content[..content.len() - 1];
+            }
+            parts.push(FormPart::parse(content)?);
+            rest = &rest[end + 1 + delimiter.len()..];
+        }
+    }
+
+    /// Get the first part of the field `name`.
+    pub fn get(&self, name: &str) -> Option<&FormPart> {
+        self.parts
+            .iter()
+            .find(|part| part.name.as_deref() == Some(name))
+    }
+
+    /// Get all parts of the field `name`, such as the files of a multiple file input.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a FormPart> + 'a {
+        self.parts
+            .iter()
+            .filter(move |part| part.name.as_deref() == Some(name))
+    }
+
+    /// An iterator over all parts, in the order they were sent.
+    pub fn iter(&self) -> std::slice::Iter<'_, FormPart> {
+        self.parts.iter()
+    }
+
+    /// The number of parts.
+    pub fn len(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Whether there are no parts.
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+}
+
+impl IntoIterator for Multipart {
+    type Item = FormPart;
+    type IntoIter = std::vec::IntoIter<FormPart>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.parts.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Multipart {
+    type Item = &'a FormPart;
+    type IntoIter = std::slice::Iter<'a, FormPart>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A part of a [`Multipart`] body: a field value or a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormPart {
+    name: Option<String>,
+    file_name: Option<String>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl FormPart {
+    /// Parse the headers and body of a part.
+    fn parse(content: &[u8]) -> crate::Result<Self> {
+        let (head, body) = match find(content, b"\r\n\r\n") {
+            Some(end) => (&content[..end], &content[end + 4..]),
+            None => match find(content, b"\n\n") {
+                Some(end) => (&content[..end], &content[end + 2..]),
+                // A part without headers starts with the empty line.
+                None if content.starts_with(b"\r\n") => (&[][..], &content[2..]),
+                None if content.starts_with(b"\n") => (&[][..], &content[1..]),
+                None => return Err(malformed("a part has no end of its headers")),
+            },
+        };
+        let head =
+            std::str::from_utf8(head).map_err(|_| malformed("a part has invalid headers"))?;
+
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in head.lines().filter(|line| !line.is_empty()) {
+            if line.starts_with(' ') || line.starts_with('\t') {
+                // An obsolete folded header continues the previous one.
+                let (_, value) = headers
+                    .last_mut()
+                    .ok_or_else(|| malformed("a part has invalid headers"))?;
+                value.push(' ');
+                value.push_str(line.trim());
+                continue;
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| malformed("a part has invalid headers"))?;
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+
+        let mut part = Self {
+            name: None,
+            file_name: None,
+            headers,
+            body: body.to_vec(),
+        };
+        if let Some(disposition) = part.header("Content-Disposition") {
+            let disposition = crate::parse::content_disposition(disposition);
+            part.name = disposition.param("name").map(str::to_string);
+            part.file_name = disposition.file_name().map(str::to_string);
+        }
+        Ok(part)
+    }
+
+    /// The name of the field of this part, from its `Content-Disposition` header.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The name of the file of this part, if it is a file.
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    /// The media type of this part, from its `Content-Type` header.
+    ///
+    /// Returns `None` for parts without the header, which are `text/plain` according to RFC 7578.
+    pub fn content_type(&self) -> Option<Mime> {
+        self.header("Content-Type")
+            .and_then(|value| value.parse().ok())
+    }
+
+    /// Get the value of the header `name` of this part.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// An iterator over the headers of this part, as names and values.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// The body of this part.
+    pub fn bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The body of this part as text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the body isn't valid UTF-8.
+    pub fn text(&self) -> Result<&str, Utf8Error> {
+        std::str::from_utf8(&self.body)
+    }
+
+    /// Take the body of this part.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.body
+    }
+}
+
+/// The error of a body which isn't valid `multipart/form-data`.
+fn malformed(reason: &str) -> Error {
+    Error::from_str(
+        StatusCode::UnprocessableEntity,
+        format!("invalid multipart body: {}", reason),
+    )
+}
+
+/// Find the first position of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Skip the rest of the line after a delimiter, which may only hold whitespace.
+fn skip_line(input: &[u8]) -> Option<&[u8]> {
+    let end = input.iter().position(|&byte| byte == b'\n')?;
+    Some(&input[end + 1..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_fields_and_files() {
+        let body = b"preamble\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"id\"\r\n\r\n\
+            42\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a \\\"b\\\".txt\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            x,y\r\n1,2\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename*=UTF-8''%E2%82%AC.txt\r\n\r\n\
+            \r\n--XyZ--\r\nepilogue";
+        let form = Multipart::parse(body, "XyZ").unwrap();
+        assert_eq!(form.len(), 3);
+        assert_eq!(form.get("id").unwrap().text().unwrap(), "42");
+        let files: Vec<_> = form.get_all("file").collect();
+        assert_eq!(files[0].file_name(), Some("a \"b\".txt"));
+        assert_eq!(files[0].bytes(), b"x,y\r\n1,2");
+        assert_eq!(files[0].content_type().unwrap().essence(), "text/csv");
+        assert_eq!(files[1].file_name(), Some("\u{20ac}.txt"));
+        assert!(files[1].bytes().is_empty());
+        assert!(form.get("missing").is_none());
+    }
+
+    #[test]
+    fn parse_rejects_truncated_bodies() {
+        assert!(Multipart::parse(b"no delimiter", "b").is_err());
+        let truncated = b"--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1";
+        assert!(Multipart::parse(truncated, "b").is_err());
+        assert!(Multipart::parse(b"--b--\r\n", "b").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,152 @@
+//! Nonces for request signing, which protect signed requests against replay.
+
+use crate::{Clock, OsRng, Request, Rng, SystemClock};
+
+use std::fmt::{Debug, Write};
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+
+/// A source of nonces for middleware which signs requests, such as HMAC, OAuth 1.0 or HTTP
+/// Message Signatures.
+///
+/// Signing middleware gets the nonce of a request with [`Request::nonce`], which generates it
+/// with the provider and stores it in the request extensions as a [`Nonce`].
+pub trait NonceProvider: Debug + Send + Sync + 'static {
+    /// Generate a new nonce.
+    fn generate(&self) -> String;
+
+    /// Whether attempts of the [`Retry`](crate::middleware::Retry) middleware reuse the nonce.
+    ///
+    /// Defaults to [`NoncePolicy::Regenerate`], as servers reject nonces they have seen.
+    fn policy(&self) -> NoncePolicy {
+        NoncePolicy::Regenerate
+    }
+}
+
+/// Whether a request which is retried keeps its nonce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoncePolicy {
+    /// Every attempt is signed with the same nonce, for servers which deduplicate retries by it.
+    Reuse,
+    /// Every attempt is signed with a new nonce.
+    Regenerate,
+}
+
+/// The nonce a request was signed with.
+///
+/// This is stored in the extensions of requests by [`Request::nonce`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nonce(String);
+
+impl Nonce {
+    /// The nonce as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// A provider of time-ordered [UUIDv7] nonces.
+///
+/// The nonces start with the current time in milliseconds, so servers can reject old ones
+/// without remembering every nonce, followed by 74 random bits.
+///
+/// ```
+/// use surf::{NonceProvider, UuidNonce};
+///
+/// let nonce = UuidNonce::new().generate();
+/// assert_eq!(nonce.len(), 36);
+/// assert_eq!(&nonce[14..15], "7");
+/// ```
+///
+/// [UUIDv7]: https://www.rfc-editor.org/rfc/rfc9562#name-uuid-version-7
+#[derive(Debug, Clone)]
+pub struct UuidNonce {
+    clock: Arc<dyn Clock>,
+    rng: Arc<dyn Rng>,
+    policy: NoncePolicy,
+}
+
+impl UuidNonce {
+    /// Create a new instance, using the system clock and the random number generator of the
+    /// operating system.
+    pub fn new() -> Self {
+        Self::with_sources(Arc::new(SystemClock), Arc::new(OsRng))
+    }
+
+    /// Create a new instance using `clock` and `rng`, such as those of a
+    /// [`Config`](crate::Config), so nonces can be reproduced in tests.
+    pub fn with_sources(clock: Arc<dyn Clock>, rng: Arc<dyn Rng>) -> Self {
+        Self {
+            clock,
+            rng,
+            policy: NoncePolicy::Regenerate,
+        }
+    }
+
+    /// Set whether retried requests reuse their nonce.
+    ///
+    /// Default: [`NoncePolicy::Regenerate`].
+    pub fn policy(mut self, policy: NoncePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+impl Default for UuidNonce {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NonceProvider for UuidNonce {
+    fn generate(&self) -> String {
+        let millis = self
+            .clock
+            .system_now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut bytes = [0; 16];
+        bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+        self.rng.fill_bytes(&mut bytes[6..]);
+        bytes[6] = 0x70 | (bytes[6] & 0x0f);
+        bytes[8] = 0x80 | (bytes[8] & 0x3f);
+
+        let mut uuid = String::with_capacity(36);
+        for (i, byte) in bytes.iter().enumerate() {
+            if let 4 | 6 | 8 | 10 = i {
+                uuid.push('-');
+            }
+            write!(uuid, "{:02x}", byte).unwrap();
+        }
+        uuid
+    }
+
+    fn policy(&self) -> NoncePolicy {
+        self.policy
+    }
+}
+
+/// The nonce shared by the attempts of a retried request.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct NonceSlot(Arc<Mutex<Option<String>>>);
+
+/// Get the nonce of `req`, generating it with `provider` unless it has one.
+pub(crate) fn request_nonce(req: &mut Request, provider: &dyn NonceProvider) -> String {
+    if let Some(nonce) = req.ext::<Nonce>() {
+        return nonce.0.clone();
+    }
+    let slot = req.ext::<NonceSlot>().cloned();
+    let nonce = match &slot {
+        Some(NonceSlot(slot)) => {
+            let mut slot = slot.lock().unwrap();
+            match (&*slot, provider.policy()) {
+                (Some(nonce), NoncePolicy::Reuse) => nonce.clone(),
+                _ => slot.insert(provider.generate()).clone(),
+            }
+        }
+        None => provider.generate(),
+    };
+    req.set_ext(Nonce(nonce.clone()));
+    nonce
+}
@@ -1,5 +1,32 @@
 use crate::http::Method;
-use crate::RequestBuilder;
+use crate::{Client, Config, RequestBuilder, Result};
+
+/// Configure the client used by the one-off functions, such as [`get`] and [`post`].
+///
+/// This gives scripts timeouts and default headers without creating a [`Client`]. The client
+/// is shared by every one-off request, so it must be configured before the first one is sent.
+/// One-off functions still need absolute URLs, even if `config` has a base URL.
+///
+/// # Errors
+///
+/// Returns an error if the global client has already been configured, or used by a one-off
+/// request, and if the backend can't be created from `config`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use surf::Config;
+///
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// surf::configure(Config::new().set_timeout(Some(Duration::from_secs(5))))?;
+/// let string = surf::get("https://httpbin.org/get").recv_string().await?;
+/// # Ok(()) }
+/// ```
+pub fn configure(config: Config) -> Result<()> {
+    Client::configure_shared(config)
+}
 
 /// Perform a one-off `GET` request.
 ///
@@ -0,0 +1,62 @@
+//! Streaming over paginated APIs.
+//!
+//! Many APIs return large collections one page at a time, with a cursor pointing to the next
+//! page somewhere in the response body. [`Client::paginate`] follows those cursors and flattens
+//! the pages into a single stream of items.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use serde::Deserialize;
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! use futures_util::TryStreamExt;
+//! use surf::{Request, Response};
+//!
+//! #[derive(Deserialize)]
+//! struct Page {
+//!     users: Vec<String>,
+//!     next_cursor: Option<String>,
+//! }
+//!
+//! impl From<Page> for Vec<String> {
+//!     fn from(page: Page) -> Self {
+//!         page.users
+//!     }
+//! }
+//!
+//! let client = surf::client();
+//! let next = |_: &Response, page: &Page| {
+//!     let cursor = page.next_cursor.as_ref()?;
+//!     let url = format!("https://example.com/users?cursor={}", cursor);
+//!     Some(surf::get(url).build())
+//! };
+//!
+//! let users: Vec<String> = client
+//!     .paginate::<Page, _>(surf::get("https://example.com/users"), next)
+//!     .try_collect()
+//!     .await?;
+//! # Ok(()) }
+//! ```
+//!
+//! [`Client::paginate`]: crate::Client::paginate
+
+use crate::{Request, Response};
+
+/// Finds the request for the next page of a paginated response.
+///
+/// This is implemented for closures taking the response and its decoded page.
+pub trait CursorExtractor<P>: Send + Sync + 'static {
+    /// Build the request for the page following `page`, or return `None` if `page` is the last
+    /// one.
+    fn next(&self, res: &Response, page: &P) -> Option<Request>;
+}
+
+impl<P, F> CursorExtractor<P> for F
+where
+    F: Fn(&Response, &P) -> Option<Request> + Send + Sync + 'static,
+{
+    fn next(&self, res: &Response, page: &P) -> Option<Request> {
+        (self)(res, page)
+    }
+}
@@ -0,0 +1,377 @@
+//! Parsers of what servers send, such as header values and bodies.
+//!
+//! These are the parsers responses are decoded with, exposed so that they can be reused on
+//! values from elsewhere and fuzzed on their own; the `fuzz` directory of the repository holds
+//! [cargo-fuzz] targets for each of them. They never panic, whatever their input: malformed parts
+//! of header values are skipped, and malformed bodies are rejected with an error.
+//!
+//! # Examples
+//!
+//! ```
+//! use surf::parse;
+//!
+//! let links = parse::links(r#"<https://api.example.com/items?page=2>; rel="next", <?page=9>; rel=last"#);
+//! let next = links.iter().find(|link| link.has_rel("next")).unwrap();
+//! assert_eq!(next.target(), "https://api.example.com/items?page=2");
+//!
+//! let disposition = parse::content_disposition("attachment; filename*=UTF-8''%E2%82%AC.txt");
+//! assert_eq!(disposition.file_name(), Some("\u{20ac}.txt"));
+//! ```
+//!
+//! [cargo-fuzz]: https://github.com/rust-fuzz/cargo-fuzz
+
+use crate::http::cache::CacheDirective;
+use crate::http::headers::{Headers, CACHE_CONTROL};
+use crate::multipart::Multipart;
+use crate::response::{DecodeError, TextDecoder};
+use crate::utils::percent_decode;
+
+use std::time::Duration;
+
+/// Decode `bytes` as text in the encoding with the `charset` label, or UTF-8 without one.
+///
+/// Encodings other than UTF-8 are only supported with the "encoding" feature.
+///
+/// # Errors
+///
+/// Returns a [`DecodeError`] holding `bytes` if the encoding is unsupported, or `bytes` aren't
+/// valid in it.
+pub fn text(bytes: &[u8], charset: Option<&str>) -> Result<String, DecodeError> {
+    TextDecoder::new(charset)
+        .and_then(|mut decoder| decoder.decode(bytes, true))
+        .map_err(|err| DecodeError {
+            data: bytes.to_vec(),
+            ..err
+        })
+}
+
+/// Parse a `multipart/form-data` body delimited by `boundary`, as
+/// [`Response::body_multipart`](crate::Response::body_multipart) does.
+///
+/// # Errors
+///
+/// Returns a `422 Unprocessable Entity` error if the body is malformed.
+pub fn multipart(body: &[u8], boundary: &str) -> crate::Result<Multipart> {
+    Multipart::parse(body, boundary)
+}
+
+/// A link of a `Link` header, as described in [RFC 8288], returned from [`links`].
+///
+/// [RFC 8288]: https://tools.ietf.org/html/rfc8288
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    target: String,
+    params: Vec<(String, String)>,
+}
+
+impl Link {
+    /// The URL the link points to, which may be relative to the URL of the response.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// The relation types of the link, from its `rel` parameter, such as `next`.
+    pub fn rel(&self) -> impl Iterator<Item = &str> {
+        self.param("rel")
+            .unwrap_or_default()
+            .split_ascii_whitespace()
+    }
+
+    /// Whether the link has the relation type `rel`, ignoring case.
+    pub fn has_rel(&self, rel: &str) -> bool {
+        self.rel()
+            .any(|candidate| candidate.eq_ignore_ascii_case(rel))
+    }
+
+    /// Get the value of the parameter `name` of the link, ignoring case, unquoted.
+    ///
+    /// Only the first occurrence of a parameter counts, as RFC 8288 requires.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        find_param(&self.params, name)
+    }
+}
+
+/// Parse the links of a `Link` header value.
+///
+/// Links without a target between `<` and `>` are skipped.
+pub fn links(value: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+        if rest.is_empty() {
+            return links;
+        }
+        let target = match rest.strip_prefix('<').and_then(|rest| rest.split_once('>')) {
+            Some((target, after)) => {
+                rest = after;
+                Some(target.trim())
+            }
+            None => None,
+        };
+        let (params, after) = parameters(rest, b';', Some(b','));
+        rest = after;
+        if let Some(target) = target {
+            links.push(Link {
+                target: target.to_string(),
+                params,
+            });
+        }
+    }
+}
+
+/// The directives of a `Cache-Control` header value.
+///
+/// Unlike [`CacheControl`](crate::http::cache::CacheControl), directive names are matched
+/// ignoring case, quoted values are accepted, and a malformed directive is skipped rather than
+/// discarding the others. Unknown directives are skipped too. Ages too large to represent are
+/// taken as 2<sup>31</sup> seconds, as RFC 9111 recommends.
+pub fn cache_control(value: &str) -> Vec<CacheDirective> {
+    let (params, _) = parameters(value, b',', None);
+    params
+        .iter()
+        .filter_map(|(name, value)| {
+            let secs = || -> Option<Duration> {
+                if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+                    return None;
+                }
+                Some(Duration::from_secs(value.parse().unwrap_or(1 << 31)))
+            };
+            let directive = match name.as_str() {
+                "immutable" => CacheDirective::Immutable,
+                "no-cache" => CacheDirective::NoCache,
+                "no-store" => CacheDirective::NoStore,
+                "no-transform" => CacheDirective::NoTransform,
+                "only-if-cached" => CacheDirective::OnlyIfCached,
+                "must-revalidate" => CacheDirective::MustRevalidate,
+                "public" => CacheDirective::Public,
+                "private" => CacheDirective::Private,
+                "proxy-revalidate" => CacheDirective::ProxyRevalidate,
+                "max-age" => CacheDirective::MaxAge(secs()?),
+                "max-stale" if value.is_empty() => CacheDirective::MaxStale(None),
+                "max-stale" => CacheDirective::MaxStale(Some(secs()?)),
+                "min-fresh" => CacheDirective::MinFresh(secs()?),
+                "s-maxage" => CacheDirective::SMaxAge(secs()?),
+                "stale-if-error" => CacheDirective::StaleIfError(secs()?),
+                "stale-while-revalidate" => CacheDirective::StaleWhileRevalidate(secs()?),
+                _ => return None,
+            };
+            Some(directive)
+        })
+        .collect()
+}
+
+/// The directives of all the `Cache-Control` headers of a message.
+pub(crate) fn cache_directives(headers: impl AsRef<Headers>) -> Vec<CacheDirective> {
+    headers
+        .as_ref()
+        .get(CACHE_CONTROL)
+        .map(|values| {
+            values
+                .iter()
+                .flat_map(|value| cache_control(value.as_str()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A parsed `Content-Disposition` header value, as described in [RFC 6266], returned from
+/// [`content_disposition`].
+///
+/// [RFC 6266]: https://tools.ietf.org/html/rfc6266
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentDisposition {
+    disposition: String,
+    params: Vec<(String, String)>,
+    file_name: Option<String>,
+}
+
+impl ContentDisposition {
+    /// The disposition type, in lowercase, such as `attachment` or `form-data`.
+    pub fn disposition(&self) -> &str {
+        &self.disposition
+    }
+
+    /// Get the value of the parameter `name`, ignoring case, unquoted.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        find_param(&self.params, name)
+    }
+
+    /// The file name, from the `filename*` parameter if it can be decoded, or else from the
+    /// `filename` parameter.
+    ///
+    /// The file name is as sent by the server, so it may hold path separators or `..`.
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+}
+
+/// Parse a `Content-Disposition` header value.
+pub fn content_disposition(value: &str) -> ContentDisposition {
+    let (disposition, rest) = value.split_once(';').unwrap_or((value, ""));
+    let (params, _) = parameters(rest, b';', None);
+    let file_name = find_param(&params, "filename*")
+        .and_then(decode_ext_value)
+        .or_else(|| find_param(&params, "filename").map(str::to_string));
+    ContentDisposition {
+        disposition: disposition.trim().to_ascii_lowercase(),
+        params,
+        file_name,
+    }
+}
+
+/// Get the value of the first parameter named `name` among `params`, ignoring case.
+fn find_param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+/// Parse the `name[=value]` parameters at the start of `input`, separated by `separator`, until
+/// its end or, if set, an `end` byte outside of a quoted value.
+///
+/// Returns the parameters, with their names in lowercase and their values unquoted, and the rest
+/// of `input` from the `end` byte. Parameters without a name are skipped, and parameters without
+/// a value have an empty one.
+fn parameters(input: &str, separator: u8, end: Option<u8>) -> (Vec<(String, String)>, &str) {
+    let bytes = input.as_bytes();
+    let at_end = |i: usize| i == bytes.len() || Some(bytes[i]) == end;
+    let mut params = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < bytes.len() && (bytes[i] == separator || bytes[i].is_ascii_whitespace()) {
+            i += 1;
+        }
+        if at_end(i) {
+            // Splitting at an ASCII byte always falls on a character boundary.
+            return (params, &input[i..]);
+        }
+
+        let start = i;
+        while !at_end(i) && bytes[i] != b'=' && bytes[i] != separator {
+            i += 1;
+        }
+        let name = input[start..i].trim().to_ascii_lowercase();
+        let mut value = Vec::new();
+        if !at_end(i) && bytes[i] == b'=' {
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            if bytes.get(i) == Some(&b'"') {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    if bytes[i] == b'\\' && i + 1 < bytes.len() {
+                        i += 1;
+                    }
+                    value.push(bytes[i]);
+                    i += 1;
+                }
+                // Skip the closing quote, if the value isn't cut short.
+                i = (i + 1).min(bytes.len());
+            } else {
+                let start = i;
+                while !at_end(i) && bytes[i] != separator {
+                    i += 1;
+                }
+                value.extend_from_slice(input[start..i].trim().as_bytes());
+            }
+        }
+        // Skip anything between the value and the next parameter.
+        while !at_end(i) && bytes[i] != separator {
+            i += 1;
+        }
+        if !name.is_empty() {
+            // Only ASCII backslashes and quotes were left out, so the value is still UTF-8.
+            params.push((name, String::from_utf8_lossy(&value).into_owned()));
+        }
+    }
+}
+
+/// Decode an RFC 8187 extended value, such as `UTF-8''%e2%82%ac.txt`.
+///
+/// Only the UTF-8 and ISO-8859-1 charsets are supported, as RFC 6266 recommends.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut fields = value.splitn(3, '\'');
+    let charset = fields.next()?;
+    let _language = fields.next()?;
+    let bytes = percent_decode(fields.next()?);
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_skip_malformed_entries() {
+        let links = links(
+            "<a>; rel=\"next last\"; title=\"x, y\", garbage; rel=next,\
+             <b>;;rel=prev;rel=other;=x;flag, <unterminated",
+        );
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].target(), "a");
+        assert!(links[0].has_rel("LAST"));
+        assert_eq!(links[0].param("title"), Some("x, y"));
+        assert_eq!(links[1].rel().collect::<Vec<_>>(), ["prev"]);
+        assert_eq!(links[1].param("flag"), Some(""));
+    }
+
+    #[test]
+    fn cache_control_skips_malformed_directives() {
+        let directives =
+            cache_control("No-Store, max-age=abc, s-maxage=\"60\", max-stale, min-fresh=99999999999999999999, x=\"a,b\"");
+        assert_eq!(
+            directives,
+            [
+                CacheDirective::NoStore,
+                CacheDirective::SMaxAge(Duration::from_secs(60)),
+                CacheDirective::MaxStale(None),
+                CacheDirective::MinFresh(Duration::from_secs(1 << 31)),
+            ]
+        );
+    }
+
+    #[test]
+    fn content_disposition_decodes_file_names() {
+        let disposition = content_disposition("Attachment; filename=\"a\\\"b.txt");
+        assert_eq!(disposition.disposition(), "attachment");
+        assert_eq!(disposition.file_name(), Some("a\"b.txt"));
+
+        let disposition =
+            content_disposition("attachment; filename=fallback; FILENAME*=iso-8859-1'en'%A3.txt");
+        assert_eq!(disposition.file_name(), Some("\u{a3}.txt"));
+
+        let disposition = content_disposition("inline; filename*=UTF-8''%FF; filename=b");
+        assert_eq!(disposition.file_name(), Some("b"));
+        assert_eq!(content_disposition("").file_name(), None);
+    }
+
+    #[test]
+    fn parsers_survive_arbitrary_input() {
+        let inputs: [&[u8]; 6] = [
+            b"",
+            b"\"",
+            b"<<>;=\\",
+            b";;,,==\"\\",
+            "\u{20ac}<\u{20ac}>;\u{20ac}=\"\u{20ac}\\\u{20ac}".as_bytes(),
+            b"--b\r\n\r\n--b\n\n--b--",
+        ];
+        for input in inputs.iter() {
+            let value = String::from_utf8_lossy(input);
+            links(&value);
+            cache_control(&value);
+            content_disposition(&value);
+            let _ = multipart(input, "b");
+            let _ = multipart(input, "");
+            let _ = text(input, Some(&value));
+        }
+    }
+}
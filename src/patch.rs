@@ -0,0 +1,131 @@
+//! JSON Patch and JSON Merge Patch request bodies, as described in [RFC 6902] and [RFC 7396].
+//!
+//! [RFC 6902]: https://tools.ietf.org/html/rfc6902
+//! [RFC 7396]: https://tools.ietf.org/html/rfc7396
+
+use crate::http::{Body, Mime};
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::Value;
+
+use std::str::FromStr;
+
+/// The media type of JSON Patch documents.
+const JSON_PATCH: &str = "application/json-patch+json";
+
+/// The media type of JSON Merge Patch documents.
+const MERGE_PATCH: &str = "application/merge-patch+json";
+
+/// An operation of a JSON Patch document, as described in [RFC 6902].
+///
+/// Paths are JSON Pointers, such as `/items/0/name`.
+///
+/// ```
+/// use serde_json::json;
+/// use surf::PatchOp;
+///
+/// let ops = [
+///     PatchOp::Test { path: "/version".into(), value: json!(3) },
+///     PatchOp::Replace { path: "/name".into(), value: json!("surf") },
+///     PatchOp::Remove { path: "/draft".into() },
+/// ];
+/// let req = surf::patch("https://example.com/packages/1").body_json_patch(&ops)?;
+/// # Ok::<(), surf::Error>(())
+/// ```
+///
+/// [RFC 6902]: https://tools.ietf.org/html/rfc6902
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchOp {
+    /// Add `value` at `path`, inserting it into an array or replacing an object member.
+    Add {
+        /// Where to add the value.
+        path: String,
+        /// The value to add.
+        value: Value,
+    },
+    /// Remove the value at `path`.
+    Remove {
+        /// The value to remove.
+        path: String,
+    },
+    /// Replace the value at `path` with `value`.
+    Replace {
+        /// The value to replace.
+        path: String,
+        /// The new value.
+        value: Value,
+    },
+    /// Move the value at `from` to `path`.
+    Move {
+        /// The value to move.
+        from: String,
+        /// Where to move the value.
+        path: String,
+    },
+    /// Copy the value at `from` to `path`.
+    Copy {
+        /// The value to copy.
+        from: String,
+        /// Where to copy the value.
+        path: String,
+    },
+    /// Check that the value at `path` equals `value`, failing the whole patch otherwise.
+    Test {
+        /// The value to check.
+        path: String,
+        /// The expected value.
+        value: Value,
+    },
+}
+
+impl Serialize for PatchOp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            PatchOp::Add { path, value } => {
+                map.serialize_entry("op", "add")?;
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("value", value)?;
+            }
+            PatchOp::Remove { path } => {
+                map.serialize_entry("op", "remove")?;
+                map.serialize_entry("path", path)?;
+            }
+            PatchOp::Replace { path, value } => {
+                map.serialize_entry("op", "replace")?;
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("value", value)?;
+            }
+            PatchOp::Move { from, path } => {
+                map.serialize_entry("op", "move")?;
+                map.serialize_entry("from", from)?;
+                map.serialize_entry("path", path)?;
+            }
+            PatchOp::Copy { from, path } => {
+                map.serialize_entry("op", "copy")?;
+                map.serialize_entry("from", from)?;
+                map.serialize_entry("path", path)?;
+            }
+            PatchOp::Test { path, value } => {
+                map.serialize_entry("op", "test")?;
+                map.serialize_entry("path", path)?;
+                map.serialize_entry("value", value)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// A JSON Patch body applying `ops` in order.
+pub(crate) fn json_patch_body(ops: &[PatchOp]) -> crate::Result<Body> {
+    let mut body = Body::from_json(&ops)?;
+    body.set_mime(Mime::from_str(JSON_PATCH).unwrap());
+    Ok(body)
+}
+
+/// A JSON Merge Patch body merging `patch` into the target.
+pub(crate) fn merge_patch_body(patch: &impl Serialize) -> crate::Result<Body> {
+    let mut body = Body::from_json(patch)?;
+    body.set_mime(Mime::from_str(MERGE_PATCH).unwrap());
+    Ok(body)
+}
@@ -0,0 +1,127 @@
+//! Problem Details for HTTP APIs, as described in [RFC 7807].
+//!
+//! [RFC 7807]: https://tools.ietf.org/html/rfc7807
+
+use crate::http::{Mime, StatusCode};
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use std::convert::TryFrom;
+use std::fmt;
+
+/// The media type of problem details in JSON.
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// A machine-readable description of an error, as described in [RFC 7807].
+///
+/// Retrieved with [`Response::problem_details`](crate::Response::problem_details), or from
+/// the [`StatusError`](crate::StatusError) returned by
+/// [`Response::error_for_status`](crate::Response::error_for_status).
+///
+/// Members of the wrong type are ignored, as the RFC requires. Members other than the standard
+/// ones are kept as [extensions](ProblemDetails::extensions).
+///
+/// [RFC 7807]: https://tools.ietf.org/html/rfc7807
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProblemDetails {
+    problem_type: String,
+    title: Option<String>,
+    status: Option<StatusCode>,
+    detail: Option<String>,
+    instance: Option<String>,
+    extensions: Map<String, Value>,
+}
+
+impl ProblemDetails {
+    /// Parse problem details from a JSON document.
+    pub(crate) fn from_slice(bytes: &[u8]) -> serde_json::Result<Self> {
+        let mut members: Map<String, Value> = serde_json::from_slice(bytes)?;
+        let mut string = |name: &str| match members.remove(name) {
+            Some(Value::String(value)) => Some(value),
+            _ => None,
+        };
+        let problem_type = string("type").unwrap_or_else(|| "about:blank".to_string());
+        let title = string("title");
+        let detail = string("detail");
+        let instance = string("instance");
+        let status = members
+            .remove("status")
+            .and_then(|status| status.as_u64())
+            .and_then(|status| u16::try_from(status).ok())
+            .and_then(|status| StatusCode::try_from(status).ok());
+        Ok(Self {
+            problem_type,
+            title,
+            status,
+            detail,
+            instance,
+            extensions: members,
+        })
+    }
+
+    /// A URI reference identifying the problem type.
+    ///
+    /// Defaults to `about:blank`, which means the problem has no semantics beyond its status.
+    pub fn problem_type(&self) -> &str {
+        &self.problem_type
+    }
+
+    /// A short, human-readable summary of the problem type.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// The status code generated by the origin server for this occurrence of the problem.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.status
+    }
+
+    /// A human-readable explanation specific to this occurrence of the problem.
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+
+    /// A URI reference identifying this occurrence of the problem.
+    pub fn instance(&self) -> Option<&str> {
+        self.instance.as_deref()
+    }
+
+    /// The members defined by the problem type, beyond the standard ones.
+    pub fn extensions(&self) -> &Map<String, Value> {
+        &self.extensions
+    }
+
+    /// Decode the extension member `name`, if it is present and of type `T`.
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let mut res = surf::get("https://api.example.com/purchases/1").await?;
+    /// if let Some(problem) = res.problem_details().await? {
+    ///     let balance: Option<u64> = problem.extension("balance");
+    ///     println!("{}, balance: {:?}", problem, balance);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn extension<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let value = self.extensions.get(name)?;
+        serde_json::from_value(value.clone()).ok()
+    }
+}
+
+impl fmt::Display for ProblemDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.title, &self.detail) {
+            (Some(title), Some(detail)) => write!(f, "{}: {}", title, detail),
+            (Some(title), None) => write!(f, "{}", title),
+            (None, Some(detail)) => write!(f, "{}", detail),
+            (None, None) => write!(f, "{}", self.problem_type),
+        }
+    }
+}
+
+/// Whether `mime` is the media type of problem details in JSON.
+pub(crate) fn is_problem_json(mime: &Mime) -> bool {
+    mime.essence() == PROBLEM_JSON
+}
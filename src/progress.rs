@@ -0,0 +1,47 @@
+//! Progress reporting for bodies.
+
+use crate::http::Body;
+use crate::utils::{map_body_reader, BodyReader};
+
+use futures_util::io::AsyncRead;
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A callback for the progress of reading a body.
+pub(crate) type Progress = dyn Fn(u64, Option<u64>) + Send + Sync + 'static;
+
+/// Wrap `body` so `progress` is called with the number of bytes read so far, and the length of
+/// the body if known, after every read.
+pub(crate) fn report_progress(body: Body, progress: Box<Progress>) -> Body {
+    let len = body.len();
+    map_body_reader(body, len, |inner| ProgressReader {
+        inner,
+        read: 0,
+        total: len.map(|len| len as u64),
+        progress,
+    })
+}
+
+struct ProgressReader {
+    inner: BodyReader,
+    read: u64,
+    total: Option<u64>,
+    progress: Box<Progress>,
+}
+
+impl AsyncRead for ProgressReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let read = futures_util::ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        if read > 0 {
+            self.read += read as u64;
+            (self.progress)(self.read, self.total);
+        }
+        Poll::Ready(Ok(read))
+    }
+}
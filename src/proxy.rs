@@ -0,0 +1,66 @@
+//! Forwarding of incoming requests, for building reverse proxies.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! use std::convert::TryInto;
+//! use surf::{Client, Config, Url};
+//!
+//! let upstream: Client = Config::new()
+//!     .set_base_url(Url::parse("http://localhost:8081/")?)
+//!     .try_into()?;
+//!
+//! let mut app = tide::with_state(upstream);
+//! app.at("*").all(|req: tide::Request<Client>| async move {
+//!     let client = req.state().clone();
+//!     let res = surf::proxy::forward(req, &client).await?;
+//!     let res: surf::http::Response = res.into();
+//!     Ok(tide::Response::from(res))
+//! });
+//! app.listen("localhost:8080").await?;
+//! # Ok(()) }
+//! ```
+
+use crate::http::{self, Url};
+use crate::utils::{relative_reference, strip_hop_by_hop_headers};
+use crate::{Client, Request, Response, Result};
+
+/// Forward an incoming request through `client`, and return the upstream response.
+///
+/// The path and query of the incoming request are joined onto the base URL of the client, or
+/// the incoming URL is used as-is if the client has no base URL. Hop-by-hop headers (see
+/// [`strip_hop_by_hop_headers`]) and the `Host` header are dropped, the peer address is
+/// appended to `X-Forwarded-For`, and the request goes through the client's middleware.
+///
+/// Both bodies are streamed: the incoming body is sent upstream as it is read, and the body of
+/// the returned response is read from upstream as it is consumed. Hop-by-hop headers are removed
+/// from the response too.
+pub async fn forward(req: impl Into<http::Request>, client: &Client) -> Result<Response> {
+    let mut req: http::Request = req.into();
+
+    let url = match client.config().base_url {
+        Some(_) => client.url(relative_reference(req.url())),
+        None => req.url().clone(),
+    };
+    *req.url_mut() = url;
+
+    strip_hop_by_hop_headers(&mut req);
+    req.remove_header("host");
+    if let Some(peer_addr) = req.peer_addr().map(forwarded_for) {
+        req.append_header("x-forwarded-for", peer_addr);
+    }
+
+    let mut res = client.send(Request::from(req)).await?;
+    strip_hop_by_hop_headers(&mut res);
+    Ok(res)
+}
+
+/// Strip the port from a peer address, leaving the host.
+fn forwarded_for(peer_addr: &str) -> String {
+    Url::parse(&format!("tcp://{}", peer_addr))
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_string()))
+        .unwrap_or_else(|| peer_addr.to_string())
+}
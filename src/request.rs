@@ -1,14 +1,25 @@
+#[cfg(feature = "encoding")]
+use crate::charset::encoded_string_body;
+#[cfg(feature = "gzip")]
+use crate::compress::compress_body;
 use crate::http::{
     self,
-    headers::{self, HeaderName, HeaderValues, ToHeaderValues},
+    headers::{self, HeaderName, HeaderValues, ToHeaderValues, HOST},
     Body, Method, Mime, Url,
 };
 use crate::middleware::Middleware;
-use crate::RequestBuilder;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::nonce::request_nonce;
+use crate::patch::{json_patch_body, merge_patch_body};
+use crate::utils::{header_lines, map_body};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::NonceProvider;
+use crate::{PatchOp, RequestBuilder};
 
 use serde::Serialize;
 
 use std::fmt;
+use std::fmt::Write;
 use std::ops::Index;
 use std::sync::Arc;
 
@@ -147,6 +158,16 @@ impl Request {
         name: impl Into<HeaderName>,
         values: impl ToHeaderValues,
     ) -> Option<HeaderValues> {
+        let name = name.into();
+        #[cfg(all(
+            any(
+                feature = "h1-client",
+                feature = "h1-client-rustls",
+                feature = "h1-client-no-tls"
+            ),
+            not(target_arch = "wasm32")
+        ))]
+        crate::header_case::record_order(&mut self.req, &name);
         self.req.insert_header(name, values)
     }
 
@@ -155,6 +176,16 @@ impl Request {
     /// Unlike `insert` this function will not override the contents of a header, but insert a
     /// header if there aren't any. Or else append to the existing list of headers.
     pub fn append_header(&mut self, name: impl Into<HeaderName>, values: impl ToHeaderValues) {
+        let name = name.into();
+        #[cfg(all(
+            any(
+                feature = "h1-client",
+                feature = "h1-client-rustls",
+                feature = "h1-client-no-tls"
+            ),
+            not(target_arch = "wasm32")
+        ))]
+        crate::header_case::record_order(&mut self.req, &name);
         self.req.append_header(name, values)
     }
 
@@ -215,6 +246,54 @@ impl Request {
         self.req.ext_mut().insert(val)
     }
 
+    /// Clone the request with its per-request options, such as its timeout, TLS configuration
+    /// and address override, to send it again from middleware.
+    ///
+    /// Cloning the underlying `http_types::Request` drops its extensions, so the ones surf
+    /// stores its options in are copied over. The body is not cloned.
+    pub(crate) fn clone_with_ext(&self) -> Self {
+        fn copy<T: Clone + Send + Sync + 'static>(from: &Request, to: &mut Request) {
+            if let Some(val) = from.ext::<T>() {
+                to.set_ext(val.clone());
+            }
+        }
+        let mut req = self.clone();
+        copy::<crate::middleware::LogContext>(self, &mut req);
+        copy::<crate::events::EventId>(self, &mut req);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            copy::<crate::timeout::RequestTimeout>(self, &mut req);
+            copy::<crate::middleware::cache::CacheMode>(self, &mut req);
+            copy::<crate::Nonce>(self, &mut req);
+        }
+        #[cfg(any(feature = "h1-client", feature = "h1-client-rustls"))]
+        copy::<crate::tls::TlsOverride>(self, &mut req);
+        #[cfg(any(
+            feature = "curl-client",
+            feature = "h1-client",
+            feature = "h1-client-rustls",
+            feature = "h1-client-no-tls"
+        ))]
+        {
+            copy::<crate::resolver::ResolveTo>(self, &mut req);
+            copy::<crate::IpVersion>(self, &mut req);
+        }
+        #[cfg(unix)]
+        copy::<crate::unix_socket::UnixSocket>(self, &mut req);
+        #[cfg(all(
+            any(
+                feature = "h1-client",
+                feature = "h1-client-rustls",
+                feature = "h1-client-no-tls"
+            ),
+            not(target_arch = "wasm32")
+        ))]
+        copy::<crate::header_case::HeaderOrder>(self, &mut req);
+        #[cfg(feature = "middleware-tracing")]
+        copy::<crate::middleware::TraceContext>(self, &mut req);
+        req
+    }
+
     /// Get the request HTTP method.
     ///
     /// # Examples
@@ -319,6 +398,47 @@ impl Request {
         Ok(())
     }
 
+    /// Pass MessagePack as the request body, with struct fields as map keys.
+    ///
+    /// # Mime
+    ///
+    /// The `content-type` is set to `application/msgpack`.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the provided data could not be serialized to
+    /// MessagePack.
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "msgpack")))]
+    #[cfg(feature = "msgpack")]
+    pub fn body_msgpack(&mut self, value: &impl Serialize) -> crate::Result<()> {
+        self.set_body(crate::msgpack::msgpack_body(value)?);
+        Ok(())
+    }
+
+    /// Pass a JSON Patch document applying `ops` as the request body.
+    ///
+    /// # Mime
+    ///
+    /// The `content-type` is set to `application/json-patch+json`.
+    pub fn body_json_patch(&mut self, ops: &[PatchOp]) -> crate::Result<()> {
+        self.set_body(json_patch_body(ops)?);
+        Ok(())
+    }
+
+    /// Pass a JSON Merge Patch document as the request body.
+    ///
+    /// # Mime
+    ///
+    /// The `content-type` is set to `application/merge-patch+json`.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the provided data could not be serialized to JSON.
+    pub fn body_merge_patch(&mut self, patch: &impl Serialize) -> crate::Result<()> {
+        self.set_body(merge_patch_body(patch)?);
+        Ok(())
+    }
+
     /// Pass a string as the request body.
     ///
     /// # Mime
@@ -328,6 +448,71 @@ impl Request {
         self.set_body(Body::from_string(string))
     }
 
+    /// Compress the request body with `encoding` as it is sent, and set `Content-Encoding`.
+    ///
+    /// Call this after setting the body. `gzip` and `deflate` are supported, and `identity`
+    /// leaves the body unchanged. The body is compressed in chunks, so large bodies are never
+    /// held in memory, and it is sent without a `Content-Length`.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error for unsupported encodings, including `br` and `zstd`,
+    /// as surf has no brotli or zstd codecs.
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "gzip")))]
+    #[cfg(feature = "gzip")]
+    pub fn compress(&mut self, encoding: http::content::Encoding) -> crate::Result<()> {
+        compress_body(self, encoding)
+    }
+
+    /// Get the nonce of this request, generating it with `provider` the first time.
+    ///
+    /// This is meant for middleware which signs requests. The nonce is stored in the request
+    /// extensions as a [`Nonce`](crate::Nonce), so every signer sees the same one. When the
+    /// request is sent again by the [`Retry`](crate::middleware::Retry) middleware, the nonce of
+    /// the previous attempt is reused or a new one is generated, as decided by
+    /// [`NonceProvider::policy`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use surf::{Nonce, UuidNonce};
+    ///
+    /// let mut req = surf::post("https://httpbin.org/post").build();
+    /// let nonce = req.nonce(&UuidNonce::new());
+    /// assert_eq!(req.ext::<Nonce>().unwrap().as_str(), nonce);
+    /// assert_eq!(req.nonce(&UuidNonce::new()), nonce);
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn nonce(&mut self, provider: &dyn NonceProvider) -> String {
+        request_nonce(self, provider)
+    }
+
+    /// Pass a string as the request body, encoded as `encoding`.
+    ///
+    /// The encoding is looked up by its [WHATWG label], such as `"shift_jis"` or `"iso-8859-1"`.
+    /// Encodings which can only be decoded, such as UTF-16, are written as utf-8.
+    ///
+    /// # Mime
+    ///
+    /// The `content-type` is set to `text/plain`, with the encoding as its `charset`.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the encoding is unsupported, or the text has
+    /// characters which can't be represented in it.
+    ///
+    /// [WHATWG label]: https://encoding.spec.whatwg.org/#names-and-labels
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "encoding")))]
+    #[cfg(feature = "encoding")]
+    pub fn body_string_encoded(
+        &mut self,
+        text: impl AsRef<str>,
+        encoding: &str,
+    ) -> crate::Result<()> {
+        self.set_body(encoded_string_body(text.as_ref(), encoding)?);
+        Ok(())
+    }
+
     /// Pass bytes as the request body.
     ///
     /// # Mime
@@ -395,11 +580,125 @@ impl Request {
         self.middleware.as_mut().unwrap().push(Arc::new(middleware));
     }
 
+    /// The request line and headers of this request, as they would be sent in HTTP/1.1, for
+    /// debugging.
+    ///
+    /// Header names are in title case and sorted, as http-types keeps them in lowercase. A `Host`
+    /// header is added from the URL if there is none, as backends add one too; backends may
+    /// add other headers, such as `Content-Length`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let req = surf::get("https://example.com:8443/search?q=cats")
+    ///     .header("accept", "text/html")
+    ///     .build();
+    /// assert_eq!(
+    ///     req.dump_head(),
+    ///     "GET /search?q=cats HTTP/1.1\nHost: example.com:8443\nAccept: text/html"
+    /// );
+    /// ```
+    pub fn dump_head(&self) -> String {
+        let url = self.url();
+        let mut target = url.path().to_string();
+        if let Some(query) = url.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+        let mut lines = vec![format!("{} {} HTTP/1.1", self.method(), target)];
+        if self.header(HOST).is_none() {
+            if let Some(host) = url.host_str() {
+                match url.port() {
+                    Some(port) => lines.push(format!("Host: {}:{}", host, port)),
+                    None => lines.push(format!("Host: {}", host)),
+                }
+            }
+        }
+        lines.extend(header_lines(self.as_ref()));
+        lines.join("\n")
+    }
+
+    /// A `curl` command line sending this request, for reproducing it in a shell.
+    ///
+    /// The body is read to be included in the command, and put back, so it is buffered in memory.
+    /// Binary bodies are quoted with the `$'...'` syntax of bash and zsh.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the body can't be read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let mut req = surf::post("https://example.com/notes")
+    ///     .body_string("it's done".into())
+    ///     .build();
+    /// assert_eq!(
+    ///     req.curl_command().await?,
+    ///     "curl -X POST 'https://example.com/notes' \\\n  \
+    ///      -H 'Content-Type: text/plain;charset=utf-8' \\\n  \
+    ///      --data-binary 'it'\\''s done'"
+    /// );
+    /// assert_eq!(req.take_body().into_string().await?, "it's done");
+    /// # Ok(()) }
+    /// ```
+    pub async fn curl_command(&mut self) -> crate::Result<String> {
+        let method = match self.method() {
+            Method::Get => String::new(),
+            Method::Head => " --head".to_string(),
+            method => format!(" -X {}", method),
+        };
+        let url = shell_quote(self.url().as_str().as_bytes());
+        let mut args = vec![format!("curl{} {}", method, url)];
+        for line in header_lines(self.as_ref()) {
+            args.push(format!("-H {}", shell_quote(line.as_bytes())));
+        }
+        if self.len() != Some(0) {
+            let body = self.take_body();
+            let mime = body.mime().clone();
+            let bytes = body.into_bytes().await?;
+            if !bytes.is_empty() {
+                args.push(format!("--data-binary {}", shell_quote(&bytes)));
+            }
+            map_body(self, |_| {
+                let mut body = Body::from_bytes(bytes);
+                body.set_mime(mime);
+                body
+            });
+        }
+        Ok(args.join(" \\\n  "))
+    }
+
     pub(crate) fn take_middleware(&mut self) -> Option<Vec<Arc<dyn Middleware>>> {
         self.middleware.take()
     }
 }
 
+/// Quote `bytes` as a single shell word: in single quotes if they are printable text, or else
+/// with `$'...'` escapes.
+fn shell_quote(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.chars().any(char::is_control) => {
+            format!("'{}'", text.replace('\'', "'\\''"))
+        }
+        _ => {
+            let mut quoted = String::from("$'");
+            for &byte in bytes {
+                match byte {
+                    b'\\' | b'\'' => write!(quoted, "\\{}", byte as char),
+                    b' '..=b'~' => write!(quoted, "{}", byte as char),
+                    _ => write!(quoted, "\\x{:02x}", byte),
+                }
+                .expect("writing to a string can't fail");
+            }
+            quoted.push('\'');
+            quoted
+        }
+    }
+}
+
 impl AsRef<http::Headers> for Request {
     fn as_ref(&self) -> &http::Headers {
         self.req.as_ref()
@@ -1,9 +1,14 @@
+#[cfg(feature = "encoding")]
+use crate::charset::encoded_string_body;
 use crate::http::{
-    headers::{HeaderName, ToHeaderValues},
-    Body, Method, Mime, Url,
+    self,
+    headers::{HeaderName, ToHeaderValues, AUTHORIZATION},
+    Body, Method, Mime, StatusCode, Url,
 };
-use crate::middleware::Middleware;
-use crate::{Client, Error, Request, Response, Result};
+use crate::middleware::{LogContext, Middleware};
+use crate::patch::{json_patch_body, merge_patch_body};
+use crate::utils::relative_reference;
+use crate::{Auth, Client, Error, PatchOp, Request, Response, Result};
 
 use futures_util::future::BoxFuture;
 use serde::Serialize;
@@ -49,7 +54,6 @@ use std::task::{Context, Poll};
 /// # Ok(())
 /// # }
 /// ```
-
 pub struct RequestBuilder {
     /// Holds the state of the request.
     req: Option<Request>,
@@ -57,6 +61,8 @@ pub struct RequestBuilder {
     client: Option<Client>,
     /// Holds the state of the `impl Future`.
     fut: Option<BoxFuture<'static, Result<Response>>>,
+    /// How `query` serializes sequences.
+    query_format: QueryArrayFormat,
 }
 
 impl RequestBuilder {
@@ -82,6 +88,7 @@ impl RequestBuilder {
             req: Some(Request::new(method, url)),
             client: None,
             fut: None,
+            query_format: QueryArrayFormat::default(),
         }
     }
 
@@ -96,6 +103,49 @@ impl RequestBuilder {
         self
     }
 
+    /// Sends the request to a different base URL.
+    ///
+    /// The part of the request URL below the client's base URL (or, without a client base URL,
+    /// the path, query and fragment) is joined onto `base`. This allows a client to occasionally
+    /// reach an alternate host or region without constructing a second client.
+    ///
+    /// As with [`Config::set_base_url`](crate::Config::set_base_url), a trailing slash on `base`
+    /// is significant.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if `base` cannot be a base, e.g. a `data:` URL.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use surf::Url;
+    /// # fn main() -> surf::Result<()> {
+    /// let req = surf::get("https://api.example.com/v1/users?page=2")
+    ///     .base_url(Url::parse("https://eu.api.example.com/")?)
+    ///     .build();
+    /// assert_eq!(req.url().as_str(), "https://eu.api.example.com/v1/users?page=2");
+    /// # Ok(()) }
+    /// ```
+    pub fn base_url(mut self, base: Url) -> Self {
+        let req = self.req.as_mut().unwrap();
+        let url = req.url();
+        let client_base = self
+            .client
+            .as_ref()
+            .and_then(|client| client.config().base_url.as_ref())
+            .filter(|client_base| url.as_str().starts_with(client_base.as_str()));
+
+        let relative = match client_base {
+            Some(client_base) => url.as_str()[client_base.as_str().len()..].to_string(),
+            None => relative_reference(url),
+        };
+
+        let url = base.join(&relative).unwrap();
+        *AsMut::<http::Request>::as_mut(req).url_mut() = url;
+        self
+    }
+
     /// Sets a header on the request.
     ///
     /// # Examples
@@ -109,6 +159,71 @@ impl RequestBuilder {
         self
     }
 
+    /// Authenticates the request with HTTP Basic authentication, replacing any `Authorization`
+    /// header set by the client.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let req = surf::get("https://httpbin.org/basic-auth/user/pass")
+    ///     .basic_auth("user", "pass")
+    ///     .build();
+    /// assert_eq!(req["Authorization"], "Basic dXNlcjpwYXNz");
+    /// ```
+    pub fn basic_auth(self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth(Auth::basic(username, password))
+    }
+
+    /// Authenticates the request with a bearer token, replacing any `Authorization` header set
+    /// by the client.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the token contains characters which aren't allowed in headers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let req = surf::get("https://httpbin.org/bearer")
+    ///     .bearer_auth("my-token")
+    ///     .build();
+    /// assert_eq!(req["Authorization"], "Bearer my-token");
+    /// ```
+    pub fn bearer_auth(self, token: impl Into<String>) -> Self {
+        self.auth(Auth::bearer(token))
+    }
+
+    fn auth(mut self, auth: Auth) -> Self {
+        let value = auth.header_value();
+        self.req
+            .as_mut()
+            .unwrap()
+            .insert_header(AUTHORIZATION, value);
+        self
+    }
+
+    /// Tunnels the request through `POST`, passing the intended method in the
+    /// `X-HTTP-Method-Override` header.
+    ///
+    /// This is useful for reaching APIs through proxies or gateways which only allow `GET` and
+    /// `POST` requests. The server must support the header.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use surf::http::Method;
+    ///
+    /// let req = surf::put("https://httpbin.org/put").method_override(Method::Put).build();
+    /// assert_eq!(req.method(), Method::Post);
+    /// assert_eq!(req["X-HTTP-Method-Override"], "PUT");
+    /// ```
+    pub fn method_override(mut self, method: Method) -> Self {
+        let req: &mut http::Request = self.req.as_mut().unwrap().as_mut();
+        req.set_method(Method::Post);
+        req.insert_header("X-HTTP-Method-Override", method.to_string());
+        self
+    }
+
     /// Sets the Content-Type header on the request.
     ///
     /// # Examples
@@ -147,6 +262,37 @@ impl RequestBuilder {
         self
     }
 
+    /// Sets the body of the request like [`body`](RequestBuilder::body), and calls `progress`
+    /// as the body is sent.
+    ///
+    /// `progress` is called with the number of bytes of the body read by the backend so far, and
+    /// the length of the body if it is known, e.g. to render a progress bar while uploading a
+    /// large file. Bytes are read shortly before they are sent, so the count runs ahead of the
+    /// bytes the server has received by the size of the send buffers.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let file = surf::Body::from_file("release.tar.gz").await?;
+    /// surf::put("https://example.com/upload/release.tar.gz")
+    ///     .body_with_progress(file, |sent, total| match total {
+    ///         Some(total) => println!("{}/{} bytes", sent, total),
+    ///         None => println!("{} bytes", sent),
+    ///     })
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn body_with_progress(
+        self,
+        body: impl Into<Body>,
+        progress: impl Fn(u64, Option<u64>) + Send + Sync + 'static,
+    ) -> Self {
+        let body = crate::progress::report_progress(body.into(), Box::new(progress));
+        self.body(body)
+    }
+
     /// Pass JSON as the request body.
     ///
     /// # Mime
@@ -178,6 +324,92 @@ impl RequestBuilder {
         Ok(self.body(Body::from_json(json)?))
     }
 
+    /// Pass MessagePack as the request body, with struct fields as map keys.
+    ///
+    /// # Mime
+    ///
+    /// The encoding is set to `application/msgpack`.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the provided data could not be serialized to
+    /// MessagePack.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use serde::Serialize;
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// #[derive(Serialize)]
+    /// struct Reading {
+    ///     sensor: String,
+    ///     value: f64,
+    /// }
+    ///
+    /// let reading = Reading { sensor: "t1".into(), value: 21.5 };
+    /// let res = surf::post("https://api.example.com/readings")
+    ///     .body_msgpack(&reading)?
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "msgpack")))]
+    #[cfg(feature = "msgpack")]
+    pub fn body_msgpack(self, value: &impl Serialize) -> crate::Result<Self> {
+        Ok(self.body(crate::msgpack::msgpack_body(value)?))
+    }
+
+    /// Pass a JSON Patch document applying `ops` as the request body.
+    ///
+    /// # Mime
+    ///
+    /// The encoding is set to `application/json-patch+json`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// use serde_json::json;
+    /// use surf::PatchOp;
+    ///
+    /// let ops = [PatchOp::Add { path: "/tags/-".into(), value: json!("async") }];
+    /// let res = surf::patch("https://httpbin.org/patch").body_json_patch(&ops)?.await?;
+    /// assert_eq!(res.status(), 200);
+    /// # Ok(()) }
+    /// ```
+    pub fn body_json_patch(self, ops: &[PatchOp]) -> crate::Result<Self> {
+        Ok(self.body(json_patch_body(ops)?))
+    }
+
+    /// Pass a JSON Merge Patch document as the request body.
+    ///
+    /// Members of `patch` replace those of the target, and `null` members remove them.
+    ///
+    /// # Mime
+    ///
+    /// The encoding is set to `application/merge-patch+json`.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the provided data could not be serialized to JSON.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// use serde_json::json;
+    ///
+    /// let patch = json!({ "title": "Hello", "draft": null });
+    /// let res = surf::patch("https://httpbin.org/patch").body_merge_patch(&patch)?.await?;
+    /// assert_eq!(res.status(), 200);
+    /// # Ok(()) }
+    /// ```
+    pub fn body_merge_patch(self, patch: &impl Serialize) -> crate::Result<Self> {
+        Ok(self.body(merge_patch_body(patch)?))
+    }
+
     /// Pass a string as the request body.
     ///
     /// # Mime
@@ -199,6 +431,71 @@ impl RequestBuilder {
         self.body(Body::from_string(string))
     }
 
+    /// Compress the request body with `encoding` as it is sent, and set `Content-Encoding`.
+    ///
+    /// Call this after setting the body. `gzip` and `deflate` are supported, and `identity`
+    /// leaves the body unchanged. The body is compressed in chunks, so large bodies are never
+    /// held in memory, and it is sent without a `Content-Length`. The server must accept
+    /// compressed request bodies.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error for unsupported encodings, including `br` and `zstd`,
+    /// as surf has no brotli or zstd codecs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use surf::http::content::Encoding;
+    ///
+    /// # fn main() -> surf::Result<()> {
+    /// let req = surf::post("https://httpbin.org/post")
+    ///     .body_string("hello ".repeat(1000))
+    ///     .compress(Encoding::Gzip)?
+    ///     .build();
+    /// assert_eq!(req["content-encoding"], "gzip");
+    /// # Ok(()) }
+    /// ```
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "gzip")))]
+    #[cfg(feature = "gzip")]
+    pub fn compress(mut self, encoding: http::content::Encoding) -> Result<Self> {
+        self.req.as_mut().unwrap().compress(encoding)?;
+        Ok(self)
+    }
+
+    /// Pass a string as the request body, encoded as `encoding`.
+    ///
+    /// This is meant for services which don't accept utf-8. The encoding is looked up by its
+    /// [WHATWG label], such as `"shift_jis"` or `"iso-8859-1"`. Encodings which can only be
+    /// decoded, such as UTF-16, are written as utf-8.
+    ///
+    /// # Mime
+    ///
+    /// The encoding is set to `text/plain`, with the encoding as its `charset`.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the encoding is unsupported, or the text has
+    /// characters which can't be represented in it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> surf::Result<()> {
+    /// let req = surf::post("https://httpbin.org/post")
+    ///     .body_string_encoded("こんにちは", "shift_jis")?
+    ///     .build();
+    /// assert_eq!(req["content-type"], "text/plain;charset=Shift_JIS");
+    /// # Ok(()) }
+    /// ```
+    ///
+    /// [WHATWG label]: https://encoding.spec.whatwg.org/#names-and-labels
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "encoding")))]
+    #[cfg(feature = "encoding")]
+    pub fn body_string_encoded(self, text: impl AsRef<str>, encoding: &str) -> Result<Self> {
+        Ok(self.body(encoded_string_body(text.as_ref(), encoding)?))
+    }
+
     /// Pass bytes as the request body.
     ///
     /// # Mime
@@ -249,7 +546,15 @@ impl RequestBuilder {
         Ok(self.body(Body::from_file(path).await?))
     }
 
-    /// Set the URL querystring.
+    /// Append `query` to the URL querystring, after the parameters it already has.
+    ///
+    /// `query` is usually a struct or a map. Sequences, such as a `Vec` field, are serialized in
+    /// the [format](Self::query_array_format) set before, and nested structs as `outer[inner]`
+    /// keys. `None` values are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `400 Bad Request` error if `query` can't be serialized as a querystring.
     ///
     /// # Examples
     ///
@@ -263,17 +568,87 @@ impl RequestBuilder {
     /// }
     ///
     /// let query = Index { page: 2 };
-    /// let mut req = surf::get("https://httpbin.org/get").query(&query)?.build();
-    /// assert_eq!(req.url().query(), Some("page=2"));
-    /// assert_eq!(req.url().as_str(), "https://httpbin.org/get?page=2");
+    /// let mut req = surf::get("https://httpbin.org/get?sort=asc").query(&query)?.build();
+    /// assert_eq!(req.url().query(), Some("sort=asc&page=2"));
+    /// assert_eq!(req.url().as_str(), "https://httpbin.org/get?sort=asc&page=2");
     /// # Ok(()) }
     /// ```
     pub fn query(mut self, query: &impl Serialize) -> std::result::Result<Self, Error> {
-        self.req.as_mut().unwrap().set_query(query)?;
-
+        let serialized = serde_qs::to_string(query)
+            .map_err(|err| Error::from_str(StatusCode::BadRequest, err.to_string()))?;
+        let pairs: Vec<String> = serialized
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                format!("{}={}", array_key(key, self.query_format), value)
+            })
+            .collect();
+        let req: &mut http::Request = self.req.as_mut().unwrap().as_mut();
+        append_query(req.url_mut(), &pairs.join("&"));
         Ok(self)
     }
 
+    /// Append the `key=value` pairs of `pairs` to the URL querystring, after the parameters it
+    /// already has.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let req = surf::get("https://httpbin.org/get")
+    ///     .query_pairs([("tag", "a b"), ("tag", "c")])
+    ///     .build();
+    /// assert_eq!(req.url().query(), Some("tag=a+b&tag=c"));
+    /// ```
+    pub fn query_pairs<I, K, V>(mut self, pairs: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let mut pairs = pairs.into_iter().peekable();
+        if pairs.peek().is_some() {
+            let req: &mut http::Request = self.req.as_mut().unwrap().as_mut();
+            let url = req.url_mut();
+            if url.query() == Some("") {
+                url.set_query(None);
+            }
+            let mut query = url.query_pairs_mut();
+            for (key, value) in pairs {
+                query.append_pair(key.as_ref(), value.as_ref());
+            }
+        }
+        self
+    }
+
+    /// Set how [`query`](Self::query) serializes sequences, such as a `Vec` field.
+    ///
+    /// Default: [`QueryArrayFormat::Repeated`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use serde::Serialize;
+    /// # fn main() -> surf::Result<()> {
+    /// use surf::QueryArrayFormat;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Filter {
+    ///     ids: Vec<u32>,
+    /// }
+    ///
+    /// let req = surf::get("https://example.com/items")
+    ///     .query_array_format(QueryArrayFormat::Brackets)
+    ///     .query(&Filter { ids: vec![1, 2] })?
+    ///     .build();
+    /// assert_eq!(req.url().query(), Some("ids[]=1&ids[]=2"));
+    /// # Ok(()) }
+    /// ```
+    pub fn query_array_format(mut self, format: QueryArrayFormat) -> Self {
+        self.query_format = format;
+        self
+    }
+
     /// Submit the request and get the response body as bytes.
     ///
     /// # Examples
@@ -306,6 +681,43 @@ impl RequestBuilder {
         res.body_string().await
     }
 
+    /// Submit the request and stream the response body to a file at `path`.
+    ///
+    /// This fails if the response status isn't a success, and otherwise saves the body with
+    /// [`Response::save_to`]. Returns the number of bytes written.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let written = surf::get("https://example.com/tool.tar.gz")
+    ///     .download_to("tool.tar.gz")
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "download")))]
+    #[cfg(all(feature = "download", not(target_arch = "wasm32")))]
+    pub async fn download_to(self, path: impl AsRef<std::path::Path>) -> Result<u64> {
+        self.download_to_with(path, crate::DownloadOptions::new())
+            .await
+    }
+
+    /// Submit the request and stream the response body to a file at `path`, like
+    /// [`download_to`](RequestBuilder::download_to), with `options` for the buffer size,
+    /// length verification, and progress reporting.
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "download")))]
+    #[cfg(all(feature = "download", not(target_arch = "wasm32")))]
+    pub async fn download_to_with(
+        self,
+        path: impl AsRef<std::path::Path>,
+        options: crate::DownloadOptions,
+    ) -> Result<u64> {
+        let mut res = self.send().await?;
+        crate::download::check_status(&res)?;
+        res.save_to_with(path, options).await
+    }
+
     /// Submit the request and decode the response body from json into a struct.
     ///
     /// # Examples
@@ -385,6 +797,186 @@ impl RequestBuilder {
         self
     }
 
+    /// Attach a key-value pair to the request, which logging middleware include in their output.
+    ///
+    /// This is useful to correlate HTTP telemetry with application identifiers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use surf::middleware::LogContext;
+    ///
+    /// let req = surf::get("https://httpbin.org/get")
+    ///     .log_context("order_id", 42)
+    ///     .build();
+    /// assert_eq!(req.ext::<LogContext>().unwrap().get("order_id"), Some("42"));
+    /// ```
+    pub fn log_context(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        let req = self.req.as_mut().unwrap();
+        let mut context = req.ext::<LogContext>().cloned().unwrap_or_default();
+        context.insert(key, value);
+        req.set_ext(context);
+        self
+    }
+
+    /// Set how the [`Cache`](crate::middleware::Cache) middleware treats this request, instead
+    /// of the mode of the middleware.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use surf::middleware::cache::CacheMode;
+    ///
+    /// let req = surf::get("https://httpbin.org/get")
+    ///     .cache_mode(CacheMode::Reload)
+    ///     .build();
+    /// assert_eq!(req.ext::<CacheMode>(), Some(&CacheMode::Reload));
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn cache_mode(mut self, mode: crate::middleware::cache::CacheMode) -> Self {
+        self.req.as_mut().unwrap().set_ext(mode);
+        self
+    }
+
+    /// Use `timeout` as the total timeout of this request, instead of the
+    /// [timeout of the client](crate::Config::set_timeout).
+    ///
+    /// The timeout covers sending the request and reading its response body. Clients created
+    /// with [`Client::new`](crate::Client::new) still apply the backend's own timeout, so only
+    /// clients created from a [`Config`](crate::Config) can extend it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let req = surf::get("https://httpbin.org/delay/90")
+    ///     .timeout(Duration::from_secs(120))
+    ///     .build();
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.req
+            .as_mut()
+            .unwrap()
+            .set_ext(crate::timeout::RequestTimeout(timeout));
+        self
+    }
+
+    /// Only connect with addresses of `version` for this request, instead of those of either
+    /// version, or of the IP version set with `BackendOptions::set_ip_version` for the
+    /// `curl-client` backend.
+    ///
+    /// This helps to debug dual-stack issues, or to reach services with broken `AAAA` records.
+    /// The request fails if the host has no address of `version`, and open connections of
+    /// another version aren't reused for it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use surf::IpVersion;
+    ///
+    /// let req = surf::get("https://httpbin.org/get")
+    ///     .ip_family(IpVersion::V4)
+    ///     .build();
+    /// assert_eq!(req.ext::<IpVersion>(), Some(&IpVersion::V4));
+    /// ```
+    #[cfg_attr(
+        feature = "docs",
+        doc(cfg(any(
+            feature = "curl-client",
+            feature = "h1-client",
+            feature = "h1-client-rustls",
+            feature = "h1-client-no-tls"
+        )))
+    )]
+    #[cfg(any(
+        feature = "curl-client",
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ))]
+    pub fn ip_family(mut self, version: crate::IpVersion) -> Self {
+        self.req.as_mut().unwrap().set_ext(version);
+        self
+    }
+
+    /// Connect to `addr` for this request, instead of an address of the host of its URL.
+    ///
+    /// The `Host` header and the name used for TLS (SNI and certificate verification) are still
+    /// those of the URL, so a specific server behind a load balancer, or a canary host, can be
+    /// tested without changing DNS. The `h1-client` backends connect to `addr` over connections
+    /// surf makes and pools itself.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let addr = "203.0.113.7:443".parse().unwrap();
+    /// let res = surf::get("https://example.com/health").resolve_to(addr).await?;
+    /// assert!(res.status().is_success());
+    /// # Ok(()) }
+    /// ```
+    #[cfg_attr(
+        feature = "docs",
+        doc(cfg(any(
+            feature = "curl-client",
+            feature = "h1-client",
+            feature = "h1-client-rustls",
+            feature = "h1-client-no-tls"
+        )))
+    )]
+    #[cfg(any(
+        feature = "curl-client",
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ))]
+    pub fn resolve_to(mut self, addr: std::net::SocketAddr) -> Self {
+        self.req
+            .as_mut()
+            .unwrap()
+            .set_ext(crate::resolver::ResolveTo(addr));
+        self
+    }
+
+    /// Use `tls_config` for this request, instead of the TLS configuration of the client (Rustls).
+    ///
+    /// The request is sent over a connection pool kept for this configuration, so e.g. a client
+    /// certificate needed by one host is never used for others. Pass the same `Arc` to reuse
+    /// connections across requests. This requires a client using the default http client, and
+    /// can't be combined with a [proxy](crate::Config::set_proxy).
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "h1-client-rustls")))]
+    #[cfg(feature = "h1-client-rustls")]
+    pub fn tls_config(mut self, tls_config: std::sync::Arc<rustls_crate::ClientConfig>) -> Self {
+        self.req
+            .as_mut()
+            .unwrap()
+            .set_ext(crate::tls::TlsOverride(tls_config));
+        self
+    }
+
+    /// Use `tls_config` for this request, instead of the TLS configuration of the client (Native
+    /// TLS).
+    ///
+    /// The request is sent over a connection pool kept for this configuration, so e.g. a client
+    /// certificate needed by one host is never used for others. Pass the same `Arc` to reuse
+    /// connections across requests. This requires a client using the default http client, and
+    /// can't be combined with a [proxy](crate::Config::set_proxy).
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "h1-client")))]
+    #[cfg(all(feature = "h1-client", not(feature = "h1-client-rustls")))]
+    pub fn tls_config(
+        mut self,
+        tls_config: std::sync::Arc<async_native_tls::TlsConnector>,
+    ) -> Self {
+        self.req
+            .as_mut()
+            .unwrap()
+            .set_ext(crate::tls::TlsOverride(tls_config));
+        self
+    }
+
     /// Return the constructed `Request`.
     pub fn build(self) -> Request {
         self.req.unwrap()
@@ -392,12 +984,82 @@ impl RequestBuilder {
 
     /// Create a `Client` and send the constructed `Request` from it.
     pub async fn send(mut self) -> Result<Response> {
-        self.client
-            .take()
-            .unwrap_or_else(Client::new_shared_or_panic)
-            .send(self.build())
-            .await
+        let client = self.take_client();
+        client.send(self.build()).await
     }
+
+    /// Take the client to send the request with, which is the global client if none was set.
+    fn take_client(&mut self) -> Client {
+        match self.client.take() {
+            Some(client) => client,
+            None => {
+                let client = Client::new_shared_or_panic();
+                let req = self.req.as_mut().unwrap();
+                for (header_name, header_values) in client.config().headers.iter() {
+                    req.append_header(header_name, header_values);
+                }
+                client
+            }
+        }
+    }
+}
+
+/// How [`RequestBuilder::query`] serializes sequences, such as a `Vec` field, as set with
+/// [`RequestBuilder::query_array_format`].
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryArrayFormat {
+    /// Repeat the key for each item, as in `ids=1&ids=2`.
+    #[default]
+    Repeated,
+    /// Suffix the key with empty brackets for each item, as in `ids[]=1&ids[]=2`, as PHP and
+    /// Rails expect.
+    Brackets,
+}
+
+/// Rewrite the trailing `[0]` array index of a key serialized by `serde_qs` in `format`.
+///
+/// The indexes of outer sequences, such as the `[0]` of `a[0][b]`, are kept, since they tell
+/// which item the rest of the key belongs to.
+fn array_key(key: &str, format: QueryArrayFormat) -> String {
+    let mut rewritten = String::with_capacity(key.len());
+    let mut rest = key;
+    while let Some(start) = rest.find('[') {
+        rewritten.push_str(&rest[..start]);
+        let segment = &rest[start + 1..];
+        match segment.find(']') {
+            Some(end)
+                if end > 0
+                    && end + 1 == segment.len()
+                    && segment[..end].bytes().all(|byte| byte.is_ascii_digit()) =>
+            {
+                if format == QueryArrayFormat::Brackets {
+                    rewritten.push_str("[]");
+                }
+                rest = &segment[end + 1..];
+            }
+            // Literal brackets are percent-encoded, so this is a nested struct or map, or the
+            // index of an outer sequence.
+            _ => {
+                rewritten.push('[');
+                rest = segment;
+            }
+        }
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
+/// Append the serialized `pairs` to the query of `url`.
+fn append_query(url: &mut Url, pairs: &str) {
+    if pairs.is_empty() {
+        return;
+    }
+    let query = match url.query().filter(|query| !query.is_empty()) {
+        Some(query) => format!("{}&{}", query, pairs),
+        None => pairs.to_string(),
+    };
+    url.set_query(Some(&query));
 }
 
 impl fmt::Debug for RequestBuilder {
@@ -411,13 +1073,9 @@ impl Future for RequestBuilder {
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         if self.fut.is_none() {
+            let client = self.take_client();
             let req = self.req.take().unwrap();
 
-            let client = self
-                .client
-                .take()
-                .unwrap_or_else(Client::new_shared_or_panic);
-
             self.fut = Some(Box::pin(async move { client.send(req).await }))
         }
 
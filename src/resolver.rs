@@ -0,0 +1,174 @@
+//! Resolving host names to the addresses to connect to.
+
+use crate::http::headers::ACCEPT;
+use crate::http::{Method, StatusCode, Url};
+use crate::{Client, Error, Request, Result};
+
+use serde_json::Value;
+
+use std::fmt::Debug;
+use std::net::{IpAddr, SocketAddr};
+
+/// The address to connect to for a request, set by `RequestBuilder::resolve_to`, or by the DNS
+/// overrides or resolver of the client.
+#[cfg(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "h1-client-no-tls"
+))]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ResolveTo(pub(crate) SocketAddr);
+
+/// The IP versions connections may use, for
+/// [`RequestBuilder::ip_family`](crate::RequestBuilder::ip_family) and the options of the
+/// `curl-client` backend.
+#[cfg(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "h1-client-no-tls"
+))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum IpVersion {
+    /// Both IPv4 and IPv6, preferring IPv6.
+    #[default]
+    Any,
+    /// Only IPv4.
+    V4,
+    /// Only IPv6.
+    V6,
+}
+
+#[cfg(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "h1-client-no-tls"
+))]
+impl IpVersion {
+    /// Whether connections to `addr` may be made.
+    pub(crate) fn allows(self, addr: &SocketAddr) -> bool {
+        match self {
+            IpVersion::Any => true,
+            IpVersion::V4 => addr.is_ipv4(),
+            IpVersion::V6 => addr.is_ipv6(),
+        }
+    }
+
+    /// The isahc equivalent of this version.
+    #[cfg(feature = "curl-client")]
+    pub(crate) fn to_isahc(self) -> isahc::config::IpVersion {
+        match self {
+            IpVersion::Any => isahc::config::IpVersion::Any,
+            IpVersion::V4 => isahc::config::IpVersion::V4,
+            IpVersion::V6 => isahc::config::IpVersion::V6,
+        }
+    }
+}
+
+/// Resolves host names to the addresses to connect to, for
+/// [`Config::set_dns_resolver`](crate::Config::set_dns_resolver) and the
+/// [`DnsFallback`](crate::middleware::DnsFallback) middleware.
+///
+/// Resolvers can look names up in other ways than DNS, such as in a service registry like
+/// Consul, or in a fixed table for tests.
+///
+/// # Examples
+///
+/// ```
+/// use std::net::SocketAddr;
+/// use surf::{Client, Resolver};
+///
+/// #[derive(Debug)]
+/// struct Localhost;
+///
+/// #[surf::utils::async_trait]
+/// impl Resolver for Localhost {
+///     async fn resolve(&self, _: &str, port: u16, _: &Client) -> surf::Result<Vec<SocketAddr>> {
+///         Ok(vec![SocketAddr::from(([127, 0, 0, 1], port))])
+///     }
+/// }
+/// ```
+#[async_trait::async_trait]
+pub trait Resolver: Debug + Send + Sync + 'static {
+    /// Resolve `host` to its addresses, in order of preference.
+    ///
+    /// `port` is the port of the URL, which the addresses should usually have, though they may
+    /// have another, such as one from a service registry.
+    ///
+    /// `client` is the client of the request, without its middleware, which resolvers can send
+    /// their queries with. Its own requests aren't resolved with the resolver, and they should be
+    /// to addresses rather than host names if the system resolver may be failing for them too.
+    async fn resolve(&self, host: &str, port: u16, client: &Client) -> Result<Vec<SocketAddr>>;
+}
+
+/// A [`Resolver`] which queries a DNS over HTTPS endpoint with the JSON API offered by public
+/// resolvers such as `https://1.1.1.1/dns-query` and `https://dns.google/resolve`.
+///
+/// `A` records are queried first, then `AAAA` records if there are none.
+#[derive(Debug, Clone)]
+pub struct DohResolver {
+    endpoint: Url,
+}
+
+impl DohResolver {
+    /// Create a new instance, querying `endpoint`.
+    pub fn new(endpoint: Url) -> Self {
+        Self { endpoint }
+    }
+
+    /// Query the records of `record_type` for `host`.
+    async fn query(&self, host: &str, record_type: &str, client: &Client) -> Result<Vec<IpAddr>> {
+        let mut url = self.endpoint.clone();
+        url.query_pairs_mut()
+            .append_pair("name", host)
+            .append_pair("type", record_type);
+        let mut req = Request::new(Method::Get, url);
+        req.insert_header(ACCEPT, "application/dns-json");
+        let mut res = client.send(req).await?;
+        if !res.status().is_success() {
+            return Err(Error::from_str(
+                StatusCode::BadGateway,
+                format!(
+                    "the DNS over HTTPS endpoint responded with {}",
+                    res.status()
+                ),
+            ));
+        }
+
+        let answer: Value = res.body_json().await?;
+        match answer["Status"].as_u64() {
+            Some(0) => {}
+            status => {
+                return Err(Error::from_str(
+                    StatusCode::BadGateway,
+                    format!("resolving {} failed with DNS status {:?}", host, status),
+                ))
+            }
+        }
+        let wanted = if record_type == "A" { 1 } else { 28 };
+        let addrs = answer["Answer"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|record| record["type"].as_u64() == Some(wanted))
+            .filter_map(|record| record["data"].as_str()?.parse().ok())
+            .collect();
+        Ok(addrs)
+    }
+}
+
+#[async_trait::async_trait]
+impl Resolver for DohResolver {
+    async fn resolve(&self, host: &str, port: u16, client: &Client) -> Result<Vec<SocketAddr>> {
+        let mut addrs = self.query(host, "A", client).await?;
+        if addrs.is_empty() {
+            addrs = self.query(host, "AAAA", client).await?;
+        }
+        Ok(addrs
+            .into_iter()
+            .map(|addr| SocketAddr::new(addr, port))
+            .collect())
+    }
+}
@@ -0,0 +1,189 @@
+//! A handle to a JSON resource, which is updated with optimistic concurrency.
+
+use crate::http::headers::{ETAG, IF_MATCH, IF_NONE_MATCH};
+use crate::http::{Method, StatusCode, Url};
+use crate::{Client, Error, Response, Result};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A JSON resource, together with the `ETag` of the representation it was last read from.
+///
+/// Created with [`Client::resource`](crate::Client::resource). Reads are revalidated with
+/// `If-None-Match`, and [`update`](Resource::update) writes with `If-Match`, so that a
+/// concurrent change on the server is detected instead of being overwritten.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use serde::{Deserialize, Serialize};
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// #[derive(Clone, Deserialize, Serialize)]
+/// struct Counter {
+///     count: u64,
+/// }
+///
+/// let client = surf::client();
+/// let mut counter = client.resource::<Counter>("https://api.example.com/counters/1");
+/// counter.fetch().await?;
+/// let counter = counter.update(|counter| counter.count += 1).await?;
+/// println!("{}", counter.count);
+/// # Ok(()) }
+/// ```
+#[derive(Debug)]
+pub struct Resource<T> {
+    client: Client,
+    url: Url,
+    max_attempts: usize,
+    state: Option<State<T>>,
+}
+
+#[derive(Debug)]
+struct State<T> {
+    value: T,
+    etag: Option<String>,
+}
+
+impl<T> Resource<T> {
+    pub(crate) fn new(client: Client, url: Url) -> Self {
+        Self {
+            client,
+            url,
+            max_attempts: 3,
+            state: None,
+        }
+    }
+
+    /// Set how many times [`update`](Resource::update) sends its request, when the resource
+    /// keeps being changed concurrently.
+    ///
+    /// Default: `3`.
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// The URL of the resource.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// The last value read or written, if any.
+    pub fn get(&self) -> Option<&T> {
+        self.state.as_ref().map(|state| &state.value)
+    }
+
+    /// The `ETag` of the last value read or written, if the server sent one.
+    pub fn etag(&self) -> Option<&str> {
+        self.state.as_ref()?.etag.as_deref()
+    }
+}
+
+impl<T: DeserializeOwned> Resource<T> {
+    /// Read the resource, whether or not it was read before.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails, the response isn't
+    /// [successful](crate::Response::is_success), or its body can't be decoded into `T`.
+    pub async fn fetch(&mut self) -> Result<&T> {
+        let req = self.client.request(Method::Get, self.url.clone());
+        let res = self.client.send(req).await?.error_for_status().await?;
+        self.store(res).await?;
+        Ok(self.get().expect("the resource was just stored"))
+    }
+
+    /// Read the resource again, unless the server confirms it is unchanged.
+    ///
+    /// The request is conditional on the `ETag` of the last value, so an unchanged resource
+    /// isn't transferred again. A resource which was never read is fetched.
+    ///
+    /// # Errors
+    ///
+    /// See [`fetch`](Resource::fetch).
+    pub async fn refresh(&mut self) -> Result<&T> {
+        let etag = match self.etag() {
+            Some(etag) => etag.to_string(),
+            None => return self.fetch().await,
+        };
+        let req = self
+            .client
+            .request(Method::Get, self.url.clone())
+            .header(IF_NONE_MATCH, etag.as_str());
+        let res = self.client.send(req).await?;
+        if res.status() != StatusCode::NotModified {
+            self.store(res.error_for_status().await?).await?;
+        }
+        Ok(self.get().expect("the resource was stored before"))
+    }
+
+    async fn store(&mut self, mut res: Response) -> Result<()> {
+        let etag = res.header(ETAG).map(|values| values.last().to_string());
+        let value = res.body_json().await?;
+        self.state = Some(State { value, etag });
+        Ok(())
+    }
+}
+
+impl<T: DeserializeOwned + Serialize + Clone> Resource<T> {
+    /// Change the resource with `f`, and write it back with a `PUT` request.
+    ///
+    /// The request is conditional on the `ETag` of the value `f` was applied to. If the server
+    /// responds with `412 Precondition Failed` because the resource was changed in the
+    /// meantime, it is fetched again and `f` is applied to the new value, up to
+    /// [`max_attempts`](Resource::max_attempts) times. The resource is fetched first if it
+    /// wasn't read before, or had no `ETag`.
+    ///
+    /// If the server responds with a JSON body, it is taken as the new value of the resource.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a request fails or isn't [successful](crate::Response::is_success),
+    /// if the server doesn't send an `ETag` to make the update conditional on, or if the
+    /// resource is still changed concurrently after the last attempt.
+    pub async fn update<F>(&mut self, mut f: F) -> Result<&T>
+    where
+        F: FnMut(&mut T),
+    {
+        let mut attempt = 1;
+        loop {
+            if self.etag().is_none() {
+                self.fetch().await?;
+            }
+            let state = self.state.as_ref().expect("the resource was fetched");
+            let etag = state.etag.clone().ok_or_else(|| {
+                Error::from_str(
+                    StatusCode::PreconditionRequired,
+                    "the server sent no ETag to make the update conditional on",
+                )
+            })?;
+            let mut value = state.value.clone();
+            f(&mut value);
+
+            let req = self
+                .client
+                .request(Method::Put, self.url.clone())
+                .header(IF_MATCH, etag.as_str())
+                .body_json(&value)?;
+            let res = self.client.send(req).await?;
+            if res.status() == StatusCode::PreconditionFailed && attempt < self.max_attempts {
+                attempt += 1;
+                self.state = None;
+                continue;
+            }
+
+            let mut res = res.error_for_status().await?;
+            let etag = res.header(ETAG).map(|values| values.last().to_string());
+            let is_json = matches!(res.content_type(), Some(mime) if mime.subtype() == "json");
+            if is_json {
+                let body = res.body_bytes().await?;
+                if !body.is_empty() {
+                    value = serde_json::from_slice(&body)?;
+                }
+            }
+            self.state = Some(State { value, etag });
+            return Ok(self.get().expect("the resource was just stored"));
+        }
+    }
+}
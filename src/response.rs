@@ -1,11 +1,16 @@
 use crate::http::{
     self,
     headers::{self, HeaderName, HeaderValues, ToHeaderValues},
-    Body, Error, Mime, StatusCode, Version,
+    Body, Error, Mime, StatusCode, Url, Version,
 };
+use crate::middleware::RedirectChain;
+use crate::multipart::Multipart;
+use crate::problem::{is_problem_json, ProblemDetails};
+use crate::utils::{header_lines, map_body_reader, BodyReader};
 
 use async_std::io::BufRead;
-use futures_util::io::AsyncRead;
+use futures_util::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite};
+use futures_util::stream::{self, BoxStream, StreamExt};
 use serde::de::DeserializeOwned;
 
 use std::fmt;
@@ -13,6 +18,7 @@ use std::io;
 use std::ops::Index;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
 
 pin_project_lite::pin_project! {
     /// An HTTP response, returned by `Request`.
@@ -39,11 +45,162 @@ impl Response {
     /// assert_eq!(res.status(), 200);
     /// # Ok(()) }
     /// ```
+    ///
+    /// Status codes which [`StatusCode`] doesn't know, such as Cloudflare's `520`, are reported
+    /// as the `x00` code of their class, which is how clients must treat them. The code the
+    /// server sent is available from [`status_raw`](Response::status_raw).
     pub fn status(&self) -> StatusCode {
         self.res.status()
     }
 
-    /// Get the HTTP protocol version.
+    /// Get the HTTP status code as the server sent it.
+    ///
+    /// Unlike [`status`](Response::status), this includes status codes which [`StatusCode`]
+    /// doesn't know, such as `520` or nginx's `499`. Only the `curl-client` backend reports
+    /// these; other backends fail the request instead.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let res = surf::get("https://httpbin.org/status/520").await?;
+    /// assert_eq!(res.status(), 500);
+    /// assert_eq!(res.status_raw(), 520);
+    /// # Ok(()) }
+    /// ```
+    pub fn status_raw(&self) -> u16 {
+        match self.ext::<RawStatus>() {
+            Some(RawStatus(code)) => *code,
+            None => self.status().into(),
+        }
+    }
+
+    /// Whether the status of the response counts as a success.
+    ///
+    /// By default every status but `4xx` and `5xx` does, which can be changed with
+    /// [`Config::set_success_predicate`](crate::Config::set_success_predicate).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let res = surf::get("https://httpbin.org/get").await?;
+    /// assert!(res.is_success());
+    /// # Ok(()) }
+    /// ```
+    pub fn is_success(&self) -> bool {
+        let predicate = match self.ext::<SuccessPredicate>() {
+            Some(&SuccessPredicate(predicate)) => predicate,
+            None => default_success,
+        };
+        predicate(self.status())
+    }
+
+    /// Turn an unsuccessful response into an error.
+    ///
+    /// Responses are unsuccessful if they have a `4xx` or `5xx` status, unless the client was
+    /// configured otherwise with
+    /// [`Config::set_success_predicate`](crate::Config::set_success_predicate). Other responses
+    /// are returned unchanged. The error has the status of the response, and
+    /// carries a [`StatusError`], which can be retrieved with `err.downcast_ref::<StatusError>()`.
+    /// If the client was configured with
+    /// [`Config::set_capture_error_bodies`](crate::Config::set_capture_error_bodies), the
+    /// start of the body is read into the `StatusError`. Bodies with the
+    /// `application/problem+json` content type are always read, and their
+    /// [problem details](ProblemDetails) are available from
+    /// [`StatusError::problem_details`](StatusError::problem_details).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let res = surf::get("https://httpbin.org/status/404").await?;
+    /// let err = res.error_for_status().await.unwrap_err();
+    /// assert_eq!(err.status(), 404);
+    /// # Ok(()) }
+    /// ```
+    pub async fn error_for_status(mut self) -> crate::Result<Self> {
+        if self.is_success() {
+            return Ok(self);
+        }
+        let status = self.status();
+
+        let content_type = self.content_type();
+        let is_problem = matches!(&content_type, Some(mime) if is_problem_json(mime));
+        let capture = self
+            .ext::<ErrorBodyLimit>()
+            .map(|&ErrorBodyLimit(limit)| limit);
+        // Problem details are always read, as they describe the error in a structured way.
+        let limit = match (capture, is_problem) {
+            (Some(limit), true) => limit.max(MAX_PROBLEM_BYTES),
+            (None, true) => MAX_PROBLEM_BYTES,
+            (capture, false) => capture.unwrap_or(0),
+        };
+        let mut body = Vec::new();
+        if limit > 0 {
+            let reader = self.take_body().into_reader();
+            // The status is the error being reported, so a failure to read the body is not.
+            let _ = reader.take(limit as u64).read_to_end(&mut body).await;
+        }
+        let problem = if is_problem {
+            ProblemDetails::from_slice(&body).ok()
+        } else {
+            None
+        };
+        body.truncate(capture.unwrap_or(0));
+
+        Err(Error::new(
+            status,
+            StatusError {
+                status,
+                content_type,
+                body,
+                problem,
+            },
+        ))
+    }
+
+    /// Reads the body as [RFC 7807] problem details, if it has the
+    /// `application/problem+json` content type.
+    ///
+    /// Returns `None` without reading the body if the content type is different.
+    ///
+    /// [RFC 7807]: https://tools.ietf.org/html/rfc7807
+    ///
+    /// # Errors
+    ///
+    /// Any I/O error encountered while reading the body is immediately returned
+    /// as an `Err`.
+    ///
+    /// If the body is not a JSON object, an `Err` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let mut res = surf::get("https://api.example.com/purchases/1").await?;
+    /// if let Some(problem) = res.problem_details().await? {
+    ///     println!("{:?}: {:?}", problem.title(), problem.detail());
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub async fn problem_details(&mut self) -> crate::Result<Option<ProblemDetails>> {
+        if !matches!(self.content_type(), Some(mime) if is_problem_json(&mime)) {
+            return Ok(None);
+        }
+        let body_bytes = self.body_bytes().await?;
+        let problem = ProblemDetails::from_slice(&body_bytes).map_err(crate::Error::from)?;
+        Ok(Some(problem))
+    }
+
+    /// Get the HTTP protocol version the response was received with, if the backend reports it.
+    ///
+    /// See [`Config::set_http_version_preference`](crate::Config::set_http_version_preference)
+    /// to choose the versions requests are sent with.
     ///
     /// # Examples
     ///
@@ -60,6 +217,35 @@ impl Response {
         self.res.version()
     }
 
+    /// The status line and headers of this response, in HTTP/1.1 form, for debugging.
+    ///
+    /// Header names are in title case and sorted, as http-types keeps them in lowercase. The
+    /// version is HTTP/1.1 if the backend doesn't report it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let res = surf::get("https://httpbin.org/get").await?;
+    /// println!("{}", res.dump_head());
+    /// // HTTP/1.1 200 OK
+    /// // Content-Length: 255
+    /// // Content-Type: application/json
+    /// // ...
+    /// # Ok(()) }
+    /// ```
+    pub fn dump_head(&self) -> String {
+        let mut lines = vec![format!(
+            "{} {} {}",
+            self.version().unwrap_or(Version::Http1_1),
+            self.status_raw(),
+            self.status().canonical_reason()
+        )];
+        lines.extend(header_lines(self.as_ref()));
+        lines.join("\n")
+    }
+
     /// Get a header.
     ///
     /// # Examples
@@ -75,6 +261,47 @@ impl Response {
         self.res.header(name)
     }
 
+    /// Get the values of a header as the bytes the server sent, such as a file name in Latin-1,
+    /// which aren't valid in the values [`header`](Response::header) returns.
+    ///
+    /// The `h1-client` backends keep the bytes of responses over the connections surf makes
+    /// itself, which [`Config::set_raw_response_headers`](crate::Config::set_raw_response_headers)
+    /// asks for, and replace the bytes outside ASCII in the values of `header` with `?`. Other
+    /// backends don't keep the bytes, so this returns those of the values of `header`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let res = surf::get("https://httpbin.org/get").await?;
+    /// if let Some(values) = res.header_raw("Content-Disposition") {
+    ///     let latin1: String = values[0].iter().map(|&byte| byte as char).collect();
+    ///     println!("{}", latin1);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn header_raw(&self, name: impl Into<HeaderName>) -> Option<Vec<&[u8]>> {
+        let name = name.into();
+        let values: Vec<&[u8]> = match self.res.ext().get::<RawHeaders>() {
+            Some(RawHeaders(headers)) => headers
+                .iter()
+                .filter(|(raw, _)| raw == name.as_str())
+                .map(|(_, value)| value.as_slice())
+                .collect(),
+            None => self
+                .header(name)?
+                .iter()
+                .map(|value| value.as_str().as_bytes())
+                .collect(),
+        };
+        if values.is_empty() {
+            None
+        } else {
+            Some(values)
+        }
+    }
+
     /// Get an HTTP header mutably.
     pub fn header_mut(&mut self, name: impl Into<HeaderName>) -> Option<&mut HeaderValues> {
         self.res.header_mut(name)
@@ -131,6 +358,88 @@ impl Response {
         self.res.ext_mut().insert(val);
     }
 
+    /// Estimate how far the server clock is off from the local clock, using the `Date` header.
+    ///
+    /// This compares the `Date` header against the local time at which the response arrived,
+    /// and is useful for middleware which signs requests with a timestamp. The `Date` header only
+    /// has a resolution of one second, and the estimate includes the time the response spent in
+    /// transit, so small offsets should not be relied upon.
+    ///
+    /// Returns `None` if the response has no valid `Date` header, if it wasn't received through
+    /// a `Client` with [`Config::set_detect_clock_skew`](crate::Config::set_detect_clock_skew)
+    /// enabled, or on wasm32.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// use std::time::SystemTime;
+    ///
+    /// let res = surf::get("https://httpbin.org/get").await?;
+    /// if let Some(skew) = res.clock_skew() {
+    ///     let server_now = skew.correct(SystemTime::now());
+    ///     println!("the server thinks it is {:?}", server_now);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn clock_skew(&self) -> Option<ClockSkew> {
+        let ReceivedAt(received_at) = self.ext::<ReceivedAt>()?;
+        let date: SystemTime = http::other::Date::from_headers(self).ok()??.into();
+        Some(match date.duration_since(*received_at) {
+            Ok(ahead) => ClockSkew::Ahead(ahead),
+            Err(err) => ClockSkew::Behind(err.duration()),
+        })
+    }
+
+    /// Get the URL the response came from.
+    ///
+    /// When redirects were followed with the [`Redirect`](crate::middleware::Redirect)
+    /// middleware, this is the URL of the last request rather than the one which was sent
+    /// initially. Returns `None` if the response wasn't received through a `Client`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// use surf::middleware::Redirect;
+    ///
+    /// let client = surf::client().with(Redirect::default());
+    /// let res = client.get("https://httpbin.org/redirect/2").await?;
+    /// assert_eq!(res.url().unwrap().as_str(), "https://httpbin.org/get");
+    /// # Ok(()) }
+    /// ```
+    pub fn url(&self) -> Option<&Url> {
+        self.ext::<ResponseUrl>().map(|ResponseUrl(url)| url)
+    }
+
+    /// Get the URLs which redirected to this response, in the order they were requested.
+    ///
+    /// This is empty unless redirects were followed with the
+    /// [`Redirect`](crate::middleware::Redirect) middleware. The statuses of the redirects are
+    /// available from the [`RedirectChain`](crate::middleware::RedirectChain) extension.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// use surf::middleware::Redirect;
+    ///
+    /// let client = surf::client().with(Redirect::default());
+    /// let res = client.get("https://httpbin.org/redirect/2").await?;
+    /// for url in res.history() {
+    ///     println!("redirected from {}", url);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn history(&self) -> Vec<&Url> {
+        self.ext::<RedirectChain>()
+            .map(|chain| chain.hops().iter().map(|(url, _)| url).collect())
+            .unwrap_or_default()
+    }
+
     /// Get the response content type as a `Mime`.
     ///
     /// Gets the `Content-Type` header and parses it to a `Mime` type.
@@ -172,6 +481,76 @@ impl Response {
         self.res.is_empty()
     }
 
+    /// Get the length the server announced in the `Content-Length` header, if any.
+    ///
+    /// Unlike [`len`](Response::len), this is the size of the body as sent, before any decoding
+    /// by the backend.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let res = surf::get("https://httpbin.org/bytes/100").await?;
+    /// assert_eq!(res.content_length(), Some(100));
+    /// # Ok(()) }
+    /// ```
+    pub fn content_length(&self) -> Option<u64> {
+        self.header(headers::CONTENT_LENGTH)?
+            .last()
+            .as_str()
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// Get the content codings of the body, in the order they were applied.
+    ///
+    /// These are the lowercased values of the `Content-Encoding` header, such as `gzip` or `br`,
+    /// without `identity`. Backends which decompress bodies may leave the header in place, so
+    /// this describes the body as sent.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let res = surf::get("https://httpbin.org/gzip").await?;
+    /// assert_eq!(res.content_encoding(), ["gzip"]);
+    /// # Ok(()) }
+    /// ```
+    pub fn content_encoding(&self) -> Vec<String> {
+        codings(self.header(headers::CONTENT_ENCODING))
+    }
+
+    /// Get the transfer codings of the body, in the order they were applied.
+    ///
+    /// These are the lowercased values of the `Transfer-Encoding` header, such as `chunked`,
+    /// without `identity`. Some backends remove the header once they have decoded the body.
+    pub fn transfer_encoding(&self) -> Vec<String> {
+        codings(self.header(headers::TRANSFER_ENCODING))
+    }
+
+    /// Returns `true` if the body was sent in chunks, without announcing its length up front.
+    ///
+    /// Bodies of unknown length can't be resumed reliably, since there is no way to tell whether
+    /// a download is complete.
+    pub fn is_chunked(&self) -> bool {
+        self.transfer_encoding().last().map(String::as_str) == Some("chunked")
+    }
+
+    /// Returns `true` if the server supports byte range requests for this resource, which allows
+    /// resuming downloads.
+    ///
+    /// This checks for `Accept-Ranges: bytes`.
+    pub fn accepts_ranges(&self) -> bool {
+        self.header("Accept-Ranges")
+            .iter()
+            .flat_map(|values| values.iter())
+            .flat_map(|value| value.as_str().split(','))
+            .any(|unit| unit.trim().eq_ignore_ascii_case("bytes"))
+    }
+
     /// Set the body reader.
     pub fn set_body(&mut self, body: impl Into<Body>) {
         self.res.set_body(body);
@@ -193,6 +572,114 @@ impl Response {
         self.res.swap_body(body)
     }
 
+    /// Replace the body with the body returned by `f`, such as to wrap its reader in one which
+    /// decrypts or transcodes it in middleware.
+    ///
+    /// The `Content-Type` header is kept as it was, and can be changed with
+    /// [`insert_header`](Response::insert_header) afterwards if `f` changes the type of the body.
+    /// If the new body has another length than the old one, a `Content-Length` header is set to
+    /// the length of the new body if that is known, and removed otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_std::io::BufReader;
+    /// use futures_util::io::AsyncReadExt;
+    /// use surf::{http, Body, Response, StatusCode};
+    ///
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let mut res = Response::from(http::Response::new(StatusCode::Ok));
+    /// res.set_body("hello world");
+    /// res.insert_header("Content-Length", "11");
+    /// res.map_body(|body| {
+    ///     let reader = body.into_reader().take(5);
+    ///     Body::from_reader(BufReader::new(reader), None)
+    /// });
+    /// assert!(res.header("Content-Length").is_none());
+    /// assert_eq!(res.body_string().await?, "hello");
+    /// # Ok(()) }
+    /// ```
+    pub fn map_body(&mut self, f: impl FnOnce(Body) -> Body) {
+        crate::utils::map_body(&mut self.res, f);
+    }
+
+    /// Copy the body into `writer` as it is read.
+    ///
+    /// This returns a response whose body yields the same bytes as before, while also writing
+    /// them to `writer`, e.g. a file, a hasher, or a cache entry. The writer is closed once the
+    /// body has been read to the end, so the body has to be consumed completely for the copy to
+    /// be complete. Reading the body applies backpressure from the writer, and write errors are
+    /// returned from the body reads.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let file = async_std::fs::File::create("page.html").await?;
+    /// let mut res = surf::get("https://httpbin.org/html").await?.tee(file);
+    /// let page = res.body_string().await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn tee<W>(mut self, writer: W) -> Self
+    where
+        W: AsyncWrite + Unpin + Send + Sync + 'static,
+    {
+        self.map_body(|body| {
+            let len = body.len();
+            map_body_reader(body, len, |inner| TeeReader {
+                inner,
+                writer,
+                len,
+                read: 0,
+                pending: Vec::new(),
+                unread: Vec::new(),
+                closed: false,
+            })
+        });
+        self
+    }
+
+    /// Take the body as a stream of chunks, as they are received.
+    ///
+    /// This is an alternative to reading the response as an `AsyncRead`, which works with the
+    /// `StreamExt` combinators, e.g. to report progress or to process a large body piece by piece.
+    /// The body is taken out of the response, which is left with an empty body.
+    ///
+    /// # Errors
+    ///
+    /// Any I/O error encountered while reading the body is yielded as an `Err`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use futures_util::stream::TryStreamExt;
+    ///
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let mut res = surf::get("https://httpbin.org/bytes/4096").await?;
+    /// let mut received = 0;
+    /// let mut chunks = res.bytes_stream();
+    /// while let Some(chunk) = chunks.try_next().await? {
+    ///     received += chunk.len();
+    ///     println!("received {} bytes", received);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn bytes_stream(&mut self) -> BoxStream<'static, crate::Result<Vec<u8>>> {
+        let body = self.take_body();
+        stream::try_unfold(body, |mut body| async move {
+            let chunk = body.fill_buf().await?.to_vec();
+            if chunk.is_empty() {
+                return Ok(None);
+            }
+            body.consume_unpin(chunk.len());
+            Ok(Some((chunk, body)))
+        })
+        .boxed()
+    }
+
     /// Reads the entire request body into a byte buffer.
     ///
     /// This method can be called after the body has already been read, but will
@@ -216,6 +703,45 @@ impl Response {
         self.res.body_bytes().await
     }
 
+    /// Stream the response body to a file at `path`, with the default
+    /// [`DownloadOptions`](crate::DownloadOptions).
+    ///
+    /// The body is written to a temporary file next to `path`, which is renamed to `path` once
+    /// the body has been read, so `path` never holds a partial file. The status of the response
+    /// isn't checked. Returns the number of bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written, reading the body fails, or the body
+    /// doesn't match its `Content-Length`, with a [`LengthMismatch`](crate::LengthMismatch).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let mut res = surf::get("https://example.com/tool.tar.gz").await?;
+    /// res.save_to("tool.tar.gz").await?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "download")))]
+    #[cfg(all(feature = "download", not(target_arch = "wasm32")))]
+    pub async fn save_to(&mut self, path: impl AsRef<std::path::Path>) -> crate::Result<u64> {
+        self.save_to_with(path, crate::DownloadOptions::new()).await
+    }
+
+    /// Stream the response body to a file at `path`, like [`save_to`](Response::save_to), with
+    /// `options` for the buffer size, length verification, and progress reporting.
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "download")))]
+    #[cfg(all(feature = "download", not(target_arch = "wasm32")))]
+    pub async fn save_to_with(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        options: crate::DownloadOptions,
+    ) -> crate::Result<u64> {
+        crate::download::save(self, path.as_ref(), &options).await
+    }
+
     /// Reads the entire response body into a string.
     ///
     /// This method can be called after the body has already been read, but will
@@ -229,6 +755,10 @@ impl Response {
     /// disabled, Surf only supports reading UTF-8 response bodies. The "encoding"
     /// feature is enabled by default.
     ///
+    /// The body is decoded as it is read, so only the decoded string is kept in memory, and not
+    /// the raw body as well. On wasm, the browser decodes bodies in encodings other than UTF-8
+    /// in one piece.
+    ///
     /// # Errors
     ///
     /// Any I/O error encountered while reading the body is immediately returned
@@ -247,13 +777,79 @@ impl Response {
     /// # Ok(()) }
     /// ```
     pub async fn body_string(&mut self) -> crate::Result<String> {
-        let bytes = self.body_bytes().await?;
-        let mime = self.content_type();
-        let claimed_encoding = mime
-            .as_ref()
-            .and_then(|mime| mime.param("charset"))
-            .map(|name| name.to_string());
-        decode_body(bytes, claimed_encoding.as_deref())
+        let mut string = String::new();
+        let mut chunks = self.text_stream();
+        while let Some(chunk) = chunks.next().await {
+            string.push_str(&chunk?);
+        }
+        Ok(string)
+    }
+
+    /// Take the body as a stream of decoded text, as it is received.
+    ///
+    /// The body is decoded with the encoding of the `charset` of the Content-Type header, as
+    /// [`body_string`](Response::body_string) does, and characters split between chunks of the
+    /// body are kept for the next string. The body is taken out of the response, which is left
+    /// with an empty body.
+    ///
+    /// # Errors
+    ///
+    /// Any I/O error encountered while reading the body is yielded as an `Err`.
+    ///
+    /// If the encoding is unsupported, the whole body is read and an `Err` with a
+    /// [`DecodeError`] holding it is yielded. If the body is incorrect for the encoding, an
+    /// `Err` with a `DecodeError` holding the chunk which failed to decode is yielded.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use futures_util::stream::TryStreamExt;
+    ///
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let mut res = surf::get("https://httpbin.org/stream/20").await?;
+    /// let mut text = res.text_stream();
+    /// while let Some(chunk) = text.try_next().await? {
+    ///     print!("{}", chunk);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn text_stream(&mut self) -> BoxStream<'static, crate::Result<String>> {
+        let charset = self
+            .content_type()
+            .and_then(|mime| mime.param("charset").map(|name| name.to_string()));
+        let body = self.take_body();
+        let decoder = TextDecoder::new(charset.as_deref());
+        stream::try_unfold(Some((body, decoder)), |state| async move {
+            let (mut body, decoder) = match state {
+                Some(state) => state,
+                None => return Ok(None),
+            };
+            let mut decoder = match decoder {
+                Ok(decoder) => decoder,
+                Err(mut err) => {
+                    // The whole body is kept in the error, for a fallback parsing strategy.
+                    body.read_to_end(&mut err.data).await?;
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, err).into());
+                }
+            };
+            loop {
+                let chunk = body.fill_buf().await?;
+                let last = chunk.is_empty();
+                let text = decoder
+                    .decode(chunk, last)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                let read = chunk.len();
+                body.consume_unpin(read);
+                match (last, text.is_empty()) {
+                    (true, true) => return Ok(None),
+                    (true, false) => return Ok(Some((text, None))),
+                    (false, true) => {}
+                    (false, false) => return Ok(Some((text, Some((body, Ok(decoder)))))),
+                }
+            }
+        })
+        .boxed()
     }
 
     /// Reads and deserialized the entire request body from json.
@@ -286,6 +882,186 @@ impl Response {
         serde_json::from_slice(&body_bytes).map_err(crate::Error::from)
     }
 
+    /// Take the body as a stream of values, decoded from newline-delimited JSON (NDJSON) as it is
+    /// received, such as the event streams of Docker or the watches of Kubernetes.
+    ///
+    /// Each line holds one JSON value, and may end with `\r\n`. Blank lines are skipped, and the
+    /// last line doesn't need a newline. Only the current line is buffered, so the body may be
+    /// endless. Lines may take up to the
+    /// [configured length](crate::Config::set_max_json_line_length), 4 MiB by default. The body
+    /// is taken out of the response, which is left with an empty body.
+    ///
+    /// # Errors
+    ///
+    /// A line which isn't valid JSON for `T`, or which is too long, is yielded as an `Err`, and
+    /// the stream goes on with the next line. The rest of a line which is too long is skipped
+    /// without being buffered. An I/O error encountered while reading the body is yielded as an
+    /// `Err`, and ends the stream.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use futures_util::stream::TryStreamExt;
+    ///
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// #[derive(serde::Deserialize)]
+    /// struct Event {
+    ///     status: String,
+    /// }
+    ///
+    /// let mut res = surf::get("unix:///var/run/docker.sock:/events").await?;
+    /// let mut events = res.body_json_lines::<Event>();
+    /// while let Some(event) = events.try_next().await? {
+    ///     println!("{}", event.status);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn body_json_lines<T>(&mut self) -> BoxStream<'static, crate::Result<T>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let max = self
+            .ext::<JsonLineLimit>()
+            .map_or(DEFAULT_MAX_JSON_LINE_LENGTH, |&JsonLineLimit(max)| max);
+        let body = self.take_body();
+        // The state holds whether the rest of a line which is too long is being skipped.
+        stream::unfold(Some((body, Vec::new(), false)), move |state| async move {
+            let (mut body, mut line, mut skipping) = state?;
+            loop {
+                let chunk = match body.fill_buf().await {
+                    Ok(chunk) => chunk,
+                    Err(err) => return Some((Err(err.into()), None)),
+                };
+                if chunk.is_empty() {
+                    return match skipping || line.iter().all(u8::is_ascii_whitespace) {
+                        true => None,
+                        false => Some((parse_json_line(&line), None)),
+                    };
+                }
+                let newline = chunk.iter().position(|&b| b == b'\n');
+                let read = newline.map_or(chunk.len(), |i| i + 1);
+                if skipping {
+                    body.consume_unpin(read);
+                    skipping = newline.is_none();
+                    continue;
+                }
+                if line.len() + read > max {
+                    body.consume_unpin(read);
+                    line.clear();
+                    let err = crate::Error::from_str(
+                        StatusCode::UnprocessableEntity,
+                        format!("a line of the JSON stream is longer than {} bytes", max),
+                    );
+                    return Some((Err(err), Some((body, line, newline.is_none()))));
+                }
+                line.extend_from_slice(&chunk[..read]);
+                body.consume_unpin(read);
+                if newline.is_some() {
+                    let blank = line.iter().all(u8::is_ascii_whitespace);
+                    let value = (!blank).then(|| parse_json_line(&line));
+                    line.clear();
+                    if let Some(value) = value {
+                        return Some((value, Some((body, line, false))));
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Reads and deserializes the entire response body from MessagePack.
+    ///
+    /// # Errors
+    ///
+    /// Any I/O error encountered while reading the body is immediately returned
+    /// as an `Err`.
+    ///
+    /// If the response isn't `application/msgpack`, or another MessagePack media type such as
+    /// `application/x-msgpack`, a `415 Unsupported Media Type` error is returned without reading
+    /// the body. If the body isn't valid MessagePack for `T`, an `Err` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use serde::Deserialize;
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// #[derive(Deserialize)]
+    /// struct Reading {
+    ///     sensor: String,
+    ///     value: f64,
+    /// }
+    ///
+    /// let mut res = surf::get("https://api.example.com/readings/latest")
+    ///     .header("Accept", "application/msgpack")
+    ///     .await?;
+    /// let reading: Reading = res.body_msgpack().await?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg_attr(feature = "docs", doc(cfg(feature = "msgpack")))]
+    #[cfg(feature = "msgpack")]
+    pub async fn body_msgpack<T: DeserializeOwned>(&mut self) -> crate::Result<T> {
+        crate::msgpack::expect_msgpack(self.content_type())?;
+        let body = self.body_bytes().await?;
+        Ok(rmp_serde::from_slice(&body)?)
+    }
+
+    /// Reads and deserialize the entire request body from json, using the error schema `E` for
+    /// responses which aren't successful.
+    ///
+    /// [Successful](Response::is_success) bodies are decoded into `T`, and other bodies into `E`,
+    /// which is returned as [`ApiError::Api`] along with the status.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ApiError::Other`] if the body can't be read or decoded. If an error body doesn't
+    /// match `E`, the error has the status of the response.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// use surf::ApiError;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// #[derive(Debug, Deserialize)]
+    /// struct ErrorBody {
+    ///     message: String,
+    /// }
+    ///
+    /// let mut res = surf::get("https://api.example.com/users/1").await?;
+    /// match res.body_json_or_error::<User, ErrorBody>().await {
+    ///     Ok(user) => println!("hello {}", user.name),
+    ///     Err(ApiError::Api { status, error }) => println!("{}: {}", status, error.message),
+    ///     Err(ApiError::Other(err)) => return Err(err),
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub async fn body_json_or_error<T, E>(&mut self) -> Result<T, ApiError<E>>
+    where
+        T: DeserializeOwned,
+        E: DeserializeOwned,
+    {
+        let status = self.status();
+        let success = self.is_success();
+        let body_bytes = self.body_bytes().await?;
+        if success {
+            serde_json::from_slice(&body_bytes).map_err(|err| ApiError::Other(err.into()))
+        } else {
+            match serde_json::from_slice(&body_bytes) {
+                Ok(error) => Err(ApiError::Api { status, error }),
+                Err(err) => Err(ApiError::Other(Error::new(status, err))),
+            }
+        }
+    }
+
     /// Reads and deserialized the entire request body from form encoding.
     ///
     /// # Errors
@@ -314,6 +1090,46 @@ impl Response {
     pub async fn body_form<T: serde::de::DeserializeOwned>(&mut self) -> crate::Result<T> {
         self.res.body_form().await
     }
+
+    /// Reads and parses the entire response body as `multipart/form-data`.
+    ///
+    /// The parts can be looked up by the name of their field, such as the fields and files some
+    /// upload APIs answer with.
+    ///
+    /// # Errors
+    ///
+    /// Any I/O error encountered while reading the body is immediately returned
+    /// as an `Err`.
+    ///
+    /// If the response isn't `multipart/*` with a boundary, or its body is malformed, an `Err`
+    /// is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[async_std::main]
+    /// # async fn main() -> surf::Result<()> {
+    /// let mut res = surf::get("https://api.example.com/v1/export").await?;
+    /// let form = res.body_multipart().await?;
+    /// for part in &form {
+    ///     println!("{:?}: {} bytes", part.name(), part.bytes().len());
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub async fn body_multipart(&mut self) -> crate::Result<Multipart> {
+        let boundary = self
+            .content_type()
+            .filter(|mime| mime.basetype() == "multipart")
+            .and_then(|mime| mime.param("boundary").map(|b| b.as_str().to_string()))
+            .ok_or_else(|| {
+                Error::from_str(
+                    StatusCode::UnsupportedMediaType,
+                    "the response isn't multipart with a boundary",
+                )
+            })?;
+        let body = self.body_bytes().await?;
+        Multipart::parse(&body, &boundary)
+    }
 }
 
 impl From<http::Response> for Response {
@@ -413,6 +1229,391 @@ impl Index<&str> for Response {
     }
 }
 
+/// A body reader which copies the bytes it reads into a writer.
+struct TeeReader<W> {
+    inner: BodyReader,
+    writer: W,
+    /// The length of the body, if known.
+    len: Option<usize>,
+    /// The number of bytes read so far.
+    read: usize,
+    /// Bytes which have been read, but not yet written.
+    pending: Vec<u8>,
+    /// The last bytes of the body, held back until the writer has been closed.
+    unread: Vec<u8>,
+    /// Whether the writer has been closed.
+    closed: bool,
+}
+
+impl<W: AsyncWrite + Unpin> TeeReader<W> {
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            let written =
+                futures_util::ready!(Pin::new(&mut self.writer).poll_write(cx, &self.pending))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending.drain(..written);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        futures_util::ready!(self.poll_write_pending(cx))?;
+        if !self.closed {
+            futures_util::ready!(Pin::new(&mut self.writer).poll_close(cx))?;
+            self.closed = true;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncRead for TeeReader<W> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        futures_util::ready!(this.poll_write_pending(cx))?;
+
+        if !this.unread.is_empty() {
+            futures_util::ready!(this.poll_finish(cx))?;
+            let read = buf.len().min(this.unread.len());
+            buf[..read].copy_from_slice(&this.unread[..read]);
+            this.unread.drain(..read);
+            return Poll::Ready(Ok(read));
+        }
+
+        let read = futures_util::ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.read += read;
+        this.pending.extend_from_slice(&buf[..read]);
+
+        // The body stops reading once it has reached its length, so don't wait for EOF to close
+        // the writer. The last bytes are held back until it is closed, so the copy is complete
+        // by the time the body has been read.
+        if read == 0 || this.len == Some(this.read) {
+            return match this.poll_finish(cx) {
+                Poll::Ready(result) => Poll::Ready(result.map(|_| read)),
+                Poll::Pending => {
+                    this.unread.extend_from_slice(&buf[..read]);
+                    Poll::Pending
+                }
+            };
+        }
+
+        // Start writing right away; whatever doesn't fit is written on the next read.
+        if let Poll::Ready(Err(err)) = this.poll_write_pending(cx) {
+            return Poll::Ready(Err(err));
+        }
+        Poll::Ready(Ok(read))
+    }
+}
+
+/// The statuses the client which sent a response counts as a success.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SuccessPredicate(pub(crate) fn(StatusCode) -> bool);
+
+/// The status code a response arrived with, if [`StatusCode`] doesn't know it.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(feature = "curl-client"), allow(dead_code))]
+pub(crate) struct RawStatus(pub(crate) u16);
+
+/// The status to report for the status code `code`.
+///
+/// Unknown codes are treated as the `x00` code of their class, as RFC 9110 requires of clients.
+/// Codes outside of the classes are invalid.
+#[cfg_attr(not(feature = "curl-client"), allow(dead_code))]
+pub(crate) fn known_status(code: u16) -> Option<StatusCode> {
+    use std::convert::TryFrom;
+
+    match code {
+        100..=599 => StatusCode::try_from(code)
+            .or_else(|_| StatusCode::try_from(code / 100 * 100))
+            .ok(),
+        _ => None,
+    }
+}
+
+/// The URL a response came from.
+#[derive(Debug, Clone)]
+pub(crate) struct ResponseUrl(pub(crate) Url);
+
+/// The codings listed in a `Content-Encoding` or `Transfer-Encoding` header.
+fn codings(values: Option<&HeaderValues>) -> Vec<String> {
+    values
+        .iter()
+        .flat_map(|values| values.iter())
+        .flat_map(|value| value.as_str().split(','))
+        .map(|coding| coding.trim().to_ascii_lowercase())
+        .filter(|coding| !coding.is_empty() && coding != "identity")
+        .collect()
+}
+
+/// Decode a line of newline-delimited JSON.
+fn parse_json_line<T: DeserializeOwned>(line: &[u8]) -> crate::Result<T> {
+    serde_json::from_slice(line).map_err(crate::Error::from)
+}
+
+/// Every status but `4xx` and `5xx` is a success.
+pub(crate) fn default_success(status: StatusCode) -> bool {
+    !status.is_client_error() && !status.is_server_error()
+}
+
+/// The most bytes of problem details read from an error response.
+const MAX_PROBLEM_BYTES: usize = 64 * 1024;
+
+/// The number of bytes of an error response body to capture.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ErrorBodyLimit(pub(crate) usize);
+
+/// The most bytes a line of newline-delimited JSON may take, unless configured otherwise.
+pub(crate) const DEFAULT_MAX_JSON_LINE_LENGTH: usize = 4 * 1024 * 1024;
+
+/// The most bytes a line of newline-delimited JSON may take, set with
+/// [`Config::set_max_json_line_length`](crate::Config::set_max_json_line_length).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct JsonLineLimit(pub(crate) usize);
+
+/// The header values of a response as the server sent them, added as an extension by the
+/// transports which read the response head themselves.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(
+    not(all(
+        any(
+            feature = "h1-client",
+            feature = "h1-client-rustls",
+            feature = "h1-client-no-tls"
+        ),
+        not(feature = "curl-client"),
+        not(target_arch = "wasm32")
+    )),
+    allow(dead_code)
+)]
+pub(crate) struct RawHeaders(Vec<(String, Vec<u8>)>);
+
+#[cfg_attr(
+    not(all(
+        any(
+            feature = "h1-client",
+            feature = "h1-client-rustls",
+            feature = "h1-client-no-tls"
+        ),
+        not(feature = "curl-client"),
+        not(target_arch = "wasm32")
+    )),
+    allow(dead_code)
+)]
+impl RawHeaders {
+    /// The headers of the response head `head`, which starts with the status line.
+    pub(crate) fn parse(head: &[u8]) -> Self {
+        fn trim(mut bytes: &[u8]) -> &[u8] {
+            while let [b' ' | b'\t' | b'\r' | b'\n', rest @ ..] = bytes {
+                bytes = rest;
+            }
+            while let [rest @ .., b' ' | b'\t' | b'\r' | b'\n'] = bytes {
+                bytes = rest;
+            }
+            bytes
+        }
+        let headers = head
+            .split(|&b| b == b'\n')
+            .skip(1)
+            .filter_map(|line| {
+                let colon = line.iter().position(|&b| b == b':')?;
+                let name = String::from_utf8_lossy(trim(&line[..colon])).to_ascii_lowercase();
+                Some((name, trim(&line[colon + 1..]).to_vec()))
+            })
+            .collect();
+        Self(headers)
+    }
+}
+
+/// A response had more headers than the client allows, set with
+/// [`Config::set_max_response_headers`](crate::Config::set_max_response_headers) and
+/// [`Config::set_max_response_header_bytes`](crate::Config::set_max_response_header_bytes).
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderLimitExceeded {
+    /// The response had too many headers.
+    Count {
+        /// The most headers allowed.
+        limit: usize,
+        /// The number of headers of the response, or of those read before it was refused.
+        actual: usize,
+    },
+    /// The headers of the response took too many bytes.
+    Bytes {
+        /// The most bytes allowed.
+        limit: usize,
+        /// The number of bytes the headers of the response took, or of those read before it was
+        /// refused.
+        actual: usize,
+    },
+}
+
+impl HeaderLimitExceeded {
+    /// Check the headers of `res` against the limits of `config`.
+    pub(crate) fn check(res: &http::Response, config: &crate::Config) -> Result<(), Self> {
+        let (mut count, mut bytes) = (0, 0);
+        for (name, values) in res.iter() {
+            for value in values {
+                count += 1;
+                // The bytes of the `name: value\r\n` line.
+                bytes += name.as_str().len() + value.as_str().len() + 4;
+            }
+        }
+        match (
+            config.max_response_headers,
+            config.max_response_header_bytes,
+        ) {
+            (Some(limit), _) if count > limit => Err(Self::Count {
+                limit,
+                actual: count,
+            }),
+            (_, Some(limit)) if bytes > limit => Err(Self::Bytes {
+                limit,
+                actual: bytes,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for HeaderLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Count { limit, actual } => write!(
+                f,
+                "the response has {} headers, more than the limit of {}",
+                actual, limit
+            ),
+            Self::Bytes { limit, actual } => write!(
+                f,
+                "the response headers take {} bytes, more than the limit of {}",
+                actual, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HeaderLimitExceeded {}
+
+/// An error created from a `4xx` or `5xx` response, by
+/// [`Response::error_for_status`](Response::error_for_status).
+#[derive(Debug, Clone)]
+pub struct StatusError {
+    status: StatusCode,
+    content_type: Option<Mime>,
+    body: Vec<u8>,
+    problem: Option<ProblemDetails>,
+}
+
+impl StatusError {
+    /// The status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The content type of the response, if any.
+    pub fn content_type(&self) -> Option<&Mime> {
+        self.content_type.as_ref()
+    }
+
+    /// The captured start of the response body.
+    ///
+    /// This is empty unless body capture was enabled with
+    /// [`Config::set_capture_error_bodies`](crate::Config::set_capture_error_bodies).
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// The [problem details](ProblemDetails) of the response, if it had an
+    /// `application/problem+json` body.
+    pub fn problem_details(&self) -> Option<&ProblemDetails> {
+        self.problem.as_ref()
+    }
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "request failed with status {} {}",
+            self.status,
+            self.status.canonical_reason()
+        )?;
+        if let Some(problem) = &self.problem {
+            write!(f, ": {}", problem)?;
+        } else if !self.body.is_empty() {
+            write!(f, ": {}", String::from_utf8_lossy(&self.body))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+/// An error from an API which describes its errors with the schema `E`.
+///
+/// Returned from [`Response::body_json_or_error`](Response::body_json_or_error).
+#[derive(Debug)]
+pub enum ApiError<E> {
+    /// The server responded with an error status, and a body matching the error schema.
+    Api {
+        /// The status of the response.
+        status: StatusCode,
+        /// The decoded error body.
+        error: E,
+    },
+    /// Reading or decoding the response failed.
+    Other(Error),
+}
+
+impl<E> From<Error> for ApiError<E> {
+    fn from(err: Error) -> Self {
+        ApiError::Other(err)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for ApiError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Api { status, error } => {
+                write!(f, "request failed with status {}: {:?}", status, error)
+            }
+            ApiError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for ApiError<E> {}
+
+/// The local time at which a response arrived.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+pub(crate) struct ReceivedAt(pub(crate) SystemTime);
+
+/// The offset of a server clock from the local clock, returned by
+/// [`Response::clock_skew`](Response::clock_skew).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSkew {
+    /// The server clock is ahead of the local clock by this much.
+    Ahead(Duration),
+    /// The server clock is behind the local clock by this much.
+    Behind(Duration),
+}
+
+impl ClockSkew {
+    /// Convert a local time to the corresponding time on the server clock.
+    pub fn correct(&self, local: SystemTime) -> SystemTime {
+        match *self {
+            ClockSkew::Ahead(skew) => local + skew,
+            ClockSkew::Behind(skew) => local - skew,
+        }
+    }
+}
+
 /// An error occurred while decoding a response body to a string.
 ///
 /// The error carries the encoding that was used to attempt to decode the body, and the raw byte
@@ -423,6 +1624,10 @@ pub struct DecodeError {
     /// The name of the encoding that was used to try to decode the input.
     pub encoding: String,
     /// The input data as bytes.
+    ///
+    /// When decoding fails part way through a body read with
+    /// [`text_stream`](Response::text_stream) or [`body_string`](Response::body_string), this is
+    /// the chunk of the body which failed to decode.
     pub data: Vec<u8>,
 }
 
@@ -457,106 +1662,148 @@ fn is_utf8_encoding(encoding_label: &str) -> bool {
         || encoding_label.eq_ignore_ascii_case("unicode-1-1-utf-8")
 }
 
-/// Decode a response body as utf-8.
-///
-/// # Errors
+/// A decoder of text in an encoding, which is fed a body chunk by chunk.
 ///
-/// If the body cannot be decoded as utf-8, this function returns an `std::io::Error` of kind
-/// `std::io::ErrorKind::InvalidData`, carrying a `DecodeError` struct.
-#[cfg(not(feature = "encoding"))]
-fn decode_body(bytes: Vec<u8>, content_encoding: Option<&str>) -> Result<String, Error> {
-    if is_utf8_encoding(content_encoding.unwrap_or("utf-8")) {
-        Ok(String::from_utf8(bytes).map_err(|err| {
-            let err = DecodeError {
-                encoding: "utf-8".to_string(),
-                data: err.into_bytes(),
-            };
-            io::Error::new(io::ErrorKind::InvalidData, err)
-        })?)
-    } else {
-        let err = DecodeError {
-            encoding: "utf-8".to_string(),
-            data: bytes,
-        };
-        Err(io::Error::new(io::ErrorKind::InvalidData, err).into())
-    }
+/// Bodies in encodings other than UTF-8 are only supported with the "encoding" feature. On
+/// wasm, such bodies are decoded in one piece by the browser when the last chunk arrives.
+pub(crate) struct TextDecoder {
+    encoding: String,
+    inner: Decoder,
 }
 
-/// Decode a response body as the given content type.
-///
-/// If the input bytes are valid utf-8, this does not make a copy.
-///
-/// # Errors
-///
-/// If an unsupported encoding is requested, or the body does not conform to the requested
-/// encoding, this function returns an `std::io::Error` of kind `std::io::ErrorKind::InvalidData`,
-/// carrying a `DecodeError` struct.
-#[cfg(all(feature = "encoding", not(target_arch = "wasm32")))]
-fn decode_body(bytes: Vec<u8>, content_encoding: Option<&str>) -> Result<String, Error> {
-    use encoding_rs::Encoding;
-    use std::borrow::Cow;
-
-    let content_encoding = content_encoding.unwrap_or("utf-8");
-    if let Some(encoding) = Encoding::for_label(content_encoding.as_bytes()) {
-        let (decoded, encoding_used, failed) = encoding.decode(&bytes);
-        if failed {
-            let err = DecodeError {
-                encoding: encoding_used.name().into(),
-                data: bytes,
-            };
-            Err(io::Error::new(io::ErrorKind::InvalidData, err).into())
-        } else {
-            Ok(match decoded {
-                // If encoding_rs returned a `Cow::Borrowed`, the bytes are guaranteed to be valid
-                // UTF-8, by virtue of being UTF-8 or being in the subset of ASCII that is the same
-                // in UTF-8.
-                Cow::Borrowed(_) => unsafe { String::from_utf8_unchecked(bytes) },
-                Cow::Owned(string) => string,
-            })
+enum Decoder {
+    /// The bytes of a UTF-8 character which was split between chunks.
+    #[cfg(any(not(feature = "encoding"), target_arch = "wasm32"))]
+    Utf8(Vec<u8>),
+    #[cfg(all(feature = "encoding", not(target_arch = "wasm32")))]
+    Encoding(encoding_rs::Decoder),
+    /// The body so far, to be decoded by the browser.
+    #[cfg(all(feature = "encoding", target_arch = "wasm32"))]
+    Buffered(Vec<u8>),
+}
+
+impl TextDecoder {
+    /// Create a decoder for the encoding with `label`, or UTF-8 without one.
+    ///
+    /// Returns a `DecodeError` without data if the encoding is unsupported.
+    pub(crate) fn new(label: Option<&str>) -> Result<Self, DecodeError> {
+        let label = label.unwrap_or("utf-8");
+        let unsupported = || DecodeError {
+            encoding: label.to_string(),
+            data: Vec::new(),
+        };
+        cfg_if::cfg_if! {
+            if #[cfg(all(feature = "encoding", not(target_arch = "wasm32")))] {
+                let encoding = encoding_rs::Encoding::for_label(label.as_bytes())
+                    .ok_or_else(unsupported)?;
+                Ok(Self {
+                    encoding: encoding.name().to_string(),
+                    inner: Decoder::Encoding(encoding.new_decoder()),
+                })
+            } else if #[cfg(all(feature = "encoding", target_arch = "wasm32"))] {
+                // Encoding names are always valid ASCII, so we can avoid including casing
+                // mapping tables.
+                let label = label.to_ascii_lowercase();
+                let inner = match is_utf8_encoding(&label) {
+                    true => Decoder::Utf8(Vec::new()),
+                    false => Decoder::Buffered(Vec::new()),
+                };
+                web_sys::TextDecoder::new_with_label(&label).map_err(|_| unsupported())?;
+                Ok(Self { encoding: label, inner })
+            } else {
+                if !is_utf8_encoding(label) {
+                    return Err(DecodeError {
+                        encoding: "utf-8".to_string(),
+                        ..unsupported()
+                    });
+                }
+                Ok(Self {
+                    encoding: "utf-8".to_string(),
+                    inner: Decoder::Utf8(Vec::new()),
+                })
+            }
         }
-    } else {
-        let err = DecodeError {
-            encoding: content_encoding.to_string(),
-            data: bytes,
+    }
+
+    /// Decode the next `chunk` of the body, which is the end of it if `last` is set.
+    ///
+    /// Returns a `DecodeError` holding `chunk` if it isn't valid in the encoding.
+    pub(crate) fn decode(&mut self, chunk: &[u8], last: bool) -> Result<String, DecodeError> {
+        let malformed = |encoding: &str| DecodeError {
+            encoding: encoding.to_string(),
+            data: chunk.to_vec(),
         };
-        Err(io::Error::new(io::ErrorKind::InvalidData, err).into())
+        match &mut self.inner {
+            #[cfg(any(not(feature = "encoding"), target_arch = "wasm32"))]
+            Decoder::Utf8(pending) => {
+                pending.extend_from_slice(chunk);
+                let valid = match std::str::from_utf8(pending) {
+                    Ok(_) => pending.len(),
+                    Err(err) if err.error_len().is_none() && !last => err.valid_up_to(),
+                    Err(_) => return Err(malformed(&self.encoding)),
+                };
+                let rest = pending.split_off(valid);
+                let text = std::mem::replace(pending, rest);
+                // The bytes up to `valid` were checked above.
+                Ok(String::from_utf8(text).expect("valid utf-8"))
+            }
+            #[cfg(all(feature = "encoding", not(target_arch = "wasm32")))]
+            Decoder::Encoding(decoder) => {
+                use encoding_rs::DecoderResult;
+
+                let mut text = String::new();
+                let mut input = chunk;
+                loop {
+                    let needed = decoder.max_utf8_buffer_length_without_replacement(input.len());
+                    text.reserve(needed.unwrap_or(input.len() * 4 + 16));
+                    let (result, read) =
+                        decoder.decode_to_string_without_replacement(input, &mut text, last);
+                    input = &input[read..];
+                    match result {
+                        DecoderResult::InputEmpty => return Ok(text),
+                        DecoderResult::OutputFull => {}
+                        DecoderResult::Malformed(..) => return Err(malformed(&self.encoding)),
+                    }
+                }
+            }
+            #[cfg(all(feature = "encoding", target_arch = "wasm32"))]
+            Decoder::Buffered(buffer) => {
+                buffer.extend_from_slice(chunk);
+                if !last {
+                    return Ok(String::new());
+                }
+                let mut bytes = std::mem::take(buffer);
+                let decoder = web_sys::TextDecoder::new_with_label(&self.encoding).unwrap();
+                decoder
+                    .decode_with_u8_array(&mut bytes)
+                    .map_err(|_| DecodeError {
+                        encoding: self.encoding.clone(),
+                        data: bytes,
+                    })
+            }
+        }
     }
 }
 
-/// Decode a response body as the given content type.
-///
-/// This always makes a copy. (It could be optimized to avoid the copy if the encoding is utf-8.)
+/// Decode a whole response body as the encoding with `label`.
 ///
 /// # Errors
 ///
 /// If an unsupported encoding is requested, or the body does not conform to the requested
 /// encoding, this function returns an `std::io::Error` of kind `std::io::ErrorKind::InvalidData`,
 /// carrying a `DecodeError` struct.
-#[cfg(all(feature = "encoding", target_arch = "wasm32"))]
-fn decode_body(mut bytes: Vec<u8>, content_encoding: Option<&str>) -> Result<String, Error> {
-    use web_sys::TextDecoder;
-
-    // Encoding names are always valid ASCII, so we can avoid including casing mapping tables
-    let content_encoding = content_encoding.unwrap_or("utf-8").to_ascii_lowercase();
-    if is_utf8_encoding(&content_encoding) {
-        return String::from_utf8(bytes)
-            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err).into());
-    }
-
-    let decoder = TextDecoder::new_with_label(&content_encoding).unwrap();
-
-    Ok(decoder.decode_with_u8_array(&mut bytes).map_err(|_| {
-        let err = DecodeError {
-            encoding: content_encoding.to_string(),
-            data: bytes,
-        };
-        io::Error::new(io::ErrorKind::InvalidData, err)
-    })?)
+#[cfg(test)]
+fn decode_body(bytes: Vec<u8>, label: Option<&str>) -> Result<String, Error> {
+    let decoded = TextDecoder::new(label).and_then(|mut decoder| decoder.decode(&bytes, true));
+    decoded.map_err(|mut err| {
+        err.data = bytes;
+        io::Error::new(io::ErrorKind::InvalidData, err).into()
+    })
 }
 
 #[cfg(test)]
 mod decode_tests {
-    use super::decode_body;
+    use super::{decode_body, TextDecoder};
 
     #[test]
     fn utf8() {
@@ -593,4 +1840,34 @@ mod decode_tests {
             assert!(result.is_err(), "Only utf-8 is supported");
         }
     }
+
+    #[test]
+    fn split_characters() {
+        let input = "Rød grød med fløde";
+        let mut decoder = TextDecoder::new(Some("utf-8")).unwrap();
+        let mut output = String::new();
+        for byte in input.as_bytes() {
+            output.push_str(&decoder.decode(&[*byte], false).unwrap());
+        }
+        output.push_str(&decoder.decode(&[], true).unwrap());
+        assert_eq!(output, input);
+
+        let mut decoder = TextDecoder::new(None).unwrap();
+        assert_eq!(decoder.decode(&[b'a', 0xc3], false).unwrap(), "a");
+        let err = decoder.decode(&[], true).unwrap_err();
+        assert!(err.encoding.eq_ignore_ascii_case("utf-8"));
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn split_euc_kr() {
+        let input = [0xb3, 0xbb, 0x20, 0xc7, 0xb0];
+        let mut decoder = TextDecoder::new(Some("euc-kr")).unwrap();
+        let mut output = String::new();
+        for byte in &input {
+            output.push_str(&decoder.decode(&[*byte], false).unwrap());
+        }
+        output.push_str(&decoder.decode(&[], true).unwrap());
+        assert_eq!(output, "내 품");
+    }
 }
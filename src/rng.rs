@@ -0,0 +1,85 @@
+//! Sources of randomness, so that randomized behavior can be reproduced.
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A source of randomness for a [`Client`](crate::Client).
+///
+/// The random source of a client is used for the jitter of the
+/// [`Retry`](crate::middleware::Retry) middleware. It is set with
+/// [`Config::set_rng`](crate::Config::set_rng).
+pub trait Rng: Debug + Send + Sync + 'static {
+    /// Fill `dest` with random bytes.
+    fn fill_bytes(&self, dest: &mut [u8]);
+
+    /// A random `u64`.
+    fn next_u64(&self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    /// A random number between `0.0` and `1.0`, inclusive.
+    fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / ((1u64 << 53) - 1) as f64
+    }
+}
+
+/// The random number generator of the operating system.
+///
+/// If it fails, the bytes are left unchanged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsRng;
+
+impl Rng for OsRng {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        let _ = getrandom::getrandom(dest);
+    }
+}
+
+/// A generator which produces the same sequence of bytes for the same seed.
+///
+/// This makes randomized behavior reproducible in tests and record/replay runs. It is not
+/// suitable for cryptographic use. Clones share the same sequence.
+///
+/// ```
+/// use surf::{Rng, SeededRng};
+///
+/// let a = SeededRng::new(42);
+/// let b = SeededRng::new(42);
+/// assert_eq!(a.next_u64(), b.next_u64());
+/// ```
+#[derive(Debug, Clone)]
+pub struct SeededRng {
+    state: Arc<AtomicU64>,
+}
+
+impl SeededRng {
+    /// Create a new instance, starting from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Arc::new(AtomicU64::new(seed)),
+        }
+    }
+}
+
+impl Rng for SeededRng {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    // SplitMix64, which advances the state by a constant, so it can be shared without locking.
+    fn next_u64(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
@@ -0,0 +1,109 @@
+//! Headers bound to an async scope, added to every request sent within it.
+
+use crate::http::headers::{HeaderName, HeaderValues, ToHeaderValues};
+use crate::Request;
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+type Headers = Arc<Vec<(HeaderName, HeaderValues)>>;
+
+thread_local! {
+    /// The headers of the scopes being polled on this thread, innermost last.
+    static SCOPES: RefCell<Vec<Headers>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Run `future` with `headers` added to every request sent within it, by any `Client`.
+///
+/// This lets context such as the identity of a user, or a trace ID, flow through deeply nested
+/// code without passing a `Client` or the headers along. Headers a request already has are kept,
+/// and scopes can be nested, with the headers of inner scopes replacing those of outer ones.
+///
+/// Requests are sent within the scope if they are sent by `future` itself, so the headers are
+/// not added to requests of tasks spawned by it, unless they are wrapped in the scope too.
+///
+/// # Panics
+///
+/// This will panic if the header values are invalid.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// async fn load_profile() -> surf::Result<String> {
+///     surf::get("https://api.example.com/profile").recv_string().await
+/// }
+///
+/// let profile = surf::with_headers(vec![("X-On-Behalf-Of", "alice")], load_profile()).await?;
+/// # Ok(()) }
+/// ```
+pub fn with_headers<I, N, V, F>(headers: I, future: F) -> WithHeaders<F>
+where
+    I: IntoIterator<Item = (N, V)>,
+    N: Into<HeaderName>,
+    V: ToHeaderValues,
+    F: Future,
+{
+    let headers = headers
+        .into_iter()
+        .map(|(name, values)| {
+            let values = values
+                .to_header_values()
+                .expect("invalid header values")
+                .collect::<Vec<_>>();
+            (name.into(), HeaderValues::from(values))
+        })
+        .collect();
+    WithHeaders {
+        future,
+        headers: Arc::new(headers),
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// A future which adds headers to the requests sent within it, returned from
+    /// [`with_headers`].
+    #[derive(Debug)]
+    pub struct WithHeaders<F> {
+        #[pin]
+        future: F,
+        headers: Headers,
+    }
+}
+
+impl<F: Future> Future for WithHeaders<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        SCOPES.with(|scopes| scopes.borrow_mut().push(this.headers.clone()));
+        let _scope = ScopeGuard;
+        this.future.poll(cx)
+    }
+}
+
+/// Leaves the innermost scope when dropped, even if polling panicked.
+struct ScopeGuard;
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        SCOPES.with(|scopes| scopes.borrow_mut().pop());
+    }
+}
+
+/// Add the headers of the scopes `req` is sent within, which it doesn't have already.
+pub(crate) fn apply(req: &mut Request) {
+    SCOPES.with(|scopes| {
+        for headers in scopes.borrow().iter().rev() {
+            for (name, values) in headers.iter() {
+                if req.header(name).is_none() {
+                    req.insert_header(name, values);
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,136 @@
+//! Cooperative shutdown of the requests of a client.
+
+use crate::utils::BodyReader;
+
+use futures_util::io::AsyncRead;
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// A handle to shut down a [`Client`](crate::Client), returned from
+/// [`Client::shutdown_token`](crate::Client::shutdown_token).
+///
+/// Once [`shutdown`](ShutdownToken::shutdown) is called, the client and its clones refuse to
+/// send new requests, and reading the body of any response they returned fails, including reads
+/// which are waiting for data. Both fail with a [`Shutdown`] error, so that long-lived
+/// downloads and streams end promptly and cleanly when a process terminates.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[async_std::main]
+/// # async fn main() -> surf::Result<()> {
+/// use surf::ErrorKind;
+///
+/// let client = surf::client();
+/// let token = client.shutdown_token();
+/// async_std::task::spawn(async move {
+///     // Wait for a termination signal here.
+///     token.shutdown();
+/// });
+/// let mut res = client.get("https://example.com/stream").await?;
+/// match res.body_bytes().await {
+///     Err(err) if ErrorKind::of(&err) == ErrorKind::Shutdown => println!("shutting down"),
+///     other => println!("{:?}", other?.len()),
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    shut_down: AtomicBool,
+    next_id: AtomicUsize,
+    /// The wakers of the body reads waiting for data, by reader.
+    wakers: Mutex<HashMap<usize, Waker>>,
+}
+
+impl ShutdownToken {
+    /// Shut down the client, failing its in-progress body reads and any later requests.
+    ///
+    /// This can't be undone.
+    pub fn shutdown(&self) {
+        self.inner.shut_down.store(true, Ordering::SeqCst);
+        let wakers = std::mem::take(&mut *self.inner.wakers.lock().unwrap());
+        for waker in wakers.into_values() {
+            waker.wake();
+        }
+    }
+
+    /// Whether the client has been shut down.
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.shut_down.load(Ordering::SeqCst)
+    }
+
+    /// Wrap a response body so that reading it fails once the client is shut down.
+    pub(crate) fn reader(&self, inner: BodyReader) -> ShutdownReader {
+        ShutdownReader {
+            inner,
+            token: self.clone(),
+            id: self.inner.next_id.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+/// The error of requests and body reads which were stopped by a [`ShutdownToken`].
+///
+/// Body reads fail with an `io::Error` wrapping this error. [`ErrorKind::of`] tells both apart
+/// from other errors as [`ErrorKind::Shutdown`].
+///
+/// [`ErrorKind::of`]: crate::ErrorKind::of
+/// [`ErrorKind::Shutdown`]: crate::ErrorKind::Shutdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shutdown;
+
+impl fmt::Display for Shutdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the client is shutting down")
+    }
+}
+
+impl StdError for Shutdown {}
+
+/// A body reader which fails once its client is shut down.
+pub(crate) struct ShutdownReader {
+    inner: BodyReader,
+    token: ShutdownToken,
+    id: usize,
+}
+
+impl AsyncRead for ShutdownReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.token.is_shutdown() {
+            return Poll::Ready(Err(io::Error::other(Shutdown)));
+        }
+        let read = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if read.is_pending() {
+            let mut wakers = this.token.inner.wakers.lock().unwrap();
+            // Check again with the lock held, so a concurrent shutdown can't miss this read.
+            if this.token.is_shutdown() {
+                return Poll::Ready(Err(io::Error::other(Shutdown)));
+            }
+            wakers.insert(this.id, cx.waker().clone());
+        }
+        read
+    }
+}
+
+impl Drop for ShutdownReader {
+    fn drop(&mut self) {
+        self.token.inner.wakers.lock().unwrap().remove(&self.id);
+    }
+}
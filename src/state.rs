@@ -0,0 +1,101 @@
+//! State shared by a client and its middleware.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+type Values = HashMap<TypeId, Box<dyn Any + Send + Sync>>;
+
+/// A store of values keyed by their type, shared by a [`Client`](crate::Client), its clones, and
+/// the client its middleware is given, returned from [`Client::state`](crate::Client::state).
+///
+/// Related middleware can coordinate through it without global statics, such as an
+/// authentication middleware publishing a token which a retry middleware invalidates. Values are
+/// cloned out of the store, so values which should be shared rather than copied, or changed in
+/// place, are usually wrapped in an `Arc`.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// #[derive(Default)]
+/// struct Retries(AtomicUsize);
+///
+/// let client = surf::client();
+/// let retries = client.state().get_or_insert_with(Arc::<Retries>::default);
+/// retries.0.fetch_add(1, Ordering::Relaxed);
+///
+/// let seen = client.clone().state().get::<Arc<Retries>>().unwrap();
+/// assert_eq!(seen.0.load(Ordering::Relaxed), 1);
+/// ```
+#[derive(Clone, Default)]
+pub struct ClientState {
+    values: Arc<RwLock<Values>>,
+}
+
+impl ClientState {
+    /// Insert `value`, returning the previous value of its type, if any.
+    pub fn insert<T: Send + Sync + 'static>(&self, value: T) -> Option<T> {
+        self.values
+            .write()
+            .unwrap()
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.downcast().ok())
+            .map(|previous| *previous)
+    }
+
+    /// Get a clone of the value of type `T`, if any.
+    pub fn get<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.values
+            .read()
+            .unwrap()
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+            .cloned()
+    }
+
+    /// Get a clone of the value of type `T`, inserting the value returned by `default` first if
+    /// there is none.
+    ///
+    /// Concurrent callers all get the same value, as `default` is called at most once.
+    pub fn get_or_insert_with<T, F>(&self, default: F) -> T
+    where
+        T: Clone + Send + Sync + 'static,
+        F: FnOnce() -> T,
+    {
+        if let Some(value) = self.get() {
+            return value;
+        }
+        let mut values = self.values.write().unwrap();
+        let value = values
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(default()));
+        value.downcast_ref::<T>().unwrap().clone()
+    }
+
+    /// Whether there is a value of type `T`.
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.values.read().unwrap().contains_key(&TypeId::of::<T>())
+    }
+
+    /// Remove the value of type `T`, returning it if there was one.
+    pub fn remove<T: Send + Sync + 'static>(&self) -> Option<T> {
+        self.values
+            .write()
+            .unwrap()
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+}
+
+impl fmt::Debug for ClientState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientState")
+            .field("len", &self.values.read().unwrap().len())
+            .finish()
+    }
+}
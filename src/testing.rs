@@ -0,0 +1,363 @@
+//! Testing code which sends requests, without a server.
+//!
+//! [`MockClient`] is an [`HttpClient`] which answers requests matching its [`Mock`]s with canned
+//! responses, and records the requests it receives for assertions.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::convert::TryInto;
+//! use surf::http::Method;
+//! use surf::testing::{Mock, MockClient};
+//! use surf::{Client, Config, Url};
+//!
+//! # #[async_std::main]
+//! # async fn main() -> surf::Result<()> {
+//! let mock = MockClient::new().mock(
+//!     Mock::new(Method::Get, "/users/1")
+//!         .match_header("Accept", "application/json")
+//!         .with_status(200)
+//!         .with_body(r#"{"name":"Chashu"}"#),
+//! );
+//! let client: Client = Config::new()
+//!     .set_http_client(mock.clone())
+//!     .set_base_url(Url::parse("http://api.example.com")?)
+//!     .try_into()?;
+//!
+//! let body = client
+//!     .get("/users/1")
+//!     .header("Accept", "application/json")
+//!     .recv_string()
+//!     .await?;
+//! assert_eq!(body, r#"{"name":"Chashu"}"#);
+//! mock.assert_all_called();
+//! assert_eq!(mock.requests()[0].url().path(), "/users/1");
+//! # Ok(()) }
+//! ```
+
+use crate::http::headers::{HeaderName, HeaderValue, HeaderValues, CONTENT_TYPE};
+use crate::http::{self, mime, Method, Mime, StatusCode, Url};
+use crate::{Body, Error, HttpClient};
+
+use http_client::Config as HttpConfig;
+
+use std::convert::TryInto;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+type BodyMatcher = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// An [`HttpClient`] which answers requests with the response of the first [`Mock`] matching
+/// them.
+///
+/// Requests which no mock matches fail with a `501 Not Implemented` error. Clones share their
+/// mocks and recorded requests, so a clone can be given to a [`Config`](crate::Config) or
+/// [`Client::with_http_client`](crate::Client::with_http_client) while the original is kept for
+/// assertions.
+#[derive(Debug, Clone, Default)]
+pub struct MockClient {
+    inner: Arc<Inner>,
+    config: HttpConfig,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    mocks: Mutex<Vec<Arc<Mock>>>,
+    requests: Mutex<Vec<ReceivedRequest>>,
+}
+
+impl MockClient {
+    /// Create a new instance, without any mocks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `mock`, which is matched after the mocks added before it.
+    pub fn mock(self, mock: Mock) -> Self {
+        self.add(mock);
+        self
+    }
+
+    /// Add `mock` to a client which is already in use, after the mocks added before it.
+    pub fn add(&self, mock: Mock) {
+        self.inner.mocks.lock().unwrap().push(Arc::new(mock));
+    }
+
+    /// The requests received so far, in the order they were received, whether a mock matched
+    /// them or not.
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.inner.requests.lock().unwrap().clone()
+    }
+
+    /// Assert that every mock was called, or called as many times as it
+    /// [expects](Mock::expect).
+    ///
+    /// # Panics
+    ///
+    /// Panics with the mocks which weren't called as expected.
+    pub fn assert_all_called(&self) {
+        let failures: Vec<String> = self
+            .inner
+            .mocks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|mock| {
+                let calls = mock.calls.load(Ordering::SeqCst);
+                match mock.expected {
+                    Some(expected) if calls != expected => Some(format!(
+                        "{} was called {} times, expected {}",
+                        mock, calls, expected
+                    )),
+                    None if calls == 0 => Some(format!("{} was never called", mock)),
+                    _ => None,
+                }
+            })
+            .collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for MockClient {
+    async fn send(&self, mut req: http::Request) -> Result<http::Response, Error> {
+        let received = ReceivedRequest {
+            method: req.method(),
+            url: req.url().clone(),
+            headers: req
+                .iter()
+                .map(|(name, values)| (name.clone(), values.clone()))
+                .collect(),
+            body: req.take_body().into_bytes().await?,
+        };
+        self.inner.requests.lock().unwrap().push(received.clone());
+
+        let mock = self
+            .inner
+            .mocks
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|mock| mock.matches(&received))
+            .cloned();
+        match mock {
+            Some(mock) => {
+                mock.calls.fetch_add(1, Ordering::SeqCst);
+                Ok(mock.response())
+            }
+            None => Err(Error::from_str(
+                StatusCode::NotImplemented,
+                format!("no mock matches {} {}", received.method, received.url),
+            )),
+        }
+    }
+
+    fn set_config(&mut self, config: HttpConfig) -> http::Result<()> {
+        self.config = config;
+        Ok(())
+    }
+
+    fn config(&self) -> &HttpConfig {
+        &self.config
+    }
+}
+
+/// A request a [`MockClient`] matches, and the response it answers it with.
+///
+/// Mocks match requests by their method and path, and optionally by their headers and body.
+/// They respond with `200 OK` and an empty body unless told otherwise.
+pub struct Mock {
+    method: Method,
+    path: String,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Option<BodyMatcher>,
+    status: StatusCode,
+    response_headers: Vec<(HeaderName, HeaderValue)>,
+    response_body: Vec<u8>,
+    response_mime: Option<Mime>,
+    expected: Option<usize>,
+    calls: AtomicUsize,
+}
+
+impl Mock {
+    /// Create a new instance, matching requests with `method` to `path`.
+    ///
+    /// If `path` has a query, such as `/search?q=cats`, the query of requests must be equal to
+    /// it. Otherwise, requests match whatever their query.
+    pub fn new(method: Method, path: impl Into<String>) -> Self {
+        Self {
+            method,
+            path: path.into(),
+            headers: Vec::new(),
+            body: None,
+            status: StatusCode::Ok,
+            response_headers: Vec::new(),
+            response_body: Vec::new(),
+            response_mime: None,
+            expected: None,
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// Only match requests with the header `name` set to `value`, among its values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` isn't a valid header value.
+    pub fn match_header(mut self, name: impl Into<HeaderName>, value: &str) -> Self {
+        let value = HeaderValue::from_bytes(value.as_bytes().to_vec()).expect("invalid header");
+        self.headers.push((name.into(), value));
+        self
+    }
+
+    /// Only match requests whose body `matcher` returns `true` for.
+    pub fn match_body(mut self, matcher: impl Fn(&[u8]) -> bool + Send + Sync + 'static) -> Self {
+        self.body = Some(Arc::new(matcher));
+        self
+    }
+
+    /// Only match requests with a JSON body equal to `expected`.
+    pub fn match_json(self, expected: serde_json::Value) -> Self {
+        self.match_body(move |body| {
+            serde_json::from_slice(body).is_ok_and(|body: serde_json::Value| body == expected)
+        })
+    }
+
+    /// Respond with `status`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `status` isn't a valid status code.
+    pub fn with_status<S>(mut self, status: S) -> Self
+    where
+        S: TryInto<StatusCode>,
+        S::Error: fmt::Debug,
+    {
+        self.status = status.try_into().expect("invalid status code");
+        self
+    }
+
+    /// Respond with the header `name` set to `value`, in addition to the headers set before.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` isn't a valid header value.
+    pub fn with_header(mut self, name: impl Into<HeaderName>, value: &str) -> Self {
+        let value = HeaderValue::from_bytes(value.as_bytes().to_vec()).expect("invalid header");
+        self.response_headers.push((name.into(), value));
+        self
+    }
+
+    /// Respond with `body`.
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.response_body = body.into();
+        self
+    }
+
+    /// Respond with `value` serialized as JSON, and an `application/json` content type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` can't be serialized.
+    pub fn with_json(mut self, value: &impl serde::Serialize) -> Self {
+        self.response_body = serde_json::to_vec(value).expect("invalid JSON");
+        self.response_mime = Some(mime::JSON);
+        self
+    }
+
+    /// Expect to be called `times` times, as checked by [`MockClient::assert_all_called`].
+    ///
+    /// Default: at least once.
+    pub fn expect(mut self, times: usize) -> Self {
+        self.expected = Some(times);
+        self
+    }
+
+    /// Whether `req` matches this mock.
+    fn matches(&self, req: &ReceivedRequest) -> bool {
+        let path_matches = match self.path.split_once('?') {
+            Some((path, query)) => req.url.path() == path && req.url.query() == Some(query),
+            None => req.url.path() == self.path,
+        };
+        path_matches
+            && req.method == self.method
+            && self.headers.iter().all(|(name, value)| {
+                req.header(name)
+                    .is_some_and(|values| values.iter().any(|v| v == value))
+            })
+            && self.body.as_ref().is_none_or(|matcher| matcher(&req.body))
+    }
+
+    /// The response to a request matching this mock.
+    fn response(&self) -> http::Response {
+        let mut res = http::Response::new(self.status);
+        let mut body = Body::from_bytes(self.response_body.clone());
+        if let Some(mime) = &self.response_mime {
+            body.set_mime(mime.clone());
+        }
+        res.set_body(body);
+        if self.response_mime.is_none() {
+            res.remove_header(CONTENT_TYPE);
+        }
+        for (name, value) in &self.response_headers {
+            res.append_header(name, value.clone());
+        }
+        res
+    }
+}
+
+impl fmt::Debug for Mock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mock")
+            .field("method", &self.method)
+            .field("path", &self.path)
+            .field("headers", &self.headers)
+            .field("body", &self.body.is_some())
+            .field("status", &self.status)
+            .field("expected", &self.expected)
+            .field("calls", &self.calls)
+            .finish()
+    }
+}
+
+impl fmt::Display for Mock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the mock of {} {}", self.method, self.path)
+    }
+}
+
+/// A request received by a [`MockClient`], returned from [`MockClient::requests`].
+#[derive(Debug, Clone)]
+pub struct ReceivedRequest {
+    method: Method,
+    url: Url,
+    headers: Vec<(HeaderName, HeaderValues)>,
+    body: Vec<u8>,
+}
+
+impl ReceivedRequest {
+    /// The method of the request.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The URL of the request.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Get the values of the header `name` of the request.
+    pub fn header(&self, name: impl Into<HeaderName>) -> Option<&HeaderValues> {
+        let name = name.into();
+        self.headers
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, values)| values)
+    }
+
+    /// The body of the request.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
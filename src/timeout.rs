@@ -0,0 +1,83 @@
+//! Timeouts measured by surf itself, on top of the backends.
+
+use crate::utils::BodyReader;
+use crate::Clock;
+#[cfg(feature = "default-client")]
+use crate::Config;
+
+#[cfg(feature = "default-client")]
+use http_client::Config as HttpConfig;
+
+use futures_util::future::BoxFuture;
+use futures_util::io::AsyncRead;
+
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// The timeout a request asked for with
+/// [`RequestBuilder::timeout`](crate::RequestBuilder::timeout).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RequestTimeout(pub(crate) Duration);
+
+/// The backend config for `config`, without the total timeout which surf enforces itself.
+///
+/// Leaving the timeout to surf lets [`RequestBuilder::timeout`](crate::RequestBuilder::timeout)
+/// extend it, and makes it cover the response body.
+#[cfg(feature = "default-client")]
+pub(crate) fn backend_config(config: &Config) -> HttpConfig {
+    let mut http_config = config.http_config.clone();
+    http_config.timeout = None;
+    http_config
+}
+
+/// A body reader which fails once the request deadline has passed, or once no data arrived for
+/// the read timeout.
+pub(crate) struct TimeoutReader {
+    pub(crate) inner: BodyReader,
+    pub(crate) clock: Arc<dyn Clock>,
+    /// Completes when the whole request has timed out.
+    // The timers are only polled through `&mut self`, the mutexes just make the reader `Sync`.
+    pub(crate) deadline: Option<Mutex<BoxFuture<'static, ()>>>,
+    pub(crate) read_timeout: Option<Duration>,
+    /// Completes when no data arrived for the read timeout, started by the first pending read.
+    pub(crate) idle: Option<Mutex<BoxFuture<'static, ()>>>,
+}
+
+impl AsyncRead for TimeoutReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if let Poll::Ready(res) = Pin::new(&mut this.inner).poll_read(cx, buf) {
+            this.idle = None;
+            return Poll::Ready(res);
+        }
+
+        if let Some(deadline) = &mut this.deadline {
+            if deadline.get_mut().unwrap().as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "request timed out",
+                )));
+            }
+        }
+        if let Some(read_timeout) = this.read_timeout {
+            let clock = &this.clock;
+            let idle = this
+                .idle
+                .get_or_insert_with(|| Mutex::new(clock.sleep(read_timeout)));
+            if idle.get_mut().unwrap().as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "response body read timed out",
+                )));
+            }
+        }
+        Poll::Pending
+    }
+}
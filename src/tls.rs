@@ -0,0 +1,46 @@
+//! Per-request TLS configuration for the h1 backends.
+
+use http_client::h1::H1Client;
+use http_client::Config as HttpConfig;
+
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+
+/// The TLS configuration type of the enabled h1 backend.
+#[cfg(feature = "h1-client-rustls")]
+pub(crate) type TlsConfig = rustls_crate::ClientConfig;
+/// The TLS configuration type of the enabled h1 backend.
+#[cfg(all(feature = "h1-client", not(feature = "h1-client-rustls")))]
+pub(crate) type TlsConfig = async_native_tls::TlsConnector;
+
+/// The TLS configuration a request asked for with
+/// [`RequestBuilder::tls_config`](crate::RequestBuilder::tls_config).
+#[derive(Clone)]
+pub(crate) struct TlsOverride(pub(crate) Arc<TlsConfig>);
+
+/// The h1 clients created for per-request TLS configurations, one for each configuration.
+///
+/// Each client keeps its own connection pool, so connections made with one configuration are
+/// never reused by requests with another.
+#[derive(Default)]
+pub(crate) struct TlsClients {
+    clients: Mutex<Vec<(Arc<TlsConfig>, Arc<H1Client>)>>,
+}
+
+impl TlsClients {
+    /// The client using `tls`, created from `http_config` the first time it is needed.
+    pub(crate) fn get(&self, tls: &Arc<TlsConfig>, http_config: &HttpConfig) -> Arc<H1Client> {
+        let mut clients = self.clients.lock().unwrap();
+        if let Some((_, client)) = clients.iter().find(|(config, _)| Arc::ptr_eq(config, tls)) {
+            return client.clone();
+        }
+        let mut http_config = http_config.clone();
+        http_config.tls_config = Some(tls.clone());
+        let client = match H1Client::try_from(http_config) {
+            Ok(client) => Arc::new(client),
+            Err(never) => match never {},
+        };
+        clients.push((tls.clone(), client.clone()));
+        client
+    }
+}
@@ -0,0 +1,114 @@
+//! Sending requests over Unix domain sockets, such as the API sockets of Docker or systemd.
+
+use crate::http::{self, StatusCode, Url};
+use crate::utils::percent_decode;
+use crate::{Config, Error, Result};
+
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+
+/// The socket to send a request over, from a `unix://` URL or `Config::set_unix_socket`.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    not(any(
+        feature = "curl-client",
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    )),
+    allow(dead_code)
+)]
+pub(crate) struct UnixSocket(pub(crate) PathBuf);
+
+/// Route `req` over the Unix socket of its `unix://` URL, or of `config`.
+///
+/// A `unix://` URL holds the path of the socket and the path of the request, separated by a
+/// colon, such as `unix:///var/run/docker.sock:/v1.41/containers/json`. It is replaced by an
+/// `http://localhost` URL with the path and query of the request, so that middleware sees an
+/// ordinary URL.
+pub(crate) fn apply(req: &mut http::Request, config: &Config) -> Result<()> {
+    if req.url().scheme() == "unix" {
+        let (socket, url) = split_url(req.url())?;
+        *req.url_mut() = url;
+        req.ext_mut().insert(UnixSocket(socket));
+    } else if let Some(socket) = &config.unix_socket {
+        if req.ext().get::<UnixSocket>().is_none() {
+            req.ext_mut().insert(UnixSocket(socket.clone()));
+        }
+    } else {
+        return Ok(());
+    }
+
+    if cfg!(not(any(
+        feature = "curl-client",
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ))) {
+        return Err(Error::from_str(
+            StatusCode::BadRequest,
+            "the http backend can't send requests over Unix sockets",
+        ));
+    }
+    Ok(())
+}
+
+/// Split a `unix://` URL into the path of the socket and the `http://localhost` URL to request.
+fn split_url(url: &Url) -> Result<(PathBuf, Url)> {
+    let (socket, path) = url.path().split_once(':').ok_or_else(|| {
+        Error::from_str(
+            StatusCode::BadRequest,
+            format!(
+                "the Unix socket URL {} has no request path after the socket path",
+                url
+            ),
+        )
+    })?;
+    let mut target = Url::parse("http://localhost/")?;
+    target.set_path(path);
+    target.set_query(url.query());
+    target.set_fragment(url.fragment());
+    let socket = OsString::from_vec(percent_decode(socket));
+    Ok((socket.into(), target))
+}
+
+/// Send `req` over `socket` with the h1 backend, on a new connection.
+#[cfg(any(
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "h1-client-no-tls"
+))]
+pub(crate) async fn send_h1(
+    socket: &std::path::Path,
+    req: http::Request,
+    config: &Config,
+) -> http::Result<http::Response> {
+    let stream = async_io::Async::<std::os::unix::net::UnixStream>::connect(socket).await?;
+    if crate::header_case::rewrites(config) {
+        let stream = crate::header_case::Recase::new(stream, config, &req);
+        return async_h1::client::connect(stream, req).await;
+    }
+    async_h1::client::connect(stream, req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_url_separates_socket_and_request() {
+        let url =
+            Url::parse("unix:///var/run/docker%20x.sock:/v1.41/containers/json?all=1").unwrap();
+        let (socket, target) = split_url(&url).unwrap();
+        assert_eq!(socket, PathBuf::from("/var/run/docker x.sock"));
+        assert_eq!(
+            target.as_str(),
+            "http://localhost/v1.41/containers/json?all=1"
+        );
+
+        let url = Url::parse("unix:///run/api.sock:").unwrap();
+        assert_eq!(split_url(&url).unwrap().1.as_str(), "http://localhost/");
+        assert!(split_url(&Url::parse("unix:///run/api.sock").unwrap()).is_err());
+    }
+}
@@ -1,3 +1,246 @@
 //! Miscellaneous utilities.
 
 pub use async_trait::async_trait;
+
+use crate::http::headers::{HeaderName, Headers, CONTENT_LENGTH, CONTENT_TYPE};
+use crate::http::{Body, Url};
+
+use futures_util::io::{AsyncBufRead, AsyncRead, BufReader};
+
+/// Headers which only apply to a single connection, besides the `Proxy-*` headers.
+const HOP_BY_HOP_HEADERS: [&str; 6] = [
+    "connection",
+    "keep-alive",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Remove the hop-by-hop headers from a request or response which is being forwarded.
+///
+/// These are `Connection`, `Keep-Alive`, `TE`, `Trailer`, `Transfer-Encoding`, `Upgrade`, all
+/// `Proxy-*` headers, and any header listed in the `Connection` header. They describe a single
+/// connection, so passing them on to the next hop can confuse it about the framing or protocol
+/// of the message.
+///
+/// See also the [`StripHopByHop`](crate::middleware::StripHopByHop) middleware.
+///
+/// # Examples
+///
+/// ```
+/// use surf::http::Response;
+/// use surf::utils::strip_hop_by_hop_headers;
+///
+/// let mut res = Response::new(200);
+/// res.insert_header("connection", "x-trace");
+/// res.insert_header("x-trace", "on");
+/// res.insert_header("proxy-authenticate", "Basic");
+/// res.insert_header("content-type", "text/plain");
+///
+/// strip_hop_by_hop_headers(&mut res);
+/// assert_eq!(res.iter().count(), 1);
+/// assert_eq!(res["content-type"], "text/plain");
+/// ```
+pub fn strip_hop_by_hop_headers(headers: &mut impl AsMut<Headers>) {
+    let headers = headers.as_mut();
+    let mut names: Vec<HeaderName> = headers
+        .get("connection")
+        .into_iter()
+        .flat_map(|values| values.iter())
+        .flat_map(|value| value.as_str().split(','))
+        .filter_map(|name| name.trim().parse().ok())
+        .collect();
+    names.extend(
+        headers
+            .iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.as_str().starts_with("proxy-"))
+            .cloned(),
+    );
+
+    for name in names {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP_HEADERS.iter() {
+        headers.remove(*name);
+    }
+}
+
+/// The reader type stored inside a `Body`.
+pub(crate) type BodyReader = Box<dyn AsyncBufRead + Unpin + Send + Sync + 'static>;
+
+/// Wrap the reader of a body, keeping its mime type.
+///
+/// `len` is the length of the new body, if known.
+pub(crate) fn map_body_reader<R>(
+    body: Body,
+    len: Option<usize>,
+    f: impl FnOnce(BodyReader) -> R,
+) -> Body
+where
+    R: AsyncRead + Unpin + Send + Sync + 'static,
+{
+    let mime = body.mime().clone();
+    let mut body = Body::from_reader(BufReader::new(f(body.into_reader())), len);
+    body.set_mime(mime);
+    body
+}
+
+/// A request or response, whose body can be replaced.
+pub(crate) trait HasBody: AsMut<Headers> + AsRef<Headers> {
+    fn take_body(&mut self) -> Body;
+    fn set_body(&mut self, body: Body);
+}
+
+impl HasBody for crate::http::Request {
+    fn take_body(&mut self) -> Body {
+        crate::http::Request::take_body(self)
+    }
+
+    fn set_body(&mut self, body: Body) {
+        crate::http::Request::set_body(self, body)
+    }
+}
+
+impl HasBody for crate::http::Response {
+    fn take_body(&mut self) -> Body {
+        crate::http::Response::take_body(self)
+    }
+
+    fn set_body(&mut self, body: Body) {
+        crate::http::Response::set_body(self, body)
+    }
+}
+
+impl HasBody for crate::Request {
+    fn take_body(&mut self) -> Body {
+        crate::Request::take_body(self)
+    }
+
+    fn set_body(&mut self, body: Body) {
+        crate::Request::set_body(self, body)
+    }
+}
+
+impl HasBody for crate::Response {
+    fn take_body(&mut self) -> Body {
+        crate::Response::take_body(self)
+    }
+
+    fn set_body(&mut self, body: Body) {
+        crate::Response::set_body(self, body)
+    }
+}
+
+/// Replace the body of a request or response with the body `f` returns.
+///
+/// The `Content-Type` header is kept as it was, as setting a body would otherwise add or change
+/// it. If the new body has another length than the old one, a `Content-Length` header is set to
+/// the new length, or removed if that is unknown. Bodies of unknown length which wrap each other
+/// leave the header alone, as it may describe the body as sent.
+pub(crate) fn map_body(message: &mut impl HasBody, f: impl FnOnce(Body) -> Body) {
+    let content_type = message.as_mut().remove(CONTENT_TYPE);
+    let body = message.take_body();
+    let old_len = body.len();
+    let body = f(body);
+    let len = body.len();
+    message.set_body(body);
+    let headers = message.as_mut();
+    match content_type {
+        Some(content_type) => headers.insert(CONTENT_TYPE, &content_type),
+        None => headers.remove(CONTENT_TYPE),
+    };
+    if len != old_len && headers.get(CONTENT_LENGTH).is_some() {
+        match len {
+            Some(len) => headers.insert(CONTENT_LENGTH, len.to_string()),
+            None => headers.remove(CONTENT_LENGTH),
+        };
+    }
+}
+
+/// The lines of `headers` in HTTP/1.1 form, with their names in title case and sorted, as
+/// http-types keeps names in lowercase and in no particular order.
+pub(crate) fn header_lines(headers: &Headers) -> Vec<String> {
+    let mut lines: Vec<(&str, String)> = headers
+        .iter()
+        .flat_map(|(name, values)| {
+            values.iter().map(move |value| {
+                (
+                    name.as_str(),
+                    format!("{}: {}", title_case(name.as_str()), value),
+                )
+            })
+        })
+        .collect();
+    lines.sort_by(|a, b| a.0.cmp(b.0));
+    lines.into_iter().map(|(_, line)| line).collect()
+}
+
+/// Capitalize the first letter of each dash-separated word of a header name, as in
+/// `Content-Type`.
+pub(crate) fn title_case(name: &str) -> String {
+    let mut titled = String::with_capacity(name.len());
+    let mut word_start = true;
+    for c in name.chars() {
+        titled.push(match word_start {
+            true => c.to_ascii_uppercase(),
+            false => c.to_ascii_lowercase(),
+        });
+        word_start = c == '-';
+    }
+    titled
+}
+
+/// The path, query and fragment of `url`, relative to the root of its origin.
+pub(crate) fn relative_reference(url: &Url) -> String {
+    let mut relative = url.path().trim_start_matches('/').to_string();
+    if let Some(query) = url.query() {
+        relative.push('?');
+        relative.push_str(query);
+    }
+    if let Some(fragment) = url.fragment() {
+        relative.push('#');
+        relative.push_str(fragment);
+    }
+    relative
+}
+
+/// Percent-encode every byte but the unreserved characters of RFC 3986.
+pub(crate) fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Decode the `%XX` escapes of a URL component, leaving malformed escapes as they are.
+pub(crate) fn percent_decode(input: &str) -> Vec<u8> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| bytes[i] == b'%' && hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
@@ -0,0 +1,316 @@
+//! A `fetch` based HTTP backend for WebAssembly.
+//!
+//! Unlike a backend bound to `window`, this resolves `fetch` and `setTimeout` from the global
+//! scope (`globalThis`), so it works in browsers and web workers as well as in Deno, Cloudflare
+//! Workers, and versions of Node that ship `fetch`.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::future::{self, Either};
+use http_client::{Config, Error, HttpClient, Request, Response};
+use http_types::headers::{HeaderName, CONTENT_TYPE};
+use http_types::{Body, Method, StatusCode, Url};
+use js_sys::{Array, ArrayBuffer, Function, Object, Promise, Reflect, TypeError, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+/// WebAssembly HTTP client which sends requests through the global `fetch` function.
+///
+/// This is the default client when the `wasm-client` feature is enabled.
+#[derive(Debug)]
+pub struct FetchClient {
+    config: Config,
+}
+
+impl FetchClient {
+    /// Create a new instance.
+    pub fn new() -> Self {
+        Self {
+            config: Config::default(),
+        }
+    }
+}
+
+impl Default for FetchClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Config> for FetchClient {
+    fn from(config: Config) -> Self {
+        Self { config }
+    }
+}
+
+impl HttpClient for FetchClient {
+    fn send<'a, 'async_trait>(
+        &'a self,
+        req: Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Response, Error>> + Send + 'async_trait>>
+    where
+        'a: 'async_trait,
+        Self: 'async_trait,
+    {
+        let timeout = self.config.timeout;
+        Box::pin(SendWrapper(Box::pin(async move {
+            let fetch = fetch(req);
+            match timeout {
+                Some(timeout) => {
+                    let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+                    match future::select(Box::pin(fetch), Box::pin(sleep(millis)?)).await {
+                        Either::Left((res, _)) => res,
+                        Either::Right(_) => Err(Error::new(
+                            StatusCode::RequestTimeout,
+                            io::Error::new(io::ErrorKind::TimedOut, "request timed out"),
+                        )),
+                    }
+                }
+                None => fetch.await,
+            }
+        })))
+    }
+
+    fn set_config(&mut self, config: Config) -> http_types::Result<()> {
+        self.config = config;
+        Ok(())
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+}
+
+/// A cross-origin request which the JS runtime blocked, most likely because of CORS.
+///
+/// Browsers deliberately hide why a cross-origin `fetch` failed, so this is reported for any
+/// cross-origin request which fails before a response arrives. It carries the parts of the
+/// request which require a CORS preflight, which the server has to allow explicitly. Network
+/// failures on cross-origin requests are reported as this error too.
+///
+/// Get it from a `surf::Error` with `err.downcast_ref::<CorsError>()`.
+#[derive(Debug, Clone)]
+pub struct CorsError {
+    origin: String,
+    method: Option<Method>,
+    headers: Vec<HeaderName>,
+}
+
+impl CorsError {
+    /// The origin the request was sent to.
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    /// The request method, if it is not allowed without a preflight.
+    pub fn method(&self) -> Option<Method> {
+        self.method
+    }
+
+    /// The request headers which are not allowed without a preflight.
+    pub fn headers(&self) -> &[HeaderName] {
+        &self.headers
+    }
+}
+
+impl fmt::Display for CorsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cross-origin request to {} failed, likely blocked by CORS",
+            self.origin
+        )?;
+        if let Some(method) = self.method {
+            write!(f, "; the server must allow the {} method", method)?;
+        }
+        if !self.headers.is_empty() {
+            let headers: Vec<_> = self.headers.iter().map(|name| name.as_str()).collect();
+            write!(
+                f,
+                "; the server must allow the headers {}",
+                headers.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CorsError {}
+
+/// Find out whether `req` is cross-origin, and which of its parts require a CORS preflight.
+///
+/// Returns `None` for same-origin requests, and in runtimes without an origin (which don't
+/// enforce CORS).
+fn cors_check(req: &Request) -> Option<CorsError> {
+    let location = Reflect::get(&js_sys::global(), &JsValue::from_str("location")).ok()?;
+    let origin = Reflect::get(&location, &JsValue::from_str("origin"))
+        .ok()?
+        .as_string()?;
+    let target = req.url().origin().ascii_serialization();
+    if Url::parse(&origin).ok()?.origin().ascii_serialization() == target {
+        return None;
+    }
+
+    let method = match req.method() {
+        Method::Get | Method::Head | Method::Post => None,
+        method => Some(method),
+    };
+    let headers = req
+        .iter()
+        .filter(|(name, values)| !is_safelisted_header(name, values.last().as_str()))
+        .map(|(name, _)| name.clone())
+        .collect();
+    Some(CorsError {
+        origin: target,
+        method,
+        headers,
+    })
+}
+
+/// Whether a header may be sent cross-origin without a preflight.
+fn is_safelisted_header(name: &HeaderName, value: &str) -> bool {
+    match name.as_str() {
+        "accept" | "accept-language" | "content-language" => true,
+        _ if *name == CONTENT_TYPE => {
+            let essence = value.split(';').next().unwrap_or_default().trim();
+            [
+                "application/x-www-form-urlencoded",
+                "multipart/form-data",
+                "text/plain",
+            ]
+            .iter()
+            .any(|safe| essence.eq_ignore_ascii_case(safe))
+        }
+        _ => false,
+    }
+}
+
+/// Wraps the fetch future so it can be handed out as `Send`.
+struct SendWrapper<T>(Pin<Box<dyn Future<Output = T>>>);
+
+// This is safe because wasm32 doesn't have threads, so the JS values held by the inner future
+// can never actually be moved to another thread.
+unsafe impl<T> Send for SendWrapper<T> {}
+
+impl<T> Future for SendWrapper<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.0.as_mut().poll(cx)
+    }
+}
+
+/// Look up a function on `globalThis`.
+fn global_fn(name: &str) -> Result<Function, Error> {
+    Reflect::get(&js_sys::global(), &JsValue::from_str(name))
+        .ok()
+        .and_then(|value| value.dyn_into::<Function>().ok())
+        .ok_or_else(|| {
+            Error::from_str(
+                StatusCode::InternalServerError,
+                format!("`globalThis.{}` is not available in this JS runtime", name),
+            )
+        })
+}
+
+fn js_error(status: StatusCode, context: &str, err: JsValue) -> Error {
+    Error::from_str(status, format!("{}: {:?}", context, err))
+}
+
+/// A future which resolves after `millis` milliseconds, using the global `setTimeout`.
+fn sleep(millis: i32) -> Result<JsFuture, Error> {
+    let set_timeout = global_fn("setTimeout")?;
+    let promise = Promise::new(&mut |resolve, _reject| {
+        let _ = set_timeout.call2(&JsValue::NULL, &resolve, &JsValue::from(millis));
+    });
+    Ok(JsFuture::from(promise))
+}
+
+/// Wait for `duration`, or not at all if the JS runtime has no `setTimeout`.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn delay(duration: std::time::Duration) -> impl Future<Output = ()> + Send {
+    let millis = duration.as_millis().min(i32::MAX as u128) as i32;
+    SendWrapper(Box::pin(async move {
+        if let Ok(sleep) = sleep(millis) {
+            let _ = sleep.await;
+        }
+    }))
+}
+
+async fn fetch(mut req: Request) -> Result<Response, Error> {
+    let fetch = global_fn("fetch")?;
+    let cors = cors_check(&req);
+
+    let init = Object::new();
+    let set = |key: &str, value: &JsValue| {
+        Reflect::set(&init, &JsValue::from_str(key), value)
+            .map_err(|e| js_error(StatusCode::BadRequest, "could not build fetch options", e))
+    };
+    set("method", &JsValue::from_str(req.method().as_ref()))?;
+
+    let headers = Array::new();
+    for (name, values) in req.iter() {
+        for value in values.iter() {
+            let pair = Array::of2(
+                &JsValue::from_str(name.as_str()),
+                &JsValue::from_str(value.as_str()),
+            );
+            headers.push(&pair);
+        }
+    }
+    set("headers", &headers)?;
+
+    let body = req.take_body().into_bytes().await?;
+    if !body.is_empty() {
+        // Copy the body into JS memory, so it stays valid regardless of what happens to the
+        // wasm linear memory while the request is in flight.
+        set("body", &Uint8Array::from(&body[..]))?;
+    }
+
+    let url = JsValue::from_str(req.url().as_str());
+    let promise: Promise = fetch
+        .call2(&JsValue::NULL, &url, &init)
+        .and_then(|p| p.dyn_into())
+        .map_err(|e| js_error(StatusCode::BadRequest, "could not start fetch", e))?;
+    let res: web_sys::Response = JsFuture::from(promise)
+        .await
+        .and_then(|res| res.dyn_into())
+        .map_err(|e| match cors {
+            // `fetch` rejects with a `TypeError` when the request is blocked.
+            Some(cors) if e.is_instance_of::<TypeError>() => {
+                Error::new(StatusCode::BadGateway, cors)
+            }
+            _ => js_error(StatusCode::BadGateway, "fetch failed", e),
+        })?;
+
+    let status = StatusCode::try_from(res.status())?;
+    let mut response = Response::new(status);
+    let entries = js_sys::try_iter(&res.headers())
+        .map_err(|e| js_error(StatusCode::BadGateway, "could not read headers", e))?
+        .into_iter()
+        .flatten();
+    for entry in entries {
+        let pair: Array = entry
+            .map_err(|e| js_error(StatusCode::BadGateway, "could not read headers", e))?
+            .unchecked_into();
+        if let (Some(name), Some(value)) = (pair.get(0).as_string(), pair.get(1).as_string()) {
+            response.append_header(name.as_str(), value.as_str());
+        }
+    }
+
+    let buffer = res
+        .array_buffer()
+        .map_err(|e| js_error(StatusCode::BadGateway, "could not read body", e))?;
+    let buffer: ArrayBuffer = JsFuture::from(buffer)
+        .await
+        .map_err(|e| js_error(StatusCode::BadGateway, "could not read body", e))?
+        .unchecked_into();
+    response.set_body(Body::from(Uint8Array::new(&buffer).to_vec()));
+
+    Ok(response)
+}
@@ -0,0 +1,28 @@
+//! Configuring the global client affects every one-off request in the process, so these tests
+//! run in their own test binary.
+
+#![cfg(feature = "default-client")]
+
+use surf::Config;
+
+#[async_std::test]
+async fn configure_global_client() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server.at("/").get(|req: tide::Request<()>| async move {
+        Ok(req.header("X-Client").unwrap().as_str().to_string())
+    });
+    surf::configure(
+        Config::new()
+            .set_http_client(server)
+            .add_header("X-Client", "script")?,
+    )?;
+
+    assert_eq!(
+        surf::get("http://example.org/").recv_string().await?,
+        "script"
+    );
+
+    let err = surf::configure(Config::new()).unwrap_err();
+    assert_eq!(err.status(), 500);
+    Ok(())
+}
@@ -152,7 +152,7 @@ fn mw_1(
     req: Request,
     client: Client,
     next: Next<'_>,
-) -> BoxFuture<Result<Response, http_types::Error>> {
+) -> BoxFuture<'_, Result<Response, http_types::Error>> {
     Box::pin(async move {
         let mut res: Response = next.run(req, client).await?;
         res.insert_ext(Mw1Marker);
@@ -164,7 +164,7 @@ fn mw_2(
     req: Request,
     client: Client,
     next: Next<'_>,
-) -> BoxFuture<Result<Response, http_types::Error>> {
+) -> BoxFuture<'_, Result<Response, http_types::Error>> {
     Box::pin(async move {
         let mut res = next.run(req, client).await?;
         res.insert_ext(Mw2Marker);
@@ -172,6 +172,146 @@ fn mw_2(
     })
 }
 
+#[async_std::test]
+async fn with_ordered_positions_middleware() -> Result<(), http_types::Error> {
+    use std::sync::{Arc, Mutex};
+    use surf::middleware::{Middleware, Position};
+
+    type Order = Arc<Mutex<Vec<&'static str>>>;
+    struct Tag(&'static str, Order);
+    struct Marker(&'static str, Order);
+    #[surf::utils::async_trait]
+    impl Middleware for Tag {
+        async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> surf::Result {
+            self.1.lock().unwrap().push(self.0);
+            next.run(req, client).await
+        }
+    }
+    #[surf::utils::async_trait]
+    impl Middleware for Marker {
+        async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> surf::Result {
+            self.1.lock().unwrap().push(self.0);
+            next.run(req, client).await
+        }
+    }
+
+    let mut server = tide::new();
+    server.at("/").get(|_| async { Ok("ok") });
+    let order = Order::default();
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client
+        .with(Tag("a", order.clone()))
+        .with(Tag("b", order.clone()));
+    let base = client.clone();
+    let client = client
+        .with_ordered(Marker("after", order.clone()), Position::after::<Tag>())
+        .with_ordered(Marker("before", order.clone()), Position::before::<Tag>())
+        .with_ordered(Tag("first", order.clone()), Position::first())
+        .with_ordered(Tag("index", order.clone()), Position::index(2));
+
+    client.get("http://example.org/").await?;
+    assert_eq!(
+        *order.lock().unwrap(),
+        ["first", "before", "index", "a", "b", "after"]
+    );
+
+    order.lock().unwrap().clear();
+    base.get("http://example.org/").await?;
+    assert_eq!(*order.lock().unwrap(), ["a", "b"]);
+    Ok(())
+}
+
+#[cfg(feature = "middleware-metrics")]
+#[async_std::test]
+async fn metrics_middleware_records_requests() -> Result<(), http_types::Error> {
+    use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, SharedString, Unit};
+    use std::sync::{Arc, Mutex};
+
+    type Events = Arc<Mutex<Vec<(String, f64)>>>;
+    struct Handle(String, Events);
+    impl metrics::CounterFn for Handle {
+        fn increment(&self, value: u64) {
+            self.1.lock().unwrap().push((self.0.clone(), value as f64));
+        }
+        fn absolute(&self, _: u64) {}
+    }
+    impl metrics::GaugeFn for Handle {
+        fn increment(&self, value: f64) {
+            self.1.lock().unwrap().push((self.0.clone(), value));
+        }
+        fn decrement(&self, value: f64) {
+            self.1.lock().unwrap().push((self.0.clone(), -value));
+        }
+        fn set(&self, _: f64) {}
+    }
+    impl metrics::HistogramFn for Handle {
+        fn record(&self, value: f64) {
+            self.1.lock().unwrap().push((self.0.clone(), value));
+        }
+    }
+    #[derive(Default)]
+    struct Recorder(Events);
+    impl Recorder {
+        fn handle(&self, key: &Key) -> Arc<Handle> {
+            let mut labels: Vec<String> = key
+                .labels()
+                .map(|label| format!("{}={}", label.key(), label.value()))
+                .collect();
+            labels.sort();
+            let name = format!("{}{{{}}}", key.name(), labels.join(","));
+            Arc::new(Handle(name, self.0.clone()))
+        }
+    }
+    impl metrics::Recorder for Recorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.handle(key))
+        }
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.handle(key))
+        }
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.handle(key))
+        }
+    }
+
+    let mut server = tide::new();
+    server
+        .at("/echo")
+        .post(|mut req: tide::Request<()>| async move { req.body_string().await });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client.with(surf::middleware::Metrics::new().prefix("http"));
+
+    let recorder = Recorder::default();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+    let body = client
+        .post("http://example.org/echo")
+        .body_string("hello".into())
+        .recv_string()
+        .await?;
+    assert_eq!(body, "hello");
+
+    let events = recorder.0.lock().unwrap().clone();
+    let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "http_request_body_bytes{host=example.org,method=POST}",
+            "http_requests_in_flight{host=example.org,method=POST}",
+            "http_requests_in_flight{host=example.org,method=POST}",
+            "http_requests_total{host=example.org,method=POST,status=200}",
+            "http_request_duration_seconds{host=example.org,method=POST,status=200}",
+            "http_response_body_bytes{host=example.org,method=POST,status=200}",
+        ]
+    );
+    let values: Vec<f64> = events.iter().map(|(_, value)| *value).collect();
+    assert_eq!(values[..4], [5.0, 1.0, -1.0, 1.0]);
+    assert_eq!(values[5], 5.0);
+    Ok(())
+}
+
 #[async_std::test]
 async fn config_client_headers() -> Result<(), http_types::Error> {
     femme::start(log::LevelFilter::Trace).ok();
@@ -198,3 +338,4773 @@ async fn config_client_headers() -> Result<(), http_types::Error> {
 
     Ok(())
 }
+
+#[async_std::test]
+async fn adaptive_concurrency_backs_off() -> Result<(), http_types::Error> {
+    use std::time::Duration;
+    use surf::ManualClock;
+
+    let clock = ManualClock::new();
+    let mut server = tide::with_state(clock.clone());
+    server
+        .at("/ok")
+        .get(|_| async { Ok(tide::Response::new(200)) });
+    server
+        .at("/busy")
+        .get(|_| async { Ok(tide::Response::new(429)) });
+    server
+        .at("/slow")
+        .get(|req: tide::Request<ManualClock>| async move {
+            req.state().advance(Duration::from_secs(2));
+            Ok(tide::Response::new(200))
+        });
+
+    let limiter = surf::middleware::AdaptiveConcurrency::new()
+        .initial_limit(8)
+        .latency_threshold(Duration::from_secs(1));
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_clock(clock)
+        .try_into()?;
+    let client = client.with(limiter.clone());
+    let url = surf::Url::parse("http://example.org/")?;
+
+    client.get("http://example.org/busy").await?;
+    assert_eq!(limiter.current_limit(&url), Some(4));
+
+    client.get("http://example.org/ok").await?;
+    assert_eq!(limiter.current_limit(&url), Some(4));
+
+    client.get("http://example.org/slow").await?;
+    assert_eq!(limiter.current_limit(&url), Some(2));
+    Ok(())
+}
+
+#[async_std::test]
+async fn history_records_requests() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server
+        .at("/echo")
+        .post(|mut req: tide::Request<()>| async move {
+            let body = req.body_string().await?;
+            Ok(tide::Response::builder(201)
+                .header("Content-Encoding", "identity")
+                .body(body)
+                .build())
+        });
+
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client.enable_history(1);
+
+    client.get("http://example.org/missing").await?;
+    let body = client
+        .post("http://example.org/echo")
+        .body_string("hello".to_string())
+        .recv_string()
+        .await?;
+    assert_eq!(body, "hello");
+
+    let history = client.history();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].method, http_types::Method::Post);
+    assert_eq!(history[0].url.path(), "/echo");
+    assert_eq!(history[0].status, Some(http_types::StatusCode::Created));
+    assert_eq!(history[0].request_body, b"hello");
+    assert_eq!(history[0].response_body, b"hello");
+    assert_eq!(history[0].request_encoding, None);
+    assert_eq!(history[0].response_encoding.as_deref(), Some("identity"));
+    Ok(())
+}
+
+#[cfg(feature = "gzip")]
+#[async_std::test]
+async fn compressed_bodies_are_recorded_decoded() -> Result<(), http_types::Error> {
+    use http_types::content::Encoding;
+    use std::sync::{Arc, Mutex};
+    use surf::middleware::{Vcr, VcrMode, Verbose};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let path = std::env::temp_dir().join(format!("surf-vcr-gzip-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let client = |server: tide::Server<()>, vcr: Vcr| -> Result<Client, http_types::Error> {
+        let client: Client = Config::new()
+            .set_http_client(server)
+            .set_base_url(surf::Url::parse("http://example.org")?)
+            .try_into()?;
+        Ok(client.with(vcr))
+    };
+
+    // The request body is echoed, still compressed.
+    let mut live = tide::new();
+    live.at("/echo")
+        .post(|mut req: tide::Request<()>| async move {
+            let body = req.body_bytes().await?;
+            let mut res = tide::Response::new(200);
+            res.insert_header("Content-Encoding", "gzip");
+            res.set_body(body);
+            Ok(res)
+        });
+    let buffer = Buffer::default();
+    let recorder = client(live, Vcr::new(&path))?
+        .with(Verbose::new().to_writer(buffer.clone()))
+        .enable_history(1);
+    let compressed = recorder
+        .post("/echo")
+        .body_string("hello world".into())
+        .compress(Encoding::Gzip)?
+        .recv_bytes()
+        .await?;
+    assert_eq!(&compressed[..2], b"\x1f\x8b");
+
+    let history = recorder.history();
+    assert_eq!(history[0].request_encoding.as_deref(), Some("gzip"));
+    assert!(history[0].request_body_decoded);
+    assert_eq!(history[0].request_body, b"hello world");
+    assert!(history[0].response_body_decoded);
+    assert_eq!(history[0].response_body, b"hello world");
+
+    let dump = String::from_utf8(buffer.0.lock().unwrap().clone())?;
+    let decoded = format!("{} bytes of gzip, 11 bytes decoded]", compressed.len());
+    assert!(dump.contains(&format!("> [body: {}\n> hello world\n", decoded)));
+    assert!(dump.contains(&format!("< [body: {}\n< hello world\n", decoded)));
+
+    let cassette: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+    let interaction = &cassette["interactions"][0];
+    for body in &[
+        &interaction["request"]["body"],
+        &interaction["response"]["body"],
+    ] {
+        assert_eq!(body["text"], "hello world");
+        assert_eq!(body["decoded"], true);
+    }
+
+    // Replayed responses have the decoded body, and requests match by their decoded bodies.
+    let mut down = tide::new();
+    down.at("*").all(|_| async { Ok(tide::Response::new(500)) });
+    let player = client(down, Vcr::new(&path).mode(VcrMode::Replay))?;
+    let mut res = player
+        .post("/echo")
+        .body_string("hello world".into())
+        .compress(Encoding::Gzip)?
+        .await?;
+    assert!(res.header("Content-Encoding").is_none());
+    assert_eq!(res.body_string().await?, "hello world");
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[async_std::test]
+async fn paginate_follows_body_cursors() -> Result<(), http_types::Error> {
+    use futures_util::TryStreamExt;
+
+    #[derive(serde::Deserialize)]
+    struct Page {
+        items: Vec<u32>,
+        next: Option<u32>,
+    }
+
+    impl From<Page> for Vec<u32> {
+        fn from(page: Page) -> Self {
+            page.items
+        }
+    }
+
+    let mut server = tide::new();
+    server
+        .at("/items")
+        .get(|req: tide::Request<()>| async move {
+            let page = match req.url().query() {
+                Some("page=2") => serde_json::json!({ "items": [3], "next": null }),
+                _ => serde_json::json!({ "items": [1, 2], "next": 2 }),
+            };
+            Ok(tide::Response::builder(200).body(page).build())
+        });
+
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let next = |_: &Response, page: &Page| {
+        let url = format!("http://example.org/items?page={}", page.next?);
+        Some(surf::get(url).build())
+    };
+    let items: Vec<u32> = client
+        .paginate(surf::get("http://example.org/items"), next)
+        .try_collect()
+        .await?;
+    assert_eq!(items, vec![1, 2, 3]);
+    Ok(())
+}
+
+#[async_std::test]
+async fn recv_json_all_keeps_order() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server.at("/:id").get(|req: tide::Request<()>| async move {
+        let id: u32 = req.param("id")?.parse()?;
+        let body = if id == 2 {
+            "not json".into()
+        } else {
+            serde_json::json!(id).to_string()
+        };
+        Ok(tide::Response::builder(200).body(body).build())
+    });
+
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let requests = (1..=4).map(|id| client.get(format!("http://example.org/{}", id)));
+    let results = client.recv_json_all::<u32, _>(requests, 2).await;
+
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0].as_ref().ok(), Some(&1));
+    assert!(results[1].is_err());
+    assert_eq!(results[2].as_ref().ok(), Some(&3));
+    assert_eq!(results[3].as_ref().ok(), Some(&4));
+    Ok(())
+}
+
+#[async_std::test]
+async fn range_requests_use_identity_encoding() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server.at("/").get(|req: tide::Request<()>| async move {
+        let encoding = req
+            .header("Accept-Encoding")
+            .map(|values| values.as_str().to_string())
+            .unwrap_or_default();
+        Ok(encoding)
+    });
+
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let encoding = client
+        .get("http://example.org/")
+        .header("Range", "bytes=100-")
+        .recv_string()
+        .await?;
+    assert_eq!(encoding, "identity");
+
+    let encoding = client
+        .get("http://example.org/")
+        .header("Range", "bytes=100-")
+        .header("Accept-Encoding", "gzip")
+        .recv_string()
+        .await?;
+    assert_eq!(encoding, "gzip");
+    Ok(())
+}
+
+#[async_std::test]
+async fn client_builder_configures_client() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server
+        .at("/api/hello")
+        .get(|req: tide::Request<()>| async move {
+            let version = req.header("x-api-version").unwrap().as_str().to_string();
+            Ok(format!("hello from v{}", version))
+        });
+
+    let client = Client::builder()
+        .base_url(surf::Url::parse("http://example.org/api/")?)
+        .header("x-api-version", "2")
+        .with(surf::middleware::Redirect::default())
+        .http_client(server)
+        .build()?;
+
+    let body = client.get("hello").recv_string().await?;
+    assert_eq!(body, "hello from v2");
+    Ok(())
+}
+
+#[async_std::test]
+async fn with_after_use_derives_client() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server.at("/").get(|_| async { Ok("ok") });
+
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client.with(mw_1);
+    client.get("http://example.org/").await?;
+
+    let derived = client.clone().with(mw_2);
+    let res = derived.get("http://example.org/").await?;
+    assert!(res.ext::<Mw1Marker>().is_some());
+    assert!(res.ext::<Mw2Marker>().is_some());
+
+    let res = client.get("http://example.org/").await?;
+    assert!(res.ext::<Mw1Marker>().is_some());
+    assert!(res.ext::<Mw2Marker>().is_none());
+    Ok(())
+}
+
+#[async_std::test]
+async fn request_base_url_override() -> Result<(), http_types::Error> {
+    let client: Client = Config::new()
+        .set_base_url(surf::Url::parse("https://api.example.com/v1/")?)
+        .try_into()?;
+
+    let req = client
+        .get("users?page=2")
+        .base_url(surf::Url::parse("https://eu.api.example.com/v1/")?)
+        .build();
+    assert_eq!(
+        req.url().as_str(),
+        "https://eu.api.example.com/v1/users?page=2"
+    );
+    Ok(())
+}
+
+#[async_std::test]
+async fn proxy_forwards_requests() -> Result<(), http_types::Error> {
+    let mut upstream = tide::new();
+    upstream
+        .at("/api/echo")
+        .post(|mut req: tide::Request<()>| async move {
+            assert!(req.header("connection").is_none());
+            assert!(req.header("x-remove-me").is_none());
+            assert_eq!(req.url().query(), Some("q=1"));
+            let body = req.body_string().await?;
+            let mut res = tide::Response::new(200);
+            res.insert_header("x-custom", req.header("x-custom").unwrap().as_str());
+            res.insert_header("keep-alive", "timeout=5");
+            res.set_body(body);
+            Ok(res)
+        });
+
+    let client: Client = Config::new()
+        .set_http_client(upstream)
+        .set_base_url(surf::Url::parse("http://upstream.example/api/")?)
+        .try_into()?;
+
+    let mut incoming = http_types::Request::post("http://proxy.example/echo?q=1");
+    incoming.insert_header("connection", "x-remove-me");
+    incoming.insert_header("x-remove-me", "1");
+    incoming.insert_header("x-custom", "kept");
+    incoming.set_body("hello");
+
+    let mut res = surf::proxy::forward(incoming, &client).await?;
+    assert_eq!(res["x-custom"], "kept");
+    assert!(res.header("keep-alive").is_none());
+    assert_eq!(res.body_string().await?, "hello");
+    Ok(())
+}
+
+#[async_std::test]
+async fn date_and_clock_skew() -> Result<(), http_types::Error> {
+    use std::time::{Duration, SystemTime};
+
+    let mut server = tide::new();
+    server.at("/").get(|req: tide::Request<()>| async move {
+        assert!(req.header("date").is_some());
+        let server_now = SystemTime::now() + Duration::from_secs(3600);
+        let mut res = tide::Response::new(200);
+        http_types::other::Date::new(server_now).apply(&mut res);
+        Ok(res)
+    });
+
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_send_date(true)
+        .try_into()?;
+    let res = client.get("http://example.org/").await?;
+
+    match res.clock_skew() {
+        Some(surf::ClockSkew::Ahead(skew)) => {
+            assert!(skew > Duration::from_secs(3590) && skew <= Duration::from_secs(3600))
+        }
+        other => panic!("unexpected clock skew {:?}", other),
+    }
+    Ok(())
+}
+
+#[async_std::test]
+async fn response_tee_copies_body() -> Result<(), http_types::Error> {
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+
+    #[derive(Clone, Default)]
+    struct SharedWriter(Arc<Mutex<(Vec<u8>, bool)>>);
+
+    impl futures_util::io::AsyncWrite for SharedWriter {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.0.lock().unwrap().0.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            self.0.lock().unwrap().1 = true;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    let mut server = tide::new();
+    server.at("/").get(|_| async { Ok("hello tee") });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let writer = SharedWriter::default();
+    let mut res = client.get("http://example.org/").await?.tee(writer.clone());
+    assert_eq!(res.body_string().await?, "hello tee");
+
+    let (copy, closed) = writer.0.lock().unwrap().clone();
+    assert_eq!(copy, b"hello tee");
+    assert!(closed);
+    Ok(())
+}
+
+#[cfg(feature = "checksum")]
+#[async_std::test]
+async fn download_verified_checks_digest() -> Result<(), http_types::Error> {
+    use surf::{Checksum, ChecksumMismatch};
+
+    let mut server = tide::new();
+    server.at("/file").get(|_| async { Ok("hello") });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let dir = std::env::temp_dir().join(format!("surf-download-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("file.txt");
+
+    let good = Checksum::Sha256(
+        "2CF24DBA5FB0A30E26E83B2AC5B9E29E1B161E5C1FA7425E73043362938B9824".to_string(),
+    );
+    let written = client
+        .download_verified("http://example.org/file", &path, good)
+        .await?;
+    assert_eq!(written, 5);
+    assert_eq!(std::fs::read_to_string(&path)?, "hello");
+    std::fs::remove_file(&path)?;
+
+    let bad = Checksum::Sha256("00".repeat(32));
+    let err = client
+        .download_verified("http://example.org/file", &path, bad)
+        .await
+        .unwrap_err();
+    assert!(err.downcast_ref::<ChecksumMismatch>().is_some());
+    assert!(!path.exists());
+    assert_eq!(std::fs::read_dir(&dir)?.count(), 0);
+
+    std::fs::remove_dir(&dir)?;
+    Ok(())
+}
+
+#[cfg(feature = "download")]
+#[async_std::test]
+async fn download_to_reports_progress_and_checks_length() -> Result<(), http_types::Error> {
+    use std::sync::{Arc, Mutex};
+    use surf::{DownloadOptions, LengthMismatch};
+
+    let mut server = tide::new();
+    server.at("/file").get(|_| async { Ok("hello world") });
+    server
+        .at("/missing")
+        .get(|_| async { Ok(tide::Response::new(404)) });
+    server.at("/short").get(|_| async {
+        let mut res = tide::Response::new(200);
+        res.set_body(Body::from_reader(
+            futures_util::io::Cursor::new("hello"),
+            Some(11),
+        ));
+        Ok(res)
+    });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let dir = std::env::temp_dir().join(format!("surf-download-to-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("file.txt");
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let options = DownloadOptions::new().buffer_size(4).progress({
+        let progress = progress.clone();
+        move |downloaded, total| progress.lock().unwrap().push((downloaded, total))
+    });
+    let written = client
+        .get("http://example.org/file")
+        .download_to_with(&path, options)
+        .await?;
+    assert_eq!(written, 11);
+    assert_eq!(std::fs::read_to_string(&path)?, "hello world");
+    assert_eq!(
+        *progress.lock().unwrap(),
+        vec![(4, Some(11)), (8, Some(11)), (11, Some(11))]
+    );
+    std::fs::remove_file(&path)?;
+
+    let err = client
+        .get("http://example.org/missing")
+        .download_to(&path)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), 404);
+    assert!(!path.exists());
+
+    let err = client
+        .get("http://example.org/short")
+        .download_to(&path)
+        .await
+        .unwrap_err();
+    let mismatch = err.downcast_ref::<LengthMismatch>().unwrap();
+    assert_eq!((mismatch.expected, mismatch.actual), (11, 5));
+    assert_eq!(std::fs::read_dir(&dir)?.count(), 0);
+
+    let mut res = client.get("http://example.org/short").await?;
+    let options = DownloadOptions::new().verify_length(false);
+    assert_eq!(res.save_to_with(&path, options).await?, 5);
+    assert_eq!(std::fs::read_to_string(&path)?, "hello");
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[async_std::test]
+async fn get_cached_json_revalidates() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use surf::CacheStatus;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/fresh")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            let mut res = tide::Response::new(200);
+            res.insert_header("cache-control", "max-age=60");
+            res.set_body(tide::Body::from_json(&vec![1, 2, 3])?);
+            Ok(res)
+        });
+    server
+        .at("/etag")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            if req.header("if-none-match").map(|v| v.as_str()) == Some("\"v1\"") {
+                return Ok(tide::Response::new(304));
+            }
+            let mut res = tide::Response::new(200);
+            res.insert_header("etag", "\"v1\"");
+            res.insert_header("cache-control", "no-cache");
+            res.set_body(tide::Body::from_json(&"hello")?);
+            Ok(res)
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let (value, status): (Vec<u32>, _) = client.get_cached_json("http://example.org/fresh").await?;
+    assert_eq!((value, status), (vec![1, 2, 3], CacheStatus::Miss));
+    let (value, status): (Vec<u32>, _) = client.get_cached_json("http://example.org/fresh").await?;
+    assert_eq!((value, status), (vec![1, 2, 3], CacheStatus::Fresh));
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    let (value, status): (String, _) = client.get_cached_json("http://example.org/etag").await?;
+    assert_eq!((value.as_str(), status), ("hello", CacheStatus::Miss));
+    let (value, status): (String, _) = client.get_cached_json("http://example.org/etag").await?;
+    assert_eq!(
+        (value.as_str(), status),
+        ("hello", CacheStatus::Revalidated)
+    );
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[async_std::test]
+async fn get_cached_json_shares_the_cache_middleware() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use surf::middleware::Cache;
+    use surf::CacheStatus;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/fresh")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            let mut res = tide::Response::new(200);
+            res.insert_header("cache-control", "max-age=60");
+            res.set_body(tide::Body::from_json(&"hello")?);
+            Ok(res)
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client.with(Cache::new());
+
+    let res = client.get("http://example.org/fresh").await?;
+    assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Miss));
+    let (value, status): (String, _) = client.get_cached_json("http://example.org/fresh").await?;
+    assert_eq!((value.as_str(), status), ("hello", CacheStatus::Fresh));
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[async_std::test]
+async fn manual_clock_drives_timing() -> Result<(), http_types::Error> {
+    use futures_util::future::join;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use surf::middleware::retry::Backoff;
+    use surf::{CacheStatus, ManualClock};
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/fresh")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            let mut res = tide::Response::new(200);
+            res.insert_header("cache-control", "max-age=60");
+            res.set_body(tide::Body::from_json(&"hello")?);
+            Ok(res)
+        });
+    server
+        .at("/flaky")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            let hit = req.state().fetch_add(1, Ordering::SeqCst);
+            Ok(tide::Response::new(if hit == 0 { 503 } else { 200 }))
+        });
+    server.at("/hang").get(|_| async {
+        futures_util::future::pending::<()>().await;
+        Ok("never")
+    });
+    let clock = ManualClock::new();
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_clock(clock.clone())
+        .set_timeout(Some(Duration::from_secs(5)))
+        .try_into()?;
+
+    let (_, status): (String, _) = client.get_cached_json("http://example.org/fresh").await?;
+    assert_eq!(status, CacheStatus::Miss);
+    clock.advance(Duration::from_secs(59));
+    let (_, status): (String, _) = client.get_cached_json("http://example.org/fresh").await?;
+    assert_eq!(status, CacheStatus::Fresh);
+    clock.advance(Duration::from_secs(1));
+    let (_, status): (String, _) = client.get_cached_json("http://example.org/fresh").await?;
+    assert_eq!(status, CacheStatus::Miss);
+    assert_eq!(hits.swap(0, Ordering::SeqCst), 2);
+
+    let advance = |duration| {
+        let clock = clock.clone();
+        async move {
+            async_std::task::yield_now().await;
+            clock.advance(duration);
+        }
+    };
+
+    let (res, ()) = join(
+        client.get("http://example.org/hang"),
+        advance(Duration::from_secs(5)),
+    )
+    .await;
+    assert_eq!(res.unwrap_err().status(), 408);
+
+    let client = client
+        .with(surf::middleware::Retry::new(1).backoff(Backoff::constant(Duration::from_secs(30))));
+    let (res, ()) = join(
+        client.get("http://example.org/flaky"),
+        advance(Duration::from_secs(30)),
+    )
+    .await;
+    assert_eq!(res?.status(), 200);
+    assert_eq!(hits.swap(0, Ordering::SeqCst), 2);
+    assert_eq!(clock.elapsed(), Duration::from_secs(95));
+    Ok(())
+}
+
+#[async_std::test]
+async fn timeouts_cover_response_bodies() -> Result<(), http_types::Error> {
+    use futures_util::future::join;
+    use futures_util::stream::{self, StreamExt, TryStreamExt};
+    use std::time::Duration;
+    use surf::ManualClock;
+
+    let mut server = tide::new();
+    server.at("/stall").get(|_| async {
+        let chunks = stream::iter(vec![Ok(b"partial".to_vec())]).chain(stream::pending());
+        let mut res = tide::Response::new(200);
+        res.set_body(tide::Body::from_reader(chunks.into_async_read(), None));
+        Ok(res)
+    });
+    server.at("/hang").get(|_| async {
+        futures_util::future::pending::<()>().await;
+        Ok("never")
+    });
+    let clock = ManualClock::new();
+    let config = Config::new()
+        .set_http_client(server)
+        .set_clock(clock.clone())
+        .set_timeout(Some(Duration::from_secs(5)));
+    let advance = |duration| {
+        let clock = clock.clone();
+        async move {
+            async_std::task::yield_now().await;
+            clock.advance(duration);
+        }
+    };
+
+    let client: Client = config.clone().try_into()?;
+    let mut res = client.get("http://example.org/stall").await?;
+    let (body, ()) = join(res.body_string(), advance(Duration::from_secs(5))).await;
+    assert!(body.is_err());
+
+    let client: Client = config
+        .set_timeout(None)
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .try_into()?;
+    let mut res = client.get("http://example.org/stall").await?;
+    let (body, ()) = join(res.body_string(), advance(Duration::from_secs(2))).await;
+    assert!(body.is_err());
+
+    let req = client
+        .get("http://example.org/hang")
+        .timeout(Duration::from_secs(30));
+    let mut req = Box::pin(req);
+    assert!(futures_util::poll!(req.as_mut()).is_pending());
+    clock.advance(Duration::from_secs(10));
+    assert!(futures_util::poll!(req.as_mut()).is_pending());
+    clock.advance(Duration::from_secs(20));
+    assert_eq!(req.await.unwrap_err().status(), 408);
+    Ok(())
+}
+
+#[async_std::test]
+async fn error_for_status_captures_body() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server.at("/ok").get(|_| async { Ok("fine") });
+    server.at("/bad").get(|_| async {
+        let mut res = tide::Response::new(422);
+        res.set_body(tide::Body::from_json(
+            &serde_json::json!({ "error": "invalid name" }),
+        )?);
+        Ok(res)
+    });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_capture_error_bodies(8)
+        .try_into()?;
+
+    let mut res = client
+        .get("http://example.org/ok")
+        .await?
+        .error_for_status()
+        .await?;
+    assert_eq!(res.body_string().await?, "fine");
+
+    let err = client
+        .get("http://example.org/bad")
+        .await?
+        .error_for_status()
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), 422);
+    let status_err = err.downcast_ref::<surf::StatusError>().unwrap();
+    assert_eq!(status_err.body(), b"{\"error\"");
+    assert_eq!(
+        status_err.content_type().unwrap().essence(),
+        "application/json"
+    );
+    assert_eq!(
+        status_err.to_string(),
+        "request failed with status 422 Unprocessable Entity: {\"error\""
+    );
+    Ok(())
+}
+
+#[async_std::test]
+async fn errors_are_categorized() -> Result<(), http_types::Error> {
+    use std::time::Duration;
+    use surf::middleware::cache::{Cache, CacheMode};
+    use surf::{ErrorKind, MiddlewareError};
+
+    let mut server = tide::new();
+    server
+        .at("/missing")
+        .get(|_| async { Ok(tide::Response::new(404)) });
+    server.at("/text").get(|_| async { Ok("not json") });
+    server.at("/slow").get(|_| async {
+        async_std::task::sleep(Duration::from_secs(5)).await;
+        Ok("late")
+    });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_timeout(Some(Duration::from_millis(50)))
+        .try_into()?;
+
+    let res = client.get("http://example.org/missing").await?;
+    let err = res.error_for_status().await.unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Status);
+
+    let err = client
+        .get("http://example.org/text")
+        .recv_json::<serde_json::Value>()
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Decode);
+
+    let err = client.get("http://example.org/slow").await.unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Timeout);
+
+    let err = client
+        .clone()
+        .with(Cache::new().mode(CacheMode::OnlyIfCached))
+        .get("http://example.org/text")
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Middleware);
+    assert_eq!(
+        err.downcast_ref::<MiddlewareError>().unwrap().middleware(),
+        "cache"
+    );
+
+    let err = http_types::Error::from_str(500, "something else");
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Other);
+    Ok(())
+}
+
+#[cfg(feature = "curl-client")]
+#[async_std::test]
+async fn refused_connections_are_connect_errors() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    drop(listener);
+
+    let err = surf::get(url).await.unwrap_err();
+    assert_eq!(surf::ErrorKind::of(&err), surf::ErrorKind::Connect);
+}
+
+#[cfg(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "h1-client-no-tls"
+))]
+#[async_std::test]
+async fn ip_family_constrains_addresses() -> Result<(), http_types::Error> {
+    use std::io::{Read, Write};
+    use surf::IpVersion;
+
+    // Only listen on IPv4, so `localhost` can only be reached over IPv4.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://localhost:{}/", listener.local_addr()?.port());
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 1024];
+        let _ = stream.read(&mut buf).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+            .unwrap();
+    });
+
+    let client = Client::new();
+    let err = client.get(&url).ip_family(IpVersion::V6).await.unwrap_err();
+    assert_eq!(surf::ErrorKind::of(&err), surf::ErrorKind::Connect);
+
+    let body = client
+        .get(&url)
+        .ip_family(IpVersion::V4)
+        .recv_string()
+        .await?;
+    assert_eq!(body, "ok");
+    Ok(())
+}
+
+#[cfg(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "h1-client-no-tls"
+))]
+#[async_std::test]
+async fn resolve_to_connects_to_the_address_with_the_url_host() -> Result<(), http_types::Error> {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 1024];
+        let len = stream.read(&mut buf).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+            .unwrap();
+        String::from_utf8_lossy(&buf[..len]).to_ascii_lowercase()
+    });
+
+    let url = format!("http://canary.invalid:{}/health", addr.port());
+    let body = Client::new()
+        .get(url)
+        .resolve_to(addr)
+        .recv_string()
+        .await?;
+    assert_eq!(body, "ok");
+    let request = server.join().unwrap();
+    assert!(request.contains(&format!("host: canary.invalid:{}", addr.port())));
+    Ok(())
+}
+
+#[cfg(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "h1-client-no-tls"
+))]
+#[async_std::test]
+async fn dns_resolver_and_overrides_choose_the_address() -> Result<(), http_types::Error> {
+    use std::io::{Read, Write};
+    use std::net::SocketAddr;
+    use surf::{ConnectionError, ConnectionErrorKind, Resolver};
+
+    #[derive(Debug)]
+    struct Registry(SocketAddr);
+
+    #[surf::utils::async_trait]
+    impl Resolver for Registry {
+        async fn resolve(&self, host: &str, _: u16, _: &Client) -> surf::Result<Vec<SocketAddr>> {
+            match host {
+                "api.service.consul" => Ok(vec![self.0]),
+                _ => Ok(vec![]),
+            }
+        }
+    }
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let server = std::thread::spawn(move || {
+        let mut hosts = Vec::new();
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let len = stream.read(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..len]).to_ascii_lowercase();
+            let host = request
+                .lines()
+                .find(|line| line.starts_with("host:"))
+                .unwrap();
+            hosts.push(host.to_string());
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+                .unwrap();
+        }
+        hosts
+    });
+
+    let client: Client = Config::new()
+        .set_dns_resolver(Registry(addr))
+        .add_dns_override("pinned.invalid", addr)
+        .try_into()?;
+    assert_eq!(
+        client.get("http://pinned.invalid/").recv_string().await?,
+        "ok"
+    );
+    let body = client
+        .get("http://api.service.consul/")
+        .recv_string()
+        .await?;
+    assert_eq!(body, "ok");
+    assert_eq!(
+        server.join().unwrap(),
+        ["host: pinned.invalid", "host: api.service.consul"]
+    );
+
+    let err = client.get("http://unknown.invalid/").await.unwrap_err();
+    let kind = err.downcast_ref::<ConnectionError>().map(|err| err.kind());
+    assert_eq!(kind, Some(ConnectionErrorKind::Dns));
+    Ok(())
+}
+
+#[async_std::test]
+async fn body_json_or_error_decodes_both_schemas() -> Result<(), http_types::Error> {
+    #[derive(Debug, serde::Deserialize)]
+    struct ErrorBody {
+        message: String,
+    }
+
+    let mut server = tide::new();
+    server
+        .at("/ok")
+        .get(|_| async { tide::Body::from_json(&vec![1, 2]) });
+    server.at("/missing").get(|_| async {
+        let mut res = tide::Response::new(404);
+        res.set_body(serde_json::json!({ "message": "no such user" }));
+        Ok(res)
+    });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let mut res = client.get("http://example.org/ok").await?;
+    let ok = res.body_json_or_error::<Vec<u32>, ErrorBody>().await;
+    assert_eq!(ok.unwrap(), vec![1, 2]);
+
+    let mut res = client.get("http://example.org/missing").await?;
+    match res.body_json_or_error::<Vec<u32>, ErrorBody>().await {
+        Err(surf::ApiError::Api { status, error }) => {
+            assert_eq!(status, 404);
+            assert_eq!(error.message, "no such user");
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+    Ok(())
+}
+
+#[async_std::test]
+async fn retry_only_idempotent_requests() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/flaky")
+        .all(|mut req: tide::Request<Arc<AtomicUsize>>| async move {
+            let body = req.body_string().await?;
+            assert_eq!(body, "payload");
+            let hit = req.state().fetch_add(1, Ordering::SeqCst);
+            Ok(tide::Response::new(if hit.is_multiple_of(2) {
+                503
+            } else {
+                200
+            }))
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client.with(surf::middleware::Retry::new(2));
+
+    let res = client
+        .post("http://example.org/flaky")
+        .body_string("payload".to_string())
+        .await?;
+    assert_eq!(res.status(), 503);
+    assert_eq!(hits.swap(0, Ordering::SeqCst), 1);
+
+    let res = client
+        .put("http://example.org/flaky")
+        .body_string("payload".to_string())
+        .await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(hits.swap(0, Ordering::SeqCst), 2);
+
+    let res = client
+        .post("http://example.org/flaky")
+        .header("Idempotency-Key", "abc")
+        .body_string("payload".to_string())
+        .await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(hits.swap(0, Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[async_std::test]
+async fn retry_backs_off_and_honors_retry_after() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use surf::middleware::retry::{Backoff, FailureStatuses};
+
+    let backoff = Backoff::exponential(Duration::from_millis(100))
+        .max_delay(Duration::from_millis(300))
+        .jitter(false);
+    assert_eq!(backoff.delay(1), Duration::from_millis(100));
+    assert_eq!(backoff.delay(2), Duration::from_millis(200));
+    assert_eq!(backoff.delay(3), Duration::from_millis(300));
+    assert_eq!(backoff.delay(100), Duration::from_millis(300));
+    let jittered = backoff.jitter(true).delay(2);
+    assert!(jittered >= Duration::from_millis(100) && jittered <= Duration::from_millis(200));
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/limited/:wait")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            if req.state().fetch_add(1, Ordering::SeqCst) == 0 {
+                let mut res = tide::Response::new(429);
+                res.insert_header("Retry-After", req.param("wait")?);
+                Ok(res)
+            } else {
+                Ok(tide::Response::new(200))
+            }
+        });
+    server
+        .at("/teapot")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            Ok(tide::Response::new(418))
+        });
+    let base: Client = Config::new().set_http_client(server).try_into()?;
+    let client = base.clone().with(
+        surf::middleware::Retry::new(2)
+            .backoff(Backoff::constant(Duration::from_millis(50)))
+            .max_retry_after(Duration::from_secs(5)),
+    );
+
+    let start = Instant::now();
+    let res = client.get("http://example.org/limited/1").await?;
+    assert_eq!(res.status(), 200);
+    assert!(start.elapsed() >= Duration::from_millis(900));
+    assert_eq!(hits.swap(0, Ordering::SeqCst), 2);
+
+    let res = client.get("http://example.org/limited/120").await?;
+    assert_eq!(res.status(), 429);
+    assert_eq!(hits.swap(0, Ordering::SeqCst), 1);
+
+    let res = client.get("http://example.org/teapot").await?;
+    assert_eq!(res.status(), 418);
+    assert_eq!(hits.swap(0, Ordering::SeqCst), 1);
+
+    let client = base.with(
+        surf::middleware::Retry::new(2)
+            .classifier(FailureStatuses::new(vec![
+                http_types::StatusCode::ImATeapot,
+            ]))
+            .backoff(Backoff::none()),
+    );
+    let res = client.get("http://example.org/teapot").await?;
+    assert_eq!(res.status(), 418);
+    assert_eq!(hits.swap(0, Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[async_std::test]
+async fn success_predicate_classifies_statuses() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server
+        .at("/missing")
+        .get(|_| async { Ok(tide::Response::new(404)) });
+    server
+        .at("/moved")
+        .get(|_| async { Ok(tide::Response::new(304)) });
+
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_success_predicate(|status| status.is_success() || status == surf::StatusCode::NotFound)
+        .try_into()?;
+
+    let res = client.get("http://example.org/missing").await?;
+    assert!(res.is_success());
+    assert_eq!(res.error_for_status().await?.status(), 404);
+
+    let res = client.get("http://example.org/moved").await?;
+    assert!(!res.is_success());
+    let err = res.error_for_status().await.unwrap_err();
+    assert_eq!(err.status(), 304);
+    Ok(())
+}
+
+#[async_std::test]
+async fn error_for_status_reads_problem_details() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server.at("/purchase").post(|_| async {
+        let mut res = tide::Response::new(403);
+        res.set_body(
+            r#"{"type": "https://example.com/probs/out-of-credit", "title": "You do not have enough credit.",
+                "status": 403, "detail": "Your current balance is 30, but that costs 50.",
+                "balance": 30, "instance": 7}"#,
+        );
+        res.set_content_type("application/problem+json");
+        Ok(res)
+    });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let mut res = client.post("http://example.org/purchase").await?;
+    let problem = res.problem_details().await?.expect("problem details");
+    assert_eq!(
+        problem.problem_type(),
+        "https://example.com/probs/out-of-credit"
+    );
+    assert_eq!(problem.status(), Some(surf::StatusCode::Forbidden));
+    assert_eq!(problem.instance(), None);
+    assert_eq!(problem.extension::<u64>("balance"), Some(30));
+
+    let res = client.post("http://example.org/purchase").await?;
+    let err = res.error_for_status().await.unwrap_err();
+    let status_error = err.downcast_ref::<surf::StatusError>().unwrap();
+    assert!(status_error.body().is_empty());
+    let problem = status_error.problem_details().expect("problem details");
+    assert_eq!(problem.title(), Some("You do not have enough credit."));
+    assert_eq!(
+        err.to_string(),
+        "request failed with status 403 Forbidden: You do not have enough credit.: \
+         Your current balance is 30, but that costs 50."
+    );
+    Ok(())
+}
+
+#[async_std::test]
+async fn resource_updates_with_if_match() -> Result<(), http_types::Error> {
+    use serde::{Deserialize, Serialize};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+    struct Counter {
+        count: u64,
+    }
+
+    // The stored counter, its version, and whether the next write loses to a concurrent one.
+    type State = Arc<Mutex<(Counter, u32, bool)>>;
+    let state: State = Arc::new(Mutex::new((Counter { count: 1 }, 1, true)));
+    let mut server = tide::with_state(state.clone());
+    server
+        .at("/counter")
+        .get(|req: tide::Request<State>| async move {
+            let (counter, version, _) = req.state().lock().unwrap().clone();
+            let etag = format!("\"v{}\"", version);
+            if req.header("If-None-Match").map(|v| v.as_str()) == Some(etag.as_str()) {
+                return Ok(tide::Response::new(304));
+            }
+            let mut res = tide::Response::new(200);
+            res.insert_header("ETag", etag);
+            res.set_body(tide::Body::from_json(&counter)?);
+            Ok(res)
+        })
+        .put(|mut req: tide::Request<State>| async move {
+            let counter: Counter = req.body_json().await?;
+            let if_match = req.header("If-Match").map(|v| v.as_str().to_string());
+            let mut state = req.state().lock().unwrap();
+            if state.2 {
+                // Another client wins the race.
+                state.0.count += 10;
+                state.1 += 1;
+                state.2 = false;
+            }
+            if if_match != Some(format!("\"v{}\"", state.1)) {
+                return Ok(tide::Response::new(412));
+            }
+            state.0 = counter;
+            state.1 += 1;
+            let mut res = tide::Response::new(204);
+            res.insert_header("ETag", format!("\"v{}\"", state.1));
+            Ok(res)
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let mut counter = client.resource::<Counter>("http://example.org/counter");
+    assert_eq!(counter.fetch().await?, &Counter { count: 1 });
+    assert_eq!(counter.etag(), Some("\"v1\""));
+    assert_eq!(counter.refresh().await?, &Counter { count: 1 });
+
+    let updated = counter.update(|counter| counter.count += 1).await?;
+    assert_eq!(updated, &Counter { count: 12 });
+    assert_eq!(counter.etag(), Some("\"v3\""));
+    assert_eq!(state.lock().unwrap().0, Counter { count: 12 });
+
+    state.lock().unwrap().2 = true;
+    let mut counter = client
+        .resource::<Counter>("http://example.org/counter")
+        .max_attempts(1);
+    let err = counter
+        .update(|counter| counter.count += 1)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), 412);
+    Ok(())
+}
+
+#[async_std::test]
+async fn cookie_store_round_trips_cookies() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server.at("/login").post(|_| async {
+        let mut res = tide::Response::new(200);
+        res.append_header("Set-Cookie", "session=abc; Path=/; HttpOnly");
+        res.append_header("Set-Cookie", "theme=dark; Path=/account; Max-Age=3600");
+        res.append_header("Set-Cookie", "other=1; Domain=example.com");
+        Ok(res)
+    });
+    server.at("/logout").post(|_| async {
+        let mut res = tide::Response::new(200);
+        res.append_header("Set-Cookie", "session=; Path=/; Max-Age=0");
+        Ok(res)
+    });
+    server.at("/*").get(|req: tide::Request<()>| async move {
+        let cookies = req
+            .header("Cookie")
+            .map(|values| values.as_str().to_string());
+        Ok(cookies.unwrap_or_default())
+    });
+    let client: Client = Config::new()
+        .set_http_client(server.clone())
+        .set_cookie_store(true)
+        .try_into()?;
+
+    client.post("http://example.org/login").await?;
+    let cookies = client
+        .get("http://example.org/account/settings")
+        .recv_string()
+        .await?;
+    assert_eq!(cookies, "theme=dark; session=abc");
+    let cookies = client.get("http://example.org/home").recv_string().await?;
+    assert_eq!(cookies, "session=abc");
+
+    let jar = client.cookie_jar().expect("cookie store");
+    let stored = jar.cookies();
+    assert_eq!(stored.len(), 2);
+    assert!(stored[0].http_only && stored[0].host_only);
+    assert!(stored[1].expires.is_some());
+
+    let exported = jar.export_json();
+    let restored: Client = Config::new()
+        .set_http_client(server)
+        .set_cookie_store(true)
+        .try_into()?;
+    let restored_jar = restored.cookie_jar().expect("cookie store");
+    restored_jar.import_json(&exported)?;
+    let names: Vec<_> = restored_jar.cookies().into_iter().map(|c| c.name).collect();
+    assert_eq!(names, ["session", "theme"]);
+
+    restored.post("http://example.org/logout").await?;
+    let cookies = restored
+        .get("http://example.org/account")
+        .recv_string()
+        .await?;
+    assert_eq!(cookies, "theme=dark");
+    Ok(())
+}
+
+#[async_std::test]
+async fn cookie_jar_saves_to_and_loads_from_a_file() -> Result<(), http_types::Error> {
+    use surf::middleware::{CookieJar, JsonFileStore};
+
+    let mut server = tide::new();
+    server.at("/login").post(|_| async {
+        let mut res = tide::Response::new(200);
+        res.append_header("Set-Cookie", "session=abc; Path=/");
+        Ok(res)
+    });
+    server
+        .at("/account")
+        .get(|req: tide::Request<()>| async move {
+            let cookies = req
+                .header("Cookie")
+                .map(|values| values.as_str().to_string());
+            Ok(cookies.unwrap_or_default())
+        });
+    let path = std::env::temp_dir().join(format!("surf-cookies-{}.json", std::process::id()));
+    std::fs::remove_file(&path).ok();
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let jar = CookieJar::with_store(JsonFileStore::new(&path));
+    jar.load()?;
+    assert!(jar.cookies().is_empty());
+    client
+        .clone()
+        .with(jar.clone())
+        .post("http://example.org/login")
+        .await?;
+    jar.save()?;
+
+    let jar = CookieJar::with_store(JsonFileStore::new(&path));
+    jar.load()?;
+    let cookies = client
+        .with(jar)
+        .get("http://example.org/account")
+        .recv_string()
+        .await?;
+    assert_eq!(cookies, "session=abc");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    std::fs::remove_file(&path)?;
+
+    assert!(CookieJar::new().save().is_err());
+    Ok(())
+}
+
+#[async_std::test]
+async fn restrict_hosts_blocks_other_hosts() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server.at("/ok").get(|_| async { Ok("ok") });
+    server
+        .at("/leak")
+        .get(|_| async { Ok(tide::Redirect::new("http://evil.example.com/collect")) });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .restrict_hosts(vec!["Example.org", "*.api.example.org"])
+        .try_into()?;
+    let client = client.with(surf::middleware::Redirect::default());
+
+    assert_eq!(
+        client.get("http://example.org/ok").recv_string().await?,
+        "ok"
+    );
+    assert_eq!(
+        client
+            .get("http://eu.api.example.org/ok")
+            .recv_string()
+            .await?,
+        "ok"
+    );
+
+    let err = client.get("http://api.example.org/ok").await.unwrap_err();
+    assert_eq!(err.status(), 403);
+    let err = client.get("http://example.org/leak").await.unwrap_err();
+    assert_eq!(err.status(), 403);
+
+    // Requests sent from within middleware are restricted as well.
+    fn exfiltrate(
+        req: Request,
+        client: Client,
+        next: Next<'_>,
+    ) -> BoxFuture<'_, Result<Response, http_types::Error>> {
+        Box::pin(async move {
+            client.get("http://evil.example.com/collect").await?;
+            next.run(req, client).await
+        })
+    }
+    let client = client.with(exfiltrate);
+    let err = client.get("http://example.org/ok").await.unwrap_err();
+    assert_eq!(err.status(), 403);
+    Ok(())
+}
+
+#[async_std::test]
+async fn proxy_routes_requests() -> Result<(), http_types::Error> {
+    let proxy = surf::Url::parse(&mockito::server_url())?;
+    let config = Config::new()
+        .set_proxy(proxy.clone())
+        .set_no_proxy(vec!["Internal.example", ".corp.example"]);
+    let url = |s: &str| surf::Url::parse(s).unwrap();
+    assert_eq!(config.proxy_for(&url("https://example.com/")), Some(&proxy));
+    assert_eq!(config.proxy_for(&url("http://internal.example/")), None);
+    assert_eq!(config.proxy_for(&url("http://a.internal.example/")), None);
+    assert_eq!(config.proxy_for(&url("http://corp.example/")), None);
+    assert_eq!(
+        config.proxy_for(&url("http://notinternal.example/")),
+        Some(&proxy)
+    );
+
+    // Only the curl backend sends requests through proxies.
+    #[cfg(feature = "curl-client")]
+    {
+        let m = mock("GET", "http://origin.invalid/data")
+            .with_status(200)
+            .with_body("proxied")
+            .create();
+        let client: Client = config.try_into()?;
+        let body = client
+            .get("http://origin.invalid/data")
+            .recv_string()
+            .await?;
+        m.assert();
+        assert_eq!(body, "proxied");
+    }
+    Ok(())
+}
+
+#[async_std::test]
+async fn seeded_rng_reproduces_retry_jitter() -> Result<(), http_types::Error> {
+    use futures_util::future::join;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use surf::middleware::retry::Backoff;
+    use surf::{ManualClock, Rng, SeededRng};
+
+    let mut bytes = [[0; 12]; 2];
+    SeededRng::new(9).fill_bytes(&mut bytes[0]);
+    SeededRng::new(9).fill_bytes(&mut bytes[1]);
+    assert_eq!(bytes[0], bytes[1]);
+
+    let backoff = Backoff::exponential(Duration::from_secs(10));
+    let delay = backoff.delay_with(1, &SeededRng::new(9));
+    assert_eq!(delay, backoff.delay_with(1, &SeededRng::new(9)));
+    assert!(delay >= Duration::from_secs(5) && delay <= Duration::from_secs(10));
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/flaky")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            let hit = req.state().fetch_add(1, Ordering::SeqCst);
+            Ok(tide::Response::new(if hit == 0 { 503 } else { 200 }))
+        });
+    let clock = ManualClock::new();
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_clock(clock.clone())
+        .set_rng(SeededRng::new(9))
+        .try_into()?;
+    let client = client.with(surf::middleware::Retry::new(1).backoff(backoff));
+
+    let drive = async {
+        async_std::task::yield_now().await;
+        clock.advance(delay - Duration::from_millis(1));
+        async_std::task::yield_now().await;
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        clock.advance(Duration::from_millis(1));
+    };
+    let (res, ()) = async_std::future::timeout(
+        Duration::from_secs(5),
+        join(client.get("http://example.org/flaky"), drive),
+    )
+    .await?;
+    assert_eq!(res?.status(), 200);
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[async_std::test]
+async fn bytes_stream_yields_body_chunks() -> Result<(), http_types::Error> {
+    use futures_util::stream::TryStreamExt;
+
+    let mut server = tide::new();
+    server.at("/data").get(|_| async {
+        let data: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        Ok(tide::Body::from_reader(
+            async_std::io::Cursor::new(data),
+            None,
+        ))
+    });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let mut res = client.get("http://example.org/data").await?;
+    let chunks: Vec<Vec<u8>> = res.bytes_stream().try_collect().await?;
+    assert!(chunks.iter().all(|chunk| !chunk.is_empty()));
+    let body: Vec<u8> = chunks.concat();
+    assert_eq!(body.len(), 100_000);
+    assert!(body.iter().enumerate().all(|(i, b)| *b == i as u8));
+    assert!(res.body_bytes().await?.is_empty());
+    Ok(())
+}
+
+#[async_std::test]
+async fn patch_bodies_set_content_types() -> Result<(), http_types::Error> {
+    use serde_json::{json, Value};
+    use surf::PatchOp;
+
+    let mut server = tide::new();
+    server
+        .at("/doc")
+        .patch(|mut req: tide::Request<()>| async move {
+            let content_type = req.content_type().unwrap().to_string();
+            let body: Value = req.body_json().await?;
+            tide::Body::from_json(&json!([content_type, body]))
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let ops = [
+        PatchOp::Test {
+            path: "/version".into(),
+            value: json!(3),
+        },
+        PatchOp::Move {
+            from: "/draft".into(),
+            path: "/final".into(),
+        },
+        PatchOp::Remove {
+            path: "/tags/0".into(),
+        },
+    ];
+    let echoed: Value = client
+        .patch("http://example.org/doc")
+        .body_json_patch(&ops)?
+        .recv_json()
+        .await?;
+    assert_eq!(
+        echoed,
+        json!([
+            "application/json-patch+json",
+            [
+                { "op": "test", "path": "/version", "value": 3 },
+                { "op": "move", "from": "/draft", "path": "/final" },
+                { "op": "remove", "path": "/tags/0" },
+            ]
+        ])
+    );
+
+    let echoed: Value = client
+        .patch("http://example.org/doc")
+        .body_merge_patch(&json!({ "title": "Hello", "draft": null }))?
+        .recv_json()
+        .await?;
+    assert_eq!(
+        echoed,
+        json!([
+            "application/merge-patch+json",
+            { "title": "Hello", "draft": null }
+        ])
+    );
+    Ok(())
+}
+
+#[cfg(feature = "encoding")]
+#[async_std::test]
+async fn encoded_string_bodies_set_charset() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server
+        .at("/echo")
+        .post(|mut req: tide::Request<()>| async move {
+            let mut res = tide::Response::new(200);
+            res.set_content_type(req.content_type().unwrap());
+            res.set_body(req.body_bytes().await?);
+            Ok(res)
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let mut res = client
+        .post("http://example.org/echo")
+        .body_string_encoded("こんにちは", "shift_jis")?
+        .await?;
+    assert_eq!(res["content-type"], "text/plain;charset=Shift_JIS");
+    assert_eq!(res.body_string().await?, "こんにちは");
+
+    let err = client
+        .post("http://example.org/echo")
+        .body_string_encoded("こんにちは", "iso-8859-1")
+        .unwrap_err();
+    assert!(err.downcast_ref::<std::io::Error>().is_some());
+    Ok(())
+}
+
+#[cfg(feature = "encoding")]
+#[async_std::test]
+async fn text_stream_decodes_charsets_incrementally() -> Result<(), http_types::Error> {
+    use futures_util::stream::TryStreamExt;
+
+    let mut server = tide::new();
+    server.at("/text").get(|_| async {
+        let mut res = tide::Response::new(200);
+        res.set_body(vec![
+            0x82, 0xb1, 0x82, 0xf1, 0x82, 0xc9, 0x82, 0xbf, 0x82, 0xcd,
+        ]);
+        res.insert_header("content-type", "text/plain; charset=shift_jis");
+        Ok(res)
+    });
+    server.at("/unknown").get(|_| async {
+        let mut res = tide::Response::new(200);
+        res.set_body("raw");
+        res.insert_header("content-type", "text/plain; charset=x-unknown");
+        Ok(res)
+    });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let mut res = client.get("http://example.org/text").await?;
+    let chunks: Vec<String> = res.text_stream().try_collect().await?;
+    assert_eq!(chunks.concat(), "こんにちは");
+    let text = client.get("http://example.org/text").recv_string().await?;
+    assert_eq!(text, "こんにちは");
+
+    let err = client
+        .get("http://example.org/unknown")
+        .recv_string()
+        .await
+        .unwrap_err();
+    let err = err.downcast_ref::<std::io::Error>().unwrap();
+    let err = err.get_ref().unwrap().downcast_ref::<surf::DecodeError>();
+    assert_eq!(err.unwrap().data, b"raw");
+    Ok(())
+}
+
+#[async_std::test]
+async fn redirect_follows_responses_per_rfc() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use surf::middleware::{Redirect, RedirectChain};
+    use surf::StatusCode;
+
+    async fn echo(mut req: tide::Request<Arc<AtomicUsize>>) -> tide::Result<String> {
+        let auth = req.header("authorization").map(|h| h.as_str().to_string());
+        let body = req.body_string().await?;
+        Ok(format!("{} {:?} {}", req.method(), auth, body))
+    }
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/submit")
+        .post(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            Ok(tide::Redirect::see_other("/echo"))
+        });
+    server
+        .at("/moved")
+        .post(|_| async { Ok(tide::Redirect::temporary("/echo")) });
+    server
+        .at("/found")
+        .post(|_| async { Ok(tide::Redirect::new("/echo")) });
+    server
+        .at("/away")
+        .get(|_| async { Ok(tide::Redirect::new("http://other.example.org/echo")) });
+    server.at("/echo").get(echo).post(echo);
+    let base: Client = Config::new().set_http_client(server).try_into()?;
+    let client = base.clone().with(Redirect::default());
+
+    let mut res = client
+        .post("http://example.org/submit")
+        .body_string("data".into())
+        .await?;
+    assert_eq!(res.body_string().await?, "GET None ");
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    let chain = res.ext::<RedirectChain>().unwrap();
+    assert_eq!(
+        chain.hops(),
+        &[(
+            surf::Url::parse("http://example.org/submit")?,
+            StatusCode::SeeOther
+        )]
+    );
+    assert_eq!(chain.url().as_str(), "http://example.org/echo");
+
+    let body = client
+        .post("http://example.org/moved")
+        .body_string("data".into())
+        .recv_string()
+        .await?;
+    assert_eq!(body, "POST None data");
+
+    let body = client
+        .post("http://example.org/found")
+        .body_string("data".into())
+        .recv_string()
+        .await?;
+    assert_eq!(body, "POST None data");
+    let body = base
+        .with(Redirect::default().post_to_get(true))
+        .post("http://example.org/found")
+        .body_string("data".into())
+        .recv_string()
+        .await?;
+    assert_eq!(body, "GET None ");
+
+    let body = client
+        .get("http://example.org/echo")
+        .header("authorization", "Bearer secret")
+        .recv_string()
+        .await?;
+    assert_eq!(body, "GET Some(\"Bearer secret\") ");
+    let body = client
+        .get("http://example.org/away")
+        .header("authorization", "Bearer secret")
+        .recv_string()
+        .await?;
+    assert_eq!(body, "GET None ");
+    Ok(())
+}
+
+#[async_std::test]
+async fn redirect_resolves_relative_locations() -> Result<(), http_types::Error> {
+    use surf::middleware::{Redirect, RedirectChain};
+
+    let mut server = tide::new();
+    server
+        .at("/docs/old")
+        .get(|_| async { Ok(tide::Redirect::new("new?v=2")) });
+    server
+        .at("/docs/new")
+        .get(|_| async { Ok(tide::Redirect::new("//cdn.example.org/docs")) });
+    server.at("/docs").get(|req: tide::Request<()>| async move {
+        Ok(format!(
+            "{}{}",
+            req.host().unwrap_or_default(),
+            req.url().path()
+        ))
+    });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client.with(Redirect::default());
+
+    let mut res = client.get("http://example.org/docs/old?v=1#usage").await?;
+    assert_eq!(res.body_string().await?, "cdn.example.org/docs");
+    let chain = res.ext::<RedirectChain>().unwrap();
+    assert_eq!(chain.hops().len(), 2);
+    assert_eq!(
+        chain.hops()[1].0.as_str(),
+        "http://example.org/docs/new?v=2#usage"
+    );
+    assert_eq!(chain.url().as_str(), "http://cdn.example.org/docs#usage");
+    Ok(())
+}
+
+#[async_std::test]
+async fn redirect_keeps_the_request_timeout() -> Result<(), http_types::Error> {
+    use std::time::Duration;
+    use surf::middleware::Redirect;
+
+    let mut server = tide::new();
+    server
+        .at("/old")
+        .get(|_| async { Ok(tide::Redirect::new("/hang")) });
+    server.at("/hang").get(|_| async {
+        futures_util::future::pending::<()>().await;
+        Ok("")
+    });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_timeout(None)
+        .try_into()?;
+    let client = client.with(Redirect::default());
+
+    let req = client
+        .get("http://example.org/old")
+        .timeout(Duration::from_millis(200));
+    let res = async_std::future::timeout(Duration::from_secs(5), req).await?;
+    assert_eq!(res.unwrap_err().status(), 408);
+    Ok(())
+}
+
+#[async_std::test]
+async fn redirect_remembers_permanent_redirects() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use surf::middleware::{PermanentRedirects, Redirect, RedirectChain};
+    use surf::StatusCode;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/legacy")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            Ok(tide::Redirect::permanent("/current"))
+        });
+    server
+        .at("/moving")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            let mut res: tide::Response = tide::Redirect::permanent("/current").into();
+            res.insert_header("cache-control", "no-store");
+            Ok(res)
+        });
+    server.at("/current").get(|_| async { Ok("current") });
+    let redirects = PermanentRedirects::new(8);
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client.with(Redirect::default().permanent_redirects(redirects.clone()));
+
+    for _ in 0..3 {
+        let mut res = client.get("http://example.org/legacy").await?;
+        assert_eq!(res.body_string().await?, "current");
+        let chain = res.ext::<RedirectChain>().unwrap();
+        assert_eq!(
+            chain.hops(),
+            &[(
+                surf::Url::parse("http://example.org/legacy")?,
+                StatusCode::PermanentRedirect
+            )]
+        );
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    for _ in 0..2 {
+        let body = client
+            .get("http://example.org/moving")
+            .recv_string()
+            .await?;
+        assert_eq!(body, "current");
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+    assert_eq!(redirects.len(), 1);
+
+    let restored = PermanentRedirects::new(8);
+    restored.import_json(&redirects.export_json())?;
+    assert_eq!(
+        restored.get(&surf::Url::parse("http://example.org/legacy")?),
+        Some((
+            surf::Url::parse("http://example.org/current")?,
+            StatusCode::PermanentRedirect
+        ))
+    );
+    Ok(())
+}
+
+#[async_std::test]
+async fn cache_middleware_serves_and_revalidates() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use surf::middleware::cache::{Cache, CacheMode};
+    use surf::{CacheStatus, ManualClock};
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/doc")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            if req.header("if-none-match").map(|h| h.as_str()) == Some("\"v1\"") {
+                return Ok(tide::Response::builder(304)
+                    .header("cache-control", "max-age=60")
+                    .build());
+            }
+            Ok(tide::Response::builder(200)
+                .header("cache-control", "max-age=60")
+                .header("etag", "\"v1\"")
+                .body("document")
+                .build())
+        })
+        .put(|_| async { Ok("updated") });
+    server
+        .at("/lang")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            let lang = req.header("accept-language").unwrap().as_str().to_string();
+            Ok(tide::Response::builder(200)
+                .header("cache-control", "max-age=60")
+                .header("vary", "Accept-Language")
+                .body(lang)
+                .build())
+        });
+    let clock = ManualClock::new();
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_clock(clock.clone())
+        .try_into()?;
+    let client = client.with(Cache::new());
+    let hits = move || hits.swap(0, Ordering::SeqCst);
+    let url = "http://example.org/doc";
+
+    let err = client
+        .get(url)
+        .cache_mode(CacheMode::OnlyIfCached)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), 504);
+
+    let mut res = client.get(url).await?;
+    assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Miss));
+    assert_eq!(res.body_string().await?, "document");
+    clock.advance(Duration::from_secs(30));
+    let mut res = client.get(url).await?;
+    assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Fresh));
+    assert_eq!(res["age"], "30");
+    assert_eq!(res.body_string().await?, "document");
+    assert_eq!(hits(), 1);
+
+    clock.advance(Duration::from_secs(30));
+    let mut res = client.get(url).await?;
+    assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Revalidated));
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.body_string().await?, "document");
+    let res = client.get(url).await?;
+    assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Fresh));
+    let res = client.get(url).cache_mode(CacheMode::Reload).await?;
+    assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Miss));
+    let res = client.get(url).cache_mode(CacheMode::NoStore).await?;
+    assert_eq!(res.ext::<CacheStatus>(), None);
+    assert_eq!(hits(), 3);
+
+    client.put(url).await?;
+    let res = client.get(url).await?;
+    assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Miss));
+    assert_eq!(hits(), 1);
+
+    for lang in &["en", "nl", "en"] {
+        let body = client
+            .get("http://example.org/lang")
+            .header("accept-language", *lang)
+            .recv_string()
+            .await?;
+        assert_eq!(body, *lang);
+    }
+    assert_eq!(hits(), 3);
+    Ok(())
+}
+
+#[async_std::test]
+async fn cache_middleware_revalidates_with_the_request_timeout() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use surf::middleware::cache::Cache;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/doc")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            if req.state().fetch_add(1, Ordering::SeqCst) > 0 {
+                futures_util::future::pending::<()>().await;
+            }
+            Ok(tide::Response::builder(200)
+                .header("cache-control", "no-cache")
+                .header("etag", "\"v1\"")
+                .body("document")
+                .build())
+        });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_timeout(None)
+        .try_into()?;
+    let client = client.with(Cache::new());
+
+    let body = client.get("http://example.org/doc").recv_string().await?;
+    assert_eq!(body, "document");
+    let req = client
+        .get("http://example.org/doc")
+        .timeout(Duration::from_millis(200));
+    let res = async_std::future::timeout(Duration::from_secs(5), req).await?;
+    assert_eq!(res.unwrap_err().status(), 408);
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[async_std::test]
+async fn cache_middleware_keeps_range_requests_apart() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use surf::middleware::cache::Cache;
+    use surf::CacheStatus;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/file")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            let range = req.header("range").map(|range| range.as_str().to_string());
+            let (status, content_range, body) = match range.as_deref() {
+                Some("bytes=0-3") => (206, Some("bytes 0-3/10"), "0123"),
+                Some(_) => (206, Some("bytes 4-5/10"), "45"),
+                None => (200, None, "0123456789"),
+            };
+            let mut res = tide::Response::builder(status)
+                .header("cache-control", "max-age=60")
+                .body(body)
+                .build();
+            if let Some(content_range) = content_range {
+                res.insert_header("content-range", content_range);
+            }
+            Ok(res)
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let hits = move || hits.swap(0, Ordering::SeqCst);
+    let url = "http://example.org/file";
+
+    let cached = client.clone().with(Cache::new());
+    assert_eq!(cached.get(url).recv_string().await?, "0123456789");
+    for _ in 0..2 {
+        let mut res = cached.get(url).header("Range", "bytes=0-3").await?;
+        assert_eq!(res.status(), 206);
+        assert_eq!(res.ext::<CacheStatus>(), None);
+        assert_eq!(res.body_string().await?, "0123");
+    }
+    assert_eq!(hits(), 3);
+
+    let partial = client.with(Cache::new().partial_responses(true));
+    for status in &[CacheStatus::Miss, CacheStatus::Fresh] {
+        let mut res = partial.get(url).header("Range", "bytes=0-3").await?;
+        assert_eq!(res.status(), 206);
+        assert_eq!(res.ext::<CacheStatus>(), Some(status));
+        assert_eq!(res.body_string().await?, "0123");
+    }
+    let res = partial.get(url).header("Range", "bytes=4-5").recv_string();
+    assert_eq!(res.await?, "45");
+    let mut res = partial.get(url).await?;
+    assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Miss));
+    assert_eq!(res.body_string().await?, "0123456789");
+    assert_eq!(hits(), 3);
+    Ok(())
+}
+
+#[async_std::test]
+async fn cache_middleware_keys_by_headers() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use surf::middleware::cache::{
+        Cache, CacheStore, CachedResponse, HeaderKey, MemoryStore, VaryStrategy,
+    };
+    use surf::CacheStatus;
+
+    /// A store which logs what it is given, to check which values it holds.
+    #[derive(Debug, Default)]
+    struct Spy(MemoryStore, Arc<Mutex<Vec<String>>>);
+    #[surf::utils::async_trait]
+    impl CacheStore for Spy {
+        async fn get(&self, key: &str) -> surf::Result<Option<CachedResponse>> {
+            self.0.get(key).await
+        }
+        async fn put(&self, key: &str, response: CachedResponse) -> surf::Result<()> {
+            let stored = format!("{} {:?}", key, response);
+            self.1.lock().unwrap().push(stored);
+            self.0.put(key, response).await
+        }
+        async fn remove(&self, key: &str) -> surf::Result<()> {
+            self.0.remove(key).await
+        }
+    }
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/me")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            let account = req.header("x-account-id").unwrap().as_str().to_string();
+            Ok(tide::Response::builder(200)
+                .header("cache-control", "max-age=60")
+                .header("vary", "Authorization")
+                .body(account)
+                .build())
+        });
+    let hits = move || hits.swap(0, Ordering::SeqCst);
+    let client = |cache: Cache| -> Result<Client, http_types::Error> {
+        let client: Client = Config::new().set_http_client(server.clone()).try_into()?;
+        Ok(client.with(cache))
+    };
+    let get = |client: &Client, account: &str, token: &str| {
+        client
+            .get("http://example.org/me")
+            .header("x-account-id", account)
+            .header("authorization", format!("Bearer {}", token))
+    };
+
+    let spy = Spy::default();
+    let stored = spy.1.clone();
+    let key = HeaderKey::new()
+        .header("X-Account-Id")
+        .header("Authorization");
+    let matching = client(Cache::with_store(spy).key_fn(key))?;
+    for (account, token) in &[("1", "secret-a"), ("1", "secret-a"), ("2", "secret-b")] {
+        let body = get(&matching, account, token).recv_string().await?;
+        assert_eq!(body, *account);
+    }
+    assert_eq!(hits(), 2);
+    let stored = stored.lock().unwrap().join("\n");
+    assert_eq!(stored.lines().count(), 2);
+    assert!(stored.contains("x-account-id="));
+    assert!(!stored.contains("secret"), "{}", stored);
+
+    // With the account in the key, responses are shared by its credentials.
+    let ignoring = client(
+        Cache::new()
+            .key_fn(HeaderKey::new().header("X-Account-Id"))
+            .vary(VaryStrategy::Ignore),
+    )?;
+    get(&ignoring, "1", "secret-a").await?;
+    let res = get(&ignoring, "1", "secret-c").await?;
+    assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Fresh));
+    let res = get(&ignoring, "2", "secret-a").await?;
+    assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Miss));
+    assert_eq!(hits(), 2);
+
+    let key_fn = |req: &Request| format!("{} by closure", req.url());
+    let refusing = client(Cache::new().key_fn(key_fn).vary(VaryStrategy::NoStore))?;
+    for _ in 0..2 {
+        let res = get(&refusing, "1", "secret-a").await?;
+        assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Miss));
+    }
+    assert_eq!(hits(), 2);
+    Ok(())
+}
+
+#[async_std::test]
+async fn cache_middleware_remembers_absent_resources() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use surf::middleware::cache::Cache;
+    use surf::{CacheStatus, ErrorKind, ManualClock};
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/missing")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            req.state().fetch_add(1, Ordering::SeqCst);
+            Ok(tide::Response::new(404))
+        });
+    let clock = ManualClock::new();
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_clock(clock.clone())
+        .try_into()?;
+    let cache = Cache::new()
+        .negative_ttl(Duration::from_secs(60))
+        .dns_failure_ttl(Duration::from_secs(10));
+    let client = client.with(cache);
+
+    for _ in 0..3 {
+        let res = client.get("http://example.org/missing").await?;
+        assert_eq!(res.status(), 404);
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+    clock.advance(Duration::from_secs(61));
+    let res = client.get("http://example.org/missing").await?;
+    assert_eq!(res.ext::<CacheStatus>(), Some(&CacheStatus::Miss));
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    struct UnknownHost(Arc<AtomicUsize>);
+    #[surf::utils::async_trait]
+    impl surf::middleware::Middleware for UnknownHost {
+        async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> surf::Result {
+            if req.url().host_str() != Some("unknown.example") {
+                return next.run(req, client).await;
+            }
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let err = std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "failed to lookup address information: Name or service not known",
+            );
+            Err(http_types::Error::new(502, err))
+        }
+    }
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let client = client.with(UnknownHost(lookups.clone()));
+    client.get("http://unknown.example/").await.unwrap_err();
+    let err = client.post("http://unknown.example/").await.unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Middleware);
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    clock.advance(Duration::from_secs(11));
+    client.get("http://unknown.example/").await.unwrap_err();
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[cfg(feature = "disk-cache")]
+#[async_std::test]
+async fn disk_store_persists_responses() -> Result<(), http_types::Error> {
+    use surf::http::StatusCode;
+    use surf::middleware::cache::{CacheStore, CachedResponse, DiskStore};
+
+    let dir = std::env::temp_dir().join(format!("surf-disk-store-{}", std::process::id()));
+    let store = DiskStore::new(&dir);
+    let response = CachedResponse {
+        status: StatusCode::Ok,
+        headers: vec![("etag".into(), "\"v1\"".into())],
+        body: b"line one\nline two".to_vec(),
+        vary: vec![("accept-language".into(), None)],
+        stored_at: std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000),
+    };
+    assert!(store.get("http://example.org/").await?.is_none());
+    store.put("http://example.org/", response.clone()).await?;
+
+    let stored = DiskStore::new(&dir)
+        .get("http://example.org/")
+        .await?
+        .unwrap();
+    assert_eq!(stored.status, response.status);
+    assert_eq!(stored.headers, response.headers);
+    assert_eq!(stored.body, response.body);
+    assert_eq!(stored.vary, response.vary);
+    assert_eq!(stored.stored_at, response.stored_at);
+
+    store.remove("http://example.org/").await?;
+    assert!(store.get("http://example.org/").await?.is_none());
+    std::fs::remove_dir_all(&dir).ok();
+    Ok(())
+}
+
+#[async_std::test]
+async fn response_exposes_transfer_metadata() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server.at("/sized").get(|_| async {
+        Ok(tide::Response::builder(200)
+            .header("content-encoding", "gzip, BR")
+            .header("accept-ranges", "bytes")
+            .header("content-length", "7")
+            .body("payload")
+            .build())
+    });
+    server.at("/chunked").get(|_| async {
+        Ok(tide::Response::builder(200)
+            .header("transfer-encoding", "gzip, chunked")
+            .header("accept-ranges", "none")
+            .build())
+    });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let res = client.get("http://example.org/sized").await?;
+    assert_eq!(res.content_encoding(), ["gzip", "br"]);
+    assert!(res.transfer_encoding().is_empty());
+    assert!(!res.is_chunked());
+    assert!(res.accepts_ranges());
+    assert_eq!(res.content_length(), Some(7));
+
+    let res = client.get("http://example.org/chunked").await?;
+    assert!(res.content_encoding().is_empty());
+    assert_eq!(res.transfer_encoding(), ["gzip", "chunked"]);
+    assert!(res.is_chunked());
+    assert!(!res.accepts_ranges());
+    assert_eq!(res.content_length(), None);
+    Ok(())
+}
+
+#[cfg(feature = "gzip")]
+#[async_std::test]
+async fn compress_streams_request_bodies() -> Result<(), http_types::Error> {
+    use miniz_oxide::inflate::{decompress_to_vec, decompress_to_vec_zlib};
+    use surf::http::content::Encoding;
+
+    let mut server = tide::new();
+    server
+        .at("/upload")
+        .post(|mut req: tide::Request<()>| async move {
+            let encoding = req.header("content-encoding").unwrap().as_str().to_string();
+            let body = req.body_bytes().await?;
+            let decoded = match encoding.as_str() {
+                "gzip" => {
+                    assert_eq!(&body[..2], &[0x1f, 0x8b]);
+                    let size = u32::from_le_bytes(body[body.len() - 4..].try_into().unwrap());
+                    let decoded = decompress_to_vec(&body[10..body.len() - 8]).unwrap();
+                    assert_eq!(size as usize, decoded.len());
+                    decoded
+                }
+                _ => decompress_to_vec_zlib(&body).unwrap(),
+            };
+            assert!(body.len() < decoded.len());
+            Ok(String::from_utf8(decoded)?)
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let text = "all work and no play makes jack a dull boy\n".repeat(2000);
+
+    for encoding in &[Encoding::Gzip, Encoding::Deflate] {
+        let body = client
+            .post("http://example.org/upload")
+            .body_string(text.clone())
+            .compress(*encoding)?
+            .recv_string()
+            .await?;
+        assert_eq!(body, text);
+    }
+    assert!(client
+        .post("http://example.org/upload")
+        .compress(Encoding::Brotli)
+        .is_err());
+    Ok(())
+}
+
+#[cfg(feature = "curl-client")]
+#[async_std::test]
+async fn backend_options_apply_to_curl() -> Result<(), http_types::Error> {
+    use std::time::Duration;
+    use surf::{BackendOptions, IpVersion};
+
+    let m = mock("GET", "/local")
+        .with_status(200)
+        .with_body("ok")
+        .create();
+    let options = BackendOptions::new()
+        .set_ip_version(IpVersion::V4)
+        .set_local_address([127, 0, 0, 1].into())
+        .set_tcp_keepalive(Duration::from_secs(30));
+    let client: Client = Config::new()
+        .set_base_url(surf::Url::parse(&mockito::server_url())?)
+        .set_backend_options(options)
+        .try_into()?;
+    let body = client.get("local").recv_string().await?;
+    m.assert();
+    assert_eq!(body, "ok");
+    Ok(())
+}
+
+#[async_std::test]
+async fn response_exposes_url_and_history() -> Result<(), http_types::Error> {
+    use surf::middleware::Redirect;
+
+    let mut server = tide::new();
+    server
+        .at("/start")
+        .get(|_| async { Ok(tide::Redirect::new("/middle")) });
+    server
+        .at("/middle")
+        .get(|_| async { Ok(tide::Redirect::temporary("/end")) });
+    server.at("/end").get(|_| async { Ok("done") });
+    let base: Client = Config::new().set_http_client(server).try_into()?;
+
+    let res = base.get("http://example.org/end").await?;
+    assert_eq!(res.url().unwrap().as_str(), "http://example.org/end");
+    assert!(res.history().is_empty());
+
+    let client = base.with(Redirect::default());
+    let res = client.get("http://example.org/start").await?;
+    assert_eq!(res.url().unwrap().as_str(), "http://example.org/end");
+    let history: Vec<_> = res.history().iter().map(|url| url.as_str()).collect();
+    assert_eq!(
+        history,
+        ["http://example.org/start", "http://example.org/middle"]
+    );
+
+    let res = surf::Response::from(http_types::Response::new(200));
+    assert_eq!(res.url(), None);
+    Ok(())
+}
+
+#[async_std::test]
+async fn responses_report_connection_reuse() -> Result<(), http_types::Error> {
+    use surf::ConnectionInfo;
+
+    let mut server = tide::new();
+    server.at("/").get(|_| async { Ok("hello") });
+    server.at("/close").get(|_| async {
+        let mut res = tide::Response::new(200);
+        res.insert_header("Connection", "close");
+        res.set_body("bye");
+        Ok(res)
+    });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let conn = |res: &surf::Response| *res.ext::<ConnectionInfo>().unwrap();
+
+    let mut first = client.get("http://example.org/").await?;
+    assert!(!conn(&first).reused());
+    assert_eq!(conn(&first).generation(), 1);
+    // The first body hasn't been read, so its connection is still busy.
+    let mut second = client.get("http://example.org/").await?;
+    assert!(!conn(&second).reused());
+    assert_eq!(conn(&second).generation(), 2);
+    first.body_string().await?;
+    second.body_string().await?;
+
+    let mut res = client.get("http://example.org/").await?;
+    assert!(res.body_string().await.is_ok());
+    assert!(conn(&res).reused());
+    assert_eq!(conn(&res).generation(), 2);
+    assert_eq!(conn(&res).requests(), 2);
+
+    let res = client.get("http://example.com/").await?;
+    assert!(!conn(&res).reused());
+    assert_eq!(conn(&res).generation(), 3);
+
+    let mut res = client.get("http://example.org/close").await?;
+    assert!(conn(&res).reused());
+    res.body_string().await?;
+    let res = client.get("http://example.org/").await?;
+    assert!(conn(&res).reused());
+    assert_eq!(conn(&res).generation(), 1);
+    let res = client.get("http://example.org/").await?;
+    assert!(!conn(&res).reused());
+    assert_eq!(conn(&res).generation(), 4);
+    Ok(())
+}
+
+#[cfg(feature = "gzip")]
+#[async_std::test]
+async fn decompress_decodes_compiled_in_codings() -> Result<(), http_types::Error> {
+    use miniz_oxide::deflate::compress_to_vec_zlib;
+    use surf::http::content::Encoding;
+    use surf::middleware::Decompress;
+
+    let text = "all work and no play makes jack a dull boy\n".repeat(2000);
+    let gzip = surf::post("http://example.org/")
+        .body_string(text.clone())
+        .compress(Encoding::Gzip)?
+        .build()
+        .take_body()
+        .into_bytes()
+        .await?;
+    // The same stream with the optional file name field.
+    let mut named = gzip[..10].to_vec();
+    named[3] |= 1 << 3;
+    named.extend_from_slice(b"jack.txt\0");
+    named.extend_from_slice(&gzip[10..]);
+    let deflate = compress_to_vec_zlib(text.as_bytes(), 6);
+
+    let encoded = |coding: &'static str, body: Vec<u8>| {
+        move |_| {
+            let body = body.clone();
+            async move {
+                let mut res = tide::Response::new(200);
+                res.insert_header("Content-Encoding", coding);
+                res.set_body(body);
+                Ok(res)
+            }
+        }
+    };
+    let mut server = tide::new();
+    server.at("/gzip").get(encoded("gzip", gzip.clone()));
+    server.at("/named").get(encoded("x-gzip", named));
+    server.at("/deflate").get(encoded("deflate", deflate));
+    server
+        .at("/decoded")
+        .get(encoded("gzip", text.clone().into_bytes()));
+    server
+        .at("/truncated")
+        .get(encoded("gzip", gzip[..gzip.len() / 2].to_vec()));
+    server
+        .at("/negotiate")
+        .get(|req: tide::Request<()>| async move {
+            Ok(req.header("accept-encoding").unwrap().as_str().to_string())
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client.with(Decompress::new());
+
+    for path in &["gzip", "named", "deflate", "decoded"] {
+        let mut res = client.get(format!("http://example.org/{}", path)).await?;
+        assert!(res.header("content-encoding").is_none());
+        assert_eq!(res.body_string().await?, text);
+    }
+    let mut res = client.get("http://example.org/truncated").await?;
+    assert!(res.body_string().await.is_err());
+
+    let accepted = client
+        .get("http://example.org/negotiate")
+        .recv_string()
+        .await?;
+    assert_eq!(accepted, "gzip, deflate");
+    let accepted = client
+        .get("http://example.org/negotiate")
+        .header("Accept-Encoding", "identity")
+        .recv_string()
+        .await?;
+    assert_eq!(accepted, "identity");
+    Ok(())
+}
+
+#[async_std::test]
+async fn auth_helpers_set_authorization() -> Result<(), http_types::Error> {
+    use surf::Auth;
+
+    let mut server = tide::new();
+    server.at("/").get(|req: tide::Request<()>| async move {
+        let auth = req.header("authorization").map(|h| h.as_str().to_string());
+        Ok(auth.unwrap_or_default())
+    });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_base_url(surf::Url::parse("http://example.org/")?)
+        .set_default_auth(Auth::basic("Aladdin", "open sesame"))
+        .try_into()?;
+
+    let auth = client.get("/").recv_string().await?;
+    assert_eq!(auth, "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==");
+    let auth = client.get("/").bearer_auth("abc.def").recv_string().await?;
+    assert_eq!(auth, "Bearer abc.def");
+    let auth = client.get("/").basic_auth("user", "").recv_string().await?;
+    assert_eq!(auth, "Basic dXNlcjo=");
+
+    let debug = format!("{:?}", Auth::basic("Aladdin", "open sesame"));
+    assert!(debug.contains("Aladdin") && !debug.contains("sesame"));
+    assert!(!format!("{:?}", Auth::bearer("abc.def")).contains("abc"));
+    Ok(())
+}
+
+#[async_std::test]
+async fn retries_reuse_or_regenerate_nonces() -> Result<(), http_types::Error> {
+    use std::sync::{Arc, Mutex};
+    use surf::middleware::{Middleware, Next, Retry};
+    use surf::{Nonce, NoncePolicy, UuidNonce};
+
+    #[derive(Debug)]
+    struct Signer(UuidNonce);
+
+    #[surf::utils::async_trait]
+    impl Middleware for Signer {
+        async fn handle(
+            &self,
+            mut req: surf::Request,
+            client: Client,
+            next: Next<'_>,
+        ) -> surf::Result<surf::Response> {
+            let nonce = req.nonce(&self.0);
+            req.insert_header("X-Nonce", nonce);
+            next.run(req, client).await
+        }
+    }
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut server = tide::with_state(seen.clone());
+    server
+        .at("/")
+        .put(|req: tide::Request<Arc<Mutex<Vec<String>>>>| async move {
+            let mut seen = req.state().lock().unwrap();
+            seen.push(req.header("X-Nonce").unwrap().as_str().to_string());
+            Ok(tide::Response::new(if seen.len() < 3 { 503 } else { 200 }))
+        });
+    let base: Client = Config::new().set_http_client(server).try_into()?;
+
+    for policy in &[NoncePolicy::Regenerate, NoncePolicy::Reuse] {
+        let client = base
+            .clone()
+            .with(Retry::new(2))
+            .with(Signer(UuidNonce::new().policy(*policy)));
+        let res = client.put("http://example.org/").await?;
+        assert_eq!(res.status(), 200);
+        let mut seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 3);
+        let distinct = seen.iter().collect::<std::collections::HashSet<_>>().len();
+        match policy {
+            NoncePolicy::Regenerate => assert_eq!(distinct, 3),
+            NoncePolicy::Reuse => assert_eq!(distinct, 1),
+        }
+        seen.clear();
+    }
+
+    let mut req = surf::put("http://example.org/").build();
+    let nonce = req.nonce(&UuidNonce::new());
+    assert_eq!(req.ext::<Nonce>().unwrap().as_str(), nonce);
+    Ok(())
+}
+
+#[cfg(feature = "curl-client")]
+#[async_std::test]
+async fn non_standard_statuses_map_to_their_class() -> Result<(), http_types::Error> {
+    let m = mock("GET", "/origin-error")
+        .with_status(520)
+        .with_body("unknown error")
+        .create();
+    let mut res = surf::get(format!("{}/origin-error", mockito::server_url())).await?;
+    m.assert();
+    assert_eq!(res.status(), 500);
+    assert_eq!(res.status_raw(), 520);
+    assert!(!res.is_success());
+    assert_eq!(res.body_string().await?, "unknown error");
+    Ok(())
+}
+
+#[async_std::test]
+async fn backend_panics_become_errors() -> Result<(), http_types::Error> {
+    #[derive(Debug)]
+    struct Panicking;
+
+    #[surf::utils::async_trait]
+    impl surf::HttpClient for Panicking {
+        async fn send(&self, _req: surf::http::Request) -> surf::Result<surf::http::Response> {
+            let _res = surf::http::Response::new(520);
+            unreachable!()
+        }
+    }
+
+    let client: Client = Config::new().set_http_client(Panicking).try_into()?;
+    let err = client.get("http://example.org/").await.unwrap_err();
+    assert_eq!(err.status(), 502);
+    assert!(err.to_string().contains("StatusCode"));
+
+    let res = surf::Response::from(http_types::Response::new(404));
+    assert_eq!(res.status_raw(), 404);
+    Ok(())
+}
+
+#[async_std::test]
+async fn backend_connection_errors_are_classified() -> Result<(), http_types::Error> {
+    use surf::{ConnectionError, ConnectionErrorKind, ErrorKind};
+
+    #[derive(Debug)]
+    struct Failing;
+
+    #[surf::utils::async_trait]
+    impl surf::HttpClient for Failing {
+        async fn send(&self, req: surf::http::Request) -> surf::Result<surf::http::Response> {
+            Err(match req.url().path() {
+                "/refused" => std::io::Error::from(std::io::ErrorKind::ConnectionRefused).into(),
+                // The h1 backend only keeps the message of errors from its connection pool.
+                "/pooled" => http_types::Error::from_str(
+                    400,
+                    "Error occurred while creating a new object: Connection reset by peer",
+                ),
+                _ => http_types::Error::from_str(500, "something else"),
+            })
+        }
+    }
+
+    let client: Client = Config::new().set_http_client(Failing).try_into()?;
+    let kind = |err: &http_types::Error| err.downcast_ref::<ConnectionError>().map(|e| e.kind());
+
+    let err = client.get("http://example.org/refused").await.unwrap_err();
+    assert_eq!(kind(&err), Some(ConnectionErrorKind::Refused));
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Connect);
+    assert_eq!(err.status(), 502);
+    let err_ref: &(dyn std::error::Error + 'static) = err.as_ref();
+    assert!(err_ref.source().unwrap().is::<std::io::Error>());
+
+    let err = client.get("http://example.org/pooled").await.unwrap_err();
+    assert_eq!(kind(&err), Some(ConnectionErrorKind::Reset));
+    assert!(err.to_string().starts_with("the connection was reset: "));
+
+    let err = client.get("http://example.org/other").await.unwrap_err();
+    assert_eq!(kind(&err), None);
+    assert_eq!(err.status(), 500);
+    Ok(())
+}
+
+#[async_std::test]
+async fn validators_reject_requests_before_sending() -> Result<(), http_types::Error> {
+    #[derive(Debug)]
+    struct TooLarge;
+
+    impl std::fmt::Display for TooLarge {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "request body too large")
+        }
+    }
+
+    impl std::error::Error for TooLarge {}
+
+    let mut server = tide::new();
+    server.at("/").post(|_| async { Ok("ok") });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .add_validator(|req| match req.header("X-Api-Key") {
+            Some(_) => Ok(()),
+            None => Err(http_types::Error::from_str(
+                surf::StatusCode::Unauthorized,
+                "missing api key",
+            )),
+        })
+        .add_validator(|req| match req.len() {
+            Some(len) if len > 4 => Err(http_types::Error::new(
+                surf::StatusCode::PayloadTooLarge,
+                TooLarge,
+            )),
+            _ => Ok(()),
+        })
+        .try_into()?;
+
+    let err = client.post("http://example.org/").await.unwrap_err();
+    assert_eq!(err.status(), surf::StatusCode::Unauthorized);
+
+    let err = client
+        .post("http://example.org/")
+        .header("X-Api-Key", "secret")
+        .body("too large")
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), surf::StatusCode::PayloadTooLarge);
+    assert!(err.downcast_ref::<TooLarge>().is_some());
+
+    let body = client
+        .post("http://example.org/")
+        .header("X-Api-Key", "secret")
+        .body("ok")
+        .recv_string()
+        .await?;
+    assert_eq!(body, "ok");
+    Ok(())
+}
+
+#[cfg(feature = "middleware-tracing")]
+#[async_std::test]
+async fn tracing_middleware_propagates_traces() -> Result<(), http_types::Error> {
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use surf::middleware::{TraceContext, Tracing};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// Records the fields of every span.
+    #[derive(Clone, Default)]
+    struct Fields(Arc<Mutex<Vec<(String, String)>>>);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            let value = format!("{:?}", value);
+            self.0
+                .lock()
+                .unwrap()
+                .push((field.name().to_string(), value));
+        }
+    }
+
+    impl Subscriber for Fields {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            span.record(&mut self.clone());
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, values: &Record<'_>) {
+            values.record(&mut self.clone());
+        }
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let mut server = tide::new();
+    server.at("/").get(|req: tide::Request<()>| async move {
+        let header = |name| req.header(name).map(|values| values.as_str().to_string());
+        Ok(format!(
+            "{} {}",
+            header("traceparent").unwrap(),
+            header("tracestate").unwrap_or_default()
+        ))
+    });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_rng(surf::SeededRng::new(7))
+        .try_into()?;
+    let client = client.with(Tracing::new());
+
+    let fields = Fields::default();
+    let _guard = tracing::subscriber::set_default(fields.clone());
+
+    // A new trace is started.
+    let mut res = client.get("http://example.org/").await?;
+    let body = res.body_string().await?;
+    let sent = res.ext::<TraceContext>().unwrap().clone();
+    assert_eq!(body.trim_end(), sent.traceparent());
+    assert!(TraceContext::parse(&sent.traceparent(), None).is_some());
+    assert!(sent.sampled());
+
+    // A trace is continued from the headers of the request, with a new span id.
+    let parent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00";
+    let mut res = client
+        .get("http://example.org/")
+        .header("traceparent", parent)
+        .header("tracestate", "congo=t61rcWkgMzE")
+        .await?;
+    let body = res.body_string().await?;
+    let sent = res.ext::<TraceContext>().unwrap();
+    assert_eq!(sent.trace_id(), 0x4bf92f3577b34da6a3ce929d0e0e4736);
+    assert_ne!(sent.span_id(), 0x00f067aa0ba902b7);
+    assert!(!sent.sampled());
+    assert_eq!(body, format!("{} congo=t61rcWkgMzE", sent.traceparent()));
+
+    // Or from a context set on the request.
+    let mut req = client
+        .get("http://example.org/")
+        .log_context("order_id", 42)
+        .log_context("note", "two words")
+        .build();
+    req.set_ext(TraceContext::new(42, 7, true).with_state("a=b"));
+    let res = client.send(req).await?;
+    let sent = res.ext::<TraceContext>().unwrap();
+    assert_eq!(sent.trace_id(), 42);
+    assert_eq!(sent.state(), Some("a=b"));
+
+    let fields = fields.0.lock().unwrap();
+    let values = |name: &str| -> Vec<String> {
+        fields
+            .iter()
+            .filter(|(field, _)| field == name)
+            .map(|(_, value)| value.clone())
+            .collect()
+    };
+    assert_eq!(values("method"), vec!["GET"; 3]);
+    assert_eq!(values("url"), vec!["http://example.org/"; 3]);
+    assert_eq!(values("status"), vec!["200"; 3]);
+    assert_eq!(values("duration").len(), 3);
+    assert_eq!(values("trace_id")[1], "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(values("context"), vec![r#"order_id=42 note="two words""#]);
+    Ok(())
+}
+
+#[async_std::test]
+async fn traffic_accounting_counts_bytes_per_host() -> Result<(), http_types::Error> {
+    use surf::middleware::{TrafficAccounting, TrafficCounter};
+
+    let mut server = tide::new();
+    server
+        .at("/echo")
+        .post(|mut req: tide::Request<()>| async move { req.body_string().await });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let traffic = TrafficAccounting::new();
+    let client = client.with(traffic.clone());
+
+    let mut res = client.post("http://example.org/echo").body("hello").await?;
+    let counter = res.ext::<TrafficCounter>().unwrap().clone();
+    assert_eq!(counter.get().response_body_bytes, 0);
+    assert_eq!(res.body_string().await?, "hello");
+    let first = counter.get();
+    assert_eq!(first.request_body_bytes, 5);
+    assert_eq!(first.response_body_bytes, 5);
+    assert!(first.request_header_bytes > "POST /echo HTTP/1.1\r\n\r\n".len() as u64);
+    assert!(first.response_header_bytes > "HTTP/1.1 200 OK\r\n\r\n".len() as u64);
+
+    client
+        .post("http://example.org/echo")
+        .body("hello world")
+        .recv_string()
+        .await?;
+    let total = traffic.host("example.org");
+    assert_eq!(total.request_body_bytes, 16);
+    assert_eq!(total.response_body_bytes, 16);
+    assert!(total.sent() > first.sent());
+    assert_eq!(traffic.totals().len(), 1);
+    assert_eq!(traffic.host("example.com"), Default::default());
+
+    traffic.reset();
+    assert!(traffic.totals().is_empty());
+    Ok(())
+}
+
+#[async_std::test]
+async fn map_body_keeps_content_headers_consistent() -> Result<(), http_types::Error> {
+    use futures_util::io::AsyncReadExt;
+
+    #[derive(Debug)]
+    struct Truncate;
+
+    #[surf::utils::async_trait]
+    impl surf::middleware::Middleware for Truncate {
+        async fn handle(
+            &self,
+            req: Request,
+            client: Client,
+            next: Next<'_>,
+        ) -> surf::Result<Response> {
+            let mut res = next.run(req, client).await?;
+            res.map_body(|body| {
+                let reader = async_std::io::BufReader::new(body.into_reader().take(5));
+                Body::from_reader(reader, Some(5))
+            });
+            Ok(res)
+        }
+    }
+
+    let mut server = tide::new();
+    server.at("/").get(|_| async {
+        let mut res = tide::Response::new(200);
+        res.set_body("hello world");
+        res.set_content_type("text/plain; charset=utf-8");
+        res.insert_header("Content-Length", "11");
+        Ok(res)
+    });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client.with(Truncate);
+
+    let mut res = client.get("http://example.org/").await?;
+    assert_eq!(res["Content-Length"], "5");
+    assert_eq!(res["Content-Type"], "text/plain;charset=utf-8");
+    assert_eq!(res.body_string().await?, "hello");
+    Ok(())
+}
+
+#[async_std::test]
+async fn body_with_progress_reports_bytes_sent() -> Result<(), http_types::Error> {
+    use std::sync::{Arc, Mutex};
+
+    let mut server = tide::new();
+    server
+        .at("/upload")
+        .put(|mut req: tide::Request<()>| async move {
+            Ok(req.body_bytes().await?.len().to_string())
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let report = {
+        let progress = progress.clone();
+        move |sent, total| progress.lock().unwrap().push((sent, total))
+    };
+    let received = client
+        .put("http://example.org/upload")
+        .body_with_progress(vec![7; 100_000], report)
+        .recv_string()
+        .await?;
+    assert_eq!(received, "100000");
+
+    let progress = progress.lock().unwrap();
+    assert!(progress.len() > 1);
+    assert!(progress.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert_eq!(progress.last(), Some(&(100_000, Some(100_000))));
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+#[async_std::test]
+async fn encryption_seals_bodies_per_host() -> Result<(), http_types::Error> {
+    use surf::middleware::encryption::{Encryption, EnvelopeKey, PayloadCipher};
+
+    let key = [3; 32];
+    let mut server = tide::new();
+    server
+        .at("/echo")
+        .post(move |mut req: tide::Request<()>| async move {
+            let cipher = EnvelopeKey::new(key);
+            let content_type = req.header("Content-Type").unwrap().as_str().to_string();
+            let original = req
+                .header("Envelope-Content-Type")
+                .unwrap()
+                .as_str()
+                .to_string();
+            let sealed = req.body_bytes().await?;
+            assert_eq!(content_type, cipher.media_type());
+            assert!(!sealed.windows(6).any(|window| window == b"secret"));
+            let opened = cipher.open(&sealed)?;
+            let reply = format!("{} ({})", String::from_utf8(opened)?, original);
+
+            let mut res = tide::Response::new(200);
+            res.set_body(cipher.seal(reply.as_bytes())?);
+            res.insert_header("Content-Type", cipher.media_type());
+            res.insert_header("Envelope-Content-Type", "text/plain");
+            Ok(res)
+        });
+    server.at("/tampered").post(move |_| async move {
+        let cipher = EnvelopeKey::new([4; 32]);
+        let mut res = tide::Response::new(200);
+        res.set_body(cipher.seal(b"forged")?);
+        res.insert_header("Content-Type", cipher.media_type());
+        Ok(res)
+    });
+    server
+        .at("/plain")
+        .post(|mut req: tide::Request<()>| async move { req.body_string().await });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client.with(Encryption::new().host("secure.example.org", EnvelopeKey::new(key)));
+
+    let mut res = client
+        .post("http://secure.example.org/echo")
+        .body_string("secret".to_string())
+        .await?;
+    assert_eq!(res["Content-Type"], "text/plain");
+    assert_eq!(
+        res.body_string().await?,
+        "secret (text/plain;charset=utf-8)"
+    );
+
+    let err = client
+        .post("http://secure.example.org/tampered")
+        .body_string("secret".to_string())
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), surf::StatusCode::BadGateway);
+
+    let plain = client
+        .post("http://example.org/plain")
+        .body_string("secret".to_string())
+        .recv_string()
+        .await?;
+    assert_eq!(plain, "secret");
+    Ok(())
+}
+
+#[async_std::test]
+async fn rate_limit_queues_or_fails_requests() -> Result<(), http_types::Error> {
+    use futures_util::future::join;
+    use std::time::Duration;
+    use surf::middleware::{Quota, RateLimit, RateLimited};
+    use surf::ManualClock;
+
+    let mut server = tide::new();
+    server.at("/").get(|_| async { Ok("ok") });
+    server.at("/exhausted").get(|_| async {
+        let mut res = tide::Response::new(200);
+        res.insert_header("X-RateLimit-Remaining", "0");
+        res.insert_header("X-RateLimit-Reset", "30");
+        Ok(res)
+    });
+    let clock = ManualClock::new();
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_clock(clock.clone())
+        .try_into()?;
+
+    let failing = client.clone().with(
+        RateLimit::new()
+            .per_host(Quota::new(2, Duration::from_secs(1)))
+            .max_wait(Duration::from_secs(0)),
+    );
+    failing.get("http://a.example.org/").await?;
+    failing.get("http://a.example.org/").await?;
+    let err = failing.get("http://a.example.org/").await.unwrap_err();
+    assert_eq!(err.status(), surf::StatusCode::TooManyRequests);
+    let limited = err.downcast_ref::<RateLimited>().unwrap();
+    assert_eq!(limited.wait(), Duration::from_millis(500));
+    failing.get("http://b.example.org/").await?;
+    clock.advance(Duration::from_millis(500));
+    failing.get("http://a.example.org/").await?;
+
+    let queuing = client
+        .clone()
+        .with(RateLimit::new().global(Quota::new(1, Duration::from_secs(1))));
+    queuing.get("http://a.example.org/").await?;
+    let advance = |duration| {
+        let clock = clock.clone();
+        async move {
+            async_std::task::yield_now().await;
+            clock.advance(duration);
+        }
+    };
+    let start = clock.elapsed();
+    let (res, ()) = join(
+        queuing.get("http://b.example.org/"),
+        advance(Duration::from_secs(1)),
+    )
+    .await;
+    res?;
+    assert_eq!(clock.elapsed() - start, Duration::from_secs(1));
+
+    let honoring = client.with(
+        RateLimit::new()
+            .honor_headers(true)
+            .max_wait(Duration::from_secs(10)),
+    );
+    honoring.get("http://a.example.org/exhausted").await?;
+    let err = honoring.get("http://a.example.org/").await.unwrap_err();
+    let limited = err.downcast_ref::<RateLimited>().unwrap();
+    assert_eq!(limited.wait(), Duration::from_secs(30));
+    honoring.get("http://b.example.org/").await?;
+    Ok(())
+}
+
+#[async_std::test]
+async fn list_reads_webdav_and_json_indexes() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server
+        .at("/dav/")
+        .method(http_types::Method::PropFind, |req: tide::Request<()>| async move {
+            assert_eq!(req.header("Depth").unwrap(), "1");
+            let mut res = tide::Response::new(207);
+            res.set_content_type("application/xml");
+            res.set_body(
+                r#"<?xml version="1.0"?>
+<D:multistatus xmlns:D="DAV:">
+  <D:response><D:href>/dav/</D:href>
+    <D:propstat><D:prop><D:resourcetype><D:collection/></D:resourcetype></D:prop></D:propstat>
+  </D:response>
+  <D:response><D:href>/dav/notes.txt</D:href>
+    <D:propstat><D:prop><D:resourcetype/><D:getcontentlength>5</D:getcontentlength></D:prop></D:propstat>
+  </D:response>
+</D:multistatus>"#,
+            );
+            Ok(res)
+        });
+    server.at("/json/").get(|_| async {
+        let mut res = tide::Response::new(200);
+        res.set_body(tide::Body::from_json(&serde_json::json!([
+            {"name": "sub dir", "type": "directory", "mtime": "Wed, 21 Oct 2015 07:28:00 GMT"},
+            {"name": "a.tar.gz", "type": "file", "size": 1024},
+        ]))?);
+        Ok(res)
+    });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_base_url(surf::Url::parse("http://example.org")?)
+        .try_into()?;
+
+    let entries = client.list("/dav").await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "notes.txt");
+    assert_eq!(entries[0].size, Some(5));
+    assert!(!entries[0].is_dir);
+
+    let entries = client.list("/json/").await?;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].name, "sub dir");
+    assert!(entries[0].is_dir);
+    assert_eq!(
+        entries[0].url.as_str(),
+        "http://example.org/json/sub%20dir/"
+    );
+    assert!(entries[0].modified.is_some());
+    assert_eq!(entries[1].size, Some(1024));
+
+    let err = client.list("/missing/").await.unwrap_err();
+    assert_eq!(err.status(), 404);
+    Ok(())
+}
+
+#[async_std::test]
+async fn send_all_limits_requests_in_flight() -> Result<(), http_types::Error> {
+    use futures_util::StreamExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct InFlight {
+        current: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    let in_flight = Arc::new(InFlight::default());
+    let mut server = tide::with_state(in_flight.clone());
+    server
+        .at("/items/:id")
+        .get(|req: tide::Request<Arc<InFlight>>| async move {
+            let state = req.state();
+            let current = state.current.fetch_add(1, Ordering::SeqCst) + 1;
+            state.max.fetch_max(current, Ordering::SeqCst);
+            let id: u64 = req.param("id")?.parse()?;
+            // Later items finish first, so that unordered results come back reversed.
+            async_std::task::sleep(Duration::from_millis(40 - id * 10)).await;
+            state.current.fetch_sub(1, Ordering::SeqCst);
+            Ok(id.to_string())
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let requests = || (0..4).map(|id| surf::get(format!("http://example.org/items/{}", id)));
+
+    let mut bodies = Vec::new();
+    let mut responses = client.send_all(requests(), 2);
+    while let Some(res) = responses.next().await {
+        bodies.push(res?.body_string().await?);
+    }
+    assert_eq!(bodies, ["0", "1", "2", "3"]);
+    assert_eq!(in_flight.max.swap(0, Ordering::SeqCst), 2);
+
+    let results: Vec<_> = client.send_all_unordered(requests(), 4).collect().await;
+    let indices: Vec<_> = results.iter().map(|(index, _)| *index).collect();
+    assert_eq!(indices, [3, 2, 1, 0]);
+    assert!(results.iter().all(|(_, res)| res.is_ok()));
+    assert_eq!(in_flight.max.load(Ordering::SeqCst), 4);
+    Ok(())
+}
+
+#[async_std::test]
+async fn retry_records_attempts_on_the_response() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use surf::middleware::retry::{Attempts, Backoff};
+    use surf::middleware::Retry;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/flaky")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            let hit = req.state().fetch_add(1, Ordering::SeqCst);
+            Ok(tide::Response::new(if hit < 2 { 503 } else { 200 }))
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client.with(Retry::new(3).backoff(Backoff::none()));
+
+    let res = client.get("http://example.org/flaky").await?;
+    assert_eq!(res.status(), 200);
+    let attempts = res.ext::<Attempts>().unwrap();
+    assert_eq!(attempts.count(), 3);
+    assert!(attempts.retried());
+    let statuses: Vec<_> = attempts.iter().map(|attempt| attempt.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            Some(surf::StatusCode::ServiceUnavailable),
+            Some(surf::StatusCode::ServiceUnavailable),
+            Some(surf::StatusCode::Ok),
+        ]
+    );
+    assert!(attempts.iter().all(|attempt| attempt.error.is_none()));
+
+    let res = client.get("http://example.org/flaky").await?;
+    assert!(!res.ext::<Attempts>().unwrap().retried());
+    Ok(())
+}
+
+#[async_std::test]
+async fn retry_keeps_the_request_timeout() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use surf::middleware::retry::Backoff;
+    use surf::middleware::Retry;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/flaky")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            if req.state().fetch_add(1, Ordering::SeqCst) == 0 {
+                return Ok(tide::Response::new(503));
+            }
+            futures_util::future::pending::<()>().await;
+            Ok(tide::Response::new(200))
+        });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_timeout(None)
+        .try_into()?;
+    let client = client.with(Retry::new(1).backoff(Backoff::none()));
+
+    let req = client
+        .get("http://example.org/flaky")
+        .timeout(Duration::from_millis(200));
+    let res = async_std::future::timeout(Duration::from_secs(5), req).await?;
+    assert_eq!(res.unwrap_err().status(), 408);
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[async_std::test]
+async fn shutdown_stops_body_reads_and_requests() -> Result<(), http_types::Error> {
+    use futures_util::future::join;
+    use futures_util::stream::{self, StreamExt, TryStreamExt};
+    use surf::{ErrorKind, Shutdown};
+
+    let mut server = tide::new();
+    server.at("/stream").get(|_| async {
+        let chunks = stream::iter(vec![Ok(b"partial".to_vec())]).chain(stream::pending());
+        let mut res = tide::Response::new(200);
+        res.set_body(tide::Body::from_reader(chunks.into_async_read(), None));
+        Ok(res)
+    });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let token = client.shutdown_token();
+    assert!(!token.is_shutdown());
+
+    let mut res = client.get("http://example.org/stream").await?;
+    let shutdown = async {
+        async_std::task::yield_now().await;
+        token.shutdown();
+    };
+    let (read, ()) = join(res.body_bytes(), shutdown).await;
+    let err = read.unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Shutdown);
+
+    let err = client
+        .clone()
+        .get("http://example.org/stream")
+        .await
+        .unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Shutdown);
+    assert!(err.downcast_ref::<Shutdown>().is_some());
+    Ok(())
+}
+
+#[async_std::test]
+async fn signing_middleware_sees_the_final_request() -> Result<(), http_types::Error> {
+    use std::time::SystemTime;
+    use surf::middleware::{Position, Signer, Signing};
+
+    #[derive(Debug)]
+    struct Checksum;
+
+    impl Signer for Checksum {
+        fn sign(&self, req: &mut Request, body: Option<&[u8]>, _: SystemTime) -> surf::Result<()> {
+            let body = body.unwrap();
+            let sum: u64 = body.iter().map(|&byte| u64::from(byte)).sum();
+            let tenant = req.header("X-Tenant").unwrap().as_str().to_string();
+            let signature = format!("{} {} {} {}", req.method(), req.url().path(), tenant, sum);
+            req.insert_header("X-Signature", signature);
+            Ok(())
+        }
+    }
+
+    let mut server = tide::new();
+    server
+        .at("/orders")
+        .post(|mut req: tide::Request<()>| async move {
+            let signature = req.header("X-Signature").unwrap().as_str().to_string();
+            let content_type = req.content_type().unwrap().to_string();
+            let body = req.body_string().await?;
+            Ok(format!("{} | {} | {}", signature, content_type, body))
+        });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .add_header("X-Tenant", "acme")?
+        .try_into()?;
+    let client = client.with_ordered(Signing::new(Checksum), Position::last());
+
+    let echoed = client
+        .post("http://example.org/orders")
+        .body_string("ab".to_string())
+        .recv_string()
+        .await?;
+    assert_eq!(
+        echoed,
+        "POST /orders acme 195 | text/plain;charset=utf-8 | ab"
+    );
+    Ok(())
+}
+
+#[async_std::test]
+async fn body_multipart_maps_parts_by_field_name() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server.at("/upload").post(|_| async {
+        let mut res = tide::Response::new(200);
+        res.set_content_type("multipart/form-data; boundary=\"sep 1\"");
+        res.set_body(
+            "--sep 1\r\n\
+             Content-Disposition: form-data; name=\"status\"\r\n\r\n\
+             stored\r\n\
+             --sep 1\r\n\
+             Content-Disposition: form-data; name=\"thumb\"; filename=\"t.png\"\r\n\
+             Content-Type: image/png\r\n\r\n\
+             \u{1}PNG\r\n\
+             --sep 1--\r\n",
+        );
+        Ok(res)
+    });
+    server.at("/plain").get(|_| async { Ok("not multipart") });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_base_url(surf::Url::parse("http://example.org")?)
+        .try_into()?;
+
+    let form = client.post("/upload").await?.body_multipart().await?;
+    assert_eq!(form.len(), 2);
+    assert_eq!(form.get("status").unwrap().text()?, "stored");
+    let thumb = form.get("thumb").unwrap();
+    assert_eq!(thumb.file_name(), Some("t.png"));
+    assert_eq!(thumb.content_type().unwrap().essence(), "image/png");
+    assert_eq!(thumb.bytes(), b"\x01PNG");
+
+    let err = client
+        .get("/plain")
+        .await?
+        .body_multipart()
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), 415);
+    Ok(())
+}
+
+#[async_std::test]
+async fn dns_fallback_resolves_through_doh() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use surf::middleware::DnsFallback;
+    use surf::DohResolver;
+
+    let queries = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::new();
+    let counter = queries.clone();
+    server.at("/dns-query").get(move |req: tide::Request<()>| {
+        let counter = counter.clone();
+        async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(req.url().host_str(), Some("9.9.9.9"));
+            assert_eq!(req.url().query(), Some("name=flaky.example&type=A"));
+            Ok(serde_json::json!({
+                "Status": 0,
+                "Answer": [
+                    {"name": "flaky.example", "type": 5, "TTL": 60, "data": "alias.example."},
+                    {"name": "alias.example", "type": 1, "TTL": 60, "data": "192.0.2.7"},
+                ],
+            }))
+        }
+    });
+    server
+        .at("/")
+        .post(|mut req: tide::Request<()>| async move {
+            assert_eq!(req.url().host_str(), Some("192.0.2.7"));
+            let host = req.header("Host").unwrap().as_str().to_string();
+            Ok(format!("{} {}", host, req.body_string().await?))
+        });
+
+    struct UnknownHost(Arc<AtomicUsize>);
+    #[surf::utils::async_trait]
+    impl surf::middleware::Middleware for UnknownHost {
+        async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> surf::Result {
+            if req.url().host_str() != Some("flaky.example") {
+                return next.run(req, client).await;
+            }
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let err = std::io::Error::other(
+                "failed to lookup address information: Temporary failure in name resolution",
+            );
+            Err(http_types::Error::new(502, err))
+        }
+    }
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let resolver = DohResolver::new(surf::Url::parse("http://9.9.9.9/dns-query")?);
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client
+        .with(DnsFallback::new(resolver))
+        .with(UnknownHost(lookups.clone()));
+
+    for _ in 0..2 {
+        let body = client
+            .post("http://flaky.example:8080/")
+            .body_string("ping".into())
+            .recv_string()
+            .await?;
+        assert_eq!(body, "flaky.example:8080 ping");
+    }
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    assert_eq!(queries.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[async_std::test]
+async fn dns_fallback_keeps_the_request_timeout() -> Result<(), http_types::Error> {
+    use std::time::Duration;
+    use surf::middleware::DnsFallback;
+    use surf::DohResolver;
+
+    let mut server = tide::new();
+    server.at("/dns-query").get(|_| async {
+        Ok(serde_json::json!({
+            "Status": 0,
+            "Answer": [{"name": "flaky.example", "type": 1, "TTL": 60, "data": "192.0.2.7"}],
+        }))
+    });
+    server.at("/").get(|_| async {
+        futures_util::future::pending::<()>().await;
+        Ok("")
+    });
+
+    struct UnknownHost;
+    #[surf::utils::async_trait]
+    impl surf::middleware::Middleware for UnknownHost {
+        async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> surf::Result {
+            if req.url().host_str() != Some("flaky.example") {
+                return next.run(req, client).await;
+            }
+            let err = std::io::Error::other("failed to lookup address information");
+            Err(http_types::Error::new(502, err))
+        }
+    }
+    let resolver = DohResolver::new(surf::Url::parse("http://9.9.9.9/dns-query")?);
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_timeout(None)
+        .try_into()?;
+    let client = client.with(DnsFallback::new(resolver)).with(UnknownHost);
+
+    let req = client
+        .get("http://flaky.example/")
+        .timeout(Duration::from_millis(200));
+    let res = async_std::future::timeout(Duration::from_secs(5), req).await?;
+    assert_eq!(res.unwrap_err().status(), 408);
+    Ok(())
+}
+
+#[async_std::test]
+async fn with_headers_adds_scoped_headers_to_requests() -> Result<(), http_types::Error> {
+    let mut server = tide::new();
+    server.at("/").get(|req: tide::Request<()>| async move {
+        let header = |name| req.header(name).map(|v| v.as_str().to_string());
+        Ok(format!(
+            "{:?} {:?}",
+            header("x-on-behalf-of"),
+            header("x-trace")
+        ))
+    });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_base_url(surf::Url::parse("http://example.org")?)
+        .try_into()?;
+
+    let bodies = surf::with_headers(vec![("X-On-Behalf-Of", "alice"), ("X-Trace", "1")], async {
+        let outer = client.get("/").recv_string().await?;
+        let inner = surf::with_headers(vec![("X-Trace", "2")], async {
+            client
+                .get("/")
+                .header("X-On-Behalf-Of", "bob")
+                .recv_string()
+                .await
+        })
+        .await?;
+        surf::Result::Ok((outer, inner))
+    })
+    .await?;
+    assert_eq!(bodies.0, r#"Some("alice") Some("1")"#);
+    assert_eq!(bodies.1, r#"Some("bob") Some("2")"#);
+    assert_eq!(client.get("/").recv_string().await?, "None None");
+    Ok(())
+}
+
+#[cfg(all(
+    unix,
+    any(
+        feature = "curl-client",
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    )
+))]
+#[async_std::test]
+async fn unix_socket_carries_requests() -> Result<(), http_types::Error> {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+
+    let path = std::env::temp_dir().join(format!("surf-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let len = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+                .unwrap();
+            let request = String::from_utf8_lossy(&buf[..len]).to_string();
+            requests.push(request.lines().next().unwrap_or_default().to_string());
+        }
+        requests
+    });
+
+    let url = format!("unix://{}:/v1.41/containers/json?all=1", path.display());
+    assert_eq!(surf::get(url).recv_string().await?, "ok");
+    let client: Client = Config::new().set_unix_socket(&path).try_into()?;
+    assert_eq!(
+        client.get("http://localhost/_ping").recv_string().await?,
+        "ok"
+    );
+
+    let requests = server.join().unwrap();
+    std::fs::remove_file(&path)?;
+    assert_eq!(
+        requests,
+        [
+            "GET /v1.41/containers/json?all=1 HTTP/1.1",
+            "GET /_ping HTTP/1.1"
+        ]
+    );
+    Ok(())
+}
+
+#[cfg(feature = "curl-client")]
+#[async_std::test]
+async fn http_version_preference_chooses_the_protocol() -> Result<(), http_types::Error> {
+    use std::io::{Read, Write};
+    use surf::HttpVersionPreference;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://127.0.0.1:{}/", listener.local_addr()?.port());
+    let server = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        for reply in [true, false] {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let len = stream.read(&mut buf).unwrap();
+            received.push(String::from_utf8_lossy(&buf[..len]).to_string());
+            if reply {
+                stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok",
+                    )
+                    .unwrap();
+            }
+        }
+        received
+    });
+
+    let client: Client = Config::new()
+        .set_http_version_preference(HttpVersionPreference::Http1Only)
+        .try_into()?;
+    let res = client.get(&url).await?;
+    assert_eq!(res.version(), Some(http_types::Version::Http1_1));
+
+    // The server only speaks HTTP/1.1, so the HTTP/2 connection preface is all it receives.
+    let client: Client = Config::new()
+        .set_http_version_preference(HttpVersionPreference::Http2)
+        .try_into()?;
+    assert!(client.get(&url).await.is_err());
+
+    let received = server.join().unwrap();
+    assert!(received[0].starts_with("GET / HTTP/1.1\r\n"));
+    assert!(received[1].starts_with("PRI * HTTP/2.0\r\n"));
+    Ok(())
+}
+
+#[async_std::test]
+async fn client_state_is_shared_with_middleware() -> Result<(), http_types::Error> {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Token(String);
+
+    fn authorize(
+        mut req: Request,
+        client: Client,
+        next: Next<'_>,
+    ) -> BoxFuture<'_, Result<Response, http_types::Error>> {
+        Box::pin(async move {
+            if let Some(Token(token)) = client.state().get() {
+                req.insert_header("Authorization", format!("Bearer {}", token));
+            }
+            next.run(req, client).await
+        })
+    }
+    fn invalidate_on_401(
+        req: Request,
+        client: Client,
+        next: Next<'_>,
+    ) -> BoxFuture<'_, Result<Response, http_types::Error>> {
+        Box::pin(async move {
+            let res = next.run(req, client.clone()).await?;
+            if res.status() == http_types::StatusCode::Unauthorized {
+                client.state().remove::<Token>();
+            }
+            Ok(res)
+        })
+    }
+
+    let mut server = tide::new();
+    server.at("/").get(|req: tide::Request<()>| async move {
+        Ok(match req.header("Authorization").map(|v| v.as_str()) {
+            Some("Bearer fresh") => tide::Response::new(200),
+            _ => tide::Response::new(401),
+        })
+    });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_base_url(surf::Url::parse("http://example.org")?)
+        .try_into()?;
+    let client = client.with(invalidate_on_401).with(authorize);
+
+    client.state().insert(Token("stale".to_string()));
+    assert_eq!(client.get("/").await?.status(), 401);
+    assert!(!client.state().contains::<Token>());
+
+    client.clone().state().insert(Token("fresh".to_string()));
+    assert_eq!(client.get("/").await?.status(), 200);
+    assert_eq!(client.state().get(), Some(Token("fresh".to_string())));
+    Ok(())
+}
+
+#[async_std::test]
+async fn response_header_limits() -> Result<(), http_types::Error> {
+    use surf::HeaderLimitExceeded;
+
+    let mut server = tide::new();
+    server.at("/").get(|_| async {
+        let mut res = tide::Response::new(200);
+        for i in 0..10 {
+            res.append_header("x-padding", format!("value-{}", i));
+        }
+        Ok(res)
+    });
+    let client = |config: Config| -> Result<Client, http_types::Error> {
+        Ok(config
+            .set_http_client(server.clone())
+            .set_base_url(surf::Url::parse("http://example.org")?)
+            .try_into()?)
+    };
+
+    let err = client(Config::new().set_max_response_headers(5))?
+        .get("/")
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), 502);
+    match err.downcast_ref::<HeaderLimitExceeded>() {
+        Some(HeaderLimitExceeded::Count { limit: 5, actual }) => assert!(*actual >= 10),
+        other => panic!("unexpected error: {:?}", other),
+    }
+
+    let err = client(Config::new().set_max_response_header_bytes(100))?
+        .get("/")
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref(),
+        Some(HeaderLimitExceeded::Bytes { limit: 100, .. })
+    ));
+
+    let res = client(
+        Config::new()
+            .set_max_response_headers(64)
+            .set_max_response_header_bytes(4096),
+    )?
+    .get("/")
+    .await?;
+    assert_eq!(res.status(), 200);
+    Ok(())
+}
+
+#[cfg(all(
+    any(
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ),
+    not(feature = "curl-client")
+))]
+#[async_std::test]
+async fn response_header_limits_stop_reading_the_head() -> Result<(), http_types::Error> {
+    use std::io::{Read, Write};
+    use surf::HeaderLimitExceeded;
+
+    // The heads never end, so the limits have to refuse them while they are read.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    std::thread::spawn(move || {
+        let mut streams = Vec::new();
+        for (i, stream) in listener.incoming().enumerate() {
+            let mut stream = stream.unwrap();
+            let _ = stream.read(&mut [0; 1024]).unwrap();
+            // The client hangs up once a limit is exceeded, which may fail the writes.
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n");
+            let _ = match i {
+                0 => (0..20).try_for_each(|i| write!(stream, "x-padding: {}\r\n", i)),
+                _ => stream.write_all(&[b'a'; 2000]),
+            };
+            streams.push(stream);
+        }
+    });
+
+    let client: Client = Config::new().set_max_response_headers(5).try_into()?;
+    let err = client.get(&url).await.unwrap_err();
+    assert_eq!(err.status(), 502);
+    assert_eq!(
+        err.downcast_ref(),
+        Some(&HeaderLimitExceeded::Count {
+            limit: 5,
+            actual: 6
+        })
+    );
+
+    let client: Client = Config::new()
+        .set_max_response_header_bytes(1000)
+        .try_into()?;
+    let err = client.get(&url).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref(),
+        Some(HeaderLimitExceeded::Bytes { limit: 1000, .. })
+    ));
+    Ok(())
+}
+
+#[cfg(all(
+    any(
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ),
+    not(feature = "curl-client")
+))]
+#[async_std::test]
+async fn header_raw_keeps_the_bytes_of_header_values() -> Result<(), http_types::Error> {
+    use std::io::{Read, Write};
+    use std::time::Duration;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let _ = stream.read(&mut [0; 1024]).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-disposition: attachment; filename=\"caf\xe9.txt\"\r\n")
+                .unwrap();
+            stream
+                .write_all(b"x-tag: a\r\nX-Tag:  b \r\ncontent-length: 2\r\n\r")
+                .unwrap();
+            // The end of the head arrives in another read.
+            std::thread::sleep(Duration::from_millis(20));
+            stream.write_all(b"\nok").unwrap();
+        }
+    });
+
+    let client: Client = Config::new().set_raw_response_headers(true).try_into()?;
+    let mut res = client.get(&url).await?;
+    assert_eq!(
+        res.header_raw("Content-Disposition"),
+        Some(vec![&b"attachment; filename=\"caf\xe9.txt\""[..]])
+    );
+    assert_eq!(
+        res["content-disposition"],
+        "attachment; filename=\"caf?.txt\""
+    );
+    assert_eq!(res.header_raw("x-tag"), Some(vec![&b"a"[..], &b"b"[..]]));
+    assert_eq!(res.header_raw("x-missing"), None);
+    assert_eq!(res.body_string().await?, "ok");
+
+    let mut server = tide::new();
+    server.at("/").get(|_| async {
+        Ok(tide::Response::builder(200)
+            .header("x-tag", "plain")
+            .build())
+    });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let res = client.get("http://example.org/").await?;
+    assert_eq!(res.header_raw("x-tag"), Some(vec![&b"plain"[..]]));
+    Ok(())
+}
+
+#[async_std::test]
+async fn mock_client_answers_matching_requests() -> Result<(), http_types::Error> {
+    use http_types::Method;
+    use surf::testing::{Mock, MockClient};
+
+    let mock = MockClient::new()
+        .mock(
+            Mock::new(Method::Post, "/cats")
+                .match_header("X-Api-Key", "secret")
+                .match_json(serde_json::json!({ "name": "Chashu" }))
+                .with_status(201)
+                .with_json(&serde_json::json!({ "id": 1 }))
+                .expect(2),
+        )
+        .mock(Mock::new(Method::Get, "/cats?page=2").with_body("[]"));
+    let client: Client = Config::new()
+        .set_http_client(mock.clone())
+        .set_base_url(surf::Url::parse("http://example.org")?)
+        .try_into()?;
+
+    for _ in 0..2 {
+        let mut res = client
+            .post("/cats")
+            .header("X-Api-Key", "secret")
+            .body_json(&serde_json::json!({ "name": "Chashu" }))?
+            .await?;
+        assert_eq!(res.status(), 201);
+        assert_eq!(res.content_type(), Some(http_types::mime::JSON));
+        assert_eq!(res.body_string().await?, r#"{"id":1}"#);
+    }
+    let err = client.post("/cats").body_string("{}".into()).await;
+    assert_eq!(err.unwrap_err().status(), 501);
+    let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| mock.assert_all_called()))
+        .unwrap_err();
+    assert_eq!(
+        panic.downcast_ref::<String>().map(String::as_str),
+        Some("the mock of GET /cats?page=2 was never called")
+    );
+
+    assert_eq!(client.get("/cats?page=2").recv_string().await?, "[]");
+    mock.assert_all_called();
+    let requests = mock.requests();
+    assert_eq!(requests.len(), 4);
+    assert_eq!(requests[0].header("X-Api-Key").unwrap(), "secret");
+    assert_eq!(requests[2].body(), b"{}");
+    Ok(())
+}
+
+#[cfg(feature = "curl-client")]
+#[async_std::test]
+async fn bodyless_methods_send_no_content_length() -> Result<(), http_types::Error> {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://127.0.0.1:{}/", listener.local_addr()?.port());
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for _ in 0..4 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let len = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nconnection: close\r\n\r\n")
+                .unwrap();
+            requests.push(String::from_utf8_lossy(&buf[..len]).to_ascii_lowercase());
+        }
+        requests
+    });
+
+    let client = Client::new();
+    client.get(&url).await?;
+    client.head(&url).await?;
+    client.delete(&url).await?;
+    client.post(&url).await?;
+
+    let requests = server.join().unwrap();
+    for request in &requests[..3] {
+        assert!(!request.contains("content-length"), "{}", request);
+        assert!(!request.contains("transfer-encoding"), "{}", request);
+    }
+    assert!(requests[3].contains("content-length: 0"), "{}", requests[3]);
+    Ok(())
+}
+
+#[async_std::test]
+async fn vcr_records_and_replays_cassettes() -> Result<(), http_types::Error> {
+    use surf::middleware::{Vcr, VcrMode};
+
+    let path = std::env::temp_dir().join(format!("surf-vcr-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let client = |server: tide::Server<()>, vcr: Vcr| -> Result<Client, http_types::Error> {
+        let client: Client = Config::new()
+            .set_http_client(server)
+            .set_base_url(surf::Url::parse("http://example.org")?)
+            .try_into()?;
+        Ok(client.with(vcr))
+    };
+    let vcr = || {
+        Vcr::new(&path)
+            .redact_query_param("token")
+            .redact_secret("s3cr3t")
+    };
+
+    let mut live = tide::new();
+    live.at("/echo")
+        .post(|mut req: tide::Request<()>| async move {
+            let body = req.body_string().await?;
+            Ok(format!("{} via s3cr3t", body))
+        });
+    let recorder = client(live, vcr())?;
+    let res = recorder
+        .post("/echo?token=abc")
+        .header("Authorization", "Bearer abc")
+        .body_string("hello".into())
+        .recv_string()
+        .await?;
+    assert_eq!(res, "hello via s3cr3t");
+
+    let cassette = std::fs::read_to_string(&path)?;
+    assert!(!cassette.contains("s3cr3t") && !cassette.contains("abc"));
+    assert!(cassette.contains("token=%5BREDACTED%5D"));
+
+    let mut down = tide::new();
+    down.at("*").all(|_| async { Ok(tide::Response::new(500)) });
+    let player = client(down, vcr().mode(VcrMode::Replay))?;
+    let replay = || player.post("/echo?token=xyz").body_string("hello".into());
+    let mut res = replay().await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.body_string().await?, "hello via [REDACTED]");
+    // Each response is replayed once, and other requests don't match.
+    assert_eq!(replay().await.unwrap_err().status(), 501);
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn query_appends_parameters() -> Result<(), http_types::Error> {
+    #[derive(serde::Serialize)]
+    struct Filter {
+        ids: Vec<u32>,
+        name: Option<&'static str>,
+        page: Page,
+    }
+    #[derive(serde::Serialize)]
+    struct Page {
+        size: u32,
+    }
+    let filter = Filter {
+        ids: vec![1, 2],
+        name: None,
+        page: Page { size: 10 },
+    };
+
+    let req = surf::get("https://example.com/items?sort=asc")
+        .query(&filter)?
+        .query_pairs([("q", "a&b")])
+        .query(&serde_json::json!({ "tag": "x y" }))?
+        .build();
+    assert_eq!(
+        req.url().query(),
+        Some("sort=asc&ids=1&ids=2&page[size]=10&q=a%26b&tag=x+y")
+    );
+
+    let req = surf::get("https://example.com/items?")
+        .query_array_format(surf::QueryArrayFormat::Brackets)
+        .query(&filter)?
+        .build();
+    assert_eq!(req.url().query(), Some("ids[]=1&ids[]=2&page[size]=10"));
+
+    let req = surf::get("https://example.com/items")
+        .query_pairs(Vec::<(&str, &str)>::new())
+        .build();
+    assert_eq!(req.url().as_str(), "https://example.com/items");
+    Ok(())
+}
+
+#[test]
+fn query_keeps_indexes_of_nested_arrays() -> Result<(), http_types::Error> {
+    let query = serde_json::json!({
+        "sort": [{ "by": "name", "dir": "asc" }, { "by": "id", "dir": "desc" }],
+        "grid": [[1, 2], [3]],
+    });
+
+    let req = surf::get("https://example.com/items")
+        .query(&query)?
+        .build();
+    assert_eq!(
+        req.url().query(),
+        Some("grid[0]=1&grid[0]=2&grid[1]=3&sort[0][by]=name&sort[0][dir]=asc&sort[1][by]=id&sort[1][dir]=desc")
+    );
+
+    let req = surf::get("https://example.com/items")
+        .query_array_format(surf::QueryArrayFormat::Brackets)
+        .query(&query)?
+        .build();
+    assert_eq!(
+        req.url().query(),
+        Some("grid[0][]=1&grid[0][]=2&grid[1][]=3&sort[0][by]=name&sort[0][dir]=asc&sort[1][by]=id&sort[1][dir]=desc")
+    );
+    Ok(())
+}
+
+#[test]
+fn config_round_trips_through_json() -> Result<(), http_types::Error> {
+    use std::time::Duration;
+
+    let config = Config::new()
+        .set_base_url(surf::Url::parse("https://api.example.com/v1/")?)
+        .add_header("X-Team", "payments")?
+        .set_timeout(None)
+        .set_connect_timeout(Some(Duration::from_millis(250)))
+        .set_max_response_headers(50)
+        .set_proxy(surf::Url::parse("http://proxy.internal:3128")?)
+        .set_no_proxy(vec!["localhost"])
+        .set_http_version_preference(surf::HttpVersionPreference::Http1Only);
+    let json = config.to_json();
+    assert!(json.contains(r#""http_version": "http1_only""#));
+
+    let loaded = Config::from_json(&json)?;
+    assert_eq!(loaded.to_json(), json);
+    assert_eq!(loaded.http_config.timeout, None);
+    assert_eq!(loaded.connect_timeout, Some(Duration::from_millis(250)));
+    assert_eq!(loaded.headers[&"x-team".into()], "payments");
+    assert_eq!(loaded.no_proxy, ["localhost"]);
+
+    let partial = Config::from_json(r#"{ "read_timeout": 2 }"#)?;
+    assert_eq!(partial.read_timeout, Some(Duration::from_secs(2)));
+    assert_eq!(partial.http_config.timeout, Some(Duration::from_secs(60)));
+
+    for invalid in [
+        r#"{ "timeout": -1 }"#,
+        r#"{ "base_url": "not a url" }"#,
+        r#"{ "timout": 1 }"#,
+        r#"{ "headers": { "bäd": ["x"] } }"#,
+        "[]",
+    ] {
+        assert_eq!(Config::from_json(invalid).unwrap_err().status(), 422);
+    }
+    Ok(())
+}
+
+#[test]
+fn config_from_env_reads_prefixed_variables() -> Result<(), http_types::Error> {
+    use std::time::Duration;
+
+    for (name, value) in [
+        ("SURF_ENV_TEST_BASE_URL", "https://api.example.com/v1/"),
+        ("SURF_ENV_TEST_TIMEOUT", "none"),
+        ("SURF_ENV_TEST_CONNECT_TIMEOUT", " 0.25 "),
+        ("SURF_ENV_TEST_MAX_CONNECTIONS", "8"),
+        ("SURF_ENV_TEST_PROXY", "http://proxy.internal:3128"),
+        (
+            "SURF_ENV_TEST_HTTPS_PROXY",
+            "http://tls-proxy.internal:3128",
+        ),
+        ("SURF_ENV_TEST_NO_PROXY", "localhost, .internal,"),
+        ("SURF_ENV_TEST_HTTP_VERSION", "HTTP1_ONLY"),
+        ("SURF_ENV_TEST_READ_TIMEOUT", ""),
+    ] {
+        std::env::set_var(name, value);
+    }
+    let config = Config::from_env("SURF_ENV_TEST_")?;
+    assert_eq!(
+        config.base_url.as_ref().map(|url| url.as_str()),
+        Some("https://api.example.com/v1/")
+    );
+    assert_eq!(config.http_config.timeout, None);
+    assert_eq!(config.connect_timeout, Some(Duration::from_millis(250)));
+    assert_eq!(config.read_timeout, None);
+    assert_eq!(config.http_config.max_connections_per_host, 8);
+    assert_eq!(
+        config.http_proxy.unwrap().host_str(),
+        Some("proxy.internal")
+    );
+    assert_eq!(
+        config.https_proxy.unwrap().host_str(),
+        Some("tls-proxy.internal")
+    );
+    assert_eq!(config.no_proxy, ["localhost", ".internal"]);
+    assert_eq!(config.http_version, surf::HttpVersionPreference::Http1Only);
+
+    std::env::set_var("SURF_ENV_TEST_BAD_MAX_CONNECTIONS", "many");
+    let err = Config::from_env("SURF_ENV_TEST_BAD").unwrap_err();
+    assert_eq!(err.status(), 422);
+    assert!(err
+        .to_string()
+        .contains("SURF_ENV_TEST_BAD_MAX_CONNECTIONS"));
+    Ok(())
+}
+
+#[async_std::test]
+async fn verbose_dumps_requests_and_responses() -> Result<(), http_types::Error> {
+    use std::sync::{Arc, Mutex};
+    use surf::middleware::Verbose;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut server = tide::new();
+    server
+        .at("/echo")
+        .post(|mut req: tide::Request<()>| async move {
+            let body = req.body_string().await?;
+            Ok(body.repeat(3))
+        });
+    let buffer = Buffer::default();
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_base_url(surf::Url::parse("http://example.org")?)
+        .try_into()?;
+    let client = client.with(Verbose::new().to_writer(buffer.clone()).body_limit(8));
+
+    let body = client
+        .post("/echo?x=1")
+        .header("x-trace", "abc")
+        .body_string("hello".into())
+        .recv_string()
+        .await?;
+    assert_eq!(body, "hellohellohello");
+
+    let dump = String::from_utf8(buffer.0.lock().unwrap().clone())?;
+    assert!(dump.starts_with("> POST /echo?x=1 HTTP/1.1\n> Host: example.org\n"));
+    assert!(dump.contains("> X-Trace: abc\n"));
+    assert!(dump.contains("> [body: 5 bytes]\n> hello\n"));
+    assert!(dump.contains("< HTTP/1.1 200 OK\n"));
+    assert!(dump.contains("< [body: first 8 of 15 bytes]\n< hellohel\n"));
+
+    let mut req = surf::put("https://example.com/blob")
+        .body_bytes([0u8, b'\'', 0xff])
+        .build();
+    assert_eq!(
+        req.curl_command().await?,
+        "curl -X PUT 'https://example.com/blob' \\\n  \
+         -H 'Content-Type: application/octet-stream' \\\n  \
+         --data-binary $'\\x00\\'\\xff'"
+    );
+    assert_eq!(req.take_body().into_bytes().await?, [0u8, b'\'', 0xff]);
+    let req = surf::head("https://example.com/").build();
+    let mut head = req.clone();
+    assert_eq!(
+        head.curl_command().await?,
+        "curl --head 'https://example.com/'"
+    );
+    assert!(head.header("content-type").is_none());
+    Ok(())
+}
+
+#[cfg(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "h1-client-no-tls"
+))]
+#[async_std::test]
+async fn header_case_spells_header_names() -> Result<(), http_types::Error> {
+    use std::io::{Read, Write};
+    use surf::HeaderCase;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 1024];
+        let len = stream.read(&mut buf).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+            .unwrap();
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    });
+
+    let client: Client = Config::new()
+        .set_header_case(HeaderCase::Title)
+        .try_into()?;
+    let body = client
+        .get(&url)
+        .header("x-request-id", "1")
+        .recv_string()
+        .await?;
+    assert_eq!(body, "ok");
+
+    let head = server.join().unwrap();
+    assert!(head.contains("\r\nX-Request-Id: 1\r\n"), "{}", head);
+    Ok(())
+}
+
+/// Serve `listener` over keep-alive connections, answering each request with `ok`, and record
+/// the heads of the requests and how many connections were made.
+#[cfg(all(
+    any(
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ),
+    not(feature = "curl-client")
+))]
+fn serve_keep_alive(
+    listener: std::net::TcpListener,
+) -> (
+    std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+) {
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    let connections = Arc::new(AtomicUsize::new(0));
+    let heads: Arc<Mutex<Vec<String>>> = Arc::default();
+    let (count, recorded) = (connections.clone(), heads.clone());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            count.fetch_add(1, Ordering::SeqCst);
+            let recorded = recorded.clone();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut head = String::new();
+                    while !head.ends_with("\r\n\r\n") {
+                        match reader.read_line(&mut head) {
+                            Ok(0) | Err(_) => return,
+                            Ok(_) => {}
+                        }
+                    }
+                    // The connection is kept open after asking the client to close it, so only
+                    // the client decides whether to reuse it.
+                    let close = if head.starts_with("GET /close ") {
+                        "connection: close\r\n"
+                    } else {
+                        ""
+                    };
+                    recorded.lock().unwrap().push(head);
+                    let res = format!("HTTP/1.1 200 OK\r\n{}content-length: 2\r\n\r\nok", close);
+                    stream.write_all(res.as_bytes()).unwrap();
+                }
+            });
+        }
+    });
+    (connections, heads)
+}
+
+#[cfg(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "h1-client-no-tls"
+))]
+#[async_std::test]
+async fn connect_timeout_gives_up_on_unanswered_connections() -> Result<(), http_types::Error> {
+    use socket2::{Domain, Socket, Type};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::time::{Duration, Instant};
+    use surf::ErrorKind;
+
+    // A listener with a full backlog drops new connection attempts unanswered.
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, None)?;
+    socket.bind(&"127.0.0.1:0".parse::<SocketAddr>()?.into())?;
+    socket.listen(0)?;
+    let listener: TcpListener = socket.into();
+    let addr = listener.local_addr()?;
+    let _queued = TcpStream::connect(addr)?;
+
+    let client: Client = Config::new()
+        .set_timeout(None)
+        .set_connect_timeout(Some(Duration::from_millis(200)))
+        .try_into()?;
+    let start = Instant::now();
+    let req = client.get(format!("http://{}/", addr));
+    let err = async_std::future::timeout(Duration::from_secs(5), req)
+        .await?
+        .unwrap_err();
+    assert_eq!(ErrorKind::of(&err), ErrorKind::Timeout);
+    assert!(start.elapsed() < Duration::from_secs(2));
+    Ok(())
+}
+
+#[cfg(all(
+    any(
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ),
+    not(feature = "curl-client")
+))]
+#[async_std::test]
+async fn header_case_reuses_connections() -> Result<(), http_types::Error> {
+    use std::sync::atomic::Ordering;
+    use surf::HeaderCase;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    let (connections, heads) = serve_keep_alive(listener);
+
+    let client: Client = Config::new()
+        .set_header_case(HeaderCase::Title)
+        .try_into()?;
+    for _ in 0..3 {
+        assert_eq!(client.get(&url).recv_string().await?, "ok");
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    // A body which wasn't read leaves its connection unusable for the next request.
+    let res = client.get(&url).await?;
+    drop(res);
+    assert_eq!(client.get(&url).recv_string().await?, "ok");
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+    let client: Client = Config::new()
+        .set_header_case(HeaderCase::Title)
+        .set_http_keep_alive(false)
+        .try_into()?;
+    client.get(&url).recv_string().await?;
+    assert_eq!(connections.load(Ordering::SeqCst), 3);
+    assert!(heads.lock().unwrap()[0].contains("\r\nConnection: keep-alive\r\n"));
+    Ok(())
+}
+
+#[cfg(all(
+    any(
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ),
+    not(feature = "curl-client")
+))]
+#[async_std::test]
+async fn header_case_honours_connection_close_and_limits() -> Result<(), http_types::Error> {
+    use futures_util::future::join_all;
+    use std::sync::atomic::Ordering;
+    use surf::HeaderCase;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let (connections, _) = serve_keep_alive(listener);
+
+    let client: Client = Config::new()
+        .set_header_case(HeaderCase::Title)
+        .set_max_connections_per_host(1)
+        .try_into()?;
+    let close = format!("http://{}/close", addr);
+    for _ in 0..2 {
+        assert_eq!(client.get(&close).recv_string().await?, "ok");
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+    let url = format!("http://{}/", addr);
+    let bodies = join_all((0..4).map(|_| client.get(&url).recv_string())).await;
+    assert!(bodies.iter().all(|body| body.as_deref().ok() == Some("ok")));
+    assert_eq!(connections.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[cfg(all(
+    any(
+        feature = "h1-client",
+        feature = "h1-client-rustls",
+        feature = "h1-client-no-tls"
+    ),
+    not(feature = "curl-client")
+))]
+#[async_std::test]
+async fn header_order_writes_host_then_insertion_order() -> Result<(), http_types::Error> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    let (_, heads) = serve_keep_alive(listener);
+    let names = |head: &str| -> Vec<String> {
+        head.lines()
+            .skip(1)
+            .filter_map(|line| line.split(':').next())
+            .filter(|name| name.starts_with("x-") || *name == "host")
+            .map(String::from)
+            .collect()
+    };
+
+    let client: Client = Config::new()
+        .set_header_order(Vec::<String>::new())
+        .try_into()?;
+    client
+        .get(&url)
+        .header("x-zebra", "1")
+        .header("x-apple", "1")
+        .header("x-mango", "1")
+        .recv_string()
+        .await?;
+    let client: Client = Config::new().set_header_order(["x-mango"]).try_into()?;
+    client
+        .get(&url)
+        .header("x-zebra", "1")
+        .header("x-apple", "1")
+        .header("x-mango", "1")
+        .recv_string()
+        .await?;
+
+    let heads = heads.lock().unwrap();
+    assert_eq!(names(&heads[0]), ["host", "x-zebra", "x-apple", "x-mango"]);
+    assert_eq!(names(&heads[1]), ["x-mango", "host", "x-zebra", "x-apple"]);
+    Ok(())
+}
+
+#[cfg(all(
+    any(feature = "h1-client", feature = "h1-client-rustls"),
+    not(feature = "curl-client")
+))]
+#[async_std::test]
+async fn header_case_is_kept_over_tls() -> Result<(), http_types::Error> {
+    use async_std::io::prelude::*;
+    use surf::HeaderCase;
+
+    const CERT: &[u8] = include_bytes!("fixtures/localhost.crt");
+    const KEY: &[u8] = include_bytes!("fixtures/localhost.key");
+
+    let mut server = rustls_crate::ServerConfig::new(rustls_crate::NoClientAuth::new());
+    let certs = rustls_crate::internal::pemfile::certs(&mut &CERT[..]).unwrap();
+    let mut keys = rustls_crate::internal::pemfile::pkcs8_private_keys(&mut &KEY[..]).unwrap();
+    server.set_single_cert(certs, keys.remove(0))?;
+    let acceptor = async_tls::TlsAcceptor::from(server);
+
+    let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("https://localhost:{}/", listener.local_addr()?.port());
+    let server = async_std::task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = acceptor.accept(stream).await.unwrap();
+        let mut head = Vec::new();
+        let mut buf = [0; 1024];
+        while !head.ends_with(b"\r\n\r\n") {
+            let len = stream.read(&mut buf).await.unwrap();
+            head.extend_from_slice(&buf[..len]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+        String::from_utf8(head).unwrap()
+    });
+
+    #[cfg(feature = "h1-client-rustls")]
+    let tls = {
+        let mut tls = rustls_crate::ClientConfig::new();
+        tls.root_store.add_pem_file(&mut &CERT[..]).unwrap();
+        tls
+    };
+    #[cfg(not(feature = "h1-client-rustls"))]
+    let tls = async_native_tls::TlsConnector::new()
+        .add_root_certificate(async_native_tls::Certificate::from_pem(CERT)?);
+    let client: Client = Config::new()
+        .set_header_case(HeaderCase::Title)
+        .set_tls_config(Some(std::sync::Arc::new(tls)))
+        .try_into()?;
+    let body = client
+        .get(&url)
+        .header("x-request-id", "1")
+        .recv_string()
+        .await?;
+    assert_eq!(body, "ok");
+    assert!(server.await.contains("\r\nX-Request-Id: 1\r\n"));
+    Ok(())
+}
+
+#[async_std::test]
+async fn endpoint_macro_builds_requests() -> Result<(), http_types::Error> {
+    #[derive(serde::Deserialize)]
+    struct Echo {
+        path: String,
+        query: String,
+        version: String,
+        body: serde_json::Value,
+    }
+
+    surf::endpoint! {
+        /// Rename a file.
+        struct RenameFile: PATCH "api/{owner}/files/{name}" -> Echo {
+            path owner: String,
+            path name: String,
+            query tag: Vec<&'static str>,
+            query dry_run: Option<bool>,
+            header x_api_version: u32,
+            body new_name: String,
+            body note: Option<String>,
+        }
+    }
+
+    let mut server = tide::new();
+    server
+        .at("/api/*")
+        .patch(|mut req: tide::Request<()>| async move {
+            let body: serde_json::Value = req.body_json().await?;
+            let echo = serde_json::json!({
+                "path": req.url().path(),
+                "query": req.url().query().unwrap_or_default(),
+                "version": req.header("x-api-version").unwrap().as_str(),
+                "body": body,
+            });
+            tide::Body::from_json(&echo)
+        });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_base_url("http://example.org/".parse()?)
+        .try_into()?;
+
+    let rename = RenameFile {
+        owner: "ann".into(),
+        name: "a b.txt".into(),
+        tag: vec!["x", "y"],
+        dry_run: None,
+        x_api_version: 2,
+        new_name: "c.txt".into(),
+        note: None,
+    };
+    let echo = rename.send(&client).await?;
+    assert_eq!(echo.path, "/api/ann/files/a%20b.txt");
+    assert_eq!(echo.query, "tag=x&tag=y");
+    assert_eq!(echo.version, "2");
+    assert_eq!(echo.body, serde_json::json!({ "new_name": "c.txt" }));
+    Ok(())
+}
+
+#[async_std::test]
+async fn events_report_request_activity() -> Result<(), http_types::Error> {
+    use futures_util::{FutureExt, StreamExt};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use surf::middleware::retry::Backoff;
+    use surf::middleware::{Redirect, Retry};
+    use surf::ClientEvent;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/old")
+        .get(|_| async { Ok(tide::Redirect::new("/new")) });
+    server
+        .at("/new")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            Ok(match req.state().fetch_add(1, Ordering::SeqCst) {
+                0 => tide::Response::new(503),
+                _ => "hello".into(),
+            })
+        });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let client = client
+        .with(Redirect::default())
+        .with(Retry::new(1).backoff(Backoff::none()));
+
+    let mut events = client.events();
+    let body = client.get("http://example.org/old").recv_string().await?;
+    assert_eq!(body, "hello");
+
+    let events: Vec<ClientEvent> =
+        std::iter::from_fn(|| events.next().now_or_never().flatten()).collect();
+    let id = events[0].id();
+    assert!(events.iter().all(|event| event.id() == id));
+    assert_eq!(
+        events[..4],
+        [
+            ClientEvent::RequestStarted {
+                id,
+                method: http_types::Method::Get,
+                url: "http://example.org/old".parse()?,
+            },
+            ClientEvent::Redirected {
+                id,
+                from: "http://example.org/old".parse()?,
+                to: "http://example.org/new".parse()?,
+                status: surf::StatusCode::Found,
+            },
+            ClientEvent::RetryScheduled {
+                id,
+                retry: 1,
+                delay: std::time::Duration::from_secs(0),
+            },
+            ClientEvent::ResponseReceived {
+                id,
+                status: surf::StatusCode::Ok,
+            },
+        ]
+    );
+    assert_eq!(
+        events.last(),
+        Some(&ClientEvent::BytesDownloaded {
+            id,
+            read: 5,
+            total: Some(5),
+        })
+    );
+    Ok(())
+}
+
+#[async_std::test]
+async fn body_json_lines_decodes_values_as_they_arrive() -> Result<(), http_types::Error> {
+    use futures_util::io::BufReader;
+    use futures_util::stream::{self, StreamExt, TryStreamExt};
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Event {
+        n: u32,
+    }
+
+    // Values are split across chunks, which the stream has to join.
+    let chunks = ["{\"n\":", "1}\r\n\n", "{\"n\":2}\nnot json\n{", "\"n\":3}"];
+    let reader = stream::iter(chunks)
+        .map(|chunk| Ok::<_, std::io::Error>(chunk.as_bytes().to_vec()))
+        .into_async_read();
+    let mut res = http_types::Response::new(200);
+    res.set_body(Body::from_reader(BufReader::with_capacity(4, reader), None));
+    let mut res = surf::Response::from(res);
+
+    let events: Vec<surf::Result<Event>> = res.body_json_lines().collect().await;
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[0].as_ref().unwrap(), &Event { n: 1 });
+    assert_eq!(events[1].as_ref().unwrap(), &Event { n: 2 });
+    assert!(events[2].is_err());
+    assert_eq!(events[3].as_ref().unwrap(), &Event { n: 3 });
+    assert_eq!(res.len(), Some(0));
+
+    let mut res = surf::Response::from(http_types::Response::new(200));
+    let events: Vec<Event> = res.body_json_lines().try_collect().await?;
+    assert!(events.is_empty());
+
+    // A line over the limit is an error, and its rest is skipped without being buffered.
+    let mut server = tide::new();
+    server.at("/").get(|_| async {
+        let long = format!("{{\"n\":{}}}", "1".repeat(64));
+        Ok(format!("{{\"n\":4}}\n{}\n{{\"n\":5}}\n{}", long, long))
+    });
+    let client: Client = Config::new()
+        .set_http_client(server)
+        .set_max_json_line_length(16)
+        .try_into()?;
+    let mut res = client.get("http://example.org/").await?;
+    let events: Vec<surf::Result<Event>> = res.body_json_lines().collect().await;
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[0].as_ref().unwrap(), &Event { n: 4 });
+    let err = events[1].as_ref().unwrap_err();
+    assert_eq!(err.status(), 422);
+    assert!(err.to_string().contains("longer than 16 bytes"));
+    assert_eq!(events[2].as_ref().unwrap(), &Event { n: 5 });
+    assert!(events[3].is_err());
+    Ok(())
+}
+
+#[async_std::test]
+async fn fetch_json_retries_and_caches() -> Result<(), http_types::Error> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use surf::FetchOptions;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let mut server = tide::with_state(hits.clone());
+    server
+        .at("/flaky")
+        .get(|req: tide::Request<Arc<AtomicUsize>>| async move {
+            assert_eq!(req.header("accept").unwrap().as_str(), "application/json");
+            Ok(match req.state().fetch_add(1, Ordering::SeqCst) {
+                0 => tide::Response::new(503),
+                _ => {
+                    let mut res = tide::Response::new(200);
+                    res.set_body(tide::Body::from_json(&serde_json::json!({ "n": 1 }))?);
+                    res.insert_header("cache-control", "max-age=60");
+                    res
+                }
+            })
+        });
+    server
+        .at("/missing")
+        .get(|_| async { Ok(tide::Response::new(404)) });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+    let options = FetchOptions::new().client(client);
+
+    let url = "http://fetch-json.example/flaky";
+    let value: serde_json::Value = surf::fetch_json(url, options.clone()).await?;
+    assert_eq!(value, serde_json::json!({ "n": 1 }));
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    let value: serde_json::Value = surf::fetch_json(url, options.clone()).await?;
+    assert_eq!(value, serde_json::json!({ "n": 1 }));
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    let url = "http://fetch-json.example/missing";
+    let err = surf::fetch_json::<serde_json::Value>(url, options)
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), 404);
+    Ok(())
+}
+
+#[cfg(feature = "msgpack")]
+#[async_std::test]
+async fn msgpack_bodies_round_trip() -> Result<(), http_types::Error> {
+    #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    struct Reading {
+        sensor: String,
+        value: f64,
+    }
+
+    let mut server = tide::new();
+    server
+        .at("/echo")
+        .post(|mut req: tide::Request<()>| async move {
+            let content_type = req.content_type().unwrap();
+            let mut res = tide::Response::new(200);
+            res.set_body(req.body_bytes().await?);
+            res.set_content_type(content_type);
+            Ok(res)
+        });
+    server
+        .at("/json")
+        .get(|_| async { tide::Body::from_json(&1) });
+    let client: Client = Config::new().set_http_client(server).try_into()?;
+
+    let reading = Reading {
+        sensor: "t1".into(),
+        value: 21.5,
+    };
+    let mut res = client
+        .post("http://example.org/echo")
+        .body_msgpack(&reading)?
+        .await?;
+    assert_eq!(res.content_type().unwrap().essence(), "application/msgpack");
+    assert_eq!(res.body_msgpack::<Reading>().await?, reading);
+
+    let mut res = client.get("http://example.org/json").await?;
+    let err = res.body_msgpack::<u32>().await.unwrap_err();
+    assert_eq!(err.status(), 415);
+    Ok(())
+}